@@ -0,0 +1,156 @@
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use iroh::sync::NamespaceId;
+use std::{collections::BTreeMap, error::Error, str::FromStr};
+
+/// The name of the file holding replica aliases, within the path on disk where the file system is stored.
+pub const ALIASES_FILE_NAME: &str = "aliases";
+
+impl OkuFs {
+    /// Loads the table of replica aliases from disk.
+    ///
+    /// # Returns
+    ///
+    /// The replicas known by each alias.
+    fn load_aliases(&self) -> Result<BTreeMap<String, NamespaceId>, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(ALIASES_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(aliases_toml) => {
+                let aliases: BTreeMap<String, String> = toml::from_str(&aliases_toml)?;
+                aliases
+                    .into_iter()
+                    .map(|(alias, namespace_id)| Ok((alias, NamespaceId::from_str(&namespace_id)?)))
+                    .collect()
+            }
+            Err(_) => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Saves the table of replica aliases to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `aliases` - The replicas known by each alias.
+    fn save_aliases(
+        &self,
+        aliases: &BTreeMap<String, NamespaceId>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let aliases: BTreeMap<&String, String> = aliases
+            .iter()
+            .map(|(alias, namespace_id)| (alias, namespace_id.to_string()))
+            .collect();
+        std::fs::write(
+            self.config.path.join(ALIASES_FILE_NAME),
+            toml::to_string(&aliases)?,
+        )?;
+        Ok(())
+    }
+
+    /// Gives a replica a human-readable alias, replacing any alias it previously had.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `alias` - The alias to give the replica.
+    pub fn set_replica_alias(
+        &self,
+        namespace_id: NamespaceId,
+        alias: impl Into<String>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let alias = alias.into();
+        if alias.is_empty() || alias.contains('/') {
+            return Err(OkuFsError::InvalidAlias(alias).into());
+        }
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut aliases = self.load_aliases()?;
+        if let Some(existing_namespace_id) = aliases.get(&alias) {
+            if *existing_namespace_id != namespace_id {
+                return Err(
+                    OkuFsError::AliasInUse(alias, existing_namespace_id.to_string()).into(),
+                );
+            }
+        }
+        aliases.retain(|_, aliased_namespace_id| *aliased_namespace_id != namespace_id);
+        aliases.insert(alias, namespace_id);
+        self.save_aliases(&aliases)
+    }
+
+    /// Removes a replica's alias, if it has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub fn remove_replica_alias(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut aliases = self.load_aliases()?;
+        let alias_count = aliases.len();
+        aliases.retain(|_, aliased_namespace_id| *aliased_namespace_id != namespace_id);
+        if aliases.len() != alias_count {
+            self.save_aliases(&aliases)?;
+        }
+        Ok(())
+    }
+
+    /// Finds the replica known by an alias.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The alias of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica, if the alias is in use.
+    pub fn resolve_alias(
+        &self,
+        alias: &str,
+    ) -> Result<Option<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        Ok(self.load_aliases()?.get(alias).copied())
+    }
+
+    /// Gets the alias of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The replica's alias, if it has one.
+    pub fn get_replica_alias(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .load_aliases()?
+            .into_iter()
+            .find(|(_, aliased_namespace_id)| *aliased_namespace_id == namespace_id)
+            .map(|(alias, _)| alias))
+    }
+
+    /// Lists all replicas in the file system, along with their aliases.
+    ///
+    /// # Returns
+    ///
+    /// A list of all replicas in the file system, each with its alias if it has one.
+    pub async fn list_replicas_with_aliases(
+        &self,
+    ) -> Result<Vec<(NamespaceId, Option<String>)>, Box<dyn Error + Send + Sync>> {
+        let aliases = self.load_aliases()?;
+        Ok(self
+            .list_replicas()
+            .await?
+            .into_iter()
+            .map(|namespace_id| {
+                let alias = aliases
+                    .iter()
+                    .find(|(_, aliased_namespace_id)| **aliased_namespace_id == namespace_id)
+                    .map(|(alias, _)| alias.clone());
+                (namespace_id, alias)
+            })
+            .collect())
+    }
+}
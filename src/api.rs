@@ -0,0 +1,489 @@
+use crate::error::OkuFsError;
+use crate::fs::{is_directory_marker, normalise_path, OkuFs, METADATA_DIRECTORY};
+use crate::http_util::{collect_limited_body, percent_decode, token_matches};
+use crate::ticket::AcceptPolicy;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    body::Incoming, header, server::conn::http1, service::service_fn, Method, Request, Response,
+    StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use iroh::{bytes::Hash, rpc_protocol::ShareMode, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, net::SocketAddr, path::PathBuf, str::FromStr};
+use tokio::net::TcpListener;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A file, as described by the API.
+pub struct ApiFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The hash of the file's content, as stored.
+    pub hash: Hash,
+    /// The size, in bytes, of the file's content, as stored.
+    pub size: u64,
+    /// The ID of the author of the latest version of the file.
+    pub author: String,
+    /// The time the latest version of the file was written, in microseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A request to import a replica from a ticket.
+pub struct AcceptTicketRequest {
+    /// The ticket, in its textual form.
+    pub ticket: String,
+    /// Whether write access granted by the ticket is kept.
+    #[serde(default)]
+    pub write: bool,
+}
+
+/// Builds a response holding a value as JSON.
+///
+/// # Arguments
+///
+/// * `status` - The status of the response.
+///
+/// * `value` - The value to send.
+///
+/// # Returns
+///
+/// The response.
+fn json_response(
+    status: StatusCode,
+    value: &impl Serialize,
+) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(serde_json::to_vec(value)?)))?)
+}
+
+impl OkuFs {
+    /// Serves the file system as a JSON API over HTTP, so that applications not written in Rust can use a running node.
+    /// Requests must carry the token as a bearer token in their `Authorization` header. Connections are not encrypted, so the API should only be served on trusted networks or through a tunnel.
+    /// Request bodies may not exceed the write limit, and file system metadata cannot be reached.
+    ///
+    /// The API offers the following endpoints, where replicas may be named by their ID or alias:
+    /// * `GET /replicas` lists the replicas; `POST /replicas` creates one.
+    /// * `DELETE /replicas/{replica}` deletes a replica.
+    /// * `GET /replicas/{replica}/files` lists the files in a replica.
+    /// * `GET`, `PUT`, and `DELETE /replicas/{replica}/files/{path}` read, write, and delete a file, whose content is sent as the body.
+    /// * `POST /replicas/{replica}/ticket` creates a ticket granting read access to a replica, or write access if `?write=true` is given.
+    /// * `POST /replicas/{replica}/sync` synchronises a replica with peers.
+    /// * `POST /tickets` imports a replica from a ticket, given as an [`AcceptTicketRequest`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen for requests on.
+    ///
+    /// * `token` - The token requests must carry.
+    pub async fn serve_api(
+        &self,
+        address: SocketAddr,
+        token: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let listener = TcpListener::bind(address).await?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let self_clone = self.clone();
+            let token = token.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let self_clone = self_clone.clone();
+                    let token = token.clone();
+                    async move { self_clone.respond_to_api_request(request, &token).await }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    tracing::warn!("{}", e);
+                }
+            });
+        }
+    }
+
+    /// Responds to a request to the API.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request.
+    ///
+    /// * `token` - The token requests must carry.
+    ///
+    /// # Returns
+    ///
+    /// The response to send.
+    async fn respond_to_api_request(
+        &self,
+        request: Request<Incoming>,
+        token: &str,
+    ) -> Result<Response<Full<Bytes>>, hyper::Error> {
+        let authorised = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|presented| token_matches(presented, token));
+        let result = match authorised {
+            true => self.route_api_request(request).await,
+            false => json_response(
+                StatusCode::UNAUTHORIZED,
+                &serde_json::json!({ "error": "invalid token" }),
+            ),
+        };
+        Ok(result.unwrap_or_else(|e| {
+            let status = match e.downcast_ref::<OkuFsError>() {
+                Some(OkuFsError::FsEntryNotFound) => StatusCode::NOT_FOUND,
+                Some(OkuFsError::ReplicaFrozen(_)) => StatusCode::FORBIDDEN,
+                Some(OkuFsError::TicketRejected(_)) => StatusCode::FORBIDDEN,
+                Some(OkuFsError::NotAuthorized(_, _)) => StatusCode::FORBIDDEN,
+                Some(OkuFsError::WriteLimitExceeded(_, _)) => StatusCode::PAYLOAD_TOO_LARGE,
+                _ if e.is::<serde_json::Error>() => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            json_response(status, &serde_json::json!({ "error": e.to_string() })).unwrap()
+        }))
+    }
+
+    /// Carries out a request to the API, once it has been authorised.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request.
+    ///
+    /// # Returns
+    ///
+    /// The response to send.
+    async fn route_api_request(
+        &self,
+        request: Request<Incoming>,
+    ) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+        let path = percent_decode(request.uri().path());
+        let segments: Vec<&str> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let method = request.method().clone();
+        match (&method, segments.as_slice()) {
+            (&Method::GET, ["replicas"]) => {
+                let replicas: Vec<String> = self
+                    .list_replicas()
+                    .await?
+                    .into_iter()
+                    .map(|namespace_id| namespace_id.to_string())
+                    .collect();
+                json_response(StatusCode::OK, &replicas)
+            }
+            (&Method::POST, ["replicas"]) => json_response(
+                StatusCode::CREATED,
+                &self.create_replica().await?.to_string(),
+            ),
+            (&Method::POST, ["tickets"]) => {
+                let body = collect_limited_body(
+                    request.into_body(),
+                    self.config.limits.received_size_limit(),
+                )
+                .await?;
+                let accept_ticket_request: AcceptTicketRequest = serde_json::from_slice(&body)?;
+                let policy = AcceptPolicy::default().allow_write(accept_ticket_request.write);
+                let namespace_id = self
+                    .accept_ticket(&accept_ticket_request.ticket, policy)
+                    .await?;
+                json_response(StatusCode::CREATED, &namespace_id.to_string())
+            }
+            (_, ["replicas", replica, rest @ ..]) => {
+                let namespace_id = self.resolve_api_replica(replica)?;
+                match (&method, rest) {
+                    (&Method::DELETE, []) => {
+                        self.delete_replica(namespace_id).await?;
+                        json_response(StatusCode::OK, &())
+                    }
+                    (&Method::GET, ["files"]) => {
+                        let files: Vec<ApiFile> = self
+                            .list_files(namespace_id)
+                            .await?
+                            .into_iter()
+                            .filter(|entry| !is_directory_marker(entry.key()))
+                            .map(|entry| ApiFile {
+                                path: self.entry_path(entry.key()),
+                                hash: entry.content_hash(),
+                                size: entry.content_len(),
+                                author: entry.author().to_string(),
+                                timestamp: entry.timestamp(),
+                            })
+                            .collect();
+                        json_response(StatusCode::OK, &files)
+                    }
+                    (_, ["files", file_path @ ..]) if !file_path.is_empty() => {
+                        let file_path = normalise_path(file_path.iter().collect());
+                        if file_path.starts_with(METADATA_DIRECTORY) {
+                            return Err(OkuFsError::FsEntryNotFound.into());
+                        }
+                        self.respond_to_api_file_request(request, namespace_id, file_path)
+                            .await
+                    }
+                    (&Method::POST, ["ticket"]) => {
+                        let mode = match request.uri().query() == Some("write=true") {
+                            true => ShareMode::Write,
+                            false => ShareMode::Read,
+                        };
+                        let ticket = self
+                            .create_replica_ticket(namespace_id, mode)
+                            .await?
+                            .to_string();
+                        json_response(StatusCode::OK, &serde_json::json!({ "ticket": ticket }))
+                    }
+                    (&Method::POST, ["sync"]) => {
+                        self.sync_replica(namespace_id).await?;
+                        json_response(StatusCode::OK, &())
+                    }
+                    _ => Err(OkuFsError::FsEntryNotFound.into()),
+                }
+            }
+            _ => Err(OkuFsError::FsEntryNotFound.into()),
+        }
+    }
+
+    /// Carries out a request to the API concerning a single file.
+    /// Files are read as their latest version by any author, as they are listed.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request.
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The response to send.
+    async fn respond_to_api_file_request(
+        &self,
+        request: Request<Incoming>,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+        match *request.method() {
+            Method::GET => {
+                let entry = self.get_latest_entry(namespace_id, path).await?;
+                if is_directory_marker(entry.key()) {
+                    return Err(OkuFsError::FsEntryNotFound.into());
+                }
+                let content = self.read_entry_content(&entry).await?;
+                Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .body(Full::new(content))?)
+            }
+            Method::PUT => {
+                let content = collect_limited_body(
+                    request.into_body(),
+                    self.config.limits.received_size_limit(),
+                )
+                .await?;
+                let hash = self
+                    .create_or_modify_file(namespace_id, path, content)
+                    .await?;
+                json_response(StatusCode::OK, &hash)
+            }
+            Method::DELETE => {
+                let entries_deleted = self.delete_file(namespace_id, path).await?;
+                json_response(StatusCode::OK, &entries_deleted)
+            }
+            _ => Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, PUT, DELETE")
+                .body(Full::default())?),
+        }
+    }
+
+    /// Finds the replica named in a request to the API.
+    ///
+    /// # Arguments
+    ///
+    /// * `replica` - The ID or alias of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica.
+    fn resolve_api_replica(
+        &self,
+        replica: &str,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        match NamespaceId::from_str(replica) {
+            Ok(namespace_id) => Ok(namespace_id),
+            Err(_) => Ok(self
+                .resolve_alias(replica)?
+                .ok_or(OkuFsError::FsEntryNotFound)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::start_test_fs;
+    use std::net::Ipv4Addr;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    /// Serves a file system's API on a free local port.
+    ///
+    /// # Arguments
+    ///
+    /// * `oku_fs` - The file system.
+    ///
+    /// # Returns
+    ///
+    /// The address the API is served on, once it is accepting connections.
+    async fn start_test_api(oku_fs: &OkuFs) -> SocketAddr {
+        let address = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let serving_fs = oku_fs.clone();
+        tokio::spawn(async move { serving_fs.serve_api(address, String::from("token")).await });
+        while TcpStream::connect(address).await.is_err() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        address
+    }
+
+    /// Sends a request to the API.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address the API is served on.
+    ///
+    /// * `method` - The method of the request.
+    ///
+    /// * `path` - The path requested.
+    ///
+    /// * `token` - The bearer token to present, if any.
+    ///
+    /// * `body` - The body of the request.
+    ///
+    /// # Returns
+    ///
+    /// The status and body of the response.
+    async fn send_api_request(
+        address: SocketAddr,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+        body: &[u8],
+    ) -> (StatusCode, Vec<u8>) {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            body.len()
+        );
+        if let Some(token) = token {
+            request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream.write_all(body).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let header_end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap();
+        let status_line = String::from_utf8_lossy(&response[..header_end]).to_string();
+        let status = status_line.split_whitespace().nth(1).unwrap();
+        (
+            StatusCode::from_bytes(status.as_bytes()).unwrap(),
+            response[header_end + 4..].to_vec(),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reject_requests_without_token() {
+        let oku_fs = start_test_fs("api-token").await;
+        let address = start_test_api(&oku_fs).await;
+        for token in [None, Some("wrong"), Some("")] {
+            let (status, _) = send_api_request(address, "GET", "/replicas", token, b"").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        let (status, _) = send_api_request(address, "GET", "/replicas", Some("token"), b"").await;
+        assert_eq!(status, StatusCode::OK);
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn route_file_requests() {
+        let oku_fs = start_test_fs("api-routes").await;
+        let address = start_test_api(&oku_fs).await;
+        let token = Some("token");
+        let (status, body) = send_api_request(address, "POST", "/replicas", token, b"").await;
+        assert_eq!(status, StatusCode::CREATED);
+        let replica: String = serde_json::from_slice(&body).unwrap();
+        let file = format!("/replicas/{}/files/d/a%20b.txt", replica);
+        let (status, _) = send_api_request(address, "PUT", &file, token, b"a").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send_api_request(address, "GET", &file, token, b"").await;
+        assert_eq!((status, body), (StatusCode::OK, b"a".to_vec()));
+        let files_path = format!("/replicas/{}/files", replica);
+        let (status, body) = send_api_request(address, "GET", &files_path, token, b"").await;
+        assert_eq!(status, StatusCode::OK);
+        let files: Vec<ApiFile> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            files
+                .iter()
+                .map(|file| file.path.clone())
+                .collect::<Vec<_>>(),
+            [PathBuf::from("/d/a b.txt")]
+        );
+        let (status, _) = send_api_request(address, "DELETE", &file, token, b"").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_api_request(address, "GET", &file, token, b"").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refuse_unknown_and_metadata_paths() {
+        let oku_fs = start_test_fs("api-not-found").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let address = start_test_api(&oku_fs).await;
+        let token = Some("token");
+        for path in [
+            String::from("/unknown"),
+            format!("/replicas/{}/unknown", namespace_id),
+            format!("/replicas/{}/files/.oku/file_metadata/a.txt", namespace_id),
+            String::from("/replicas/unknown-alias/files"),
+        ] {
+            let (status, _) = send_api_request(address, "GET", &path, token, b"").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", path);
+        }
+        let metadata_path = format!("/replicas/{}/files/.oku/a.txt", namespace_id);
+        let (status, _) = send_api_request(address, "PUT", &metadata_path, token, b"a").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refuse_bodies_over_write_limit() {
+        let mut oku_fs = start_test_fs("api-limit").await;
+        oku_fs.config.limits.max_write_size = Some(4);
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let address = start_test_api(&oku_fs).await;
+        let file = format!("/replicas/{}/files/a.txt", namespace_id);
+        let (status, _) = send_api_request(address, "PUT", &file, Some("token"), b"abcd").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_api_request(address, "PUT", &file, Some("token"), b"abcde").await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        let (status, body) = send_api_request(address, "GET", &file, Some("token"), b"").await;
+        assert_eq!((status, body), (StatusCode::OK, b"abcd".to_vec()));
+        oku_fs.shutdown();
+    }
+}
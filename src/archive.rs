@@ -0,0 +1,112 @@
+use crate::fs::{is_directory_marker, OkuFs};
+use chrono::{Datelike, Timelike};
+use iroh::sync::NamespaceId;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    io::{Cursor, Write},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The format of an archive holding a replica's files.
+pub enum ArchiveFormat {
+    /// A tar archive.
+    #[default]
+    Tar,
+    /// A zip archive, compressed with DEFLATE.
+    Zip,
+}
+
+impl OkuFs {
+    /// Writes the latest version of every file in a replica into an archive, preserving paths and modification times.
+    /// Explicitly created directories are written as directory entries, so that empty directories survive the archive.
+    /// Tar archives are streamed into the writer as they are built; zip archives are assembled in memory first, as their format requires seeking.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to export.
+    ///
+    /// * `writer` - The destination of the archive.
+    ///
+    /// * `format` - The format of the archive.
+    ///
+    /// # Returns
+    ///
+    /// The number of files written into the archive.
+    pub async fn export_replica_archive(
+        &self,
+        namespace_id: NamespaceId,
+        mut writer: impl Write,
+        format: ArchiveFormat,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let files = self.list_files(namespace_id).await?;
+        let file_count = files
+            .iter()
+            .filter(|file| !is_directory_marker(file.key()))
+            .count();
+        match format {
+            ArchiveFormat::Tar => {
+                let mut archive = tar::Builder::new(writer);
+                for file in files {
+                    let file_path = self.entry_path(file.key());
+                    if is_directory_marker(file.key()) {
+                        let mut header = tar::Header::new_gnu();
+                        header.set_entry_type(tar::EntryType::Directory);
+                        header.set_size(0);
+                        header.set_mtime(file.timestamp() / 1_000_000);
+                        header.set_mode(0o755);
+                        archive.append_data(
+                            &mut header,
+                            file_path.strip_prefix("/")?,
+                            std::io::empty(),
+                        )?;
+                        continue;
+                    }
+                    let content = self.read_entry_content(&file).await?;
+                    let mut header = tar::Header::new_gnu();
+                    header.set_size(content.len() as u64);
+                    header.set_mtime(file.timestamp() / 1_000_000);
+                    header.set_mode(0o644);
+                    archive.append_data(&mut header, file_path.strip_prefix("/")?, &content[..])?;
+                }
+                archive.into_inner()?.flush()?;
+            }
+            ArchiveFormat::Zip => {
+                let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+                for file in files {
+                    let file_path = self.entry_path(file.key());
+                    let mut options = zip::write::SimpleFileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated)
+                        .unix_permissions(0o644);
+                    if let Some(modified) =
+                        chrono::DateTime::from_timestamp_micros(file.timestamp() as i64)
+                    {
+                        if let Ok(modified) = zip::DateTime::from_date_and_time(
+                            modified.year() as u16,
+                            modified.month() as u8,
+                            modified.day() as u8,
+                            modified.hour() as u8,
+                            modified.minute() as u8,
+                            modified.second() as u8,
+                        ) {
+                            options = options.last_modified_time(modified);
+                        }
+                    }
+                    if is_directory_marker(file.key()) {
+                        archive.add_directory(
+                            file_path.strip_prefix("/")?.to_string_lossy(),
+                            options.unix_permissions(0o755),
+                        )?;
+                        continue;
+                    }
+                    let content = self.read_entry_content(&file).await?;
+                    archive.start_file(file_path.strip_prefix("/")?.to_string_lossy(), options)?;
+                    archive.write_all(&content)?;
+                }
+                writer.write_all(archive.finish()?.get_ref())?;
+                writer.flush()?;
+            }
+        }
+        Ok(file_count)
+    }
+}
@@ -0,0 +1,234 @@
+use crate::event::OkuFsEvent;
+use crate::fs::OkuFs;
+use iroh::{
+    bytes::Hash,
+    sync::{AuthorId, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, io::Write, path::PathBuf};
+
+/// The name of the file holding the audit log, within the path on disk where the file system is stored.
+pub const AUDIT_LOG_FILE_NAME: &str = "audit.jsonl";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Where an operation recorded in the audit log came from.
+pub enum AuditOrigin {
+    /// The operation was performed on this node.
+    Local,
+    /// The operation was performed by a peer and received during synchronisation.
+    Sync,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// An operation recorded in the audit log.
+pub enum AuditOperation {
+    /// A replica was created.
+    ReplicaCreated,
+    /// A replica was imported from a peer.
+    ReplicaImported,
+    /// A replica was deleted.
+    ReplicaDeleted,
+    /// A file was created or modified.
+    EntryInserted,
+    /// A file or directory was deleted.
+    EntryDeleted,
+    /// A file was moved to another path.
+    EntryRenamed {
+        /// The new path of the file.
+        to: PathBuf,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A record of an operation changing the file system.
+pub struct AuditRecord {
+    /// The time the operation was recorded, in microseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The operation.
+    pub operation: AuditOperation,
+    /// The ID of the replica changed.
+    pub namespace_id: NamespaceId,
+    /// The path of the file or directory changed, if the operation changed one.
+    pub path: Option<PathBuf>,
+    /// The hash of the content written, if the operation wrote any.
+    pub hash: Option<Hash>,
+    /// The ID of the author who performed the operation.
+    pub author: AuthorId,
+    /// Whether the operation was performed on this node or received from a peer.
+    pub origin: AuditOrigin,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// Criteria selecting records from the audit log. Records must meet every criterion given.
+pub struct AuditQuery {
+    /// The ID of the replica changed.
+    pub namespace_id: Option<NamespaceId>,
+    /// A path the changed file or directory must be at or beneath. Renames match on either path.
+    pub path: Option<PathBuf>,
+    /// The ID of the author who performed the operation.
+    pub author: Option<AuthorId>,
+    /// Whether the operation was performed on this node or received from a peer.
+    pub origin: Option<AuditOrigin>,
+    /// The earliest time of interest, in microseconds since the Unix epoch.
+    pub since: Option<u64>,
+    /// The latest time of interest, in microseconds since the Unix epoch.
+    pub until: Option<u64>,
+    /// The most records returned. The most recent records are kept.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// Checks whether a record meets the criteria.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record.
+    ///
+    /// # Returns
+    ///
+    /// Whether the record is selected.
+    fn matches(&self, record: &AuditRecord) -> bool {
+        let path_matches = match &self.path {
+            None => true,
+            Some(path) => {
+                record
+                    .path
+                    .as_ref()
+                    .is_some_and(|record_path| record_path.starts_with(path))
+                    || matches!(&record.operation, AuditOperation::EntryRenamed { to } if to.starts_with(path))
+            }
+        };
+        path_matches
+            && self
+                .namespace_id
+                .is_none_or(|namespace_id| record.namespace_id == namespace_id)
+            && self.author.is_none_or(|author| record.author == author)
+            && self.origin.is_none_or(|origin| record.origin == origin)
+            && self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp <= until)
+    }
+}
+
+impl OkuFs {
+    /// Lists the operations recorded in the audit log, such as to find who deleted a file and when.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The criteria selecting records.
+    ///
+    /// # Returns
+    ///
+    /// The records meeting the criteria, from oldest to newest.
+    pub fn query_audit_log(
+        &self,
+        query: &AuditQuery,
+    ) -> Result<Vec<AuditRecord>, Box<dyn Error + Send + Sync>> {
+        let audit_log = match std::fs::read_to_string(self.config.path.join(AUDIT_LOG_FILE_NAME)) {
+            Ok(audit_log) => audit_log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut records = Vec::new();
+        for line in audit_log.lines().filter(|line| !line.is_empty()) {
+            let record: AuditRecord = serde_json::from_str(line)?;
+            if query.matches(&record) {
+                records.push(record);
+            }
+        }
+        if let Some(limit) = query.limit {
+            records.drain(..records.len().saturating_sub(limit));
+        }
+        Ok(records)
+    }
+
+    /// Appends an event to the audit log, if it records a change to the file system.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event.
+    ///
+    /// * `origin` - Whether the event occurred on this node or was received from a peer.
+    pub(crate) fn record_audit(
+        &self,
+        event: &OkuFsEvent,
+        origin: AuditOrigin,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.config.audit_log {
+            return Ok(());
+        }
+        let (operation, namespace_id, path, hash, author) = match event.clone() {
+            OkuFsEvent::ReplicaCreated(namespace_id) => (
+                AuditOperation::ReplicaCreated,
+                namespace_id,
+                None,
+                None,
+                self.author_id,
+            ),
+            OkuFsEvent::ReplicaImported(namespace_id) => (
+                AuditOperation::ReplicaImported,
+                namespace_id,
+                None,
+                None,
+                self.author_id,
+            ),
+            OkuFsEvent::ReplicaDeleted(namespace_id) => (
+                AuditOperation::ReplicaDeleted,
+                namespace_id,
+                None,
+                None,
+                self.author_id,
+            ),
+            OkuFsEvent::EntryInserted {
+                namespace_id,
+                path,
+                hash,
+                author,
+            } => (
+                AuditOperation::EntryInserted,
+                namespace_id,
+                Some(path),
+                Some(hash),
+                author,
+            ),
+            OkuFsEvent::EntryDeleted {
+                namespace_id,
+                path,
+                author,
+            } => (
+                AuditOperation::EntryDeleted,
+                namespace_id,
+                Some(path),
+                None,
+                author,
+            ),
+            OkuFsEvent::EntryRenamed {
+                namespace_id,
+                from,
+                to,
+                author,
+            } => (
+                AuditOperation::EntryRenamed { to },
+                namespace_id,
+                Some(from),
+                None,
+                author,
+            ),
+            _ => return Ok(()),
+        };
+        let record = AuditRecord {
+            timestamp: chrono::Utc::now().timestamp_micros() as u64,
+            operation,
+            namespace_id,
+            path,
+            hash,
+            author,
+            origin,
+        };
+        let mut audit_log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.config.path.join(AUDIT_LOG_FILE_NAME))?;
+        writeln!(audit_log, "{}", serde_json::to_string(&record)?)?;
+        Ok(())
+    }
+}
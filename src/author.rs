@@ -0,0 +1,119 @@
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use bytes::Bytes;
+use futures::StreamExt;
+use iroh::{
+    bytes::Hash,
+    sync::{AuthorId, NamespaceId},
+};
+use std::{error::Error, path::PathBuf};
+
+impl OkuFs {
+    /// Gets the author the file system writes as unless told otherwise.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the default author.
+    pub fn default_author(&self) -> AuthorId {
+        self.author_id
+    }
+
+    /// Lists the authors held on this node, any of which can be written as.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the authors held on this node.
+    pub async fn list_authors(&self) -> Result<Vec<AuthorId>, Box<dyn Error + Send + Sync>> {
+        let authors = self.node.authors.list().await?;
+        futures::pin_mut!(authors);
+        let mut authors_list = Vec::new();
+        while let Some(author_id) = authors.next().await {
+            authors_list.push(author_id?);
+        }
+        Ok(authors_list)
+    }
+
+    /// Creates a new author on this node, giving the node another identity to write as.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new author.
+    pub async fn create_author(&self) -> Result<AuthorId, Box<dyn Error + Send + Sync>> {
+        Ok(self.node.authors.create().await?)
+    }
+
+    /// Creates a file (if it does not exist) or modifies an existing file, writing as a given author rather than the default author.
+    /// Writes made as another author are never coalesced.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to create or modify.
+    ///
+    /// * `path` - The path of the file to create or modify.
+    ///
+    /// * `data` - The data to write to the file.
+    ///
+    /// * `author_id` - The author to write the file as, which must be held on this node.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file.
+    pub async fn create_or_modify_file_as(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data: impl Into<Bytes>,
+        author_id: AuthorId,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        if author_id == self.author_id {
+            return self.create_or_modify_file(namespace_id, path, data).await;
+        }
+        self.ensure_not_frozen(namespace_id)?;
+        self.ensure_author_held(author_id).await?;
+        self.authorize_write(namespace_id, path.clone(), author_id)
+            .await?;
+        self.write_file(namespace_id, path, data.into(), author_id)
+            .await
+    }
+
+    /// Deletes a given author's version of a file, rather than the default author's.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to delete.
+    ///
+    /// * `path` - The path of the file to delete.
+    ///
+    /// * `author_id` - The author to delete the file as, which must be held on this node.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries deleted in the replica, which should be 1 if the author's version of the file was successfully deleted.
+    pub async fn delete_file_as(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        author_id: AuthorId,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.ensure_author_held(author_id).await?;
+        self.authorize_write(namespace_id, path.clone(), author_id)
+            .await?;
+        self.remove_file(namespace_id, path, author_id).await
+    }
+
+    /// Rejects writing as an author whose credentials are not held on this node.
+    ///
+    /// # Arguments
+    ///
+    /// * `author_id` - The author to write as.
+    pub(crate) async fn ensure_author_held(
+        &self,
+        author_id: AuthorId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if author_id != self.author_id && !self.list_authors().await?.contains(&author_id) {
+            return Err(OkuFsError::AuthorNotFound(author_id.to_string()).into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use crate::fs::{is_directory_marker, OkuFs};
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::Hash,
+    client::Entry,
+    sync::{store::DownloadPolicy, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, path::PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// How much of a file's content is held locally.
+pub enum Availability {
+    /// The file's content is held in full.
+    Local,
+    /// The file's content is being downloaded.
+    Partial {
+        /// The number of bytes downloaded so far.
+        downloaded: u64,
+        /// The size of the file's content, in bytes.
+        size: u64,
+    },
+    /// None of the file's content is held, but it will be downloaded once a peer provides it.
+    Pending,
+    /// None of the file's content is held, and the replica's download policy excludes it.
+    MetadataOnly,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The availability of one file.
+pub struct EntryAvailability {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The latest entry of the file.
+    pub entry: Entry,
+    /// How much of the file's content is held locally.
+    pub availability: Availability,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// A summary of how much of a replica is held locally, such as while it is being imported.
+pub struct AvailabilitySummary {
+    /// The number of files in the replica.
+    pub files: usize,
+    /// The number of files whose content is held in full.
+    pub local_files: usize,
+    /// The number of files whose content is being downloaded.
+    pub partial_files: usize,
+    /// The number of files whose content is yet to be downloaded.
+    pub pending_files: usize,
+    /// The number of files whose content is excluded by the replica's download policy.
+    pub metadata_only_files: usize,
+    /// The number of bytes of content held locally.
+    pub local_bytes: u64,
+    /// The size of the content of all files, in bytes.
+    pub total_bytes: u64,
+}
+
+impl OkuFs {
+    /// Reports how much of the content of a file, or of each file in a directory, is held locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file or directory.
+    ///
+    /// * `path` - The path of the file or directory.
+    ///
+    /// # Returns
+    ///
+    /// The availability of the file, or of each file within the directory.
+    pub async fn availability(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Vec<EntryAvailability>, Box<dyn Error + Send + Sync>> {
+        let entries = match self.get_latest_entry(namespace_id, path.clone()).await {
+            Ok(entry) => vec![entry],
+            Err(_) => self.list_directory_entries(namespace_id, path).await?,
+        };
+        self.entry_availabilities(namespace_id, entries).await
+    }
+
+    /// Summarises how much of a replica's content is held locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The number of files, and bytes of content, held locally.
+    pub async fn availability_summary(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<AvailabilitySummary, Box<dyn Error + Send + Sync>> {
+        let entries = self.list_files(namespace_id).await?;
+        let mut summary = AvailabilitySummary::default();
+        for entry_availability in self.entry_availabilities(namespace_id, entries).await? {
+            summary.files += 1;
+            summary.total_bytes += entry_availability.entry.content_len();
+            match entry_availability.availability {
+                Availability::Local => {
+                    summary.local_files += 1;
+                    summary.local_bytes += entry_availability.entry.content_len();
+                }
+                Availability::Partial { downloaded, .. } => {
+                    summary.partial_files += 1;
+                    summary.local_bytes += downloaded;
+                }
+                Availability::Pending => summary.pending_files += 1,
+                Availability::MetadataOnly => summary.metadata_only_files += 1,
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Determines the availability of each of several files.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the files.
+    ///
+    /// * `entries` - The latest entries of the files.
+    ///
+    /// # Returns
+    ///
+    /// The availability of each file, leaving out directory markers.
+    pub(crate) async fn entry_availabilities(
+        &self,
+        namespace_id: NamespaceId,
+        entries: Vec<Entry>,
+    ) -> Result<Vec<EntryAvailability>, Box<dyn Error + Send + Sync>> {
+        let download_policy = self
+            .open_document(namespace_id)
+            .await?
+            .get_download_policy()
+            .await?;
+        let incomplete_blobs = self.node.blobs.list_incomplete().await?;
+        pin_mut!(incomplete_blobs);
+        let mut downloaded_sizes: HashMap<Hash, u64> = HashMap::new();
+        while let Some(incomplete_blob) = incomplete_blobs.next().await {
+            let incomplete_blob = incomplete_blob?;
+            downloaded_sizes.insert(incomplete_blob.hash, incomplete_blob.size);
+        }
+        let mut availabilities = Vec::new();
+        for entry in entries {
+            if is_directory_marker(entry.key()) {
+                continue;
+            }
+            let availability = if let Some(downloaded) = downloaded_sizes.get(&entry.content_hash())
+            {
+                Availability::Partial {
+                    downloaded: *downloaded,
+                    size: entry.content_len(),
+                }
+            } else if self
+                .node
+                .blobs
+                .read(entry.content_hash())
+                .await
+                .is_ok_and(|reader| reader.is_complete())
+            {
+                Availability::Local
+            } else if is_downloaded(&download_policy, entry.key()) {
+                Availability::Pending
+            } else {
+                Availability::MetadataOnly
+            };
+            availabilities.push(EntryAvailability {
+                path: self.entry_path(entry.key()),
+                entry,
+                availability,
+            });
+        }
+        Ok(availabilities)
+    }
+}
+
+/// Determines whether a replica's download policy lets an entry's content be downloaded.
+///
+/// # Arguments
+///
+/// * `download_policy` - The replica's download policy.
+///
+/// * `key` - The key of the entry.
+///
+/// # Returns
+///
+/// Whether the entry's content is downloaded when a peer provides it.
+fn is_downloaded(download_policy: &DownloadPolicy, key: &[u8]) -> bool {
+    match download_policy {
+        DownloadPolicy::NothingExcept(filters) => filters.iter().any(|filter| filter.matches(key)),
+        DownloadPolicy::EverythingExcept(filters) => {
+            filters.iter().all(|filter| !filter.matches(key))
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use crate::alias::ALIASES_FILE_NAME;
+use crate::dns::DNS_KEY_FILE_NAME;
+use crate::download_policy::DOWNLOAD_POLICIES_FILE_NAME;
+use crate::encryption::ENCRYPTION_KEYS_DIRECTORY_NAME;
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use crate::keys::{decrypt_with_passphrase, encrypt_with_passphrase, write_private_file};
+use crate::pin::PINNED_REPLICAS_FILE_NAME;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    net::key::SecretKey,
+    rpc_protocol::ShareMode,
+    sync::{AuthorId, Capability, CapabilityKind, NamespaceId},
+    ticket::DocTicket,
+    util::path::IrohPaths,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// The name of the file describing a backup's contents, within the backup.
+pub const BACKUP_MANIFEST_FILE_NAME: &str = "backup.json";
+
+/// The name of the file holding the node's secret key, within a backup.
+pub const BACKUP_NODE_KEY_FILE_NAME: &str = "node_key";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A description of the contents of a backup.
+pub struct BackupManifest {
+    /// The ID of the node the backup was taken of.
+    pub node_id: String,
+    /// The authors held by the node.
+    pub authors: Vec<AuthorId>,
+    /// The capabilities to the replicas held by the node, holding their secret keys where the node could write to them.
+    pub replicas: Vec<Capability>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What was restored from a backup.
+pub struct RestoreReport {
+    /// The replicas imported from the backup.
+    pub replicas: Vec<NamespaceId>,
+    /// The authors held by the backed-up node that are not held by this node.
+    /// Author keys cannot be read from a running node, so authors are carried over with [`OkuFs::export_author_key`] and [`crate::fs::OkuFsConfigBuilder::author_key`].
+    pub missing_authors: Vec<AuthorId>,
+}
+
+/// Gets the settings files held in a backup, relative to the path on disk where the file system is stored.
+///
+/// # Arguments
+///
+/// * `fs_path` - The path on disk where the file system is stored.
+///
+/// # Returns
+///
+/// The paths of the settings files that exist, relative to the file system's path.
+fn backed_up_files(fs_path: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = [
+        ALIASES_FILE_NAME,
+        PINNED_REPLICAS_FILE_NAME,
+        DOWNLOAD_POLICIES_FILE_NAME,
+        DNS_KEY_FILE_NAME,
+    ]
+    .into_iter()
+    .map(PathBuf::from)
+    .filter(|file| fs_path.join(file).is_file())
+    .collect();
+    if let Ok(keys) = std::fs::read_dir(fs_path.join(ENCRYPTION_KEYS_DIRECTORY_NAME)) {
+        files.extend(
+            keys.flatten()
+                .map(|key| PathBuf::from(ENCRYPTION_KEYS_DIRECTORY_NAME).join(key.file_name())),
+        );
+    }
+    files
+}
+
+/// Checks whether a file in a backup is one that may be restored.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file within the backup.
+///
+/// # Returns
+///
+/// Whether the file is a settings file, rather than one that could be written elsewhere on disk.
+fn is_restorable_file(path: &Path) -> bool {
+    let names = [
+        ALIASES_FILE_NAME,
+        PINNED_REPLICAS_FILE_NAME,
+        DOWNLOAD_POLICIES_FILE_NAME,
+        DNS_KEY_FILE_NAME,
+        BACKUP_NODE_KEY_FILE_NAME,
+    ];
+    match path.parent() {
+        Some(parent) if parent == Path::new("") => names.iter().any(|name| path == Path::new(name)),
+        Some(parent) if parent == Path::new(ENCRYPTION_KEYS_DIRECTORY_NAME) => path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.parse::<NamespaceId>().is_ok()),
+        _ => false,
+    }
+}
+
+impl OkuFs {
+    /// Writes the node's identity and library into a single file, so that they can be moved to another machine.
+    /// The backup holds the node's secret key, the capabilities to its replicas, the keys of encrypted replicas, and the replicas' aliases, pins, and download policies.
+    /// As it holds secret keys, the backup is encrypted with a passphrase, and may only be read by the user running the node.
+    /// Replicas' contents are not included, and are synchronised from peers once restored; use [`OkuFs::export_replica_bundle`] to move a replica without network access.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path on disk to write the backup to.
+    ///
+    /// * `passphrase` - The passphrase to encrypt the backup with.
+    pub async fn backup(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let node_path = self.node_path()?;
+        let mut replicas = Vec::new();
+        let docs = self.node.docs.list().await?;
+        pin_mut!(docs);
+        while let Some(doc) = docs.next().await {
+            let (namespace_id, capability_kind) = doc?;
+            replicas.push(match capability_kind {
+                CapabilityKind::Write => {
+                    self.open_document(namespace_id)
+                        .await?
+                        .share(ShareMode::Write)
+                        .await?
+                        .capability
+                }
+                CapabilityKind::Read => Capability::Read(namespace_id),
+            });
+        }
+        let manifest = BackupManifest {
+            node_id: self.node.node_id().to_string(),
+            authors: self.list_authors().await?,
+            replicas,
+        };
+        let mut archive = tar::Builder::new(Vec::new());
+        let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_bytes.len() as u64);
+        header.set_mode(0o600);
+        archive.append_data(&mut header, BACKUP_MANIFEST_FILE_NAME, &manifest_bytes[..])?;
+        archive.append_path_with_name(
+            IrohPaths::SecretKey.with_root(node_path),
+            BACKUP_NODE_KEY_FILE_NAME,
+        )?;
+        for file in backed_up_files(&self.config.path) {
+            archive.append_path_with_name(self.config.path.join(&file), file)?;
+        }
+        let archive = archive.into_inner()?;
+        write_private_file(
+            path.as_ref(),
+            &encrypt_with_passphrase(&archive, passphrase)?,
+        )
+    }
+
+    /// Restores the node's identity and library from a backup, replacing its aliases, pins, and download policies.
+    /// The replicas in the backup are imported and synchronised from peers. The node's secret key is replaced, taking effect once the file system is next started.
+    /// Nothing is restored unless the whole backup can be read and is valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path on disk of the backup.
+    ///
+    /// * `passphrase` - The passphrase the backup was encrypted with.
+    ///
+    /// # Returns
+    ///
+    /// The replicas imported, and the authors in the backup that this node does not hold.
+    pub async fn restore(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+    ) -> Result<RestoreReport, Box<dyn Error + Send + Sync>> {
+        let archive = decrypt_with_passphrase(&std::fs::read(path)?, passphrase).map_err(|_| {
+            OkuFsError::InvalidBackup(String::from(
+                "the passphrase is incorrect, or the backup is damaged",
+            ))
+        })?;
+        let mut manifest: Option<BackupManifest> = None;
+        let mut node_key = None;
+        let mut encryption_keys = Vec::new();
+        let mut files = Vec::new();
+        for archive_entry in tar::Archive::new(archive.as_slice()).entries()? {
+            let mut archive_entry = archive_entry?;
+            let entry_path = archive_entry.path()?.to_path_buf();
+            let mut content = Vec::new();
+            archive_entry.read_to_end(&mut content)?;
+            if entry_path == Path::new(BACKUP_MANIFEST_FILE_NAME) {
+                manifest = Some(serde_json::from_slice(&content)?);
+            } else if !is_restorable_file(&entry_path) {
+                return Err(OkuFsError::InvalidBackup(format!(
+                    "unexpected file {}",
+                    entry_path.display()
+                ))
+                .into());
+            } else if entry_path == Path::new(BACKUP_NODE_KEY_FILE_NAME) {
+                SecretKey::try_from_openssh(&content)
+                    .map_err(|e| OkuFsError::InvalidBackup(format!("invalid node key ({})", e)))?;
+                node_key = Some(content);
+            } else if entry_path.starts_with(ENCRYPTION_KEYS_DIRECTORY_NAME) {
+                let namespace_id = entry_path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.parse::<NamespaceId>().ok())
+                    .ok_or(OkuFsError::InvalidBackup(format!(
+                        "unexpected file {}",
+                        entry_path.display()
+                    )))?;
+                let key: [u8; 32] = content.try_into().map_err(|_| {
+                    OkuFsError::InvalidBackup(format!("invalid key for replica {}", namespace_id))
+                })?;
+                encryption_keys.push((namespace_id, key));
+            } else {
+                files.push((entry_path, content));
+            }
+        }
+        let manifest = manifest.ok_or(OkuFsError::InvalidBackup(format!(
+            "missing {}",
+            BACKUP_MANIFEST_FILE_NAME
+        )))?;
+
+        if let Some(node_key) = node_key {
+            write_private_file(
+                &IrohPaths::SecretKey.with_root(self.node_path()?),
+                &node_key,
+            )?;
+        }
+        for (namespace_id, key) in encryption_keys {
+            self.set_encryption_key(namespace_id, key)?;
+        }
+        for (entry_path, content) in files {
+            let destination = self.config.path.join(&entry_path);
+            if let Some(parent) = destination.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(destination, content)?;
+        }
+        let held_replicas = self.list_replicas().await?;
+        let mut report = RestoreReport::default();
+        for capability in manifest.replicas {
+            let document = self
+                .node
+                .docs
+                .import(DocTicket {
+                    capability,
+                    nodes: Vec::new(),
+                })
+                .await?;
+            self.restore_download_policy(&document).await?;
+            report.replicas.push(document.id());
+            if !held_replicas.contains(&document.id()) {
+                self.forward_remote_events(document).await?;
+            }
+        }
+        self.sync_pinned_replicas()?;
+        let held_authors = self.list_authors().await?;
+        report.missing_authors = manifest
+            .authors
+            .into_iter()
+            .filter(|author| !held_authors.contains(author))
+            .collect();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::sync::NamespaceSecret;
+
+    #[test]
+    fn restore_only_settings_files() {
+        let namespace_id = NamespaceSecret::from_bytes(&[1; 32]).id();
+        let encryption_key =
+            PathBuf::from(ENCRYPTION_KEYS_DIRECTORY_NAME).join(namespace_id.to_string());
+        for path in [
+            PathBuf::from(ALIASES_FILE_NAME),
+            PathBuf::from(PINNED_REPLICAS_FILE_NAME),
+            PathBuf::from(DOWNLOAD_POLICIES_FILE_NAME),
+            PathBuf::from(DNS_KEY_FILE_NAME),
+            PathBuf::from(BACKUP_NODE_KEY_FILE_NAME),
+            encryption_key.clone(),
+        ] {
+            assert!(is_restorable_file(&path), "{}", path.display());
+        }
+        for path in [
+            PathBuf::new(),
+            PathBuf::from(BACKUP_MANIFEST_FILE_NAME),
+            PathBuf::from(ENCRYPTION_KEYS_DIRECTORY_NAME),
+            PathBuf::from("/").join(ALIASES_FILE_NAME),
+            PathBuf::from("..").join(ALIASES_FILE_NAME),
+            PathBuf::from("replicas").join(ALIASES_FILE_NAME),
+            PathBuf::from(ENCRYPTION_KEYS_DIRECTORY_NAME).join("not-a-replica"),
+            PathBuf::from(ENCRYPTION_KEYS_DIRECTORY_NAME)
+                .join("..")
+                .join(ALIASES_FILE_NAME),
+            encryption_key.join(ALIASES_FILE_NAME),
+        ] {
+            assert!(!is_restorable_file(&path), "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use clap::{Parser, Subcommand};
+use iroh::{rpc_protocol::ShareMode, sync::NamespaceId};
+use oku_fs::{
+    discovery::ContentRequest,
+    fs::{is_directory_marker, load_or_create_config, OkuFs, FS_PATH},
+    list::ListOptions,
+    ticket::AcceptPolicy,
+};
+use std::{
+    error::Error,
+    io::{Read, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+#[derive(Parser)]
+#[command(name = "oku", version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+    /// Disable all networking, working only with replicas held locally.
+    #[arg(long, global = true)]
+    offline: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Create, list, or delete replicas.
+    #[command(subcommand)]
+    Replica(ReplicaCommands),
+    /// Write a file, reading its content from a local file or standard input.
+    Put {
+        #[arg(value_name = "REPLICA")]
+        replica: String,
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        #[arg(short, long, value_name = "LOCAL_PATH")]
+        file: Option<PathBuf>,
+    },
+    /// Read a file, writing its content to a local file or standard output.
+    Get {
+        #[arg(value_name = "REPLICA")]
+        replica: String,
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        #[arg(short, long, value_name = "LOCAL_PATH")]
+        output: Option<PathBuf>,
+    },
+    /// List the files in a replica, or in a directory within it.
+    Ls {
+        #[arg(value_name = "REPLICA")]
+        replica: String,
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+    },
+    /// Delete a file, or a directory and everything in it, as written by any author.
+    Rm {
+        #[arg(value_name = "REPLICA")]
+        replica: String,
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Create a ticket granting access to a replica.
+    Share {
+        #[arg(value_name = "REPLICA")]
+        replica: String,
+        #[arg(short, long)]
+        write: bool,
+    },
+    /// Import a replica from a ticket.
+    Fetch {
+        #[arg(value_name = "TICKET")]
+        ticket: String,
+        #[arg(short, long)]
+        write: bool,
+    },
+    /// Publish a file or directory outside of any replica, reading from standard input if no path is given, and print a ticket to it.
+    Add {
+        #[arg(value_name = "LOCAL_PATH")]
+        path: Option<PathBuf>,
+    },
+    /// Read published content by its hash or ticket, writing it to a local file or directory, or to standard output.
+    Cat {
+        #[arg(value_name = "CONTENT")]
+        content: String,
+        #[arg(short, long, value_name = "LOCAL_PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Serve the replicas over WebDAV, so they can be mounted by the operating system.
+    #[cfg(feature = "webdav")]
+    Mount {
+        #[arg(short, long, value_name = "ADDRESS", default_value = "127.0.0.1:4918")]
+        address: std::net::SocketAddr,
+        /// The token clients must give as their password.
+        #[arg(short, long, value_name = "TOKEN")]
+        token: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReplicaCommands {
+    /// Create a replica.
+    Create,
+    /// List the replicas held.
+    List,
+    /// Delete a replica.
+    Delete {
+        #[arg(value_name = "REPLICA")]
+        replica: String,
+    },
+}
+
+fn resolve_replica(
+    node: &OkuFs,
+    replica: &str,
+) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+    match NamespaceId::from_str(replica) {
+        Ok(replica_id) => Ok(replica_id),
+        Err(_) => node
+            .resolve_alias(replica)?
+            .ok_or(format!("no replica has the ID or alias {}", replica).into()),
+    }
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let cli = Cli::parse();
+    let mut config = load_or_create_config(FS_PATH)?;
+    config.offline |= cli.offline;
+    let node = OkuFs::start(&config).await?;
+    match cli.command {
+        Commands::Replica(ReplicaCommands::Create) => {
+            let replica_id = node.create_replica().await?;
+            println!("{}", replica_id);
+        }
+        Commands::Replica(ReplicaCommands::List) => {
+            let replicas = node.list_replicas_with_aliases().await?;
+            for (replica, alias) in replicas {
+                match alias {
+                    Some(alias) => println!("{} ({})", replica, alias),
+                    None => println!("{}", replica),
+                }
+            }
+        }
+        Commands::Replica(ReplicaCommands::Delete { replica }) => {
+            let replica_id = resolve_replica(&node, &replica)?;
+            node.delete_replica(replica_id).await?;
+            println!("Removed replica with ID: {}", replica_id);
+        }
+        Commands::Put {
+            replica,
+            path,
+            file,
+        } => {
+            let replica_id = resolve_replica(&node, &replica)?;
+            let data = match file {
+                Some(file) => std::fs::read(file)?,
+                None => {
+                    let mut data = Vec::new();
+                    std::io::stdin().read_to_end(&mut data)?;
+                    data
+                }
+            };
+            let hash = node
+                .create_or_modify_file(replica_id, path.clone(), data)
+                .await?;
+            println!("Wrote {} to {:?}", hash, path);
+        }
+        Commands::Get {
+            replica,
+            path,
+            output,
+        } => {
+            let replica_id = resolve_replica(&node, &replica)?;
+            let data = node.read_file(replica_id, path).await?;
+            match output {
+                Some(output) => std::fs::write(output, data)?,
+                None => std::io::stdout().write_all(&data)?,
+            }
+        }
+        Commands::Ls { replica, path } => {
+            let replica_id = resolve_replica(&node, &replica)?;
+            let options = ListOptions {
+                path,
+                ..Default::default()
+            };
+            let files = node.list_files_with_options(replica_id, &options).await?;
+            for file in files {
+                if !is_directory_marker(file.key()) {
+                    println!(
+                        "{}\t{}",
+                        config.key_codec.decode(file.key()).display(),
+                        file.content_len()
+                    );
+                }
+            }
+        }
+        Commands::Rm {
+            replica,
+            path,
+            recursive,
+        } => {
+            let replica_id = resolve_replica(&node, &replica)?;
+            // Deleting only removes this node's version, so every author's version is purged instead.
+            let report = match recursive {
+                true => {
+                    let report = node.purge_directory(replica_id, path.clone()).await?;
+                    println!("Removed directory at {:?}", path);
+                    report
+                }
+                false => {
+                    let report = node.purge_file(replica_id, path.clone()).await?;
+                    println!("Removed file at {:?}", path);
+                    report
+                }
+            };
+            if report.hidden_entries > 0 {
+                println!(
+                    "{} version(s) written by authors not held on this node were hidden rather than removed, and will reappear if rewritten",
+                    report.hidden_entries
+                );
+            }
+        }
+        Commands::Share { replica, write } => {
+            let replica_id = resolve_replica(&node, &replica)?;
+            let mode = match write {
+                true => ShareMode::Write,
+                false => ShareMode::Read,
+            };
+            let ticket = node.create_replica_ticket(replica_id, mode).await?;
+            println!("{}", ticket);
+        }
+        Commands::Fetch { ticket, write } => {
+            let policy = AcceptPolicy::default().allow_write(write);
+            let replica_id = node.accept_ticket(&ticket, policy).await?;
+            println!("{}", replica_id);
+        }
+        Commands::Add { path } => {
+            let ticket = match path {
+                Some(path) => node.publish_path(path).await?,
+                None => {
+                    let mut data = Vec::new();
+                    std::io::stdin().read_to_end(&mut data)?;
+                    node.publish_bytes(data).await?
+                }
+            };
+            println!("{}", ticket);
+        }
+        Commands::Cat { content, output } => {
+            let content = ContentRequest::from_str(content.trim())?;
+            match output {
+                Some(output) => {
+                    node.export_published(content, output).await?;
+                }
+                None => std::io::stdout().write_all(&node.read_published(content).await?)?,
+            }
+        }
+        #[cfg(feature = "webdav")]
+        Commands::Mount { address, token } => {
+            println!("Serving replicas over WebDAV at http://{}", address);
+            node.serve_webdav(address, token).await?;
+        }
+    }
+    Ok(())
+}
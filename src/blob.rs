@@ -0,0 +1,101 @@
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use bytes::Bytes;
+use iroh::{
+    bytes::{BlobFormat, Hash, Tag},
+    net::NodeAddr,
+    rpc_protocol::{BlobDownloadRequest, SetTagOption},
+};
+use std::error::Error;
+use tokio_util::sync::CancellationToken;
+
+/// Names the tag keeping content fetched by its hash from being garbage collected.
+///
+/// # Arguments
+///
+/// * `hash` - The hash of the content.
+///
+/// # Returns
+///
+/// The tag kept on the content.
+pub(crate) fn blob_tag(hash: Hash) -> Tag {
+    Tag::from(format!("oku-blob-{}", hash))
+}
+
+impl OkuFs {
+    /// Reads content by its hash, such as that of a previous version of a file or of a hash found in the audit log.
+    /// The content is read as it is stored, without being decrypted or decompressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the content.
+    ///
+    /// # Returns
+    ///
+    /// The content, if it is held in full locally.
+    pub async fn read_blob(&self, hash: Hash) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let mut reader = self.node.blobs.read(hash).await?;
+        if !reader.is_complete() {
+            return Err(OkuFsError::BlobNotHeld(hash.to_string()).into());
+        }
+        Ok(reader.read_to_bytes().await?)
+    }
+
+    /// Fetches content by its hash from peers providing it, so that it can then be read locally.
+    /// Each provider is asked in turn until one supplies the content. Fetched content is kept by garbage collection until released with [`OkuFs::release_blob`].
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the content.
+    ///
+    /// * `providers` - The peers to fetch the content from.
+    ///
+    /// # Returns
+    ///
+    /// The content.
+    pub async fn fetch_blob(
+        &self,
+        hash: Hash,
+        providers: Vec<NodeAddr>,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        if let Ok(content) = self.read_blob(hash).await {
+            return Ok(content);
+        }
+        let cancellation = CancellationToken::new();
+        for provider in providers {
+            let operation = format!("Fetching content {} from {}", hash, provider.node_id);
+            let blob_download_request = BlobDownloadRequest {
+                hash,
+                format: BlobFormat::Raw,
+                peer: provider,
+                tag: SetTagOption::Named(blob_tag(hash)),
+            };
+            let outcome = self
+                .with_network_timeout(&operation, &cancellation, async {
+                    self.node
+                        .blobs
+                        .download(blob_download_request)
+                        .await?
+                        .finish()
+                        .await?;
+                    Ok(())
+                })
+                .await;
+            match outcome {
+                Ok(()) => return self.read_blob(hash).await,
+                Err(e) => tracing::warn!(%hash, "{}", e),
+            }
+        }
+        Err(OkuFsError::BlobUnavailable(hash.to_string()).into())
+    }
+
+    /// Releases content fetched by its hash, so that garbage collection may remove it once no entry refers to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the content.
+    pub async fn release_blob(&self, hash: Hash) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.node.tags.delete(blob_tag(hash)).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,744 @@
+use crate::blob::blob_tag;
+use crate::error::OkuFsError;
+use crate::event::OkuFsEvent;
+use crate::fs::{is_directory_marker, normalise_path, OkuFs};
+use crate::limits::SYNC_SLOT_TIMEOUT;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::Hash,
+    client::LiveEvent,
+    net::{relay::RelayMode, MagicEndpoint, NodeAddr},
+    rpc_protocol::{DocGetManyRequest, ShareMode},
+    sync::{
+        actor::{OpenOpts, SyncHandle},
+        net::connect_and_sync,
+        store::Query,
+        Capability, ContentStatus, NamespaceId, SignedEntry,
+    },
+    ticket::DocTicket,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    io::{Read, Write},
+    net::{Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+};
+
+/// The name of the file describing a bundle's contents, within the bundle.
+pub const BUNDLE_MANIFEST_FILE_NAME: &str = "bundle.json";
+
+/// The directory holding a bundle's content, within the bundle.
+pub const BUNDLE_BLOBS_DIRECTORY: &str = "blobs";
+
+/// The name of the file describing a replica bundle's contents, within the bundle.
+pub const REPLICA_BUNDLE_MANIFEST_FILE_NAME: &str = "replica.json";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A description of the contents of a bundle.
+pub struct BundleManifest {
+    /// The ID of the replica the bundle was exported from.
+    pub namespace_id: NamespaceId,
+    /// The files held in the bundle.
+    pub entries: Vec<BundleEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A description of a file held in a bundle.
+pub struct BundleEntry {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The ID of the author who wrote the file.
+    pub author: String,
+    /// The time the file was written, in microseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The hash of the file's content.
+    pub hash: Hash,
+    /// The size, in bytes, of the file's content.
+    pub size: u64,
+    /// Whether the entry marks an explicitly created directory rather than holding a file.
+    #[serde(default)]
+    pub directory: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A description of the contents of a replica bundle.
+pub struct ReplicaBundleManifest {
+    /// The capability to the replica, holding its secret key if the bundle grants write access.
+    pub capability: Capability,
+    /// The key with which the replica's file contents are encrypted, if the replica is encrypted.
+    #[serde(default)]
+    pub encryption_key: Option<[u8; 32]>,
+    /// The entries held in the bundle as signed by their authors, including file system metadata, directory markers and deletions.
+    pub entries: Vec<SignedEntry>,
+}
+
+/// Reads the files held in a bundle.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the bundle.
+///
+/// # Returns
+///
+/// The path and content of each file in the bundle.
+fn read_bundle_files(reader: impl Read) -> Result<Vec<(PathBuf, Vec<u8>)>, std::io::Error> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = Vec::new();
+    for archive_entry in archive.entries()? {
+        let mut archive_entry = archive_entry?;
+        let entry_path = archive_entry.path()?.to_path_buf();
+        let mut content = Vec::new();
+        archive_entry.read_to_end(&mut content)?;
+        files.push((entry_path, content));
+    }
+    Ok(files)
+}
+
+impl OkuFs {
+    /// Writes the latest version of every file in a replica, along with its content, into a single bundle.
+    /// The bundle can be imported by another node without any network access.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to export.
+    ///
+    /// * `writer` - The destination of the bundle.
+    ///
+    /// # Returns
+    ///
+    /// The number of files written into the bundle.
+    pub async fn export_bundle(
+        &self,
+        namespace_id: NamespaceId,
+        writer: impl Write,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let files = self.list_files(namespace_id).await?;
+        let mut archive = tar::Builder::new(writer);
+        let mut entries = Vec::new();
+        let mut written_hashes = HashSet::new();
+        for file in &files {
+            // Files are bundled as they are read, so the bundle does not depend on how this replica stores them.
+            let content = self.read_entry_content(file).await?;
+            let hash = Hash::new(&content);
+            entries.push(BundleEntry {
+                path: self.entry_path(file.key()),
+                author: file.author().to_string(),
+                timestamp: file.timestamp(),
+                hash,
+                size: content.len() as u64,
+                directory: is_directory_marker(file.key()),
+            });
+            if !written_hashes.insert(hash) {
+                continue;
+            }
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            archive.append_data(
+                &mut header,
+                format!("{}/{}", BUNDLE_BLOBS_DIRECTORY, hash),
+                &content[..],
+            )?;
+        }
+        let manifest = BundleManifest {
+            namespace_id,
+            entries,
+        };
+        let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_bytes.len() as u64);
+        header.set_mode(0o644);
+        archive.append_data(&mut header, BUNDLE_MANIFEST_FILE_NAME, &manifest_bytes[..])?;
+        archive.into_inner()?.flush()?;
+        Ok(files.len())
+    }
+
+    /// Imports a bundle into a new replica, verifying its content against the hashes it lists.
+    /// The files are written by this file system's author.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source of the bundle.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica.
+    pub async fn import_bundle(
+        &self,
+        reader: impl Read,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        let files = read_bundle_files(reader)?;
+        let mut manifest: Option<BundleManifest> = None;
+        let mut imported_sizes = HashMap::new();
+        let mut tags = Vec::new();
+        let mut result = Ok(());
+        for (entry_path, content) in files {
+            if entry_path == Path::new(BUNDLE_MANIFEST_FILE_NAME) {
+                match serde_json::from_slice(&content) {
+                    Ok(bundle_manifest) => manifest = Some(bundle_manifest),
+                    Err(e) => {
+                        result = Err(e.into());
+                        break;
+                    }
+                }
+            } else if let Ok(hash_name) = entry_path.strip_prefix(BUNDLE_BLOBS_DIRECTORY) {
+                let expected_hash: Hash = match hash_name.to_string_lossy().parse() {
+                    Ok(expected_hash) => expected_hash,
+                    Err(e) => {
+                        result = Err(Box::new(e) as Box<dyn Error + Send + Sync>);
+                        break;
+                    }
+                };
+                let outcome = match self.node.blobs.add_bytes(content).await {
+                    Ok(outcome) => outcome,
+                    Err(e) => {
+                        result = Err(e.into());
+                        break;
+                    }
+                };
+                tags.push(outcome.tag);
+                if outcome.hash != expected_hash {
+                    result = Err(OkuFsError::InvalidBundle(format!(
+                        "content listed as {} has hash {}",
+                        expected_hash, outcome.hash
+                    ))
+                    .into());
+                    break;
+                }
+                imported_sizes.insert(outcome.hash, outcome.size);
+            }
+        }
+        let result = match (result, manifest) {
+            (Err(e), _) => Err(e),
+            (Ok(()), None) => Err(OkuFsError::InvalidBundle(format!(
+                "missing {}",
+                BUNDLE_MANIFEST_FILE_NAME
+            ))
+            .into()),
+            (Ok(()), Some(manifest)) => {
+                self.import_bundle_manifest(manifest, &imported_sizes).await
+            }
+        };
+        // The replica's entries now protect the imported content.
+        for tag in tags {
+            self.node.tags.delete(tag).await?;
+        }
+        result
+    }
+
+    /// Writes the files described by a bundle's manifest into a new replica, once their content has been imported.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The manifest of the bundle.
+    ///
+    /// * `imported_sizes` - The size of each piece of content imported from the bundle, by its hash.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica.
+    async fn import_bundle_manifest(
+        &self,
+        manifest: BundleManifest,
+        imported_sizes: &HashMap<Hash, u64>,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        for entry in &manifest.entries {
+            match imported_sizes.get(&entry.hash) {
+                None => {
+                    return Err(OkuFsError::InvalidBundle(format!(
+                        "missing content of {}",
+                        entry.path.display()
+                    ))
+                    .into())
+                }
+                // Entries are written with the size of their content, so a size that does not match would make the file unreadable.
+                Some(size) if *size != entry.size => {
+                    return Err(OkuFsError::InvalidBundle(format!(
+                        "{} is listed with {} bytes but has {}",
+                        entry.path.display(),
+                        entry.size,
+                        size
+                    ))
+                    .into())
+                }
+                Some(_) => (),
+            }
+        }
+        let namespace_id = self.create_replica().await?;
+        let document = self.open_document(namespace_id).await?;
+        for entry in manifest.entries {
+            let key = if entry.directory {
+                self.entry_prefix(entry.path.clone())
+            } else {
+                self.entry_key(entry.path.clone())
+            };
+            document
+                .set_hash(self.author_id, key, entry.hash, entry.size)
+                .await?;
+            self.emit(OkuFsEvent::EntryInserted {
+                namespace_id,
+                path: if entry.directory {
+                    normalise_path(entry.path).join("")
+                } else {
+                    normalise_path(entry.path)
+                },
+                hash: entry.hash,
+                author: self.author_id,
+            });
+        }
+        Ok(namespace_id)
+    }
+
+    /// Writes a replica, including the capability to access it, into a single bundle, so that it can be moved to another node without any network access.
+    /// Unlike [`OkuFs::export_bundle`], the bundle holds the replica itself rather than a copy of its files: every entry of every author is included as they signed it, with its content as stored.
+    /// The bundle of an encrypted replica holds the replica's key, and so should be kept as secret as the key itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to export.
+    ///
+    /// * `include_write_key` - Whether the bundle grants write access to the replica, rather than only read access.
+    ///
+    /// * `writer` - The destination of the bundle.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries written into the bundle.
+    pub async fn export_replica_bundle(
+        &self,
+        namespace_id: NamespaceId,
+        include_write_key: bool,
+        writer: impl Write,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.flush_writes(namespace_id).await?;
+        let document = self.open_document(namespace_id).await?;
+        let capability = match include_write_key {
+            true => document.share(ShareMode::Write).await?.capability,
+            false => Capability::Read(namespace_id),
+        };
+        let entries = self.signed_entries(namespace_id).await?;
+        let mut archive = tar::Builder::new(writer);
+        let mut written_hashes = HashSet::new();
+        for entry in &entries {
+            // Deletions have no content.
+            if entry.content_len() == 0 || !written_hashes.insert(entry.content_hash()) {
+                continue;
+            }
+            let content = self.read_blob(entry.content_hash()).await?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            archive.append_data(
+                &mut header,
+                format!("{}/{}", BUNDLE_BLOBS_DIRECTORY, entry.content_hash()),
+                &content[..],
+            )?;
+        }
+        let entry_count = entries.len();
+        let manifest = ReplicaBundleManifest {
+            capability,
+            encryption_key: self.encryption_key(namespace_id)?,
+            entries,
+        };
+        let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_bytes.len() as u64);
+        header.set_mode(0o644);
+        archive.append_data(
+            &mut header,
+            REPLICA_BUNDLE_MANIFEST_FILE_NAME,
+            &manifest_bytes[..],
+        )?;
+        archive.into_inner()?.flush()?;
+        Ok(entry_count)
+    }
+
+    /// Lists every entry of a replica as signed by its author, including deletions.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The latest entry of each author at each key in the replica.
+    async fn signed_entries(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<SignedEntry>, Box<dyn Error + Send + Sync>> {
+        // The document client drops the signatures of the entries it returns, so the node is queried directly.
+        let responses = self
+            .node
+            .controller()
+            .server_streaming(DocGetManyRequest {
+                doc_id: namespace_id,
+                query: Query::all().include_empty().build(),
+            })
+            .await?;
+        pin_mut!(responses);
+        let mut entries = Vec::new();
+        while let Some(response) = responses.next().await {
+            entries.push(response??.entry);
+        }
+        Ok(entries)
+    }
+
+    /// Imports a replica from a bundle, verifying its content against the hashes it lists.
+    /// Entries keep the signatures of their authors, so the imported replica is the same as the one exported, whether or not the bundle grants write access.
+    /// The key of an encrypted replica held in the bundle is kept by this node.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source of the bundle.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the imported replica.
+    pub async fn import_replica_bundle(
+        &self,
+        reader: impl Read,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        let files = read_bundle_files(reader)?;
+        let mut manifest: Option<ReplicaBundleManifest> = None;
+        let mut imported_hashes = HashSet::new();
+        let mut result = Ok(());
+        for (entry_path, content) in files {
+            if entry_path == Path::new(REPLICA_BUNDLE_MANIFEST_FILE_NAME) {
+                match serde_json::from_slice(&content) {
+                    Ok(replica_manifest) => manifest = Some(replica_manifest),
+                    Err(e) => {
+                        result = Err(e.into());
+                        break;
+                    }
+                }
+            } else if let Ok(hash_name) = entry_path.strip_prefix(BUNDLE_BLOBS_DIRECTORY) {
+                let expected_hash: Hash = match hash_name.to_string_lossy().parse() {
+                    Ok(expected_hash) => expected_hash,
+                    Err(e) => {
+                        result = Err(Box::new(e) as Box<dyn Error + Send + Sync>);
+                        break;
+                    }
+                };
+                // Content is kept from garbage collection until the replica's entries refer to it.
+                let outcome = self
+                    .node
+                    .blobs
+                    .add_bytes_named(content, blob_tag(expected_hash))
+                    .await?;
+                if outcome.hash != expected_hash {
+                    self.node.tags.delete(outcome.tag).await?;
+                    result = Err(OkuFsError::InvalidBundle(format!(
+                        "content listed as {} has hash {}",
+                        expected_hash, outcome.hash
+                    ))
+                    .into());
+                    break;
+                }
+                imported_hashes.insert(outcome.hash);
+            }
+        }
+        let result = match (result, manifest) {
+            (Err(e), _) => Err(e),
+            (Ok(()), None) => Err(OkuFsError::InvalidBundle(format!(
+                "missing {}",
+                REPLICA_BUNDLE_MANIFEST_FILE_NAME
+            ))
+            .into()),
+            (Ok(()), Some(manifest)) => {
+                self.import_replica_manifest(manifest, &imported_hashes)
+                    .await
+            }
+        };
+        // The replica's entries now protect the imported content.
+        for hash in imported_hashes {
+            self.node.tags.delete(blob_tag(hash)).await?;
+        }
+        result
+    }
+
+    /// Imports the replica described by a bundle's manifest, once its content has been imported.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The manifest of the bundle.
+    ///
+    /// * `imported_hashes` - The hashes of the content imported from the bundle.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the imported replica.
+    async fn import_replica_manifest(
+        &self,
+        manifest: ReplicaBundleManifest,
+        imported_hashes: &HashSet<Hash>,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        let namespace_id = manifest.capability.id();
+        if let Some(missing) = manifest.entries.iter().find(|entry| {
+            entry.content_len() != 0 && !imported_hashes.contains(&entry.content_hash())
+        }) {
+            return Err(OkuFsError::InvalidBundle(format!(
+                "missing content of {}",
+                self.entry_path(missing.key()).display()
+            ))
+            .into());
+        }
+        if let Some(encryption_key) = manifest.encryption_key {
+            self.set_encryption_key(namespace_id, encryption_key)?;
+        }
+        let document = self
+            .node
+            .docs
+            .import(DocTicket {
+                capability: manifest.capability.clone(),
+                nodes: Vec::new(),
+            })
+            .await?;
+        self.emit(OkuFsEvent::ReplicaImported(namespace_id));
+        self.forward_remote_events(document.clone()).await?;
+        self.insert_signed_entries(&document, manifest.capability, manifest.entries)
+            .await?;
+        Ok(namespace_id)
+    }
+
+    /// Inserts entries signed by other authors into a replica held by this node.
+    /// The node only accepts such entries from peers, so they are synchronised to it from a temporary replica kept in memory, over a connection on this machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The document backing the replica.
+    ///
+    /// * `capability` - The capability to the replica.
+    ///
+    /// * `entries` - The signed entries to insert.
+    async fn insert_signed_entries(
+        &self,
+        document: &iroh::client::mem::Doc,
+        capability: Capability,
+        entries: Vec<SignedEntry>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let sync = SyncHandle::spawn(
+            iroh::sync::store::fs::Store::memory(),
+            None,
+            "bundle".to_string(),
+        );
+        let result = async {
+            let namespace_id = sync.import_namespace(capability).await?;
+            sync.open(namespace_id, OpenOpts::default().sync()).await?;
+            for entry in entries {
+                sync.insert_remote(namespace_id, entry, [0; 32], ContentStatus::Complete)
+                    .await?;
+            }
+            let endpoint = MagicEndpoint::builder()
+                .relay_mode(RelayMode::Disabled)
+                .bind(0)
+                .await?;
+            let (ipv4_address, ipv6_address) = self.node.magic_endpoint().local_addr()?;
+            let direct_addresses = std::iter::once(ipv4_address)
+                .chain(ipv6_address)
+                .map(|mut address| {
+                    if address.ip().is_unspecified() {
+                        match address.is_ipv4() {
+                            true => address.set_ip(Ipv4Addr::LOCALHOST.into()),
+                            false => address.set_ip(Ipv6Addr::LOCALHOST.into()),
+                        }
+                    }
+                    address
+                })
+                .collect();
+            let node_addr = NodeAddr::from_parts(self.node.node_id(), None, direct_addresses);
+            let events = document.subscribe().await?;
+            pin_mut!(events);
+            connect_and_sync(&endpoint, &sync, namespace_id, node_addr).await?;
+            // The node may still be inserting the last entries it received once the connection ends.
+            let finished = tokio::time::timeout(SYNC_SLOT_TIMEOUT, async {
+                while let Some(event) = events.next().await {
+                    if let LiveEvent::SyncFinished(sync_event) = event? {
+                        if sync_event.peer == endpoint.node_id() {
+                            return Ok(sync_event.result);
+                        }
+                    }
+                }
+                Ok::<_, Box<dyn Error + Send + Sync>>(Err("no longer receiving events".to_string()))
+            })
+            .await;
+            endpoint.close(0u8.into(), b"").await?;
+            finished??.map_err(OkuFsError::InvalidBundle)?;
+            Ok::<(), Box<dyn Error + Send + Sync>>(())
+        }
+        .await;
+        sync.shutdown().await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::start_test_fs;
+
+    /// Rewrites the files held in a bundle.
+    ///
+    /// # Arguments
+    ///
+    /// * `bundle` - The bundle.
+    ///
+    /// * `edit` - Gives the new content of each file in the bundle, or `None` to leave it out.
+    ///
+    /// # Returns
+    ///
+    /// The rewritten bundle.
+    fn rewrite_bundle(bundle: &[u8], edit: impl Fn(&Path, Vec<u8>) -> Option<Vec<u8>>) -> Vec<u8> {
+        let mut archive = tar::Builder::new(Vec::new());
+        for (path, content) in read_bundle_files(bundle).unwrap() {
+            let Some(content) = edit(&path, content) else {
+                continue;
+            };
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            archive
+                .append_data(&mut header, path, &content[..])
+                .unwrap();
+        }
+        archive.into_inner().unwrap()
+    }
+
+    /// Checks that importing a bundle fails because the bundle is invalid.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - The result of importing the bundle.
+    fn assert_invalid_bundle(result: Result<NamespaceId, Box<dyn Error + Send + Sync>>) {
+        let error = result.unwrap_err();
+        assert!(
+            matches!(
+                error.downcast_ref::<OkuFsError>(),
+                Some(OkuFsError::InvalidBundle(_))
+            ),
+            "{}",
+            error
+        );
+    }
+
+    /// Counts the tags held by a file system's node.
+    ///
+    /// # Arguments
+    ///
+    /// * `oku_fs` - The file system.
+    ///
+    /// # Returns
+    ///
+    /// The number of tags.
+    async fn count_tags(oku_fs: &OkuFs) -> usize {
+        let tags = oku_fs.node.tags.list().await.unwrap();
+        pin_mut!(tags);
+        let mut count = 0;
+        while let Some(tag) = tags.next().await {
+            tag.unwrap();
+            count += 1;
+        }
+        count
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_exported_bundle() {
+        let oku_fs = start_test_fs("bundle").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a.txt"), "a")
+            .await
+            .unwrap();
+        let mut bundle = Vec::new();
+        oku_fs
+            .export_bundle(namespace_id, &mut bundle)
+            .await
+            .unwrap();
+        let imported_namespace_id = oku_fs.import_bundle(&bundle[..]).await.unwrap();
+        assert_ne!(imported_namespace_id, namespace_id);
+        assert_eq!(
+            oku_fs
+                .read_file(imported_namespace_id, PathBuf::from("/a.txt"))
+                .await
+                .unwrap(),
+            "a"
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reject_bundle_with_tampered_content() {
+        let oku_fs = start_test_fs("bundle-tampered").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a.txt"), "a")
+            .await
+            .unwrap();
+        let mut bundle = Vec::new();
+        oku_fs
+            .export_bundle(namespace_id, &mut bundle)
+            .await
+            .unwrap();
+        let tag_count = count_tags(&oku_fs).await;
+        let tampered = rewrite_bundle(&bundle, |path, content| {
+            match path.starts_with(BUNDLE_BLOBS_DIRECTORY) {
+                true => Some(b"b".to_vec()),
+                false => Some(content),
+            }
+        });
+        assert_invalid_bundle(oku_fs.import_bundle(&tampered[..]).await);
+        let missing_content = rewrite_bundle(&bundle, |path, content| {
+            (!path.starts_with(BUNDLE_BLOBS_DIRECTORY)).then_some(content)
+        });
+        assert_invalid_bundle(oku_fs.import_bundle(&missing_content[..]).await);
+        let missing_manifest = rewrite_bundle(&bundle, |path, content| {
+            (path != Path::new(BUNDLE_MANIFEST_FILE_NAME)).then_some(content)
+        });
+        assert_invalid_bundle(oku_fs.import_bundle(&missing_manifest[..]).await);
+        let wrong_size = rewrite_bundle(&bundle, |path, content| {
+            match path == Path::new(BUNDLE_MANIFEST_FILE_NAME) {
+                true => {
+                    let mut manifest: BundleManifest = serde_json::from_slice(&content).unwrap();
+                    manifest.entries[0].size += 1;
+                    Some(serde_json::to_vec(&manifest).unwrap())
+                }
+                false => Some(content),
+            }
+        });
+        assert_invalid_bundle(oku_fs.import_bundle(&wrong_size[..]).await);
+        // Content imported from rejected bundles is left to garbage collection.
+        assert_eq!(count_tags(&oku_fs).await, tag_count);
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reject_replica_bundle_with_tampered_content() {
+        let oku_fs = start_test_fs("replica-bundle-tampered").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a.txt"), "a")
+            .await
+            .unwrap();
+        let mut bundle = Vec::new();
+        oku_fs
+            .export_replica_bundle(namespace_id, false, &mut bundle)
+            .await
+            .unwrap();
+        let tampered = rewrite_bundle(&bundle, |path, content| {
+            match path.starts_with(BUNDLE_BLOBS_DIRECTORY) {
+                true => Some(b"b".to_vec()),
+                false => Some(content),
+            }
+        });
+        assert_invalid_bundle(oku_fs.import_replica_bundle(&tampered[..]).await);
+        let missing_content = rewrite_bundle(&bundle, |path, content| {
+            (!path.starts_with(BUNDLE_BLOBS_DIRECTORY)).then_some(content)
+        });
+        assert_invalid_bundle(oku_fs.import_replica_bundle(&missing_content[..]).await);
+        oku_fs.shutdown();
+    }
+}
@@ -0,0 +1,1031 @@
+use crate::error::OkuFsError;
+use crate::fs::{is_directory_marker, is_metadata_key, OkuFs};
+use bytes::Bytes;
+use iroh::sync::NamespaceId;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The largest piece of a file's content held in one block, matching the default of IPFS tooling.
+pub const CAR_CHUNK_SIZE: usize = 256 * 1024;
+
+/// The most blocks linked to by one block of a file, matching the default of IPFS tooling.
+pub const CAR_MAX_LINKS: usize = 174;
+
+/// The most links followed from the root of a CAR file to any block beneath it.
+pub const CAR_MAX_DEPTH: usize = 256;
+
+/// The most blocks visited while unpacking a CAR file, counting a block once for each link followed to it.
+pub const CAR_MAX_VISITS: usize = 1 << 20;
+
+/// The multicodec code of blocks holding UnixFS nodes.
+const DAG_PB_CODEC: u64 = 0x70;
+
+/// The multicodec code of blocks holding raw content.
+const RAW_CODEC: u64 = 0x55;
+
+/// The multihash code of SHA-256 digests.
+const SHA2_256_CODE: u64 = 0x12;
+
+/// The multihash code of content embedded in place of a digest.
+const IDENTITY_CODE: u64 = 0x00;
+
+/// The UnixFS type of raw content.
+const UNIXFS_RAW: u64 = 0;
+
+/// The UnixFS type of directories.
+const UNIXFS_DIRECTORY: u64 = 1;
+
+/// The UnixFS type of files.
+const UNIXFS_FILE: u64 = 2;
+
+/// The UnixFS type of sharded directories.
+const UNIXFS_HAMT_SHARD: u64 = 5;
+
+#[derive(Debug, Default)]
+/// A directory of a replica, as laid out in a CAR file.
+struct CarDirectory {
+    /// The files directly within the directory, by name.
+    files: BTreeMap<String, Bytes>,
+    /// The directories directly within the directory, by name.
+    directories: BTreeMap<String, CarDirectory>,
+}
+
+impl CarDirectory {
+    /// Finds a directory within this one, creating it and any directories above it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the directory, relative to this one.
+    ///
+    /// # Returns
+    ///
+    /// The directory.
+    fn directory_mut(&mut self, path: &Path) -> &mut CarDirectory {
+        path.iter().fold(self, |directory, name| {
+            directory
+                .directories
+                .entry(name.to_string_lossy().to_string())
+                .or_default()
+        })
+    }
+}
+
+/// A block of a CAR file, referred to by its content identifier (CID).
+type CarBlock = (Vec<u8>, Vec<u8>);
+
+impl OkuFs {
+    /// Writes the latest version of every file in a replica into a CAR file, so that it can be imported by IPFS tooling.
+    /// Files and directories are laid out as UnixFS, with CIDv1 identifiers and raw leaves. The archive is assembled in memory first, as its header names the root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to export.
+    ///
+    /// * `writer` - The destination of the CAR file.
+    ///
+    /// # Returns
+    ///
+    /// The CID of the root directory, in its base32 form.
+    pub async fn export_replica_car(
+        &self,
+        namespace_id: NamespaceId,
+        mut writer: impl Write,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut root = CarDirectory::default();
+        for file in self.list_files(namespace_id).await? {
+            let file_path = self.entry_path(file.key());
+            let file_path = file_path.strip_prefix("/")?;
+            if is_directory_marker(file.key()) {
+                root.directory_mut(file_path);
+                continue;
+            }
+            let (Some(parent), Some(file_name)) = (file_path.parent(), file_path.file_name())
+            else {
+                continue;
+            };
+            let content = self.read_entry_content(&file).await?;
+            root.directory_mut(parent)
+                .files
+                .insert(file_name.to_string_lossy().to_string(), content);
+        }
+
+        let mut blocks = Vec::new();
+        let (root_cid, _) = encode_directory(&root, &mut blocks);
+        write_car(&root_cid, blocks, &mut writer)?;
+        Ok(multibase::encode(multibase::Base::Base32Lower, root_cid))
+    }
+
+    /// Writes the files held in a CAR file into a replica, such as a dataset exported from IPFS.
+    /// The CAR file's first root may be a UnixFS directory, whose contents are written beneath the given path, or a single file, which is written at the given path.
+    /// Every block is checked against its CID before being used. Empty files are skipped, as a replica cannot hold empty content, as is anything within the directory reserved for file system metadata.
+    /// Both the CAR file and the files unpacked from it are held in memory, and so may not exceed the read limit, or [`crate::limits::DEFAULT_MAX_DECODED_SIZE`] if none is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to import into.
+    ///
+    /// * `path` - The path the CAR file's root is written at.
+    ///
+    /// * `reader` - The source of the CAR file.
+    ///
+    /// # Returns
+    ///
+    /// The number of files written into the replica.
+    pub async fn import_car(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        reader: impl Read,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let max_size = self.config.limits.decoded_size_limit();
+        let mut car = Vec::new();
+        reader.take(max_size + 1).read_to_end(&mut car)?;
+        if car.len() as u64 > max_size {
+            return Err(OkuFsError::ReadLimitExceeded(car.len() as u64, max_size).into());
+        }
+        let (root, blocks) = read_car(&car)?;
+        let entries = unpack_car(&root, &blocks, path, max_size)?;
+        let mut file_count = 0;
+        for (entry_path, content) in entries {
+            let key = match content {
+                Some(_) => self.entry_key(entry_path.clone()),
+                None => self.entry_prefix(entry_path.clone()),
+            };
+            if is_metadata_key(&key) {
+                continue;
+            }
+            match content {
+                Some(content) if content.is_empty() => {}
+                Some(content) => {
+                    self.create_or_modify_file(namespace_id, entry_path, content)
+                        .await?;
+                    file_count += 1;
+                }
+                None => self.create_directory(namespace_id, entry_path).await?,
+            }
+        }
+        Ok(file_count)
+    }
+}
+
+/// Writes blocks into a CAR file, beneath a single root.
+///
+/// # Arguments
+///
+/// * `root_cid` - The CID of the root block.
+///
+/// * `blocks` - The blocks.
+///
+/// * `writer` - The destination of the CAR file.
+fn write_car(
+    root_cid: &[u8],
+    blocks: Vec<CarBlock>,
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut header = Vec::new();
+    // A DAG-CBOR map of `roots`, holding the root's CID under tag 42, and `version`.
+    header.push(0xa2);
+    header.push(0x65);
+    header.extend_from_slice(b"roots");
+    header.push(0x81);
+    header.extend_from_slice(&[0xd8, 0x2a]);
+    header.push(0x58);
+    header.push(root_cid.len() as u8 + 1);
+    header.push(0x00);
+    header.extend_from_slice(root_cid);
+    header.push(0x67);
+    header.extend_from_slice(b"version");
+    header.push(0x01);
+    let mut section = Vec::new();
+    write_varint(&mut section, header.len() as u64);
+    writer.write_all(&section)?;
+    writer.write_all(&header)?;
+    for (cid, data) in blocks {
+        let mut section = Vec::new();
+        write_varint(&mut section, (cid.len() + data.len()) as u64);
+        section.extend_from_slice(&cid);
+        section.extend_from_slice(&data);
+        writer.write_all(&section)?;
+    }
+    writer.flush()
+}
+
+/// Appends an unsigned variable-length integer.
+///
+/// # Arguments
+///
+/// * `buffer` - The buffer to append to.
+///
+/// * `value` - The integer.
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Reads an unsigned variable-length integer.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to read from.
+///
+/// * `position` - The position of the integer, which is moved past it.
+///
+/// # Returns
+///
+/// The integer.
+fn read_varint(bytes: &[u8], position: &mut usize) -> Result<u64, OkuFsError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes
+            .get(*position)
+            .ok_or(OkuFsError::InvalidCar("truncated integer".to_string()))?;
+        *position += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(OkuFsError::InvalidCar("integer too large".to_string()))
+}
+
+/// Reads a length-prefixed run of bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to read from.
+///
+/// * `position` - The position of the length prefix, which is moved past the run.
+///
+/// # Returns
+///
+/// The run of bytes.
+fn read_length_prefixed<'a>(bytes: &'a [u8], position: &mut usize) -> Result<&'a [u8], OkuFsError> {
+    let length = read_varint(bytes, position)? as usize;
+    let run = bytes
+        .get(*position..position.saturating_add(length))
+        .ok_or(OkuFsError::InvalidCar("truncated section".to_string()))?;
+    *position += length;
+    Ok(run)
+}
+
+/// Creates the CIDv1 identifying a block.
+///
+/// # Arguments
+///
+/// * `codec` - The multicodec code of the block's encoding.
+///
+/// * `data` - The content of the block.
+///
+/// # Returns
+///
+/// The CID of the block.
+fn block_cid(codec: u64, data: &[u8]) -> Vec<u8> {
+    let mut cid = Vec::new();
+    write_varint(&mut cid, 1);
+    write_varint(&mut cid, codec);
+    write_varint(&mut cid, SHA2_256_CODE);
+    write_varint(&mut cid, 32);
+    cid.extend_from_slice(&Sha256::digest(data));
+    cid
+}
+
+/// Appends a length-delimited protobuf field.
+///
+/// # Arguments
+///
+/// * `buffer` - The buffer to append to.
+///
+/// * `field` - The number of the field.
+///
+/// * `value` - The content of the field.
+fn write_bytes_field(buffer: &mut Vec<u8>, field: u64, value: &[u8]) {
+    write_varint(buffer, field << 3 | 2);
+    write_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value);
+}
+
+/// Appends a varint protobuf field.
+///
+/// # Arguments
+///
+/// * `buffer` - The buffer to append to.
+///
+/// * `field` - The number of the field.
+///
+/// * `value` - The content of the field.
+fn write_varint_field(buffer: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(buffer, field << 3);
+    write_varint(buffer, value);
+}
+
+/// Encodes a UnixFS node as a DAG-PB block.
+///
+/// # Arguments
+///
+/// * `links` - The CID, name, and cumulative size of each block linked to.
+///
+/// * `data` - The node's UnixFS data.
+///
+/// # Returns
+///
+/// The block.
+fn encode_pb_node(links: &[(Vec<u8>, String, u64)], data: &[u8]) -> Vec<u8> {
+    let mut node = Vec::new();
+    for (cid, name, tsize) in links {
+        let mut link = Vec::new();
+        write_bytes_field(&mut link, 1, cid);
+        write_bytes_field(&mut link, 2, name.as_bytes());
+        write_varint_field(&mut link, 3, *tsize);
+        write_bytes_field(&mut node, 2, &link);
+    }
+    write_bytes_field(&mut node, 1, data);
+    node
+}
+
+/// Encodes a file's content as blocks, splitting it into chunks linked to by UnixFS nodes if it does not fit in one block.
+///
+/// # Arguments
+///
+/// * `content` - The file's content.
+///
+/// * `blocks` - The blocks encoded so far, to which the file's blocks are added.
+///
+/// # Returns
+///
+/// The CID of the file's root block, and the cumulative size of the file's blocks.
+fn encode_file(content: &[u8], blocks: &mut Vec<CarBlock>) -> (Vec<u8>, u64) {
+    // Each layer holds the CID, content size, and cumulative block size of its nodes.
+    let mut layer: Vec<(Vec<u8>, u64, u64)> = content
+        .chunks(CAR_CHUNK_SIZE)
+        .map(|chunk| {
+            let cid = block_cid(RAW_CODEC, chunk);
+            blocks.push((cid.clone(), chunk.to_vec()));
+            (cid, chunk.len() as u64, chunk.len() as u64)
+        })
+        .collect();
+    if layer.is_empty() {
+        let cid = block_cid(RAW_CODEC, &[]);
+        blocks.push((cid.clone(), Vec::new()));
+        return (cid, 0);
+    }
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(CAR_MAX_LINKS)
+            .map(|children| {
+                let file_size: u64 = children.iter().map(|child| child.1).sum();
+                let mut data = Vec::new();
+                write_varint_field(&mut data, 1, UNIXFS_FILE);
+                write_varint_field(&mut data, 3, file_size);
+                for child in children {
+                    write_varint_field(&mut data, 4, child.1);
+                }
+                let links: Vec<(Vec<u8>, String, u64)> = children
+                    .iter()
+                    .map(|child| (child.0.clone(), String::new(), child.2))
+                    .collect();
+                let node = encode_pb_node(&links, &data);
+                let cid = block_cid(DAG_PB_CODEC, &node);
+                let tsize = node.len() as u64 + children.iter().map(|child| child.2).sum::<u64>();
+                blocks.push((cid.clone(), node));
+                (cid, file_size, tsize)
+            })
+            .collect();
+    }
+    let (cid, _, tsize) = layer.remove(0);
+    (cid, tsize)
+}
+
+/// Encodes a directory, and everything within it, as blocks.
+///
+/// # Arguments
+///
+/// * `directory` - The directory.
+///
+/// * `blocks` - The blocks encoded so far, to which the directory's blocks are added.
+///
+/// # Returns
+///
+/// The CID of the directory's block, and the cumulative size of the directory's blocks.
+fn encode_directory(directory: &CarDirectory, blocks: &mut Vec<CarBlock>) -> (Vec<u8>, u64) {
+    let mut links = BTreeMap::new();
+    for (name, content) in directory.files.iter() {
+        let (cid, tsize) = encode_file(content, blocks);
+        links.insert(name.clone(), (cid, tsize));
+    }
+    for (name, subdirectory) in directory.directories.iter() {
+        let (cid, tsize) = encode_directory(subdirectory, blocks);
+        links.insert(name.clone(), (cid, tsize));
+    }
+    let links: Vec<(Vec<u8>, String, u64)> = links
+        .into_iter()
+        .map(|(name, (cid, tsize))| (cid, name, tsize))
+        .collect();
+    let mut data = Vec::new();
+    write_varint_field(&mut data, 1, UNIXFS_DIRECTORY);
+    let node = encode_pb_node(&links, &data);
+    let cid = block_cid(DAG_PB_CODEC, &node);
+    let tsize = node.len() as u64 + links.iter().map(|link| link.2).sum::<u64>();
+    blocks.push((cid.clone(), node));
+    (cid, tsize)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// What a CID says about the block it identifies.
+struct ParsedCid {
+    /// The multicodec code of the block's encoding.
+    codec: u64,
+    /// The multihash code of the block's digest.
+    hash_code: u64,
+    /// The digest of the block.
+    digest: Vec<u8>,
+}
+
+/// Reads a CID, in either its original or version 1 form.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to read from.
+///
+/// * `position` - The position of the CID, which is moved past it.
+///
+/// # Returns
+///
+/// The parsed CID.
+fn read_cid(bytes: &[u8], position: &mut usize) -> Result<ParsedCid, OkuFsError> {
+    let codec = match bytes.get(*position..*position + 2) {
+        // Original CIDs are bare SHA-256 multihashes of DAG-PB blocks.
+        Some([0x12, 0x20]) => DAG_PB_CODEC,
+        _ => {
+            let version = read_varint(bytes, position)?;
+            if version != 1 {
+                return Err(OkuFsError::InvalidCar(format!(
+                    "unsupported CID version {}",
+                    version
+                )));
+            }
+            read_varint(bytes, position)?
+        }
+    };
+    let hash_code = read_varint(bytes, position)?;
+    let digest = read_length_prefixed(bytes, position)?.to_vec();
+    Ok(ParsedCid {
+        codec,
+        hash_code,
+        digest,
+    })
+}
+
+/// Reads the header and blocks of a CAR file, checking each block against its CID.
+///
+/// # Arguments
+///
+/// * `car` - The CAR file.
+///
+/// # Returns
+///
+/// The CID of the first root, and the content of each block by CID.
+fn read_car(car: &[u8]) -> Result<(ParsedCid, HashMap<ParsedCid, Vec<u8>>), OkuFsError> {
+    let mut position = 0;
+    let mut header = read_length_prefixed(car, &mut position)?;
+    let mut end = car.len();
+    // Version 2 files wrap a version 1 file, located by the fixed-size header following the version.
+    if header == [0xa1, 0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x02] {
+        let read_u64 = |offset: usize| {
+            car.get(offset..offset + 8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+                .ok_or(OkuFsError::InvalidCar("truncated header".to_string()))
+        };
+        let data_offset = read_u64(position + 16)?;
+        let data_size = read_u64(position + 24)?;
+        position = data_offset;
+        end = data_offset.saturating_add(data_size).min(car.len());
+        header = read_length_prefixed(&car[..end], &mut position)?;
+    }
+    let root = find_root(header)?;
+    let mut blocks = HashMap::new();
+    while position < end {
+        let section = read_length_prefixed(&car[..end], &mut position)?;
+        let mut section_position = 0;
+        let cid = read_cid(section, &mut section_position)?;
+        let data = &section[section_position..];
+        let valid = match cid.hash_code {
+            SHA2_256_CODE => Sha256::digest(data).as_slice() == cid.digest,
+            IDENTITY_CODE => data == cid.digest,
+            hash_code => {
+                return Err(OkuFsError::InvalidCar(format!(
+                    "unsupported hash {:#x}",
+                    hash_code
+                )))
+            }
+        };
+        if !valid {
+            return Err(OkuFsError::InvalidCar(
+                "block does not match its CID".to_string(),
+            ));
+        }
+        blocks.insert(cid, data.to_vec());
+    }
+    Ok((root, blocks))
+}
+
+/// Finds the first root CID named in a CAR file's DAG-CBOR header.
+///
+/// # Arguments
+///
+/// * `header` - The header.
+///
+/// # Returns
+///
+/// The CID of the first root.
+fn find_root(header: &[u8]) -> Result<ParsedCid, OkuFsError> {
+    // CIDs are the only byte strings under tag 42 in the header, so the first one is the first root.
+    let tagged_cid = header
+        .windows(3)
+        .position(|window| window[0] == 0xd8 && window[1] == 0x2a && window[2] & 0xe0 == 0x40)
+        .ok_or(OkuFsError::InvalidCar("no root".to_string()))?;
+    let mut position = tagged_cid + 2;
+    let length_bits = header[position] & 0x1f;
+    position += 1;
+    let length = match length_bits {
+        0..=23 => length_bits as usize,
+        24 => {
+            position += 1;
+            *header.get(position - 1).unwrap_or(&0) as usize
+        }
+        _ => return Err(OkuFsError::InvalidCar("root too long".to_string())),
+    };
+    let cid_bytes = header
+        .get(position..position + length)
+        .ok_or(OkuFsError::InvalidCar("truncated root".to_string()))?;
+    // Tagged CIDs are prefixed with the identity multibase.
+    let cid_bytes = cid_bytes.strip_prefix(&[0x00]).unwrap_or(cid_bytes);
+    read_cid(cid_bytes, &mut 0)
+}
+
+/// The fields of a protobuf message relevant to UnixFS.
+enum ProtobufValue<'a> {
+    /// An integer.
+    Varint(u64),
+    /// A run of bytes, such as a string or an embedded message.
+    Bytes(&'a [u8]),
+}
+
+/// Reads the fields of a protobuf message.
+///
+/// # Arguments
+///
+/// * `message` - The message.
+///
+/// # Returns
+///
+/// The number and value of each field, in order.
+fn read_protobuf(message: &[u8]) -> Result<Vec<(u64, ProtobufValue<'_>)>, OkuFsError> {
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while position < message.len() {
+        let key = read_varint(message, &mut position)?;
+        let value = match key & 0x7 {
+            0 => ProtobufValue::Varint(read_varint(message, &mut position)?),
+            1 => {
+                position += 8;
+                continue;
+            }
+            2 => ProtobufValue::Bytes(read_length_prefixed(message, &mut position)?),
+            5 => {
+                position += 4;
+                continue;
+            }
+            wire_type => {
+                return Err(OkuFsError::InvalidCar(format!(
+                    "unsupported protobuf wire type {}",
+                    wire_type
+                )))
+            }
+        };
+        fields.push((key >> 3, value));
+    }
+    Ok(fields)
+}
+
+/// Bounds the work done unpacking a CAR file, whose blocks may be nested deeply or linked to many times over.
+struct UnpackBudget {
+    /// The number of blocks visited so far.
+    visits: usize,
+    /// The number of bytes of file content unpacked so far.
+    size: u64,
+    /// The most bytes of file content that may be unpacked.
+    max_size: u64,
+}
+
+impl UnpackBudget {
+    /// Accounts for visiting a block.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - The number of links followed from the root to the block.
+    fn visit(&mut self, depth: usize) -> Result<(), OkuFsError> {
+        if depth > CAR_MAX_DEPTH {
+            return Err(OkuFsError::InvalidCar(format!(
+                "blocks nested more than {} deep",
+                CAR_MAX_DEPTH
+            )));
+        }
+        self.visits += 1;
+        if self.visits > CAR_MAX_VISITS {
+            return Err(OkuFsError::InvalidCar(format!(
+                "more than {} blocks linked to",
+                CAR_MAX_VISITS
+            )));
+        }
+        Ok(())
+    }
+
+    /// Accounts for unpacking file content.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The number of bytes unpacked.
+    fn unpack(&mut self, len: usize) -> Result<(), OkuFsError> {
+        self.size += len as u64;
+        if self.size > self.max_size {
+            return Err(OkuFsError::ReadLimitExceeded(self.size, self.max_size));
+        }
+        Ok(())
+    }
+}
+
+/// Reads the files and directories beneath the root of a CAR file.
+///
+/// # Arguments
+///
+/// * `root` - The CID of the root block.
+///
+/// * `blocks` - The content of each block in the CAR file, by CID.
+///
+/// * `path` - The path the root is written at.
+///
+/// * `max_size` - The most bytes of file content that may be unpacked.
+///
+/// # Returns
+///
+/// The paths read, in order, with the content of files.
+fn unpack_car(
+    root: &ParsedCid,
+    blocks: &HashMap<ParsedCid, Vec<u8>>,
+    path: PathBuf,
+    max_size: u64,
+) -> Result<Vec<(PathBuf, Option<Bytes>)>, OkuFsError> {
+    let mut budget = UnpackBudget {
+        visits: 0,
+        size: 0,
+        max_size,
+    };
+    let mut entries = Vec::new();
+    // Blocks still to be read, with their paths and depths; links are pushed in reverse, so that they are read in order.
+    let mut pending = vec![(root.clone(), path, 0)];
+    while let Some((cid, path, depth)) = pending.pop() {
+        budget.visit(depth)?;
+        let node = read_node(&cid, blocks)?;
+        match node.unixfs_type {
+            UNIXFS_DIRECTORY => {
+                for (child_cid, name) in node.links.into_iter().rev() {
+                    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                        return Err(OkuFsError::InvalidCar(format!("invalid name {:?}", name)));
+                    }
+                    pending.push((child_cid, path.join(name), depth + 1));
+                }
+                entries.push((path, None));
+            }
+            UNIXFS_FILE | UNIXFS_RAW => {
+                budget.unpack(node.content.len())?;
+                let mut content = node.content.to_vec();
+                read_file_content(node.links, blocks, depth, &mut budget, &mut content)?;
+                entries.push((path, Some(content.into())));
+            }
+            UNIXFS_HAMT_SHARD => {
+                return Err(OkuFsError::InvalidCar(
+                    "sharded directories are not supported".to_string(),
+                ))
+            }
+            // Symbolic links and metadata have no counterpart in replicas.
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// A block read as a UnixFS node.
+struct UnixFsNode<'a> {
+    /// The UnixFS type of the node.
+    unixfs_type: u64,
+    /// The content embedded in the node.
+    content: &'a [u8],
+    /// The CID and name of each block the node links to.
+    links: Vec<(ParsedCid, String)>,
+}
+
+/// Reads a block as a UnixFS node.
+///
+/// # Arguments
+///
+/// * `cid` - The CID of the block.
+///
+/// * `blocks` - The content of each block in the CAR file, by CID.
+///
+/// # Returns
+///
+/// The node.
+fn read_node<'a>(
+    cid: &ParsedCid,
+    blocks: &'a HashMap<ParsedCid, Vec<u8>>,
+) -> Result<UnixFsNode<'a>, OkuFsError> {
+    let block = blocks
+        .get(cid)
+        .ok_or(OkuFsError::InvalidCar("missing block".to_string()))?;
+    match cid.codec {
+        RAW_CODEC => Ok(UnixFsNode {
+            unixfs_type: UNIXFS_RAW,
+            content: block,
+            links: Vec::new(),
+        }),
+        DAG_PB_CODEC => {
+            let mut links = Vec::new();
+            let mut data: &[u8] = &[];
+            for (field, value) in read_protobuf(block)? {
+                match (field, value) {
+                    (1, ProtobufValue::Bytes(bytes)) => data = bytes,
+                    (2, ProtobufValue::Bytes(link)) => {
+                        let mut link_cid = None;
+                        let mut name = String::new();
+                        for (field, value) in read_protobuf(link)? {
+                            match (field, value) {
+                                (1, ProtobufValue::Bytes(bytes)) => {
+                                    link_cid = Some(read_cid(bytes, &mut 0)?)
+                                }
+                                (2, ProtobufValue::Bytes(bytes)) => {
+                                    name = String::from_utf8_lossy(bytes).to_string()
+                                }
+                                _ => {}
+                            }
+                        }
+                        links.push((
+                            link_cid
+                                .ok_or(OkuFsError::InvalidCar("link without CID".to_string()))?,
+                            name,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            let mut unixfs_type = UNIXFS_RAW;
+            let mut content: &[u8] = &[];
+            for (field, value) in read_protobuf(data)? {
+                match (field, value) {
+                    (1, ProtobufValue::Varint(value)) => unixfs_type = value,
+                    (2, ProtobufValue::Bytes(bytes)) => content = bytes,
+                    _ => {}
+                }
+            }
+            Ok(UnixFsNode {
+                unixfs_type,
+                content,
+                links,
+            })
+        }
+        codec => Err(OkuFsError::InvalidCar(format!(
+            "unsupported codec {:#x}",
+            codec
+        ))),
+    }
+}
+
+/// Reads the content of a file spread across blocks.
+///
+/// # Arguments
+///
+/// * `links` - The blocks linked to by the file's root block.
+///
+/// * `blocks` - The content of each block in the CAR file, by CID.
+///
+/// * `depth` - The number of links followed from the root of the CAR file to the file's root block.
+///
+/// * `budget` - The work that may still be done unpacking the CAR file.
+///
+/// * `content` - The content read so far, to which the file's content is added.
+fn read_file_content(
+    links: Vec<(ParsedCid, String)>,
+    blocks: &HashMap<ParsedCid, Vec<u8>>,
+    depth: usize,
+    budget: &mut UnpackBudget,
+    content: &mut Vec<u8>,
+) -> Result<(), OkuFsError> {
+    let mut pending: Vec<(ParsedCid, usize)> = links
+        .into_iter()
+        .rev()
+        .map(|(cid, _)| (cid, depth + 1))
+        .collect();
+    while let Some((cid, depth)) = pending.pop() {
+        budget.visit(depth)?;
+        let node = read_node(&cid, blocks)?;
+        budget.unpack(node.content.len())?;
+        content.extend_from_slice(node.content);
+        pending.extend(
+            node.links
+                .into_iter()
+                .rev()
+                .map(|(child_cid, _)| (child_cid, depth + 1)),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::start_test_fs;
+
+    /// Writes blocks into a CAR file, and reads them back.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_cid` - The CID of the root block.
+    ///
+    /// * `blocks` - The blocks.
+    ///
+    /// # Returns
+    ///
+    /// The CID of the root, and the content of each block by CID.
+    fn round_trip(
+        root_cid: &[u8],
+        blocks: Vec<CarBlock>,
+    ) -> (ParsedCid, HashMap<ParsedCid, Vec<u8>>) {
+        let mut car = Vec::new();
+        write_car(root_cid, blocks, &mut car).unwrap();
+        read_car(&car).unwrap()
+    }
+
+    /// Encodes a UnixFS file node linking to other blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `links` - The CIDs of the blocks linked to.
+    ///
+    /// * `blocks` - The blocks encoded so far, to which the node is added.
+    ///
+    /// # Returns
+    ///
+    /// The CID of the node.
+    fn encode_file_node(links: &[Vec<u8>], blocks: &mut Vec<CarBlock>) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_varint_field(&mut data, 1, UNIXFS_FILE);
+        let links: Vec<(Vec<u8>, String, u64)> = links
+            .iter()
+            .map(|cid| (cid.clone(), String::new(), 0))
+            .collect();
+        let node = encode_pb_node(&links, &data);
+        let cid = block_cid(DAG_PB_CODEC, &node);
+        blocks.push((cid.clone(), node));
+        cid
+    }
+
+    #[test]
+    fn unpack_written_directory() {
+        let mut root = CarDirectory::default();
+        root.files.insert("a.txt".to_string(), Bytes::from("a"));
+        // Large enough to be split across several blocks.
+        let large = Bytes::from(vec![7u8; CAR_CHUNK_SIZE * 3 + 1]);
+        root.directory_mut(Path::new("b/c"))
+            .files
+            .insert("large".to_string(), large.clone());
+        let mut blocks = Vec::new();
+        let (root_cid, _) = encode_directory(&root, &mut blocks);
+        let (root, blocks) = round_trip(&root_cid, blocks);
+        let entries = unpack_car(&root, &blocks, PathBuf::from("/x"), u64::MAX).unwrap();
+        assert_eq!(
+            entries,
+            [
+                (PathBuf::from("/x"), None),
+                (PathBuf::from("/x/a.txt"), Some(Bytes::from("a"))),
+                (PathBuf::from("/x/b"), None),
+                (PathBuf::from("/x/b/c"), None),
+                (PathBuf::from("/x/b/c/large"), Some(large)),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn skip_metadata_when_importing() {
+        let oku_fs = start_test_fs("car-metadata").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let mut root = CarDirectory::default();
+        root.files.insert("a.txt".to_string(), Bytes::from("a"));
+        root.directory_mut(Path::new(".oku/hints"))
+            .files
+            .insert("b".to_string(), Bytes::from("b"));
+        let mut blocks = Vec::new();
+        let (root_cid, _) = encode_directory(&root, &mut blocks);
+        let mut car = Vec::new();
+        write_car(&root_cid, blocks, &mut car).unwrap();
+        let file_count = oku_fs
+            .import_car(namespace_id, PathBuf::from("/"), &car[..])
+            .await
+            .unwrap();
+        assert_eq!(file_count, 1);
+        assert!(!oku_fs
+            .exists(namespace_id, PathBuf::from("/.oku/hints/b"))
+            .await
+            .unwrap());
+        oku_fs.shutdown();
+    }
+
+    #[test]
+    fn reject_tampered_block() {
+        let mut blocks = Vec::new();
+        let (root_cid, _) = encode_file(b"content", &mut blocks);
+        blocks[0].1 = b"tampered".to_vec();
+        let mut car = Vec::new();
+        write_car(&root_cid, blocks, &mut car).unwrap();
+        assert!(matches!(read_car(&car), Err(OkuFsError::InvalidCar(_))));
+    }
+
+    #[test]
+    fn reject_deeply_nested_blocks() {
+        let mut blocks = Vec::new();
+        let (mut cid, _) = encode_file(b"leaf", &mut blocks);
+        for _ in 0..CAR_MAX_DEPTH * 4 {
+            cid = encode_file_node(&[cid], &mut blocks);
+        }
+        let (root, blocks) = round_trip(&cid, blocks);
+        assert!(matches!(
+            unpack_car(&root, &blocks, PathBuf::from("/"), u64::MAX),
+            Err(OkuFsError::InvalidCar(_))
+        ));
+    }
+
+    #[test]
+    fn reject_blocks_expanding_past_limit() {
+        let mut blocks = Vec::new();
+        let (mut cid, _) = encode_file(&[0u8; 1024], &mut blocks);
+        // Each node links to the one below it twice, doubling the content at each level.
+        for _ in 0..64 {
+            cid = encode_file_node(&[cid.clone(), cid], &mut blocks);
+        }
+        let (root, blocks) = round_trip(&cid, blocks);
+        assert!(matches!(
+            unpack_car(&root, &blocks, PathBuf::from("/"), 1024 * 1024),
+            Err(OkuFsError::ReadLimitExceeded(_, 1048576))
+        ));
+    }
+
+    #[test]
+    fn reject_blocks_linked_too_often() {
+        let mut blocks = Vec::new();
+        let (mut cid, _) = encode_file(&[], &mut blocks);
+        // Empty content never reaches the size limit, however often it is linked to.
+        for _ in 0..64 {
+            cid = encode_file_node(&[cid.clone(), cid], &mut blocks);
+        }
+        let (root, blocks) = round_trip(&cid, blocks);
+        assert!(matches!(
+            unpack_car(&root, &blocks, PathBuf::from("/"), u64::MAX),
+            Err(OkuFsError::InvalidCar(_))
+        ));
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for value in [0, 1, 0x7f, 0x80, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut buffer = Vec::new();
+            write_varint(&mut buffer, value);
+            let mut position = 0;
+            assert_eq!(read_varint(&buffer, &mut position).unwrap(), value);
+            assert_eq!(position, buffer.len());
+        }
+        let mut position = 0;
+        assert!(read_varint(&[0x80; 11], &mut position).is_err());
+    }
+
+    #[test]
+    fn reject_truncated_car() {
+        let mut blocks = Vec::new();
+        let (root_cid, _) = encode_file(b"content", &mut blocks);
+        let mut car = Vec::new();
+        write_car(&root_cid, blocks, &mut car).unwrap();
+        assert!(read_car(&car).is_ok());
+        for length in [0, 1, car.len() - 1] {
+            assert!(
+                matches!(read_car(&car[..length]), Err(OkuFsError::InvalidCar(_))),
+                "{}",
+                length
+            );
+        }
+    }
+}
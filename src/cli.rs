@@ -1,7 +1,7 @@
 use bytes::Bytes;
 use clap::{Parser, Subcommand};
 use iroh::sync::NamespaceId;
-use oku_fs::fs::OkuFs;
+use oku_fs::fs::{load_or_create_config, OkuFs, FS_PATH};
 use std::{error::Error, path::PathBuf};
 
 #[derive(Parser)]
@@ -21,11 +21,31 @@ enum Commands {
         #[arg(short, long, value_name = "DATA")]
         data: Bytes,
     },
+    ImportDirectory {
+        #[arg(short, long, value_name = "REPLICA_ID")]
+        replica_id: NamespaceId,
+        #[arg(short, long, value_name = "LOCAL_PATH")]
+        local_path: PathBuf,
+        #[arg(short, long, value_name = "PATH")]
+        path: PathBuf,
+    },
     ListFiles {
         #[arg(value_name = "REPLICA_ID")]
         replica_id: NamespaceId,
     },
     ListReplicas,
+    SetAlias {
+        #[arg(short, long, value_name = "REPLICA_ID")]
+        replica_id: NamespaceId,
+        #[arg(short, long, value_name = "ALIAS")]
+        alias: String,
+    },
+    SetPrivate {
+        #[arg(short, long, value_name = "REPLICA_ID")]
+        replica_id: NamespaceId,
+        #[arg(short, long)]
+        private: bool,
+    },
     GetFile {
         #[arg(short, long, value_name = "REPLICA_ID")]
         replica_id: NamespaceId,
@@ -56,18 +76,53 @@ enum Commands {
         #[arg(short, long, value_name = "NEW_PATH")]
         new_path: PathBuf,
     },
+    MoveDirectory {
+        #[arg(short, long, value_name = "REPLICA_ID")]
+        replica_id: NamespaceId,
+        #[arg(short, long, value_name = "OLD_PATH")]
+        old_path: PathBuf,
+        #[arg(short, long, value_name = "NEW_PATH")]
+        new_path: PathBuf,
+    },
+    CopyFile {
+        #[arg(long, value_name = "OLD_REPLICA_ID")]
+        old_replica_id: NamespaceId,
+        #[arg(long, value_name = "OLD_PATH")]
+        old_path: PathBuf,
+        #[arg(long, value_name = "NEW_REPLICA_ID")]
+        new_replica_id: NamespaceId,
+        #[arg(long, value_name = "NEW_PATH")]
+        new_path: PathBuf,
+    },
+    CopyDirectory {
+        #[arg(long, value_name = "OLD_REPLICA_ID")]
+        old_replica_id: NamespaceId,
+        #[arg(long, value_name = "OLD_PATH")]
+        old_path: PathBuf,
+        #[arg(long, value_name = "NEW_REPLICA_ID")]
+        new_replica_id: NamespaceId,
+        #[arg(long, value_name = "NEW_PATH")]
+        new_path: PathBuf,
+    },
     GetReplica {
         #[arg(short, long, value_name = "REPLICA_ID")]
         replica_id: NamespaceId,
         #[arg(short, long, value_name = "PATH", default_missing_value = None)]
         path: Option<PathBuf>,
     },
+    ResolveName {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    #[cfg(all(unix, feature = "daemon"))]
+    Daemon,
 }
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let cli = Cli::parse();
-    let node = OkuFs::start().await?;
+    let config = load_or_create_config(FS_PATH)?;
+    let node = OkuFs::start(&config).await?;
     match cli.command {
         Some(Commands::CreateReplica) => {
             let replica_id = node.create_replica().await?;
@@ -82,6 +137,16 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 .await?;
             println!("Created file at {:?}", path);
         }
+        Some(Commands::ImportDirectory {
+            replica_id,
+            local_path,
+            path,
+        }) => {
+            let imported = node.import_directory(replica_id, local_path, path).await?;
+            for file in imported {
+                println!("Imported file at {:?}", file);
+            }
+        }
         Some(Commands::ListFiles { replica_id }) => {
             let files = node.list_files(replica_id).await?;
             for file in files {
@@ -89,9 +154,26 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             }
         }
         Some(Commands::ListReplicas) => {
-            let replicas = node.list_replicas().await?;
-            for replica in replicas {
-                println!("{}", replica);
+            let replicas = node.list_replicas_with_aliases().await?;
+            for (replica, alias) in replicas {
+                match alias {
+                    Some(alias) => println!("{} ({})", replica, alias),
+                    None => println!("{}", replica),
+                }
+            }
+        }
+        Some(Commands::SetAlias { replica_id, alias }) => {
+            node.set_replica_alias(replica_id, alias.clone())?;
+            println!("Set alias of replica {} to {}", replica_id, alias);
+        }
+        Some(Commands::SetPrivate {
+            replica_id,
+            private,
+        }) => {
+            node.set_replica_private(replica_id, private).await?;
+            match private {
+                true => println!("Replica {} is now private", replica_id),
+                false => println!("Replica {} is now public", replica_id),
             }
         }
         Some(Commands::GetFile { replica_id, path }) => {
@@ -119,6 +201,45 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 .await?;
             println!("Moved file from {:?} to {:?}", old_path, new_path);
         }
+        Some(Commands::MoveDirectory {
+            replica_id,
+            old_path,
+            new_path,
+        }) => {
+            node.move_directory(replica_id, old_path.clone(), new_path.clone())
+                .await?;
+            println!("Moved directory from {:?} to {:?}", old_path, new_path);
+        }
+        Some(Commands::CopyFile {
+            old_replica_id,
+            old_path,
+            new_replica_id,
+            new_path,
+        }) => {
+            node.copy_file(
+                old_replica_id,
+                old_path.clone(),
+                new_replica_id,
+                new_path.clone(),
+            )
+            .await?;
+            println!("Copied file from {:?} to {:?}", old_path, new_path);
+        }
+        Some(Commands::CopyDirectory {
+            old_replica_id,
+            old_path,
+            new_replica_id,
+            new_path,
+        }) => {
+            node.copy_directory(
+                old_replica_id,
+                old_path.clone(),
+                new_replica_id,
+                new_path.clone(),
+            )
+            .await?;
+            println!("Copied directory from {:?} to {:?}", old_path, new_path);
+        }
         Some(Commands::GetReplica { replica_id, path }) => {
             node.get_external_replica(replica_id, path, true, true)
                 .await?;
@@ -127,9 +248,17 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 println!("{:#?}", file);
             }
         }
+        Some(Commands::ResolveName { name }) => {
+            let replica_id = node.resolve_dns_name(&name).await?;
+            println!("{} points to replica {}", name, replica_id);
+        }
+        #[cfg(all(unix, feature = "daemon"))]
+        Some(Commands::Daemon) => {
+            node.run_daemon().await?;
+        }
         None => {
             println!("Node will listen for incoming connections.");
-            loop {}
+            std::future::pending::<()>().await;
         }
     }
     Ok(())
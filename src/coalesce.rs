@@ -0,0 +1,250 @@
+use crate::compression::Compression;
+use crate::fs::{normalise_path, OkuFs};
+use bytes::Bytes;
+use iroh::{bytes::Hash, sync::NamespaceId};
+use std::{collections::HashMap, error::Error, path::PathBuf, time::Duration};
+
+#[derive(Clone, Debug)]
+/// A write held back in case it is soon superseded.
+struct PendingWrite {
+    /// The data to write to the file.
+    data: Bytes,
+    /// Distinguishes this write from later writes to the same file.
+    generation: u64,
+}
+
+#[derive(Debug, Default)]
+/// Writes held back so that rapid successive writes to a file are recorded as one entry version.
+pub(crate) struct WriteCoalescer {
+    /// How long writes to each coalescing replica are held back.
+    windows: HashMap<NamespaceId, Duration>,
+    /// The latest held-back write to each file.
+    pending: HashMap<(NamespaceId, PathBuf), PendingWrite>,
+    /// The generation given to the next held-back write.
+    next_generation: u64,
+}
+
+impl OkuFs {
+    /// Holds back writes to a replica, so that writes to a file in quick succession are recorded as one entry version.
+    /// A write is recorded once no further write to the same file arrives within the window.
+    /// Writes are no longer coalesced when the file system restarts; held-back writes should be flushed with [`OkuFs::flush_writes`] before shutting down.
+    /// Writes to encrypted or compressed replicas are never held back, as the hash their content will have cannot be known in advance.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `window` - How long to wait for further writes to a file before recording it.
+    pub async fn set_write_coalescing(
+        &self,
+        namespace_id: NamespaceId,
+        window: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.open_document(namespace_id).await?;
+        self.write_coalescer
+            .lock()
+            .unwrap()
+            .windows
+            .insert(namespace_id, window);
+        Ok(())
+    }
+
+    /// Stops holding back writes to a replica, recording any writes already held back.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub async fn disable_write_coalescing(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.write_coalescer
+            .lock()
+            .unwrap()
+            .windows
+            .remove(&namespace_id);
+        self.flush_writes(namespace_id).await
+    }
+
+    /// Gets how long writes to a replica are held back.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The window within which writes to a file are coalesced, if writes to the replica are coalesced.
+    pub fn write_coalescing(&self, namespace_id: NamespaceId) -> Option<Duration> {
+        self.write_coalescer
+            .lock()
+            .unwrap()
+            .windows
+            .get(&namespace_id)
+            .copied()
+    }
+
+    /// Records every write to a replica that is being held back.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub async fn flush_writes(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let pending_writes: Vec<(PathBuf, PendingWrite)> = {
+            let mut write_coalescer = self.write_coalescer.lock().unwrap();
+            let paths: Vec<PathBuf> = write_coalescer
+                .pending
+                .keys()
+                .filter(|(pending_namespace_id, _)| *pending_namespace_id == namespace_id)
+                .map(|(_, path)| path.clone())
+                .collect();
+            paths
+                .into_iter()
+                .filter_map(|path| {
+                    let pending_write = write_coalescer
+                        .pending
+                        .remove(&(namespace_id, path.clone()))?;
+                    Some((path, pending_write))
+                })
+                .collect()
+        };
+        for (path, pending_write) in pending_writes {
+            self.write_file(namespace_id, path, pending_write.data, self.author_id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Holds back a write if writes to the replica are coalesced.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `data` - The data to write to the file.
+    ///
+    /// # Returns
+    ///
+    /// The hash the file will have once written, if the write was held back.
+    pub(crate) fn coalesce_write(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data: &Bytes,
+    ) -> Option<Hash> {
+        if self.is_replica_encrypted(namespace_id)
+            || !matches!(
+                self.replica_compression(namespace_id),
+                Ok(Compression::None)
+            )
+        {
+            return None;
+        }
+        let path = normalise_path(path);
+        let (window, generation) = {
+            let mut write_coalescer = self.write_coalescer.lock().unwrap();
+            let window = *write_coalescer.windows.get(&namespace_id)?;
+            let generation = write_coalescer.next_generation;
+            write_coalescer.next_generation += 1;
+            write_coalescer.pending.insert(
+                (namespace_id, path.clone()),
+                PendingWrite {
+                    data: data.clone(),
+                    generation,
+                },
+            );
+            (window, generation)
+        };
+        let self_clone = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(window).await;
+            let pending_write = {
+                let mut write_coalescer = self_clone.write_coalescer.lock().unwrap();
+                let key = (namespace_id, path.clone());
+                match write_coalescer.pending.get(&key) {
+                    // A later write restarts the window.
+                    Some(pending_write) if pending_write.generation == generation => {
+                        write_coalescer.pending.remove(&key)
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(pending_write) = pending_write {
+                if let Err(e) = self_clone
+                    .write_file(namespace_id, path, pending_write.data, self_clone.author_id)
+                    .await
+                {
+                    tracing::error!(%namespace_id, "{}", e);
+                }
+            }
+        });
+        Some(Hash::new(data))
+    }
+
+    /// Gets the data of a write being held back.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The data waiting to be written to the file, if any.
+    pub(crate) fn pending_write(&self, namespace_id: NamespaceId, path: PathBuf) -> Option<Bytes> {
+        self.write_coalescer
+            .lock()
+            .unwrap()
+            .pending
+            .get(&(namespace_id, normalise_path(path)))
+            .map(|pending_write| pending_write.data.clone())
+    }
+
+    /// Records a held-back write immediately, such as before the file is copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    pub(crate) async fn flush_write(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = normalise_path(path);
+        let pending_write = self
+            .write_coalescer
+            .lock()
+            .unwrap()
+            .pending
+            .remove(&(namespace_id, path.clone()));
+        if let Some(pending_write) = pending_write {
+            self.write_file(namespace_id, path, pending_write.data, self.author_id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Drops held-back writes to files that are being deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the files.
+    ///
+    /// * `path` - The path of a file, or of a directory whose files are being deleted.
+    pub(crate) fn discard_pending_writes(&self, namespace_id: NamespaceId, path: PathBuf) {
+        let path = normalise_path(path);
+        self.write_coalescer.lock().unwrap().pending.retain(
+            |(pending_namespace_id, pending_path), _| {
+                *pending_namespace_id != namespace_id || !pending_path.starts_with(&path)
+            },
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::discovery::ContentRequest;
+use crate::error::OkuFsError;
+use crate::fs::{is_directory_marker, OkuFs};
+use crate::list::ListOptions;
+use bytes::Bytes;
+use iroh::{
+    bytes::{format::collection::Collection, BlobFormat, Hash, HashAndFormat},
+    net::NodeAddr,
+    rpc_protocol::SetTagOption,
+    sync::NamespaceId,
+    ticket::BlobTicket,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A file within a collection.
+pub struct CollectionEntry {
+    /// The name of the file, which is its path relative to the collection.
+    pub name: String,
+    /// The hash of the file's content.
+    pub hash: Hash,
+    /// The size of the file's content, in bytes, if it is held locally.
+    pub size: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A ticket pointing to a whole collection, so that every file in it is fetched together under a single hash.
+/// Collection tickets are written as blob tickets, so that they can be used by other Iroh tooling.
+pub struct CollectionTicket(BlobTicket);
+
+impl CollectionTicket {
+    /// Wraps a blob ticket pointing to a collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The blob ticket.
+    ///
+    /// # Returns
+    ///
+    /// The collection ticket, or an error if the blob ticket does not point to a collection.
+    pub fn new(ticket: BlobTicket) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match ticket.format() {
+            BlobFormat::HashSeq => Ok(CollectionTicket(ticket)),
+            BlobFormat::Raw => Err(OkuFsError::NotACollection(ticket.hash().to_string()).into()),
+        }
+    }
+
+    /// Gets the hash of the collection.
+    pub fn hash(&self) -> Hash {
+        self.0.hash()
+    }
+
+    /// Gets the address of the node providing the collection.
+    pub fn node_addr(&self) -> &NodeAddr {
+        self.0.node_addr()
+    }
+
+    /// Gets the blob ticket the collection ticket is written as.
+    pub fn blob_ticket(&self) -> &BlobTicket {
+        &self.0
+    }
+}
+
+impl fmt::Display for CollectionTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for CollectionTicket {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CollectionTicket::new(BlobTicket::from_str(s.trim())?)
+    }
+}
+
+impl From<CollectionTicket> for BlobTicket {
+    fn from(ticket: CollectionTicket) -> Self {
+        ticket.0
+    }
+}
+
+impl From<CollectionTicket> for ContentRequest {
+    fn from(ticket: CollectionTicket) -> Self {
+        ContentRequest::Ticket(ticket.0)
+    }
+}
+
+/// Checks that the name of a file within a collection is a relative path not leaving the collection, so that it can be saved to disk.
+///
+/// # Arguments
+///
+/// * `name` - The name of the file.
+fn validate_collection_entry_name(name: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let valid = !name.is_empty()
+        && Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    match valid {
+        true => Ok(()),
+        false => Err(OkuFsError::InvalidCollectionEntryName(name.to_string()).into()),
+    }
+}
+
+impl OkuFs {
+    /// Publishes several files together as a collection, so that they are fetched together under a single hash.
+    /// Like other published content, the collection is immutable, and is kept by garbage collection until unpublished.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The name and content of each file. Names are paths relative to the collection, such as `images/logo.png`.
+    ///
+    /// # Returns
+    ///
+    /// A ticket pointing to the collection and this node.
+    pub async fn create_collection(
+        &self,
+        files: Vec<(String, Bytes)>,
+    ) -> Result<CollectionTicket, Box<dyn Error + Send + Sync>> {
+        for (name, _) in files.iter() {
+            validate_collection_entry_name(name)?;
+        }
+        let mut collection = Collection::default();
+        let mut file_tags = Vec::new();
+        for (name, content) in files {
+            let outcome = self.node.blobs.add_bytes(content).await?;
+            collection.push(name, outcome.hash);
+            file_tags.push(outcome.tag);
+        }
+        // The collection keeps the content of its files, so the tags added with each file are no longer needed.
+        let (hash, tag) = self
+            .node
+            .blobs
+            .create_collection(collection, SetTagOption::Auto, file_tags)
+            .await?;
+        CollectionTicket::new(
+            self.record_published_content(HashAndFormat::hash_seq(hash), tag)
+                .await?,
+        )
+    }
+
+    /// Publishes the latest version of the files in a replica, or in a directory within it, as a collection.
+    /// Files are named by their paths relative to the directory, and their content is published as it is read, so that recipients can read the content of files in encrypted or compressed replicas.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica holding the files.
+    ///
+    /// * `path` - An optional directory within the replica holding the files.
+    ///
+    /// # Returns
+    ///
+    /// A ticket pointing to the collection and this node.
+    pub async fn create_collection_from_replica(
+        &self,
+        namespace_id: NamespaceId,
+        path: Option<PathBuf>,
+    ) -> Result<CollectionTicket, Box<dyn Error + Send + Sync>> {
+        let directory = path.clone().unwrap_or_else(|| PathBuf::from("/"));
+        let options = ListOptions {
+            path,
+            ..Default::default()
+        };
+        let mut files = Vec::new();
+        for entry in self.list_files_with_options(namespace_id, &options).await? {
+            if is_directory_marker(entry.key()) {
+                continue;
+            }
+            let entry_path = self.entry_path(entry.key());
+            let name = entry_path
+                .strip_prefix(&directory)?
+                .to_string_lossy()
+                .to_string();
+            files.push((name, self.read_entry_content(&entry).await?));
+        }
+        self.create_collection(files).await
+    }
+
+    /// Lists the files in a collection, fetching the collection first if it is not held locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The collection, as a hash and format or a ticket.
+    ///
+    /// # Returns
+    ///
+    /// The name, hash, and size of each file in the collection.
+    pub async fn list_collection(
+        &self,
+        content: ContentRequest,
+    ) -> Result<Vec<CollectionEntry>, Box<dyn Error + Send + Sync>> {
+        let hash_and_format = self.fetch_published(content).await?;
+        if hash_and_format.format != BlobFormat::HashSeq {
+            return Err(OkuFsError::NotACollection(hash_and_format.hash.to_string()).into());
+        }
+        let collection = self.node.blobs.get_collection(hash_and_format.hash).await?;
+        let mut entries = Vec::new();
+        for (name, hash) in collection {
+            let size = match self.node.blobs.read(hash).await {
+                Ok(reader) if reader.is_complete() => Some(reader.size()),
+                _ => None,
+            };
+            entries.push(CollectionEntry { name, hash, size });
+        }
+        Ok(entries)
+    }
+
+    /// Reads a file within a collection, fetching the collection first if it is not held locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The collection, as a hash and format or a ticket.
+    ///
+    /// * `name` - The name of the file within the collection.
+    ///
+    /// # Returns
+    ///
+    /// The content of the file.
+    pub async fn read_collection_file(
+        &self,
+        content: ContentRequest,
+        name: &str,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let entry = self
+            .list_collection(content)
+            .await?
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .ok_or(OkuFsError::FsEntryNotFound)?;
+        self.read_blob(entry.hash).await
+    }
+}
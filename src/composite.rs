@@ -0,0 +1,147 @@
+use crate::error::OkuFsError;
+use crate::fs::{normalise_path, OkuFs};
+use bytes::Bytes;
+use iroh::{client::Entry, sync::NamespaceId};
+use std::{collections::BTreeMap, error::Error, path::PathBuf};
+
+/// A read-only view overlaying several replicas, each mounted under a prefix, as one logical namespace.
+#[derive(Clone, Debug)]
+pub struct CompositeView {
+    /// The file system holding the replicas.
+    fs: OkuFs,
+    /// The replicas in the view, keyed by the prefixes they are mounted under.
+    mounts: BTreeMap<PathBuf, NamespaceId>,
+}
+
+impl CompositeView {
+    /// Creates a view with no replicas mounted.
+    ///
+    /// # Arguments
+    ///
+    /// * `fs` - The file system holding the replicas to view.
+    ///
+    /// # Returns
+    ///
+    /// An empty view.
+    pub fn new(fs: OkuFs) -> Self {
+        CompositeView {
+            fs,
+            mounts: BTreeMap::new(),
+        }
+    }
+
+    /// Mounts a replica under a prefix in the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The path under which the replica's files appear.
+    ///
+    /// * `namespace_id` - The ID of the replica to mount.
+    pub fn mount(mut self, prefix: impl Into<PathBuf>, namespace_id: NamespaceId) -> Self {
+        self.mounts
+            .insert(normalise_path(prefix.into()), namespace_id);
+        self
+    }
+
+    /// Gets the replicas in the view.
+    ///
+    /// # Returns
+    ///
+    /// The replicas in the view, keyed by the prefixes they are mounted under.
+    pub fn mounts(&self) -> &BTreeMap<PathBuf, NamespaceId> {
+        &self.mounts
+    }
+
+    /// Finds the replica holding a path in the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path in the view.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica holding the path, and the path within that replica.
+    pub fn resolve(&self, path: PathBuf) -> Option<(NamespaceId, PathBuf)> {
+        let path = normalise_path(path);
+        self.mounts
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.components().count())
+            .map(|(prefix, namespace_id)| {
+                (
+                    *namespace_id,
+                    normalise_path(path.strip_prefix(prefix).unwrap().to_path_buf()),
+                )
+            })
+    }
+
+    /// Lists the files in the view under a path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to list files under.
+    ///
+    /// # Returns
+    ///
+    /// The files under the path, each with its path in the view.
+    pub async fn list(
+        &self,
+        path: PathBuf,
+    ) -> Result<Vec<(PathBuf, Entry)>, Box<dyn Error + Send + Sync>> {
+        let path = normalise_path(path);
+        let mut files = Vec::new();
+        for (prefix, namespace_id) in &self.mounts {
+            if !prefix.starts_with(&path) && !path.starts_with(prefix) {
+                continue;
+            }
+            for entry in self.fs.list_files(*namespace_id).await? {
+                let entry_path = self.fs.entry_path(entry.key());
+                let view_path = prefix.join(entry_path.strip_prefix("/").unwrap_or(&entry_path));
+                if view_path.starts_with(&path)
+                    && self
+                        .resolve(view_path.clone())
+                        .map(|(resolved_id, _)| resolved_id)
+                        == Some(*namespace_id)
+                {
+                    files.push((view_path, entry));
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Reads a file in the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file in the view.
+    ///
+    /// # Returns
+    ///
+    /// The data read from the file.
+    pub async fn read(&self, path: PathBuf) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        // The entry read is the one described by `stat`, which may have been written by any author.
+        let entry = self.stat(path).await?.ok_or(OkuFsError::FsEntryNotFound)?;
+        self.fs.read_entry_content(&entry).await
+    }
+
+    /// Gets the entry of a file in the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file in the view.
+    ///
+    /// # Returns
+    ///
+    /// The latest entry of the file, if it exists.
+    pub async fn stat(&self, path: PathBuf) -> Result<Option<Entry>, Box<dyn Error + Send + Sync>> {
+        let Some((namespace_id, replica_path)) = self.resolve(path) else {
+            return Ok(None);
+        };
+        let document = self.fs.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::single_latest_per_key()
+            .key_exact(self.fs.entry_key(replica_path))
+            .build();
+        Ok(document.get_one(query).await?)
+    }
+}
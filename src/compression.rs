@@ -0,0 +1,336 @@
+use crate::error::OkuFsError;
+use crate::fs::{is_directory_marker, is_metadata_key, OkuFs, METADATA_DIRECTORY};
+use bytes::Bytes;
+use iroh::{bytes::Hash, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, io::Read, path::PathBuf, str::FromStr};
+
+/// The name of the file holding the compression used for each replica, within the path on disk where the file system is stored.
+pub const COMPRESSION_FILE_NAME: &str = "compression";
+
+/// The directory holding the codecs of compressed content, within the directory reserved for file system metadata.
+pub const CODECS_DIRECTORY_NAME: &str = "codecs";
+
+/// The codec recorded for content compressed with Zstandard.
+pub const ZSTD_CODEC: &str = "zstd";
+
+/// The bytes beginning every Zstandard frame.
+const ZSTD_MAGIC_NUMBER: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Gets the path at which the codec of compressed content is recorded.
+/// Codecs are recorded against the hash of the stored content, so every file referring to the content is read the same way.
+///
+/// # Arguments
+///
+/// * `hash` - The hash of the stored content.
+///
+/// # Returns
+///
+/// The path of the content's codec within a replica.
+pub fn codec_path(hash: Hash) -> PathBuf {
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(CODECS_DIRECTORY_NAME)
+        .join(hash.to_string())
+}
+
+/// Decompresses Zstandard-compressed content, stopping as soon as it grows past a limit.
+/// Content written by peers may expand far beyond its compressed size, so it is never decompressed in full before being checked.
+///
+/// # Arguments
+///
+/// * `content` - The compressed content.
+///
+/// * `max_size` - The most bytes the decompressed content may hold.
+///
+/// # Returns
+///
+/// The decompressed content.
+fn decompress_limited(
+    content: &[u8],
+    max_size: u64,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut decompressed = Vec::new();
+    zstd::stream::read::Decoder::new(content)?
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() as u64 > max_size {
+        return Err(OkuFsError::ReadLimitExceeded(decompressed.len() as u64, max_size).into());
+    }
+    Ok(decompressed)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// How file contents are compressed before being stored and synchronised.
+pub enum Compression {
+    /// File contents are stored as written.
+    #[default]
+    None,
+    /// File contents are compressed with Zstandard at the given level.
+    Zstd(i32),
+}
+
+impl OkuFs {
+    /// Loads the table of replica compression settings from disk.
+    ///
+    /// # Returns
+    ///
+    /// The compression used for each replica that compresses its files.
+    fn load_compression_settings(
+        &self,
+    ) -> Result<BTreeMap<NamespaceId, Compression>, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(COMPRESSION_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(settings_toml) => {
+                let settings: BTreeMap<String, Compression> = toml::from_str(&settings_toml)?;
+                settings
+                    .into_iter()
+                    .map(|(namespace_id, compression)| {
+                        Ok((NamespaceId::from_str(&namespace_id)?, compression))
+                    })
+                    .collect()
+            }
+            Err(_) => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Sets how files written to a replica by this node are compressed.
+    /// Files already in the replica are unchanged, and files are decompressed when read regardless of this setting.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `compression` - How to compress files written to the replica.
+    pub fn set_replica_compression(
+        &self,
+        namespace_id: NamespaceId,
+        compression: Compression,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut settings = self.load_compression_settings()?;
+        match compression {
+            Compression::None => settings.remove(&namespace_id),
+            _ => settings.insert(namespace_id, compression),
+        };
+        let settings: BTreeMap<String, Compression> = settings
+            .into_iter()
+            .map(|(namespace_id, compression)| (namespace_id.to_string(), compression))
+            .collect();
+        std::fs::write(
+            self.config.path.join(COMPRESSION_FILE_NAME),
+            toml::to_string(&settings)?,
+        )?;
+        Ok(())
+    }
+
+    /// Gets how files written to a replica by this node are compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// How files written to the replica are compressed.
+    pub fn replica_compression(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Compression, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .load_compression_settings()?
+            .remove(&namespace_id)
+            .unwrap_or_default())
+    }
+
+    /// Creates a file (if it does not exist) or modifies an existing file, compressing it in a given way rather than as the replica's setting dictates.
+    /// The write is never coalesced.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to create or modify.
+    ///
+    /// * `path` - The path of the file to create or modify.
+    ///
+    /// * `data` - The data to write to the file.
+    ///
+    /// * `compression` - How to compress the file.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file.
+    pub async fn create_or_modify_file_with_compression(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data: impl Into<Bytes>,
+        compression: Compression,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        self.write_file_with_compression(
+            namespace_id,
+            path,
+            data.into(),
+            self.author_id,
+            compression,
+        )
+        .await
+    }
+
+    /// Compresses a file's content before it is written to a replica.
+    /// File system metadata, directory markers, and content that does not shrink are left uncompressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry_key` - The key of the entry being written.
+    ///
+    /// * `content` - The file's content.
+    ///
+    /// * `compression` - How to compress the file.
+    ///
+    /// # Returns
+    ///
+    /// The content to store, and whether it was compressed.
+    pub(crate) fn compress_content(
+        &self,
+        entry_key: &[u8],
+        content: Bytes,
+        compression: Compression,
+    ) -> Result<(Bytes, bool), Box<dyn Error + Send + Sync>> {
+        let Compression::Zstd(level) = compression else {
+            return Ok((content, false));
+        };
+        if is_metadata_key(entry_key) || is_directory_marker(entry_key) {
+            return Ok((content, false));
+        }
+        let compressed = zstd::bulk::compress(&content, level)?;
+        if compressed.len() >= content.len() {
+            return Ok((content, false));
+        }
+        Ok((compressed.into(), true))
+    }
+
+    /// Records that content stored in a replica is compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `hash` - The hash of the stored content.
+    pub(crate) async fn record_compressed_content(
+        &self,
+        namespace_id: NamespaceId,
+        hash: Hash,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_bytes(self.author_id, self.entry_key(codec_path(hash)), ZSTD_CODEC)
+            .await?;
+        Ok(())
+    }
+
+    /// Checks whether content stored in a replica is compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `hash` - The hash of the stored content.
+    ///
+    /// # Returns
+    ///
+    /// Whether a codec is recorded for the content.
+    pub(crate) async fn is_content_compressed(
+        &self,
+        namespace_id: NamespaceId,
+        hash: Hash,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        match self.get_latest_entry(namespace_id, codec_path(hash)).await {
+            Ok(_) => Ok(true),
+            Err(e) => match e.downcast_ref::<OkuFsError>() {
+                Some(OkuFsError::FsEntryNotFound) => Ok(false),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Records that content shared from one replica into another is compressed, if it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_namespace_id` - The ID of the replica holding the content.
+    ///
+    /// * `to_namespace_id` - The ID of the replica the content is shared with.
+    ///
+    /// * `hash` - The hash of the stored content.
+    pub(crate) async fn copy_codec(
+        &self,
+        from_namespace_id: NamespaceId,
+        to_namespace_id: NamespaceId,
+        hash: Hash,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if from_namespace_id != to_namespace_id
+            && self.is_content_compressed(from_namespace_id, hash).await?
+            && !self.is_content_compressed(to_namespace_id, hash).await?
+        {
+            self.record_compressed_content(to_namespace_id, hash)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Decompresses the content of an entry read from a replica, if it is compressed.
+    /// Decompression stops once the content exceeds the read limit, or [`crate::limits::DEFAULT_MAX_DECODED_SIZE`] if none is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica the content was read from.
+    ///
+    /// * `entry_key` - The key of the entry the content was read from.
+    ///
+    /// * `hash` - The hash of the content as stored.
+    ///
+    /// * `content` - The content stored in the entry, decrypted if the replica is encrypted.
+    ///
+    /// # Returns
+    ///
+    /// The file's content.
+    pub(crate) async fn decompress_content(
+        &self,
+        namespace_id: NamespaceId,
+        entry_key: &[u8],
+        hash: Hash,
+        content: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        // Only content beginning a Zstandard frame can be compressed, so other content is not checked against the replica.
+        if is_metadata_key(entry_key)
+            || is_directory_marker(entry_key)
+            || !content.starts_with(&ZSTD_MAGIC_NUMBER)
+            || !self.is_content_compressed(namespace_id, hash).await?
+        {
+            return Ok(content);
+        }
+        Ok(decompress_limited(&content, self.config.limits.decoded_size_limit())?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_within_limit() {
+        let content = vec![0u8; 2 * 1024 * 1024];
+        let compressed = zstd::bulk::compress(&content, 3).unwrap();
+        assert_eq!(
+            decompress_limited(&compressed, content.len() as u64).unwrap(),
+            content
+        );
+        let error = decompress_limited(&compressed, 1024 * 1024).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::ReadLimitExceeded(_, 1048576))
+        ));
+    }
+}
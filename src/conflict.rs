@@ -0,0 +1,258 @@
+use crate::error::OkuFsError;
+use crate::event::OkuFsEvent;
+use crate::fs::{is_directory_marker, is_metadata_key, normalise_path, OkuFs, METADATA_DIRECTORY};
+use bytes::Bytes;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::Hash,
+    client::Entry,
+    sync::{store::Query, AuthorId, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    path::PathBuf,
+};
+
+/// The directory holding records of resolved conflicts, within the directory reserved for file system metadata.
+pub const CONFLICT_RESOLUTIONS_DIRECTORY_NAME: &str = "conflict_resolutions";
+
+/// Gets the path at which the resolution of a file's conflict is recorded.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// The path of the record within a replica.
+pub fn conflict_resolution_path(path: PathBuf) -> PathBuf {
+    let path = normalise_path(path);
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(CONFLICT_RESOLUTIONS_DIRECTORY_NAME)
+        .join(path.strip_prefix("/").unwrap_or(&path))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A file written differently by several authors, where the latest version shadows the others.
+pub struct Conflict {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// Each author's version of the file, from newest to oldest. Versions with no content are deletions.
+    pub versions: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// How a conflict should be resolved.
+pub enum ConflictResolution {
+    /// Keep one author's version of the file.
+    ChooseAuthor(AuthorId),
+    /// Replace the file with content combining the conflicting versions.
+    Merge(Bytes),
+}
+
+impl OkuFs {
+    /// Lists the files in a replica that several authors have written differently since any earlier resolution.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The conflicting files, each with every author's version.
+    pub async fn list_conflicts(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<Conflict>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let entries = document
+            .get_many(Query::all().include_empty().build())
+            .await?;
+        pin_mut!(entries);
+        let mut versions_by_key: BTreeMap<Vec<u8>, Vec<Entry>> = BTreeMap::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if is_metadata_key(entry.key()) || is_directory_marker(entry.key()) {
+                continue;
+            }
+            versions_by_key
+                .entry(entry.key().to_vec())
+                .or_default()
+                .push(entry);
+        }
+        let mut conflicts = Vec::new();
+        for (key, versions) in versions_by_key {
+            let path = self.entry_path(&key);
+            if let Some(conflict) = self.as_conflict(namespace_id, path, versions).await? {
+                conflicts.push(conflict);
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Resolves a conflict by writing a version of the file that supersedes all others.
+    /// The resolution is recorded in the replica, so that peers no longer report the conflict either.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `resolution` - Which content the file should have.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's content, or `None` if the chosen version was a deletion.
+    pub async fn resolve_conflict(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        resolution: ConflictResolution,
+    ) -> Result<Option<Hash>, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        let versions = self.file_versions(namespace_id, path.clone()).await?;
+        let resolved_through = versions
+            .iter()
+            .map(|version| version.timestamp())
+            .max()
+            .unwrap_or_default();
+        let hash = match resolution {
+            ConflictResolution::ChooseAuthor(author_id) => {
+                let chosen = versions
+                    .iter()
+                    .find(|version| version.author() == author_id)
+                    .ok_or(OkuFsError::FileVersionNotFound)?;
+                if chosen.content_len() == 0 {
+                    self.delete_file(namespace_id, path.clone()).await?;
+                    None
+                } else {
+                    Some(
+                        self.set_entry_content(namespace_id, path.clone(), chosen)
+                            .await?,
+                    )
+                }
+            }
+            ConflictResolution::Merge(content) => Some(
+                self.write_file(namespace_id, path.clone(), content, self.author_id)
+                    .await?,
+            ),
+        };
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_bytes(
+                self.author_id,
+                self.entry_key(conflict_resolution_path(path)),
+                resolved_through.to_string(),
+            )
+            .await?;
+        Ok(hash)
+    }
+
+    /// Reports a conflict if an entry received from a peer leaves its file written differently by several authors.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `entry` - The entry received from a peer.
+    pub(crate) async fn detect_conflict(
+        &self,
+        namespace_id: NamespaceId,
+        entry: &Entry,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if is_metadata_key(entry.key()) || is_directory_marker(entry.key()) {
+            return Ok(());
+        }
+        let path = self.entry_path(entry.key());
+        let versions = self.file_versions(namespace_id, path.clone()).await?;
+        if let Some(conflict) = self.as_conflict(namespace_id, path, versions).await? {
+            self.emit(OkuFsEvent::ConflictDetected {
+                namespace_id,
+                path: conflict.path,
+                authors: conflict
+                    .versions
+                    .iter()
+                    .map(|version| version.author())
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Gets every author's version of a file, including deletions.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// Each author's version of the file.
+    async fn file_versions(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let query = Query::key_exact(self.entry_key(path))
+            .include_empty()
+            .build();
+        let versions = document.get_many(query).await?;
+        pin_mut!(versions);
+        let mut file_versions = Vec::new();
+        while let Some(version) = versions.next().await {
+            file_versions.push(version?);
+        }
+        Ok(file_versions)
+    }
+
+    /// Determines whether a file's versions conflict, disregarding versions settled by an earlier resolution.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `versions` - Each author's version of the file.
+    ///
+    /// # Returns
+    ///
+    /// The conflict, if the unsettled versions differ.
+    async fn as_conflict(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        mut versions: Vec<Entry>,
+    ) -> Result<Option<Conflict>, Box<dyn Error + Send + Sync>> {
+        if versions.len() < 2 {
+            return Ok(None);
+        }
+        let resolved_through = match self
+            .get_latest_entry(namespace_id, conflict_resolution_path(path.clone()))
+            .await
+        {
+            Ok(resolution) => String::from_utf8_lossy(&self.read_entry_content(&resolution).await?)
+                .parse::<u64>()
+                .unwrap_or_default(),
+            Err(_) => 0,
+        };
+        versions.retain(|version| version.timestamp() > resolved_through);
+        let hashes: HashSet<Hash> = versions
+            .iter()
+            .map(|version| version.content_hash())
+            .collect();
+        if versions.len() < 2 || hashes.len() < 2 {
+            return Ok(None);
+        }
+        versions.sort_by_key(|version| std::cmp::Reverse(version.timestamp()));
+        Ok(Some(Conflict { path, versions }))
+    }
+}
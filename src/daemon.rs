@@ -0,0 +1,108 @@
+use crate::fs::OkuFs;
+use std::{
+    error::Error,
+    os::unix::{io::FromRawFd, net::UnixDatagram},
+};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// The first file descriptor passed by a service manager using socket activation.
+pub const LISTEN_FDS_START: i32 = 3;
+
+/// Informs the service manager supervising this process of a change in its state, following the `sd_notify` protocol.
+/// Nothing is sent if the process is not supervised.
+///
+/// # Arguments
+///
+/// * `state` - The new state, such as `READY=1` or `STOPPING=1`.
+///
+/// # Returns
+///
+/// Whether the service manager was informed.
+pub fn notify_service_manager(state: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let Some(notify_socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let socket = UnixDatagram::unbound()?;
+    let notify_socket = notify_socket.to_string_lossy().to_string();
+    match notify_socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(abstract_name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(abstract_name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), &notify_socket)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Takes the listening socket passed to this process by a service manager using socket activation, if there is one.
+///
+/// # Returns
+///
+/// The first socket passed to this process, if it was socket-activated.
+pub fn activated_listener() -> Result<Option<tokio::net::UnixListener>, Box<dyn Error + Send + Sync>>
+{
+    let for_this_process = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let fd_count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<i32>().ok())
+        .unwrap_or_default();
+    if !for_this_process || fd_count < 1 {
+        return Ok(None);
+    }
+    // The sockets are not passed on to child processes.
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    // Safety: the service manager passes ownership of the descriptors starting at `LISTEN_FDS_START` to this process.
+    let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(tokio::net::UnixListener::from_std(listener)?))
+}
+
+impl OkuFs {
+    /// Runs the file system as a long-lived service until it is asked to stop.
+    /// The file system is served to local processes through the socket passed in by the service manager, or through [`OkuFs::ipc_socket_path`] if none was passed.
+    /// Readiness is reported to the service manager once the socket is listening.
+    /// On `SIGTERM` or `SIGINT`, held-back writes are recorded and the file system is shut down.
+    pub async fn run_daemon(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let listener = match activated_listener()? {
+            Some(listener) => listener,
+            None => {
+                let socket_path = self.ipc_socket_path();
+                if socket_path.exists()
+                    && tokio::net::UnixStream::connect(&socket_path).await.is_err()
+                {
+                    std::fs::remove_file(&socket_path)?;
+                }
+                tokio::net::UnixListener::bind(socket_path)?
+            }
+        };
+        let self_clone = self.clone();
+        let ipc_server = tokio::spawn(async move {
+            if let Err(e) = self_clone.serve_ipc_listener(listener).await {
+                tracing::error!("{}", e);
+            }
+        });
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        notify_service_manager("READY=1\nSTATUS=Serving the file system.")?;
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = interrupt.recv() => {}
+        }
+        notify_service_manager("STOPPING=1")?;
+        ipc_server.abort();
+        for namespace_id in self.list_replicas().await? {
+            self.flush_writes(namespace_id).await?;
+        }
+        self.shutdown();
+        Ok(())
+    }
+}
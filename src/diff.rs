@@ -0,0 +1,155 @@
+use crate::fs::{is_directory_marker, is_metadata_key, OkuFs};
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::Hash,
+    sync::{store::Query, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    path::PathBuf,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A state of a replica that changes can be found between.
+pub enum ReplicaState {
+    /// The files recorded by a snapshot, identified by its label.
+    Snapshot(String),
+    /// The files as they were at a time, in microseconds since the Unix epoch.
+    ///
+    /// A replica only holds the latest version of each file written by each author, so earlier states are reconstructed from the versions still held; a file rewritten by the same author after this time is described by the version held before it, if any.
+    Timestamp(u64),
+    /// The files as they are now.
+    Current,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// The kind of change made to a file between two states of a replica.
+pub enum ChangeKind {
+    /// The file did not exist in the earlier state.
+    Added,
+    /// The file's content differs between the states.
+    Modified,
+    /// The file does not exist in the later state.
+    Deleted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A change made to a file between two states of a replica.
+pub struct Change {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The kind of change.
+    pub kind: ChangeKind,
+    /// The hash of the file's content in the earlier state, if it existed.
+    pub from: Option<Hash>,
+    /// The hash of the file's content in the later state, if it exists.
+    pub to: Option<Hash>,
+}
+
+impl OkuFs {
+    /// Finds the changes made to the files in a replica between two of its states, such as what changed since a snapshot was taken or since a replica was last looked at.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `from` - The earlier state of the replica.
+    ///
+    /// * `to` - The later state of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The files added, modified, or deleted between the states, ordered by path.
+    pub async fn diff(
+        &self,
+        namespace_id: NamespaceId,
+        from: &ReplicaState,
+        to: &ReplicaState,
+    ) -> Result<Vec<Change>, Box<dyn Error + Send + Sync>> {
+        let from_files = self.files_at_state(namespace_id, from).await?;
+        let to_files = self.files_at_state(namespace_id, to).await?;
+        let paths: BTreeSet<&PathBuf> = from_files.keys().chain(to_files.keys()).collect();
+        Ok(paths
+            .into_iter()
+            .filter_map(|path| {
+                let from_hash = from_files.get(path).copied();
+                let to_hash = to_files.get(path).copied();
+                let kind = match (from_hash, to_hash) {
+                    (None, Some(_)) => ChangeKind::Added,
+                    (Some(_), None) => ChangeKind::Deleted,
+                    (Some(from_hash), Some(to_hash)) if from_hash != to_hash => {
+                        ChangeKind::Modified
+                    }
+                    _ => return None,
+                };
+                Some(Change {
+                    path: path.clone(),
+                    kind,
+                    from: from_hash,
+                    to: to_hash,
+                })
+            })
+            .collect())
+    }
+
+    /// Gets the files in a replica at one of its states.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `state` - The state of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The hash of each file's content, by path.
+    async fn files_at_state(
+        &self,
+        namespace_id: NamespaceId,
+        state: &ReplicaState,
+    ) -> Result<BTreeMap<PathBuf, Hash>, Box<dyn Error + Send + Sync>> {
+        let before = match state {
+            ReplicaState::Snapshot(label) => {
+                return Ok(self
+                    .get_snapshot(namespace_id, label)
+                    .await?
+                    .files
+                    .into_iter()
+                    .map(|(path, file)| (path, file.hash))
+                    .collect());
+            }
+            ReplicaState::Timestamp(timestamp) => *timestamp,
+            ReplicaState::Current => u64::MAX,
+        };
+        self.flush_writes(namespace_id).await?;
+        let document = self.open_document(namespace_id).await?;
+        let entries = document
+            .get_many(Query::all().include_empty().build())
+            .await?;
+        pin_mut!(entries);
+        // The latest version of each file written no later than the given time; deletions are held as empty versions.
+        let mut latest: HashMap<Vec<u8>, (u64, Option<Hash>)> = HashMap::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if is_metadata_key(entry.key())
+                || is_directory_marker(entry.key())
+                || entry.timestamp() > before
+            {
+                continue;
+            }
+            let hash = (entry.content_len() > 0).then(|| entry.content_hash());
+            match latest.get(entry.key()) {
+                Some((timestamp, _)) if *timestamp >= entry.timestamp() => {}
+                _ => {
+                    latest.insert(entry.key().to_vec(), (entry.timestamp(), hash));
+                }
+            }
+        }
+        Ok(latest
+            .into_iter()
+            .filter_map(|(key, (_, hash))| Some((self.entry_path(&key), hash?)))
+            .collect())
+    }
+}
@@ -1,14 +1,25 @@
 use crate::error::OkuDiscoveryError;
+use crate::fs::OkuFs;
+use crate::glob::Glob;
+use crate::timeout::DEFAULT_NETWORK_TIMEOUT;
 use futures::StreamExt;
 use iroh::{
     bytes::{Hash, HashAndFormat},
     sync::NamespaceId,
     ticket::{BlobTicket, DocTicket},
 };
-use iroh_mainline_content_discovery::announce_dht;
+use iroh_mainline_content_discovery::{announce_dht, to_infohash};
+use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use std::{collections::BTreeSet, error::Error, str::FromStr, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::instrument;
 
 /// The delay between republishing content to the mainline DHT.
 pub const REPUBLISH_DELAY: Duration = Duration::from_secs(60 * 60);
@@ -22,6 +33,282 @@ pub const DISCOVERY_PORT: u16 = 4938;
 /// The number of parallel announcements to make to the mainline DHT.
 pub const ANNOUNCE_PARALLELISM: usize = 10;
 
+/// The time to wait for the network to settle after a change before republishing.
+pub const NETWORK_CHANGE_SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// The delay between checks of which relay the node is connected to.
+pub const RELAY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_initial_delay() -> Duration {
+    INITIAL_PUBLISH_DELAY
+}
+
+fn default_interval() -> Duration {
+    REPUBLISH_DELAY
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// When replicas are announced to the mainline DHT.
+pub struct AnnounceSchedule {
+    /// Whether replicas are announced periodically. Replicas can still be announced on demand when disabled.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// The delay before replicas are first announced, and before they are announced again after the network changes.
+    #[serde(default = "default_initial_delay")]
+    pub initial_delay: Duration,
+    /// The time between announcements.
+    #[serde(default = "default_interval")]
+    pub interval: Duration,
+    /// The most time randomly added to each interval, so that many nodes started together do not announce at once.
+    #[serde(default)]
+    pub jitter: Duration,
+}
+
+impl Default for AnnounceSchedule {
+    fn default() -> Self {
+        AnnounceSchedule {
+            enabled: true,
+            initial_delay: INITIAL_PUBLISH_DELAY,
+            interval: REPUBLISH_DELAY,
+            jitter: Duration::ZERO,
+        }
+    }
+}
+
+impl AnnounceSchedule {
+    /// Picks how long to wait after an announcement before the next one.
+    ///
+    /// # Returns
+    ///
+    /// The interval, less the initial delay which precedes every announcement, plus a random amount of jitter.
+    pub(crate) fn next_delay(&self) -> Duration {
+        let jitter_nanos = self.jitter.as_nanos().min(u64::MAX as u128) as u64;
+        let jitter = match jitter_nanos {
+            0 => Duration::ZERO,
+            _ => Duration::from_nanos(OsRng.next_u64() % (jitter_nanos + 1)),
+        };
+        self.interval.saturating_sub(self.initial_delay) + jitter
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Whether a replica's announcement can be found on the mainline DHT.
+pub struct AnnouncementStatus {
+    /// The ID of the replica.
+    pub namespace_id: NamespaceId,
+    /// The addresses the replica is announced to be available at.
+    pub peers: Vec<SocketAddr>,
+    /// The DHT nodes holding the announcement.
+    pub dht_nodes: Vec<SocketAddr>,
+    /// Whether one of the addresses the replica is announced at belongs to this node.
+    pub announced_by_self: bool,
+    /// How long the DHT took to answer.
+    pub duration: Duration,
+}
+
+impl AnnouncementStatus {
+    /// Whether any node can be found holding the replica.
+    pub fn is_resolvable(&self) -> bool {
+        !self.peers.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// A summary of how announcements to the mainline DHT have gone since the node started.
+pub struct AnnounceHealth {
+    /// When replicas were last announced.
+    pub last_attempt: Option<SystemTime>,
+    /// When every replica was last announced successfully.
+    pub last_success: Option<SystemTime>,
+    /// The number of replicas last announced.
+    pub attempted: usize,
+    /// The replicas which could not be announced the last time replicas were announced.
+    pub failed: Vec<NamespaceId>,
+    /// The number of announcements in a row in which a replica could not be announced.
+    pub consecutive_failures: u32,
+}
+
+impl OkuFs {
+    /// Announces all replicas, except private ones, to the mainline DHT immediately, such as after creating a replica, rather than waiting for the next scheduled announcement.
+    /// Replicas are announced even if periodic announcements are disabled.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas that were successfully announced.
+    #[instrument(skip_all, err)]
+    pub async fn announce_now(&self) -> Result<Vec<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        self.ensure_online("Announcing replicas")?;
+        self.announce(&self.mainline_dht()).await
+    }
+
+    /// Announces all replicas, except private ones, to the mainline DHT.
+    ///
+    /// # Arguments
+    ///
+    /// * `dht` - The connection to the mainline DHT to announce through.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas that were successfully announced.
+    #[instrument(skip_all, err)]
+    pub(crate) async fn announce(
+        &self,
+        dht: &mainline::Dht,
+    ) -> Result<Vec<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        self.ensure_online("Announcing replicas")?;
+        let replicas = self.list_public_replicas().await?;
+        let announced = announce_replicas(dht, replicas.clone()).await?;
+        for namespace_id in announced.iter() {
+            self.notify_observers(|observer| observer.on_announce(*namespace_id));
+        }
+        let failed: Vec<NamespaceId> = replicas
+            .into_iter()
+            .filter(|namespace_id| !announced.contains(namespace_id))
+            .collect();
+        let now = SystemTime::now();
+        let mut announce_health = self.announce_health.lock().unwrap();
+        announce_health.last_attempt = Some(now);
+        announce_health.attempted = announced.len() + failed.len();
+        match failed.is_empty() {
+            true => {
+                announce_health.last_success = Some(now);
+                announce_health.consecutive_failures = 0;
+            }
+            false => announce_health.consecutive_failures += 1,
+        }
+        announce_health.failed = failed;
+        Ok(announced)
+    }
+
+    /// Summarises how announcements to the mainline DHT have gone, such as for finding out why peers cannot find a replica.
+    ///
+    /// # Returns
+    ///
+    /// When replicas were last announced, and which of them could not be.
+    pub fn announce_health(&self) -> AnnounceHealth {
+        self.announce_health.lock().unwrap().clone()
+    }
+
+    /// Looks a replica up on the mainline DHT, as peers fetching it would, to check that its announcement can be found.
+    /// The lookup is abandoned after the configured network timeout, reporting what was found until then.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The addresses the replica is announced at, the DHT nodes holding the announcement, and whether this node is among the addresses.
+    #[instrument(skip_all, fields(%namespace_id), err)]
+    pub async fn verify_announcement(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<AnnouncementStatus, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!(
+            "Verifying the announcement of replica {}",
+            namespace_id
+        ))?;
+        let own_addresses: BTreeSet<IpAddr> = self
+            .node
+            .my_addr()
+            .await?
+            .direct_addresses()
+            .map(|address| address.ip())
+            .collect();
+        let info_hash = to_infohash(HashAndFormat::raw(Hash::new(namespace_id)));
+        let timeout = self
+            .config
+            .network_timeout
+            .unwrap_or(DEFAULT_NETWORK_TIMEOUT);
+        let started = Instant::now();
+        let mut peers = BTreeSet::new();
+        let mut dht_nodes = BTreeSet::new();
+        let mut responses = self.mainline_dht().as_async().get_peers(info_hash);
+        let _ = tokio::time::timeout(timeout, async {
+            while let Some(response) = responses.next_async().await {
+                peers.insert(response.peer);
+                dht_nodes.insert(response.from.address);
+            }
+        })
+        .await;
+        let announced_by_self = peers
+            .iter()
+            .any(|peer| peer.port() == DISCOVERY_PORT && own_addresses.contains(&peer.ip()));
+        Ok(AnnouncementStatus {
+            namespace_id,
+            peers: peers.into_iter().collect(),
+            dht_nodes: dht_nodes.into_iter().collect(),
+            announced_by_self,
+            duration: started.elapsed(),
+        })
+    }
+
+    /// Informs the file system that the network has changed, such as after waking from sleep or switching networks.
+    /// The node rebinds its sockets, and its address and replicas are republished without waiting for the next scheduled announcement.
+    ///
+    /// Changes to the node's addresses or relay are detected automatically; this is only needed when the platform reports a change first.
+    pub async fn network_change(&self) {
+        self.node.magic_endpoint().network_change().await;
+        self.network_changed.notify_one();
+    }
+
+    /// Watches for changes to the node's addresses or relay, signalling that the node should be republished when one occurs.
+    pub(crate) fn watch_network_changes(&self) {
+        let magic_endpoint = self.node.magic_endpoint().clone();
+        let network_changed = self.network_changed.clone();
+        tokio::spawn(async move {
+            let mut endpoints = magic_endpoint.local_endpoints();
+            let mut addresses = endpoint_addresses(endpoints.next().await.unwrap_or_default());
+            let mut relay_url = magic_endpoint.my_relay();
+            let mut relay_check = tokio::time::interval(RELAY_CHECK_INTERVAL);
+            loop {
+                let changed = tokio::select! {
+                    new_endpoints = endpoints.next() => {
+                        let Some(new_endpoints) = new_endpoints else {
+                            break;
+                        };
+                        let new_addresses = endpoint_addresses(new_endpoints);
+                        let changed = new_addresses != addresses;
+                        addresses = new_addresses;
+                        changed
+                    }
+                    _ = relay_check.tick() => {
+                        let new_relay_url = magic_endpoint.my_relay();
+                        let changed = new_relay_url != relay_url;
+                        relay_url = new_relay_url;
+                        changed
+                    }
+                };
+                if changed {
+                    // Changes tend to arrive in bursts while interfaces come up, so wait for them to finish.
+                    tokio::time::sleep(NETWORK_CHANGE_SETTLE_DELAY).await;
+                    network_changed.notify_one();
+                }
+            }
+        });
+    }
+}
+
+/// Collects the addresses of a node's endpoints, ignoring the order they are reported in.
+///
+/// # Arguments
+///
+/// * `endpoints` - The endpoints of the node.
+///
+/// # Returns
+///
+/// The addresses of the endpoints.
+fn endpoint_addresses(endpoints: Vec<iroh::net::config::Endpoint>) -> BTreeSet<SocketAddr> {
+    endpoints
+        .into_iter()
+        .map(|endpoint| endpoint.addr)
+        .collect()
+}
+
 /// Announces a local replica to the mainline DHT.
 ///
 /// # Arguments
@@ -30,27 +317,53 @@ pub const ANNOUNCE_PARALLELISM: usize = 10;
 pub async fn announce_replica(
     namespace_id: NamespaceId,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut content = BTreeSet::new();
-    content.insert(HashAndFormat::raw(Hash::new(namespace_id)));
-    let dht = mainline::Dht::default();
-    let announce_stream = announce_dht(dht, content, DISCOVERY_PORT, ANNOUNCE_PARALLELISM);
+    announce_replicas(&mainline::Dht::default(), [namespace_id]).await?;
+    Ok(())
+}
+
+/// Announces several local replicas to the mainline DHT in one batch, sharing a single connection to the DHT.
+///
+/// # Arguments
+///
+/// * `dht` - The connection to the mainline DHT to announce through.
+///
+/// * `namespace_ids` - The IDs of the replicas to announce.
+///
+/// # Returns
+///
+/// The IDs of the replicas that were successfully announced.
+#[instrument(skip_all, err)]
+pub async fn announce_replicas(
+    dht: &mainline::Dht,
+    namespace_ids: impl IntoIterator<Item = NamespaceId>,
+) -> Result<Vec<NamespaceId>, Box<dyn Error + Send + Sync>> {
+    let replicas: BTreeMap<HashAndFormat, NamespaceId> = namespace_ids
+        .into_iter()
+        .map(|namespace_id| (HashAndFormat::raw(Hash::new(namespace_id)), namespace_id))
+        .collect();
+    let content: BTreeSet<HashAndFormat> = replicas.keys().copied().collect();
+    let announce_stream = announce_dht(dht.clone(), content, DISCOVERY_PORT, ANNOUNCE_PARALLELISM);
     tokio::pin!(announce_stream);
+    let mut announced = Vec::new();
     while let Some((content, res)) = announce_stream.next().await {
         match res {
-            Ok(_) => {}
-            Err(e) => eprintln!(
+            Ok(_) => {
+                tracing::debug!(%content, "Announced replica");
+                announced.extend(replicas.get(&content))
+            }
+            Err(e) => tracing::warn!(
                 "{}",
                 OkuDiscoveryError::ProblemAnnouncingContent(content.to_string(), e.to_string())
             ),
         }
     }
-    Ok(())
+    Ok(announced)
 }
 
 /*
 The `ContentRequest` enum is derived from the `ContentArg` enum in the `iroh-examples` repository (https://github.com/n0-computer/iroh-examples/blob/6f184933efa72eec1d8cf2e8d07905650c0fdb46/content-discovery/iroh-mainline-content-discovery-cli/src/args.rs#L23).
 */
-#[derive(Debug, Clone, derive_more::From)]
+#[derive(Debug, Clone, derive_more::From, Serialize, Deserialize)]
 /// A request for content, which can be a raw hash, a hash and format pair, or a blob ticket.
 pub enum ContentRequest {
     /// A raw hash.
@@ -102,7 +415,7 @@ impl FromStr for ContentRequest {
 /// A content ticket sent in response to a peer requesting content.
 pub enum PeerTicketResponse {
     /// A ticket pointing to a replica.
-    Document(DocTicket),
+    Document(Box<DocTicket>),
     /// A list of tickets pointing to files.
     Entries(Vec<BlobTicket>),
 }
@@ -114,6 +427,9 @@ pub struct PeerContentRequest {
     pub namespace_id: NamespaceId,
     /// An optional path of requested files within the replica.
     pub path: Option<PathBuf>,
+    /// Patterns the paths of requested files must match, if any.
+    #[serde(default)]
+    pub patterns: Vec<Glob>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,267 @@
+use crate::error::OkuDiscoveryError;
+use crate::fs::OkuFs;
+use crate::network::record_text;
+use futures::future::BoxFuture;
+use iroh::{sync::NamespaceId, ticket::DocTicket};
+use pkarr::{
+    dns::{
+        rdata::{RData, TXT},
+        Name, Packet, ResourceRecord, CLASS,
+    },
+    Keypair, PublicKey, SignedPacket,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, str::FromStr};
+use tracing::instrument;
+
+/// The label under a domain name where the TXT record pointing to a replica is looked up first, in the manner of DNSLink.
+pub const DNS_RECORD_LABEL: &str = "_oku";
+
+/// The prefix of the TXT record values pointing to a replica.
+pub const DNS_RECORD_PREFIX: &str = "oku=";
+
+/// The time, in seconds, for which resolvers may cache published records pointing to a replica.
+pub const DNS_RECORD_TTL: u32 = 300;
+
+/// The name of the file holding the key signing the node's pkarr records, within the path on disk where the file system is stored.
+pub const DNS_KEY_FILE_NAME: &str = "dns_key";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A TXT record pointing a domain name to a replica.
+pub struct DnsRecord {
+    /// The fully qualified name the record is published at.
+    pub name: String,
+    /// The text of the record.
+    pub value: String,
+    /// The time, in seconds, for which resolvers may cache the record.
+    pub ttl: u32,
+}
+
+/// A DNS provider able to publish records under a domain, such as through the API of a domain registrar.
+pub trait DnsProvider: Send + Sync {
+    /// Creates a TXT record, replacing any TXT records previously published at the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record to publish.
+    fn set_txt_record<'a>(
+        &'a self,
+        record: &'a DnsRecord,
+    ) -> BoxFuture<'a, Result<(), Box<dyn Error + Send + Sync>>>;
+}
+
+/// Creates the TXT record pointing a domain name to a replica, to be published under the domain.
+///
+/// # Arguments
+///
+/// * `name` - The domain name, such as `docs.example.org`.
+///
+/// * `namespace_id` - The ID of the replica.
+///
+/// # Returns
+///
+/// The record, which is found by [`OkuFs::resolve_dns_name`] once published.
+pub fn replica_dns_record(name: &str, namespace_id: NamespaceId) -> DnsRecord {
+    DnsRecord {
+        name: format!("{}.{}.", DNS_RECORD_LABEL, name.trim_end_matches('.')),
+        value: format!("{}{}", DNS_RECORD_PREFIX, namespace_id),
+        ttl: DNS_RECORD_TTL,
+    }
+}
+
+impl OkuFs {
+    /// Finds the replica a domain name points to, so that replicas can be shared with memorable names.
+    /// The replica is given by a TXT record of the form `oku=<replica ID or ticket>`, either at `_oku.<name>` or at the name itself.
+    /// Names which are pkarr public keys are resolved from the mainline DHT and the configured pkarr relays instead.
+    /// The replica can then be fetched like any other, such as with [`OkuFs::get_external_replica`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The domain name, such as `docs.example.org`.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica the domain name points to.
+    #[instrument(skip(self), err)]
+    pub async fn resolve_dns_name(
+        &self,
+        name: &str,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Resolving {}", name))?;
+        let name = name.trim_end_matches('.');
+        if let Ok(public_key) = PublicKey::try_from(name) {
+            return self.resolve_pkarr_name(public_key).await;
+        }
+        let resolver = iroh::net::dns::default_resolver();
+        for record_name in [
+            format!("{}.{}.", DNS_RECORD_LABEL, name),
+            format!("{}.", name),
+        ] {
+            let Ok(txt_lookup) = resolver.txt_lookup(record_name).await else {
+                continue;
+            };
+            let namespace_id = txt_lookup.iter().find_map(|txt| {
+                let text: Vec<u8> = txt.iter().flat_map(|chunk| chunk.iter()).copied().collect();
+                parse_dns_record(&String::from_utf8_lossy(&text))
+            });
+            if let Some(namespace_id) = namespace_id {
+                return Ok(namespace_id);
+            }
+        }
+        Err(OkuDiscoveryError::NoReplicaRecord(name.to_string()).into())
+    }
+
+    /// Finds the replica a pkarr public key points to.
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - The public key the record pointing to the replica is signed with.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica the public key points to.
+    #[instrument(skip_all, fields(public_key = %public_key.to_z32()), err)]
+    async fn resolve_pkarr_name(
+        &self,
+        public_key: PublicKey,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        let pkarr = self.pkarr_client();
+        let mut signed_packet = pkarr.resolve(public_key.clone()).await;
+        for relay in &self.config.pkarr_relays {
+            if signed_packet.is_some() {
+                break;
+            }
+            signed_packet = pkarr
+                .relay_get(relay, public_key.clone())
+                .await
+                .ok()
+                .flatten();
+        }
+        signed_packet
+            .and_then(|signed_packet| {
+                signed_packet
+                    .resource_records(DNS_RECORD_LABEL)
+                    .chain(signed_packet.resource_records("@"))
+                    .filter_map(record_text)
+                    .find_map(|text| parse_dns_record(&text))
+            })
+            .ok_or(OkuDiscoveryError::NoReplicaRecord(public_key.to_z32()).into())
+    }
+
+    /// Points a domain name to a replica by publishing a TXT record under the domain through a DNS provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The DNS provider managing the domain.
+    ///
+    /// * `name` - The domain name, such as `docs.example.org`.
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The published record.
+    pub async fn publish_dns_name(
+        &self,
+        provider: &dyn DnsProvider,
+        name: &str,
+        namespace_id: NamespaceId,
+    ) -> Result<DnsRecord, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Publishing {}", name))?;
+        self.open_document(namespace_id).await?;
+        let record = replica_dns_record(name, namespace_id);
+        provider.set_txt_record(&record).await?;
+        Ok(record)
+    }
+
+    /// Loads the key signing the node's pkarr records from disk, or creates a new key if none exists.
+    /// The key is separate from the node's own key, whose pkarr record holds the node's address.
+    ///
+    /// # Returns
+    ///
+    /// The key signing the node's pkarr records.
+    pub fn dns_keypair(&self) -> Result<Keypair, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(DNS_KEY_FILE_NAME);
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Keypair::from_secret_key(&bytes[..32].try_into()?)),
+            Err(_) => {
+                let keypair = Keypair::random();
+                std::fs::write(path, keypair.secret_key())?;
+                Ok(keypair)
+            }
+        }
+    }
+
+    /// Creates a signed pkarr record pointing the node's pkarr public key to a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The signed record, which can be published to the mainline DHT or to pkarr relays.
+    pub fn pkarr_record(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<SignedPacket, Box<dyn Error + Send + Sync>> {
+        let keypair = self.dns_keypair()?;
+        let value = format!("{}{}", DNS_RECORD_PREFIX, namespace_id);
+        let mut packet = Packet::new_reply(0);
+        packet.answers.push(ResourceRecord::new(
+            Name::new(DNS_RECORD_LABEL)?,
+            CLASS::IN,
+            DNS_RECORD_TTL,
+            RData::TXT(TXT::try_from(value.as_str())?.into_owned()),
+        ));
+        Ok(SignedPacket::from_packet(&keypair, &packet)?)
+    }
+
+    /// Points the node's pkarr public key to a replica, publishing a signed record to the mainline DHT and to the configured pkarr relays.
+    /// Only one replica can be pointed to at a time; publishing another replaces the record.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The name to share, which is the node's pkarr public key and is found by [`OkuFs::resolve_dns_name`].
+    pub async fn publish_pkarr_name(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Publishing a name for replica {}", namespace_id))?;
+        self.open_document(namespace_id).await?;
+        let signed_packet = self.pkarr_record(namespace_id)?;
+        let pkarr = self.pkarr_client();
+        let mut outcome = pkarr.publish(&signed_packet).await.map(|_| ());
+        for relay in &self.config.pkarr_relays {
+            let relay_outcome = pkarr.relay_put(relay, &signed_packet).await;
+            // The record is published if any of the DHT or relays accepted it.
+            if outcome.is_err() {
+                outcome = relay_outcome;
+            }
+        }
+        outcome?;
+        Ok(signed_packet.public_key().to_z32())
+    }
+}
+
+/// Reads the replica a TXT record points to.
+///
+/// # Arguments
+///
+/// * `text` - The text of the TXT record.
+///
+/// # Returns
+///
+/// The ID of the replica, if the record points to one.
+pub(crate) fn parse_dns_record(text: &str) -> Option<NamespaceId> {
+    let value = text.trim().strip_prefix(DNS_RECORD_PREFIX)?.trim();
+    NamespaceId::from_str(value).ok().or_else(|| {
+        DocTicket::from_str(value)
+            .ok()
+            .map(|ticket| ticket.capability.id())
+    })
+}
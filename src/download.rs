@@ -0,0 +1,243 @@
+use crate::availability::Availability;
+use crate::fs::OkuFs;
+use crate::limits::SYNC_SLOT_TIMEOUT;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::{BlobFormat, Hash, Tag},
+    client::LiveEvent,
+    net::NodeAddr,
+    rpc_protocol::{BlobDownloadRequest, SetTagOption},
+    sync::NamespaceId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+    sync::{Arc, Mutex},
+};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What was fetched when downloading a replica's content from several peers.
+pub struct DownloadReport {
+    /// The number of pieces of content downloaded.
+    pub downloaded: usize,
+    /// The number of bytes downloaded.
+    pub downloaded_bytes: u64,
+    /// The content no peer could provide.
+    pub failed: Vec<Hash>,
+}
+
+#[derive(Debug, Default)]
+/// The content waiting to be downloaded, shared between the peers downloading it.
+struct DownloadQueue {
+    /// The content yet to be downloaded, along with the peers that have failed to provide it.
+    pending: VecDeque<(Hash, HashSet<usize>)>,
+    /// The content currently being downloaded.
+    in_progress: usize,
+    /// What has been fetched so far.
+    report: DownloadReport,
+}
+
+impl DownloadQueue {
+    /// Takes the next content a peer has not yet failed to provide.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The index of the peer asking for work.
+    ///
+    /// # Returns
+    ///
+    /// The content to download, along with the peers that have failed to provide it, if any is left for the peer.
+    fn take(&mut self, peer: usize) -> Option<(Hash, HashSet<usize>)> {
+        let position = self
+            .pending
+            .iter()
+            .position(|(_, failed_peers)| !failed_peers.contains(&peer))?;
+        self.in_progress += 1;
+        self.pending.remove(position)
+    }
+}
+
+impl OkuFs {
+    /// Downloads the content of a replica's entries from several peers at once.
+    /// Each peer is asked for different content, and content a peer fails to provide is asked of the remaining peers, so faster peers provide more of the replica.
+    /// Only content allowed by the replica's download policy, and not already held, is downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `peers` - The peers to download the content from.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the download.
+    ///
+    /// # Returns
+    ///
+    /// The content downloaded, and the content no peer could provide.
+    pub async fn download_from_peers(
+        &self,
+        namespace_id: NamespaceId,
+        peers: Vec<NodeAddr>,
+        cancellation: CancellationToken,
+    ) -> Result<DownloadReport, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!(
+            "Downloading the content of replica {}",
+            namespace_id
+        ))?;
+        let document = self.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::single_latest_per_key().build();
+        let entries = document.get_many(query).await?;
+        pin_mut!(entries);
+        let mut non_empty_entries = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if entry.content_len() > 0 {
+                non_empty_entries.push(entry);
+            }
+        }
+        let mut hashes = HashSet::new();
+        let mut queue = DownloadQueue::default();
+        for entry_availability in self
+            .entry_availabilities(namespace_id, non_empty_entries)
+            .await?
+        {
+            let hash = entry_availability.entry.content_hash();
+            if matches!(
+                entry_availability.availability,
+                Availability::Pending | Availability::Partial { .. }
+            ) && hashes.insert(hash)
+            {
+                queue.pending.push_back((hash, HashSet::new()));
+            }
+        }
+        if peers.is_empty() {
+            queue.report.failed = queue.pending.into_iter().map(|(hash, _)| hash).collect();
+            return Ok(queue.report);
+        }
+
+        let queue = Arc::new(Mutex::new(queue));
+        let workers = peers.iter().enumerate().map(|(peer_index, peer)| {
+            let queue = queue.clone();
+            let peer_count = peers.len();
+            async move {
+                loop {
+                    let next = queue.lock().unwrap().take(peer_index);
+                    let Some((hash, mut failed_peers)) = next else {
+                        if queue.lock().unwrap().in_progress == 0 {
+                            break;
+                        }
+                        // Content being downloaded by another peer may yet be handed back if that peer fails.
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        continue;
+                    };
+                    let downloaded = self.download_blob(hash, peer.clone()).await;
+                    let mut queue = queue.lock().unwrap();
+                    queue.in_progress -= 1;
+                    match downloaded {
+                        Ok(downloaded_bytes) => {
+                            queue.report.downloaded += 1;
+                            queue.report.downloaded_bytes += downloaded_bytes;
+                        }
+                        Err(_) => {
+                            failed_peers.insert(peer_index);
+                            if failed_peers.len() == peer_count {
+                                queue.report.failed.push(hash);
+                            } else {
+                                queue.pending.push_back((hash, failed_peers));
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        tokio::select! {
+            _ = futures::future::join_all(workers) => {}
+            _ = cancellation.cancelled() => {}
+        }
+        let mut queue = queue.lock().unwrap();
+        let mut report = std::mem::take(&mut queue.report);
+        report
+            .failed
+            .extend(queue.pending.drain(..).map(|(hash, _)| hash));
+        Ok(report)
+    }
+
+    /// Downloads one piece of content from a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the content.
+    ///
+    /// * `peer` - The peer to download the content from.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes downloaded.
+    async fn download_blob(
+        &self,
+        hash: Hash,
+        peer: NodeAddr,
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        // The content is only tagged while downloading, as the replica refers to it once held.
+        let tag = Tag::from(format!("oku-download-{}", hash));
+        let blob_download_request = BlobDownloadRequest {
+            hash,
+            format: BlobFormat::Raw,
+            peer,
+            tag: SetTagOption::Named(tag.clone()),
+        };
+        let outcome = self
+            .node
+            .blobs
+            .download(blob_download_request)
+            .await?
+            .finish()
+            .await;
+        self.node.tags.delete(tag).await?;
+        Ok(outcome?.downloaded_size)
+    }
+
+    /// Downloads a newly imported replica's content from several peers at once, once its entries have been fetched.
+    /// Until then, the replica's download policy is expected to exclude all content, so that it is not fetched from only the first peer synced with.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `peers` - The peers to download the content from.
+    ///
+    /// * `synced` - Whether the replica's entries have already been fetched.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the download.
+    pub(crate) async fn spawn_parallel_download(
+        &self,
+        namespace_id: NamespaceId,
+        peers: Vec<NodeAddr>,
+        synced: bool,
+        cancellation: CancellationToken,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let events = document.subscribe().await?;
+        let self_clone = self.clone();
+        tokio::spawn(async move {
+            if !synced {
+                let _ = tokio::time::timeout(SYNC_SLOT_TIMEOUT, async {
+                    tokio::pin!(events);
+                    while let Some(Ok(event)) = events.next().await {
+                        if let LiveEvent::SyncFinished(_) = event {
+                            break;
+                        }
+                    }
+                })
+                .await;
+            }
+            self_clone.restore_download_policy(&document).await?;
+            self_clone
+                .download_from_peers(namespace_id, peers, cancellation)
+                .await?;
+            Ok::<(), Box<dyn Error + Send + Sync>>(())
+        });
+        Ok(())
+    }
+}
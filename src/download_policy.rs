@@ -0,0 +1,168 @@
+use crate::fs::OkuFs;
+use crate::glob::Glob;
+use iroh::sync::{store::DownloadPolicy, NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, str::FromStr};
+
+/// The name of the file holding the download policies chosen for replicas, within the path on disk where the file system is stored.
+pub const DOWNLOAD_POLICIES_FILE_NAME: &str = "download_policies";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The download policies chosen for replicas, as saved on disk.
+struct DownloadPolicies {
+    /// The chosen policies, by replica ID.
+    policies: BTreeMap<String, DownloadPolicy>,
+}
+
+impl OkuFs {
+    /// Loads the download policies chosen for replicas from disk.
+    ///
+    /// # Returns
+    ///
+    /// The chosen policies of replicas not downloading everything.
+    fn load_download_policies(
+        &self,
+    ) -> Result<BTreeMap<NamespaceId, DownloadPolicy>, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(DOWNLOAD_POLICIES_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(download_policies_toml) => {
+                let download_policies: DownloadPolicies = toml::from_str(&download_policies_toml)?;
+                download_policies
+                    .policies
+                    .into_iter()
+                    .map(|(namespace_id, policy)| {
+                        Ok((NamespaceId::from_str(&namespace_id)?, policy))
+                    })
+                    .collect()
+            }
+            Err(_) => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Saves the download policies chosen for replicas to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `download_policies` - The chosen policies of replicas not downloading everything.
+    fn save_download_policies(
+        &self,
+        download_policies: &BTreeMap<NamespaceId, DownloadPolicy>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let download_policies = DownloadPolicies {
+            policies: download_policies
+                .iter()
+                .map(|(namespace_id, policy)| (namespace_id.to_string(), policy.clone()))
+                .collect(),
+        };
+        std::fs::write(
+            self.config.path.join(DOWNLOAD_POLICIES_FILE_NAME),
+            toml::to_string(&download_policies)?,
+        )?;
+        Ok(())
+    }
+
+    /// Gets the download policy chosen for a replica, deciding which files' content is downloaded from peers.
+    /// A replica holding only some of its files' content can be kept that way by choosing a policy excluding the rest.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The chosen policy, which downloads everything unless set otherwise.
+    pub fn get_download_policy(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<DownloadPolicy, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .load_download_policies()?
+            .remove(&namespace_id)
+            .unwrap_or_default())
+    }
+
+    /// Chooses the download policy of a replica, deciding which files' content is downloaded from peers.
+    /// The policy is kept across restarts, and is restored after any policy used temporarily while the replica is imported.
+    /// Content already downloaded is kept; use [`OkuFs::gc`] to remove content no longer wanted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `policy` - The policy to choose.
+    pub async fn set_download_policy(
+        &self,
+        namespace_id: NamespaceId,
+        policy: DownloadPolicy,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        {
+            let _settings_guard = self.settings_lock.lock().unwrap();
+            let mut download_policies = self.load_download_policies()?;
+            match policy == DownloadPolicy::default() {
+                true => download_policies.remove(&namespace_id),
+                false => download_policies.insert(namespace_id, policy.clone()),
+            };
+            self.save_download_policies(&download_policies)?;
+        }
+        document.set_download_policy(policy).await?;
+        Ok(())
+    }
+
+    /// Chooses to download only the content of files in a replica matching any of several patterns, such as `/photos/**/*.jpg`, so that only part of a large replica is mirrored.
+    /// Patterns are converted into filters on the prefixes of entry keys, so within a directory matched by a wildcard, files not matching the pattern may be downloaded too.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `patterns` - The patterns the paths of downloaded files must match.
+    pub async fn set_download_patterns(
+        &self,
+        namespace_id: NamespaceId,
+        patterns: &[Glob],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut filters = Vec::new();
+        for filter in patterns
+            .iter()
+            .flat_map(|pattern| pattern.filters(&self.config.key_codec))
+        {
+            if !filters.contains(&filter) {
+                filters.push(filter);
+            }
+        }
+        self.set_download_policy(namespace_id, DownloadPolicy::NothingExcept(filters))
+            .await
+    }
+
+    /// Applies the download policy chosen for a replica, such as after a policy used temporarily while importing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The document backing the replica.
+    pub(crate) async fn restore_download_policy(
+        &self,
+        document: &iroh::client::mem::Doc,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let policy = self.get_download_policy(document.id())?;
+        document.set_download_policy(policy).await?;
+        Ok(())
+    }
+
+    /// Forgets the download policy chosen for a replica, such as when it is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub(crate) fn remove_download_policy(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut download_policies = self.load_download_policies()?;
+        if download_policies.remove(&namespace_id).is_some() {
+            self.save_download_policies(&download_policies)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+use crate::error::OkuFsError;
+use crate::fs::{is_directory_marker, is_metadata_key, OkuFs};
+use crate::keys::write_private_file;
+use bytes::Bytes;
+use crypto_secretbox::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Key, Nonce, XSalsa20Poly1305,
+};
+use iroh::sync::NamespaceId;
+use std::{error::Error, path::PathBuf};
+
+/// The directory holding the keys of encrypted replicas, within the path on disk where the file system is stored.
+pub const ENCRYPTION_KEYS_DIRECTORY_NAME: &str = "keys";
+
+/// The length, in bytes, of the nonce preceding the content of each encrypted entry.
+const NONCE_LENGTH: usize = 24;
+
+/// The number of bytes encryption adds to a file's content: a nonce before it, and an authentication tag after it.
+pub(crate) const ENCRYPTION_OVERHEAD: u64 = NONCE_LENGTH as u64 + 16;
+
+impl OkuFs {
+    /// Gets the path on disk at which the key of an encrypted replica is held.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The path of the replica's key.
+    fn encryption_key_path(&self, namespace_id: NamespaceId) -> PathBuf {
+        self.config
+            .path
+            .join(ENCRYPTION_KEYS_DIRECTORY_NAME)
+            .join(namespace_id.to_string())
+    }
+
+    /// Creates a replica whose file contents are encrypted with a key held only on this node before being stored.
+    /// Files are decrypted transparently when read.
+    /// Peers syncing the replica receive only the encrypted contents, unless they are also given the key.
+    /// Only file contents are encrypted: paths, and the metadata attached to files with [`OkuFs::set_metadata`], are stored as they are and can be read by any peer syncing the replica.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica.
+    pub async fn create_encrypted_replica(
+        &self,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        let namespace_id = self.create_replica().await?;
+        let key = XSalsa20Poly1305::generate_key(&mut OsRng);
+        self.set_encryption_key(namespace_id, key.into())?;
+        Ok(namespace_id)
+    }
+
+    /// Checks whether a replica's file contents are encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether this node holds a key for the replica.
+    pub fn is_replica_encrypted(&self, namespace_id: NamespaceId) -> bool {
+        self.encryption_key_path(namespace_id).exists()
+    }
+
+    /// Gets the key of an encrypted replica, so that it can be given to another node holding the replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The replica's key, if the replica is encrypted.
+    pub fn encryption_key(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Option<[u8; 32]>, Box<dyn Error + Send + Sync>> {
+        match std::fs::read(self.encryption_key_path(namespace_id)) {
+            Ok(key) => Ok(Some(key.try_into().map_err(|_| {
+                OkuFsError::InvalidEncryptionKey(namespace_id.to_string())
+            })?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Sets the key with which a replica's file contents are encrypted, such as one received from another node holding the replica.
+    /// Files written before the key was set are not encrypted, and cannot be read until the key is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `key` - The key to encrypt the replica's file contents with.
+    pub fn set_encryption_key(
+        &self,
+        namespace_id: NamespaceId,
+        key: [u8; 32],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        write_private_file(&self.encryption_key_path(namespace_id), &key)
+    }
+
+    /// Removes the key of an encrypted replica from this node.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub(crate) fn remove_encryption_key(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match std::fs::remove_file(self.encryption_key_path(namespace_id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks whether file contents can be shared between two replicas without being re-encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_namespace_id` - The ID of the replica holding the content.
+    ///
+    /// * `to_namespace_id` - The ID of the replica to share the content with.
+    ///
+    /// # Returns
+    ///
+    /// Whether both replicas are unencrypted, or encrypted with the same key.
+    pub(crate) fn shares_encryption(
+        &self,
+        from_namespace_id: NamespaceId,
+        to_namespace_id: NamespaceId,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(from_namespace_id == to_namespace_id
+            || self.encryption_key(from_namespace_id)? == self.encryption_key(to_namespace_id)?)
+    }
+
+    /// Encrypts a file's content before it is written to a replica, if the replica is encrypted.
+    /// File system metadata and directory markers are never encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica the file is being written to.
+    ///
+    /// * `entry_key` - The key of the entry being written.
+    ///
+    /// * `content` - The file's content.
+    ///
+    /// # Returns
+    ///
+    /// The content to store, which is a nonce followed by the encrypted content if the replica is encrypted.
+    pub(crate) fn encrypt_content(
+        &self,
+        namespace_id: NamespaceId,
+        entry_key: &[u8],
+        content: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        if is_metadata_key(entry_key) || is_directory_marker(entry_key) {
+            return Ok(content);
+        }
+        let Some(key) = self.encryption_key(namespace_id)? else {
+            return Ok(content);
+        };
+        let cipher = XSalsa20Poly1305::new(Key::from_slice(&key));
+        let nonce = XSalsa20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, content.as_ref())
+            .map_err(|_| OkuFsError::InvalidEncryptionKey(namespace_id.to_string()))?;
+        Ok([nonce.as_slice(), &ciphertext].concat().into())
+    }
+
+    /// Decrypts the content of an entry read from a replica, if the replica is encrypted.
+    /// File system metadata and directory markers are never encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica the content was read from.
+    ///
+    /// * `entry_key` - The key of the entry the content was read from.
+    ///
+    /// * `content` - The content stored in the entry.
+    ///
+    /// # Returns
+    ///
+    /// The file's content.
+    pub(crate) fn decrypt_content(
+        &self,
+        namespace_id: NamespaceId,
+        entry_key: &[u8],
+        content: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        if is_metadata_key(entry_key) || is_directory_marker(entry_key) {
+            return Ok(content);
+        }
+        let Some(key) = self.encryption_key(namespace_id)? else {
+            return Ok(content);
+        };
+        if content.len() < NONCE_LENGTH {
+            return Err(OkuFsError::DecryptionFailed(namespace_id.to_string()).into());
+        }
+        let (nonce, ciphertext) = content.split_at(NONCE_LENGTH);
+        let cipher = XSalsa20Poly1305::new(Key::from_slice(&key));
+        Ok(cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| OkuFsError::DecryptionFailed(namespace_id.to_string()))?
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{tests::start_test_fs, METADATA_DIRECTORY};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn encrypt_and_decrypt_content() {
+        let oku_fs = start_test_fs("encryption").await;
+        let namespace_id = oku_fs.create_encrypted_replica().await.unwrap();
+        let entry_key = oku_fs.entry_key(PathBuf::from("/a.txt"));
+        let content = Bytes::from("secret");
+        let encrypted = oku_fs
+            .encrypt_content(namespace_id, &entry_key, content.clone())
+            .unwrap();
+        assert_eq!(
+            encrypted.len() as u64,
+            content.len() as u64 + ENCRYPTION_OVERHEAD
+        );
+        assert!(!encrypted
+            .windows(content.len())
+            .any(|window| window == content.as_ref()));
+        // Each write is encrypted with a new nonce.
+        assert_ne!(
+            oku_fs
+                .encrypt_content(namespace_id, &entry_key, content.clone())
+                .unwrap(),
+            encrypted
+        );
+        assert_eq!(
+            oku_fs
+                .decrypt_content(namespace_id, &entry_key, encrypted)
+                .unwrap(),
+            content
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn leave_metadata_and_unencrypted_replicas_as_is() {
+        let oku_fs = start_test_fs("encryption-exempt").await;
+        let encrypted_namespace_id = oku_fs.create_encrypted_replica().await.unwrap();
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let content = Bytes::from("content");
+        let metadata_key =
+            oku_fs.entry_key(PathBuf::from(METADATA_DIRECTORY).join("manifest.toml"));
+        let directory_marker = oku_fs.entry_prefix(PathBuf::from("/a"));
+        for entry_key in [metadata_key, directory_marker] {
+            assert_eq!(
+                oku_fs
+                    .encrypt_content(encrypted_namespace_id, &entry_key, content.clone())
+                    .unwrap(),
+                content
+            );
+        }
+        let entry_key = oku_fs.entry_key(PathBuf::from("/a.txt"));
+        assert_eq!(
+            oku_fs
+                .encrypt_content(namespace_id, &entry_key, content.clone())
+                .unwrap(),
+            content
+        );
+        assert!(!oku_fs.is_replica_encrypted(namespace_id));
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reject_tampered_content() {
+        let oku_fs = start_test_fs("encryption-tampered").await;
+        let namespace_id = oku_fs.create_encrypted_replica().await.unwrap();
+        let entry_key = oku_fs.entry_key(PathBuf::from("/a.txt"));
+        let encrypted = oku_fs
+            .encrypt_content(namespace_id, &entry_key, Bytes::from("secret"))
+            .unwrap();
+        let mut tampered = encrypted.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        let truncated = encrypted.slice(..NONCE_LENGTH - 1);
+        for content in [Bytes::from(tampered), truncated] {
+            let error = oku_fs
+                .decrypt_content(namespace_id, &entry_key, content)
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<OkuFsError>(),
+                Some(OkuFsError::DecryptionFailed(_))
+            ));
+        }
+        // Content encrypted with another key cannot be read.
+        oku_fs.set_encryption_key(namespace_id, [1; 32]).unwrap();
+        let error = oku_fs
+            .decrypt_content(namespace_id, &entry_key, encrypted)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::DecryptionFailed(_))
+        ));
+        oku_fs.shutdown();
+    }
+}
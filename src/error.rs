@@ -12,6 +12,294 @@ pub enum OkuFsError {
     )]
     /// File system entry not found.
     FsEntryNotFound,
+    #[error("Author {0} not found.")]
+    #[diagnostic(
+        code(fs::author_not_found),
+        url(docsrs),
+        help("Please ensure that the configured author exists on this node.")
+    )]
+    /// Author not found.
+    AuthorNotFound(String),
+    #[error("File version not found.")]
+    #[diagnostic(
+        code(fs::file_version_not_found),
+        url(docsrs),
+        help("Please choose a version listed in the file's history.")
+    )]
+    /// File version not found.
+    FileVersionNotFound,
+    #[error("Invalid alias: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_alias),
+        url(docsrs),
+        help("Aliases must be non-empty and cannot contain slashes.")
+    )]
+    /// Invalid alias.
+    InvalidAlias(String),
+    #[error("Alias {0} is already in use by replica {1}.")]
+    #[diagnostic(
+        code(fs::alias_in_use),
+        url(docsrs),
+        help("Please remove the alias from the other replica, or choose a different alias.")
+    )]
+    /// Alias already in use.
+    AliasInUse(String, String),
+    #[error("Cannot start node: {0}")]
+    #[diagnostic(
+        code(fs::cannot_start_node),
+        url(docsrs),
+        help("The local store may be damaged. Consider starting with the integrity check set to quarantine.")
+    )]
+    /// Cannot start node.
+    CannotStartNode(String),
+    #[error("Replica {0} is frozen.")]
+    #[diagnostic(
+        code(fs::replica_frozen),
+        url(docsrs),
+        help("Unfreeze the replica before writing to it.")
+    )]
+    /// Replica is frozen.
+    ReplicaFrozen(String),
+    #[error("Ticket rejected: {0}")]
+    #[diagnostic(
+        code(fs::ticket_rejected),
+        url(docsrs),
+        help("The replica does not satisfy the acceptance policy. Relax the policy if the replica is trusted.")
+    )]
+    /// Ticket rejected by acceptance policy.
+    TicketRejected(String),
+    #[error("Invalid bundle: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_bundle),
+        url(docsrs),
+        help("The bundle may be incomplete or damaged. Please export it again.")
+    )]
+    /// Invalid bundle.
+    InvalidBundle(String),
+    #[error("Invalid backup: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_backup),
+        url(docsrs),
+        help("The backup may be incomplete or damaged. Please take it again.")
+    )]
+    /// Invalid backup.
+    InvalidBackup(String),
+    #[error("Invalid secret key: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_secret_key),
+        url(docsrs),
+        help("Check the passphrase, and that the key was exported by Oku.")
+    )]
+    /// Invalid secret key.
+    InvalidSecretKey(String),
+    #[error("Request to the running node failed: {0}")]
+    #[diagnostic(
+        code(fs::ipc_request_failed),
+        url(docsrs),
+        help("The node serving the socket could not carry out the request.")
+    )]
+    /// Request over a local socket failed.
+    IpcRequestFailed(String),
+    #[error("Request refused over the remote control.")]
+    #[diagnostic(
+        code(fs::ipc_request_refused),
+        url(docsrs),
+        help("Requests reaching beyond the node's replicas must be sent from the node's machine, unless the remote control is configured to allow them.")
+    )]
+    /// Request refused over the remote control.
+    IpcRequestRefused,
+    #[error("Reading {0} bytes exceeds the limit of {1} bytes.")]
+    #[diagnostic(
+        code(fs::read_limit_exceeded),
+        url(docsrs),
+        help("Read the file in smaller ranges, or raise the read limit in the configuration.")
+    )]
+    /// Read exceeds the configured limit.
+    ReadLimitExceeded(u64, u64),
+    #[error("Writing {0} bytes exceeds the limit of {1} bytes.")]
+    #[diagnostic(
+        code(fs::write_limit_exceeded),
+        url(docsrs),
+        help("Write a smaller file, or raise the write limit in the configuration.")
+    )]
+    /// Write exceeds the configured limit.
+    WriteLimitExceeded(u64, u64),
+    #[error("Invalid member name: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_member_name),
+        url(docsrs),
+        help("Member names must not be empty or contain slashes.")
+    )]
+    /// Invalid member name.
+    InvalidMemberName(String),
+    #[error("Member {0} not found.")]
+    #[diagnostic(
+        code(fs::member_not_found),
+        url(docsrs),
+        help("Please list the shared folder's members to find the correct name.")
+    )]
+    /// Member not found.
+    MemberNotFound(String),
+    #[error("Invalid capability token: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_capability),
+        url(docsrs),
+        help("Please ensure that the token is a UCAN signed with an Ed25519 key.")
+    )]
+    /// Invalid capability token.
+    InvalidCapability(String),
+    #[error("{0} is not authorised to write to {1}.")]
+    #[diagnostic(
+        code(fs::not_authorized),
+        url(docsrs),
+        help("Please record a capability token granting the author write access to the path in the replica.")
+    )]
+    /// Author not authorised to write.
+    NotAuthorized(String, String),
+    #[error("Invalid encryption key for replica {0}.")]
+    #[diagnostic(
+        code(fs::invalid_encryption_key),
+        url(docsrs),
+        help("Encryption keys must be 32 bytes long.")
+    )]
+    /// Invalid encryption key.
+    InvalidEncryptionKey(String),
+    #[error("Unable to decrypt content in replica {0}.")]
+    #[diagnostic(
+        code(fs::decryption_failed),
+        url(docsrs),
+        help("Please ensure that the replica's encryption key matches the one its files were written with.")
+    )]
+    /// Decryption failed.
+    DecryptionFailed(String),
+    #[error("{0} timed out after {1:?}.")]
+    #[diagnostic(
+        code(fs::timed_out),
+        url(docsrs),
+        help("No peer responded in time. Please check the network connection, or configure a longer network timeout.")
+    )]
+    /// Network operation timed out.
+    TimedOut(String, std::time::Duration),
+    #[error("{0} was cancelled.")]
+    #[diagnostic(
+        code(fs::cancelled),
+        url(docsrs),
+        help("The operation was cancelled before it finished.")
+    )]
+    /// Network operation cancelled.
+    Cancelled(String),
+    #[error("{0} cannot be done while the file system is offline.")]
+    #[diagnostic(
+        code(fs::offline),
+        url(docsrs),
+        help("Please bring the file system online before using the network.")
+    )]
+    /// Network operation attempted while offline.
+    Offline(String),
+    #[error("Replica {0} is private.")]
+    #[diagnostic(
+        code(fs::replica_private),
+        url(docsrs),
+        help("Private replicas can only be shared with tickets.")
+    )]
+    /// Replica is private.
+    ReplicaPrivate(String),
+    #[error("Invalid CAR file: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_car),
+        url(docsrs),
+        help("The CAR file may be damaged, or may use encodings other than UnixFS with SHA-256 hashes.")
+    )]
+    /// CAR file could not be read.
+    InvalidCar(String),
+    #[error("Synchronising replica {0} failed: {1}.")]
+    #[diagnostic(
+        code(fs::sync_failed),
+        url(docsrs),
+        help("The replica's peers may be unreachable. Synchronisation will be retried if the replica's policy requires it.")
+    )]
+    /// Synchronising a replica with its peers failed.
+    SyncFailed(String, String),
+    #[error("Invalid glob pattern {0}: {1}.")]
+    #[diagnostic(
+        code(fs::invalid_glob),
+        url(docsrs),
+        help("Patterns may use `*`, `?`, `[...]`, `**`, and `{{a,b}}`, but cannot refer to parent directories.")
+    )]
+    /// Invalid glob pattern.
+    InvalidGlob(String, String),
+    #[error("Content {0} is not held locally.")]
+    #[diagnostic(
+        code(fs::blob_not_held),
+        url(docsrs),
+        help("Fetch the content from a peer holding it first.")
+    )]
+    /// Content is not held in the local store.
+    BlobNotHeld(String),
+    #[error("No provider could supply content {0}.")]
+    #[diagnostic(
+        code(fs::blob_unavailable),
+        url(docsrs),
+        help("The providers may be unreachable, or may no longer hold the content.")
+    )]
+    /// No provider could supply the content asked for.
+    BlobUnavailable(String),
+    #[error("Cannot move directory {0} into {1}, which lies within it.")]
+    #[diagnostic(
+        code(fs::move_into_self),
+        url(docsrs),
+        help("Copy the directory instead, or move it somewhere outside itself.")
+    )]
+    /// A directory cannot be moved into itself.
+    MoveIntoSelf(String, String),
+    #[error("Invalid snapshot label: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_snapshot_label),
+        url(docsrs),
+        help("Snapshot labels must be non-empty and cannot contain slashes.")
+    )]
+    /// Invalid snapshot label.
+    InvalidSnapshotLabel(String),
+    #[error("Snapshot {0} not found.")]
+    #[diagnostic(
+        code(fs::snapshot_not_found),
+        url(docsrs),
+        help("Check the label against the replica's list of snapshots.")
+    )]
+    /// Snapshot not found.
+    SnapshotNotFound(String),
+    #[error("The node's data is not kept on disk.")]
+    #[diagnostic(
+        code(fs::storage_not_on_disk),
+        url(docsrs),
+        help("Use a storage backend keeping the node's data on disk, such as the default.")
+    )]
+    /// The node's data is not kept on disk.
+    StorageNotOnDisk,
+    #[error("Content {0} is a collection of several pieces of content.")]
+    #[diagnostic(
+        code(fs::published_collection),
+        url(docsrs),
+        help("Save the collection to a directory on disk instead of reading it.")
+    )]
+    /// Published content is a collection, and cannot be read as a single piece of content.
+    PublishedCollection(String),
+    #[error("Content {0} is not a collection.")]
+    #[diagnostic(
+        code(fs::not_a_collection),
+        url(docsrs),
+        help("Read the content directly, or use a ticket pointing to a collection.")
+    )]
+    /// Content expected to be a collection is a single piece of content.
+    NotACollection(String),
+    #[error("Invalid name for a file in a collection: {0}.")]
+    #[diagnostic(
+        code(fs::invalid_collection_entry_name),
+        url(docsrs),
+        help("Names must be non-empty relative paths, and cannot refer to parent directories.")
+    )]
+    /// Invalid name for a file in a collection.
+    InvalidCollectionEntryName(String),
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -25,6 +313,10 @@ pub enum OkuDiscoveryError {
     #[diagnostic(code(discovery::problem_announcing_content), url(docsrs))]
     /// Problem announcing content.
     ProblemAnnouncingContent(String, String),
+    #[error("No replica record found for {0}.")]
+    #[diagnostic(code(discovery::no_replica_record), url(docsrs))]
+    /// No DNS record pointing to a replica was found for a name.
+    NoReplicaRecord(String),
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -34,4 +326,16 @@ pub enum OkuRelayError {
     #[diagnostic(code(relay::cannot_satisfy_request), url(docsrs))]
     /// No connected node can satisfy request.
     CannotSatisfyRequest(String),
+    #[error("Problem connecting to relay {0} ({1}).")]
+    #[diagnostic(code(relay::problem_connecting), url(docsrs))]
+    /// Problem connecting to a relay.
+    ProblemConnecting(String, String),
+    #[error("The node holding {0} disconnected before responding.")]
+    #[diagnostic(code(relay::node_disconnected), url(docsrs))]
+    /// The connected node disconnected before responding to a request.
+    NodeDisconnected(String),
+    #[error("The node holding {0} could not satisfy the request ({1}).")]
+    #[diagnostic(code(relay::problem_satisfying_request), url(docsrs))]
+    /// The connected node could not satisfy a request.
+    ProblemSatisfyingRequest(String, String),
 }
@@ -0,0 +1,411 @@
+use crate::audit::AuditOrigin;
+use crate::fs::{is_metadata_key, parse_rename_hint_key, OkuFs};
+use crate::usage::UsageLevel;
+use futures::{Stream, StreamExt};
+use iroh::{
+    bytes::Hash,
+    client::{Entry, LiveEvent},
+    net::NodeId,
+    sync::{AuthorId, ContentStatus, NamespaceId},
+};
+use std::{error::Error, path::PathBuf};
+use tokio::sync::broadcast;
+use tracing::Instrument;
+
+/// The number of events buffered for each subscriber before older events are dropped.
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An event occurring in the file system.
+pub enum OkuFsEvent {
+    /// A replica was created.
+    ReplicaCreated(NamespaceId),
+    /// A replica was imported from a peer.
+    ReplicaImported(NamespaceId),
+    /// A replica was deleted.
+    ReplicaDeleted(NamespaceId),
+    /// A replica was marked as private or public.
+    ReplicaPrivacyChanged {
+        /// The ID of the replica.
+        namespace_id: NamespaceId,
+        /// Whether the replica is now private.
+        private: bool,
+    },
+    /// A replica was pinned or unpinned.
+    ReplicaPinChanged {
+        /// The ID of the replica.
+        namespace_id: NamespaceId,
+        /// Whether the replica is now pinned.
+        pinned: bool,
+    },
+    /// A file was created or modified.
+    EntryInserted {
+        /// The ID of the replica containing the file.
+        namespace_id: NamespaceId,
+        /// The path of the file.
+        path: PathBuf,
+        /// The hash of the file's content.
+        hash: Hash,
+        /// The ID of the author who wrote the file.
+        author: AuthorId,
+    },
+    /// A file or directory was deleted.
+    EntryDeleted {
+        /// The ID of the replica containing the file or directory.
+        namespace_id: NamespaceId,
+        /// The path of the file or directory.
+        path: PathBuf,
+        /// The ID of the author who deleted the file or directory.
+        author: AuthorId,
+    },
+    /// A file was moved from one path to another.
+    EntryRenamed {
+        /// The ID of the replica containing the file.
+        namespace_id: NamespaceId,
+        /// The original path of the file.
+        from: PathBuf,
+        /// The new path of the file.
+        to: PathBuf,
+        /// The ID of the author who moved the file.
+        author: AuthorId,
+    },
+    /// A replica's size crossed a threshold of its quota.
+    QuotaThresholdCrossed {
+        /// The ID of the replica.
+        namespace_id: NamespaceId,
+        /// The level of usage the replica has reached.
+        level: UsageLevel,
+        /// The size of the replica, in bytes.
+        usage: u64,
+        /// The quota of the replica, in bytes.
+        quota: u64,
+    },
+    /// The size of the local content store reached its configured watermark.
+    StoreWatermarkReached {
+        /// The size of the local content store, in bytes.
+        usage: u64,
+        /// The watermark of the local content store, in bytes.
+        watermark: u64,
+    },
+    /// A replica finished synchronising with a peer.
+    SyncFinished(NamespaceId),
+    /// Content received from a peer finished downloading.
+    ContentReady {
+        /// The ID of the replica the content was downloaded for.
+        namespace_id: NamespaceId,
+        /// The hash of the content.
+        hash: Hash,
+    },
+    /// A file received from a peer was written differently by several authors.
+    ConflictDetected {
+        /// The ID of the replica containing the file.
+        namespace_id: NamespaceId,
+        /// The path of the file.
+        path: PathBuf,
+        /// The IDs of the authors whose versions conflict, from newest to oldest.
+        authors: Vec<AuthorId>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An event occurring in a single replica, as reported by [`OkuFs::subscribe_replica`].
+pub enum OkuReplicaEvent {
+    /// A file was created or modified.
+    EntryInserted {
+        /// The path of the file.
+        path: PathBuf,
+        /// The hash of the file's content, as stored.
+        hash: Hash,
+        /// The size, in bytes, of the file's content, as stored.
+        size: u64,
+        /// The ID of the author who wrote the file.
+        author: AuthorId,
+        /// The peer the change was received from, or `None` if it was made on this node.
+        peer: Option<NodeId>,
+        /// Whether the file's content is held locally; content received from peers becomes available once it has downloaded.
+        content_available: bool,
+    },
+    /// A file or directory was deleted.
+    EntryDeleted {
+        /// The path of the file or directory.
+        path: PathBuf,
+        /// The ID of the author who deleted the file or directory.
+        author: AuthorId,
+        /// The peer the change was received from, or `None` if it was made on this node.
+        peer: Option<NodeId>,
+    },
+    /// A file was moved from one path to another.
+    EntryRenamed {
+        /// The original path of the file.
+        from: PathBuf,
+        /// The new path of the file.
+        to: PathBuf,
+        /// The ID of the author who moved the file.
+        author: AuthorId,
+        /// The peer the change was received from, or `None` if it was made on this node.
+        peer: Option<NodeId>,
+    },
+    /// Content received from a peer finished downloading.
+    ContentReady {
+        /// The hash of the content.
+        hash: Hash,
+    },
+    /// A peer joined the replica's swarm.
+    PeerJoined(NodeId),
+    /// A peer left the replica's swarm.
+    PeerLeft(NodeId),
+    /// A synchronisation with a peer finished.
+    SyncFinished {
+        /// The peer synchronised with.
+        peer: NodeId,
+        /// Whether the synchronisation succeeded, or why it failed.
+        result: Result<(), String>,
+    },
+}
+
+impl OkuFs {
+    /// Subscribes to events occurring in the file system.
+    ///
+    /// # Returns
+    ///
+    /// A receiver of file system events.
+    pub fn subscribe(&self) -> broadcast::Receiver<OkuFsEvent> {
+        self.event_sender.subscribe()
+    }
+
+    /// Broadcasts an event to any subscribers.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to broadcast.
+    pub(crate) fn emit(&self, event: OkuFsEvent) {
+        self.emit_from(event, AuditOrigin::Local)
+    }
+
+    /// Broadcasts an event to any subscribers, recording it in the audit log as having the given origin.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to broadcast.
+    ///
+    /// * `origin` - Whether the event occurred on this node or was received from a peer.
+    pub(crate) fn emit_from(&self, event: OkuFsEvent, origin: AuditOrigin) {
+        if let Err(e) = self.record_audit(&event, origin) {
+            tracing::error!("{}", e);
+        }
+        if let OkuFsEvent::EntryInserted { namespace_id, .. } = event {
+            if self.config.replica_quota.is_some() || self.config.store_watermark.is_some() {
+                self.schedule_usage_check(namespace_id);
+            }
+        }
+        // Sending only fails when there are no subscribers.
+        let _ = self.event_sender.send(event);
+    }
+
+    /// Describes an entry received from a peer as a file system event.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica the entry was received for.
+    ///
+    /// * `entry` - The entry.
+    ///
+    /// # Returns
+    ///
+    /// An event describing the file created, modified, deleted, or moved by the entry, or `None` if the entry holds file system metadata.
+    pub(crate) fn remote_entry_event(
+        &self,
+        namespace_id: NamespaceId,
+        entry: &Entry,
+    ) -> Option<OkuFsEvent> {
+        if let Some((from, to)) = parse_rename_hint_key(entry.key()) {
+            return Some(OkuFsEvent::EntryRenamed {
+                namespace_id,
+                from,
+                to,
+                author: entry.author(),
+            });
+        }
+        if is_metadata_key(entry.key()) {
+            return None;
+        }
+        let path = self.entry_path(entry.key());
+        Some(match entry.content_len() {
+            0 => OkuFsEvent::EntryDeleted {
+                namespace_id,
+                path,
+                author: entry.author(),
+            },
+            _ => OkuFsEvent::EntryInserted {
+                namespace_id,
+                path,
+                hash: entry.content_hash(),
+                author: entry.author(),
+            },
+        })
+    }
+
+    /// Subscribes to the events occurring in a single replica, including changes made on this node and those received from peers.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// A stream of the replica's events, which ends once the replica is closed, such as when it is deleted.
+    pub async fn subscribe_replica(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<impl Stream<Item = OkuReplicaEvent> + Send + Unpin, Box<dyn Error + Send + Sync>>
+    {
+        let document = self.open_document(namespace_id).await?;
+        let events = document.subscribe().await?;
+        let self_clone = self.clone();
+        Ok(events
+            .filter_map(move |event| {
+                // The document is held for as long as the stream, as the replica is closed once its last document is dropped.
+                let _ = &document;
+                futures::future::ready(event.ok().and_then(|event| self_clone.replica_event(event)))
+            })
+            .boxed())
+    }
+
+    /// Describes an event occurring in a replica without referring to its raw keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event, as reported by the replica's document.
+    ///
+    /// # Returns
+    ///
+    /// The event, unless it concerns file system metadata other than a moved file.
+    fn replica_event(&self, event: LiveEvent) -> Option<OkuReplicaEvent> {
+        let (entry, peer, content_available) = match event {
+            LiveEvent::InsertLocal { entry } => (entry, None, true),
+            LiveEvent::InsertRemote {
+                from,
+                entry,
+                content_status,
+            } => (entry, Some(from), content_status == ContentStatus::Complete),
+            LiveEvent::ContentReady { hash } => {
+                return Some(OkuReplicaEvent::ContentReady { hash })
+            }
+            LiveEvent::NeighborUp(peer) => return Some(OkuReplicaEvent::PeerJoined(peer)),
+            LiveEvent::NeighborDown(peer) => return Some(OkuReplicaEvent::PeerLeft(peer)),
+            LiveEvent::SyncFinished(sync_event) => {
+                return Some(OkuReplicaEvent::SyncFinished {
+                    peer: sync_event.peer,
+                    result: sync_event.result,
+                })
+            }
+        };
+        if let Some((from, to)) = parse_rename_hint_key(entry.key()) {
+            return Some(OkuReplicaEvent::EntryRenamed {
+                from,
+                to,
+                author: entry.author(),
+                peer,
+            });
+        }
+        if is_metadata_key(entry.key()) {
+            return None;
+        }
+        let path = self.entry_path(entry.key());
+        Some(match entry.content_len() {
+            0 => OkuReplicaEvent::EntryDeleted {
+                path,
+                author: entry.author(),
+                peer,
+            },
+            size => OkuReplicaEvent::EntryInserted {
+                path,
+                hash: entry.content_hash(),
+                size,
+                author: entry.author(),
+                peer,
+                content_available,
+            },
+        })
+    }
+
+    /// Broadcasts the changes a replica receives from peers as file system events.
+    /// This is done once for each replica, however many times it is asked for, so it can be asked for wherever a replica appears.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The document backing the replica.
+    pub(crate) async fn forward_remote_events(
+        &self,
+        document: iroh::client::mem::Doc,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let namespace_id = document.id();
+        if !self.forwarded_replicas.lock().unwrap().insert(namespace_id) {
+            return Ok(());
+        }
+        let events = match document.subscribe().await {
+            Ok(events) => events,
+            Err(e) => {
+                self.forwarded_replicas
+                    .lock()
+                    .unwrap()
+                    .remove(&namespace_id);
+                return Err(e.into());
+            }
+        };
+        let self_clone = self.clone();
+        tokio::spawn(
+            async move {
+                tokio::pin!(events);
+                while let Some(Ok(event)) = events.next().await {
+                    match event {
+                        LiveEvent::InsertRemote { entry, .. } => {
+                            if parse_rename_hint_key(entry.key()).is_none() {
+                                if let Err(e) =
+                                    self_clone.record_overwrite(namespace_id, &entry).await
+                                {
+                                    tracing::error!("{}", e);
+                                }
+                                if let Err(e) =
+                                    self_clone.detect_conflict(namespace_id, &entry).await
+                                {
+                                    tracing::error!("{}", e);
+                                }
+                            }
+                            if let Some(event) = self_clone.remote_entry_event(namespace_id, &entry)
+                            {
+                                self_clone.emit_from(event, AuditOrigin::Sync);
+                            }
+                        }
+                        LiveEvent::SyncFinished(sync_event) => {
+                            let duration = sync_event
+                                .finished
+                                .duration_since(sync_event.started)
+                                .unwrap_or_default();
+                            let peer = sync_event.peer;
+                            match &sync_event.result {
+                                Ok(()) => tracing::info!(%peer, ?duration, "Sync finished"),
+                                Err(e) => tracing::warn!(%peer, ?duration, "Sync failed: {}", e),
+                            }
+                            self_clone.notify_observers(|observer| {
+                                observer.on_sync_finish(namespace_id, &sync_event.result)
+                            });
+                            self_clone.emit(OkuFsEvent::SyncFinished(namespace_id))
+                        }
+                        LiveEvent::ContentReady { hash } => {
+                            self_clone.emit(OkuFsEvent::ContentReady { namespace_id, hash })
+                        }
+                        _ => {}
+                    }
+                }
+                self_clone
+                    .forwarded_replicas
+                    .lock()
+                    .unwrap()
+                    .remove(&namespace_id);
+            }
+            .instrument(tracing::info_span!("sync", %namespace_id)),
+        );
+        Ok(())
+    }
+}
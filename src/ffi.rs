@@ -0,0 +1,532 @@
+use crate::fs::{is_directory_marker, load_or_create_config, OkuFs};
+use crate::ticket::AcceptPolicy;
+use iroh::{rpc_protocol::ShareMode, sync::NamespaceId};
+use std::{
+    cell::RefCell,
+    error::Error,
+    ffi::{c_char, c_int, CStr, CString},
+    path::PathBuf,
+    str::FromStr,
+    sync::OnceLock,
+};
+use tokio::runtime::Runtime;
+
+thread_local! {
+    /// The reason for the latest failure on this thread.
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The runtime file systems started through the C interface run on.
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Gets the runtime file systems started through the C interface run on, starting it if needed.
+///
+/// # Returns
+///
+/// The runtime, or an error if it could not be started.
+fn runtime() -> Result<&'static Runtime, Box<dyn Error + Send + Sync>> {
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = Runtime::new()?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Records the result of an operation, so that the reason for a failure can be retrieved with [`oku_fs_last_error`].
+///
+/// # Arguments
+///
+/// * `result` - The result of the operation.
+///
+/// # Returns
+///
+/// The value produced by the operation, if it succeeded.
+fn record<T>(result: Result<T, Box<dyn Error + Send + Sync>>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(e.to_string()));
+            None
+        }
+    }
+}
+
+/// Records the result of an operation returning a string.
+///
+/// # Arguments
+///
+/// * `result` - The result of the operation.
+///
+/// # Returns
+///
+/// The string, to be freed with [`oku_fs_free_string`], or null if the operation failed.
+fn record_string(result: Result<String, Box<dyn Error + Send + Sync>>) -> *mut c_char {
+    record(result.and_then(|string| Ok(CString::new(string)?)))
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Records the result of an operation returning nothing.
+///
+/// # Arguments
+///
+/// * `result` - The result of the operation.
+///
+/// # Returns
+///
+/// `0` if the operation succeeded, or `-1` if it failed.
+fn record_status(result: Result<(), Box<dyn Error + Send + Sync>>) -> c_int {
+    match record(result) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Reads a string given through the C interface.
+///
+/// # Arguments
+///
+/// * `string` - A pointer to a null-terminated UTF-8 string.
+///
+/// # Returns
+///
+/// The string.
+///
+/// # Safety
+///
+/// `string` must be null or point to a null-terminated string that outlives the returned reference.
+unsafe fn read_string<'a>(string: *const c_char) -> Result<&'a str, Box<dyn Error + Send + Sync>> {
+    if string.is_null() {
+        return Err("a null string was given".into());
+    }
+    Ok(CStr::from_ptr(string).to_str()?)
+}
+
+/// Reads the ID of a replica given through the C interface.
+///
+/// # Arguments
+///
+/// * `replica` - A pointer to the ID of the replica, as a null-terminated string.
+///
+/// # Returns
+///
+/// The ID of the replica.
+///
+/// # Safety
+///
+/// `replica` must be null or point to a null-terminated string.
+unsafe fn read_replica(
+    replica: *const c_char,
+) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+    Ok(NamespaceId::from_str(read_string(replica)?)?)
+}
+
+/// Gets the file system behind a handle given through the C interface.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// # Returns
+///
+/// The file system.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down.
+unsafe fn read_handle<'a>(fs: *const OkuFs) -> Result<&'a OkuFs, Box<dyn Error + Send + Sync>> {
+    fs.as_ref().ok_or("a null file system was given".into())
+}
+
+/// Starts a file system, so that it can be used by applications not written in Rust, such as through Swift or Kotlin.
+/// The file system's configuration is loaded from the given path, and created there if none exists.
+/// The handle returned is passed to every other function of the C interface. Replicas are named by their IDs, and tickets are given in their textual form.
+/// Functions returning a pointer return null if they fail, and functions returning an integer return `0` if they succeed and `-1` if they fail; the reason for the latest failure on the calling thread is given by [`oku_fs_last_error`].
+/// Functions block the calling thread until they finish, and must not be called from within an asynchronous Rust runtime.
+///
+/// # Arguments
+///
+/// * `path` - The path on disk where the file system is stored, as a null-terminated string.
+///
+/// # Returns
+///
+/// A handle to the running file system, to be stopped with [`oku_fs_shutdown`], or null if it could not be started.
+///
+/// # Safety
+///
+/// `path` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_start(path: *const c_char) -> *mut OkuFs {
+    record(read_string(path).and_then(|path| {
+        let config = load_or_create_config(path)?;
+        runtime()?.block_on(OkuFs::start(&config))
+    }))
+    .map_or(std::ptr::null_mut(), |fs| Box::into_raw(Box::new(fs)))
+}
+
+/// Stops a file system, invalidating its handle.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_shutdown(fs: *mut OkuFs) {
+    if !fs.is_null() {
+        let fs = Box::from_raw(fs);
+        if let Some(runtime) = record(runtime()) {
+            let _guard = runtime.enter();
+            fs.shutdown();
+        }
+    }
+}
+
+/// Gets the reason for the latest failure on the calling thread.
+///
+/// # Returns
+///
+/// A description of the failure, to be freed with [`oku_fs_free_string`], or null if nothing has failed.
+#[no_mangle]
+pub extern "C" fn oku_fs_last_error() -> *mut c_char {
+    LAST_ERROR
+        .with(|last_error| last_error.borrow().clone())
+        .and_then(|last_error| CString::new(last_error).ok())
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Frees a string returned through the C interface.
+///
+/// # Arguments
+///
+/// * `string` - The string.
+///
+/// # Safety
+///
+/// `string` must be null or a string returned through the C interface that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Frees the content of a file returned through the C interface.
+///
+/// # Arguments
+///
+/// * `bytes` - The content.
+///
+/// * `length` - The length, in bytes, of the content.
+///
+/// # Safety
+///
+/// `bytes` must be null or content returned through the C interface with the given length that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_free_bytes(bytes: *mut u8, length: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            bytes, length,
+        )));
+    }
+}
+
+/// Creates a replica.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// # Returns
+///
+/// The ID of the new replica, or null if it could not be created.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_create_replica(fs: *const OkuFs) -> *mut c_char {
+    record_string(
+        read_handle(fs).and_then(|fs| Ok(runtime()?.block_on(fs.create_replica())?.to_string())),
+    )
+}
+
+/// Deletes a replica.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `replica` - The ID of the replica, as a null-terminated string.
+///
+/// # Returns
+///
+/// `0` if the replica was deleted, or `-1` if it could not be.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, and `replica` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_delete_replica(fs: *const OkuFs, replica: *const c_char) -> c_int {
+    record_status(read_handle(fs).and_then(|fs| {
+        let namespace_id = read_replica(replica)?;
+        runtime()?.block_on(fs.delete_replica(namespace_id))
+    }))
+}
+
+/// Lists the replicas held by the file system.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// # Returns
+///
+/// A JSON array of the replicas' IDs, or null if they could not be listed.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_list_replicas(fs: *const OkuFs) -> *mut c_char {
+    record_string(read_handle(fs).and_then(|fs| {
+        let replicas: Vec<String> = runtime()?
+            .block_on(fs.list_replicas())?
+            .into_iter()
+            .map(|namespace_id| namespace_id.to_string())
+            .collect();
+        Ok(serde_json::to_string(&replicas)?)
+    }))
+}
+
+/// Lists the files in a replica.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `replica` - The ID of the replica, as a null-terminated string.
+///
+/// # Returns
+///
+/// A JSON array of the files' paths, or null if they could not be listed.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, and `replica` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_list_files(
+    fs: *const OkuFs,
+    replica: *const c_char,
+) -> *mut c_char {
+    record_string(read_handle(fs).and_then(|fs| {
+        let namespace_id = read_replica(replica)?;
+        let files: Vec<PathBuf> = runtime()?
+            .block_on(fs.list_files(namespace_id))?
+            .into_iter()
+            .filter(|entry| !is_directory_marker(entry.key()))
+            .map(|entry| fs.entry_path(entry.key()))
+            .collect();
+        Ok(serde_json::to_string(&files)?)
+    }))
+}
+
+/// Creates a file, or replaces the content of an existing one.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `replica` - The ID of the replica containing the file, as a null-terminated string.
+///
+/// * `path` - The path of the file, as a null-terminated string.
+///
+/// * `data` - The content of the file.
+///
+/// * `length` - The length, in bytes, of the content.
+///
+/// # Returns
+///
+/// `0` if the file was written, or `-1` if it could not be.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, `replica` and `path` must be null or point to null-terminated strings, and `data` must be null or point to `length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_create_or_modify_file(
+    fs: *const OkuFs,
+    replica: *const c_char,
+    path: *const c_char,
+    data: *const u8,
+    length: usize,
+) -> c_int {
+    record_status(read_handle(fs).and_then(|fs| {
+        let namespace_id = read_replica(replica)?;
+        let path = PathBuf::from(read_string(path)?);
+        let data = match data.is_null() {
+            true => Vec::new(),
+            false => std::slice::from_raw_parts(data, length).to_vec(),
+        };
+        runtime()?.block_on(fs.create_or_modify_file(namespace_id, path, data))?;
+        Ok(())
+    }))
+}
+
+/// Reads the content of a file.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `replica` - The ID of the replica containing the file, as a null-terminated string.
+///
+/// * `path` - The path of the file, as a null-terminated string.
+///
+/// * `length` - Where to write the length, in bytes, of the content.
+///
+/// # Returns
+///
+/// The content of the file, to be freed with [`oku_fs_free_bytes`], or null if it could not be read.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, `replica` and `path` must be null or point to null-terminated strings, and `length` must be null or point to a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_read_file(
+    fs: *const OkuFs,
+    replica: *const c_char,
+    path: *const c_char,
+    length: *mut usize,
+) -> *mut u8 {
+    record(read_handle(fs).and_then(|fs| {
+        let namespace_id = read_replica(replica)?;
+        let path = PathBuf::from(read_string(path)?);
+        let length = length.as_mut().ok_or("a null length was given")?;
+        let content = runtime()?.block_on(fs.read_file(namespace_id, path))?;
+        *length = content.len();
+        Ok(Box::into_raw(content.to_vec().into_boxed_slice()) as *mut u8)
+    }))
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Deletes a file.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `replica` - The ID of the replica containing the file, as a null-terminated string.
+///
+/// * `path` - The path of the file, as a null-terminated string.
+///
+/// # Returns
+///
+/// `0` if the file was deleted, or `-1` if it could not be.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, and `replica` and `path` must be null or point to null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_delete_file(
+    fs: *const OkuFs,
+    replica: *const c_char,
+    path: *const c_char,
+) -> c_int {
+    record_status(read_handle(fs).and_then(|fs| {
+        let namespace_id = read_replica(replica)?;
+        let path = PathBuf::from(read_string(path)?);
+        runtime()?.block_on(fs.delete_file(namespace_id, path))?;
+        Ok(())
+    }))
+}
+
+/// Fetches the latest version of a replica from peers.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `replica` - The ID of the replica, as a null-terminated string.
+///
+/// # Returns
+///
+/// `0` if the replica was fetched, or `-1` if it could not be.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, and `replica` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_fetch_replica(fs: *const OkuFs, replica: *const c_char) -> c_int {
+    record_status(read_handle(fs).and_then(|fs| {
+        let namespace_id = read_replica(replica)?;
+        runtime()?.block_on(fs.get_external_replica(namespace_id, None, true, true))
+    }))
+}
+
+/// Creates a ticket granting access to a replica.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `replica` - The ID of the replica, as a null-terminated string.
+///
+/// * `write` - Whether the ticket grants write access, rather than only read access.
+///
+/// # Returns
+///
+/// The ticket, or null if it could not be created.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, and `replica` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_share_replica(
+    fs: *const OkuFs,
+    replica: *const c_char,
+    write: bool,
+) -> *mut c_char {
+    record_string(read_handle(fs).and_then(|fs| {
+        let namespace_id = read_replica(replica)?;
+        let mode = match write {
+            true => ShareMode::Write,
+            false => ShareMode::Read,
+        };
+        Ok(runtime()?
+            .block_on(fs.create_replica_ticket(namespace_id, mode))?
+            .to_string())
+    }))
+}
+
+/// Imports a replica from a ticket.
+///
+/// # Arguments
+///
+/// * `fs` - The handle of the file system.
+///
+/// * `ticket` - The ticket, as a null-terminated string.
+///
+/// * `write` - Whether write access granted by the ticket is kept.
+///
+/// # Returns
+///
+/// The ID of the imported replica, or null if it could not be imported.
+///
+/// # Safety
+///
+/// `fs` must be null or a handle returned by [`oku_fs_start`] that has not been shut down, and `ticket` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oku_fs_accept_ticket(
+    fs: *const OkuFs,
+    ticket: *const c_char,
+    write: bool,
+) -> *mut c_char {
+    record_string(read_handle(fs).and_then(|fs| {
+        let ticket = read_string(ticket)?;
+        let policy = AcceptPolicy::default().allow_write(write);
+        Ok(runtime()?
+            .block_on(fs.accept_ticket(ticket, policy))?
+            .to_string())
+    }))
+}
@@ -0,0 +1,243 @@
+use crate::error::OkuFsError;
+use crate::fs::{normalise_path, OkuFs, METADATA_DIRECTORY};
+use futures::{pin_mut, StreamExt};
+use iroh::{client::Entry, sync::NamespaceId};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    path::PathBuf,
+};
+
+/// The directory holding the metadata of files, within the directory reserved for file system metadata.
+pub const FILE_METADATA_DIRECTORY_NAME: &str = "file_metadata";
+
+/// Metadata attached to a file, such as its content type or labels, as named values.
+pub type FileMetadata = BTreeMap<String, String>;
+
+/// Gets the path at which a file's metadata is held.
+/// The metadata of files is held under a directory mirroring the layout of the replica.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// The path of the file's metadata within a replica.
+pub fn file_metadata_path(path: PathBuf) -> PathBuf {
+    let path = normalise_path(path);
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(FILE_METADATA_DIRECTORY_NAME)
+        .join(path.strip_prefix("/").unwrap_or(&path))
+}
+
+impl OkuFs {
+    /// Attaches metadata to a file, replacing any metadata it already had.
+    /// The metadata is held separately from the file's content, so changing it does not change the file.
+    /// Metadata is never encrypted, even in an encrypted replica, so it can be read by any peer syncing the replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `metadata` - The metadata to attach. If empty, the file's metadata is removed.
+    pub async fn set_metadata(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        metadata: &FileMetadata,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        self.get_latest_entry(namespace_id, path.clone()).await?;
+        let document = self.open_document(namespace_id).await?;
+        let metadata_key = self.entry_key(file_metadata_path(path));
+        // Entries with no content mark deletions, so empty metadata is removed instead of written.
+        if metadata.is_empty() {
+            document.del(self.author_id, metadata_key).await?;
+        } else {
+            document
+                .set_bytes(self.author_id, metadata_key, toml::to_string(metadata)?)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Gets the metadata attached to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The file's metadata, which is empty if none was attached.
+    pub async fn get_metadata(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<FileMetadata, Box<dyn Error + Send + Sync>> {
+        match self
+            .get_latest_entry(namespace_id, file_metadata_path(path))
+            .await
+        {
+            Ok(entry) => self.parse_metadata(&entry).await,
+            Err(e) => match e.downcast_ref::<OkuFsError>() {
+                Some(OkuFsError::FsEntryNotFound) => Ok(FileMetadata::new()),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Lists the files in a replica along with their metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The latest entry of each file, paired with the file's metadata.
+    pub async fn list_files_with_metadata(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<(Entry, FileMetadata)>, Box<dyn Error + Send + Sync>> {
+        let files = self.list_files(namespace_id).await?;
+        let document = self.open_document(namespace_id).await?;
+        let metadata_root = file_metadata_path(PathBuf::from("/"));
+        let query = iroh::sync::store::Query::single_latest_per_key()
+            .key_prefix(self.entry_prefix(metadata_root.clone()))
+            .build();
+        let metadata_entries = document.get_many(query).await?;
+        pin_mut!(metadata_entries);
+        let mut metadata_by_path = HashMap::new();
+        while let Some(metadata_entry) = metadata_entries.next().await {
+            let metadata_entry = metadata_entry?;
+            let metadata_path = self.entry_path(metadata_entry.key());
+            if let Ok(path) = metadata_path.strip_prefix(&metadata_root) {
+                metadata_by_path.insert(
+                    normalise_path(path.to_path_buf()),
+                    self.parse_metadata(&metadata_entry).await?,
+                );
+            }
+        }
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                let metadata = metadata_by_path
+                    .remove(&self.entry_path(file.key()))
+                    .unwrap_or_default();
+                (file, metadata)
+            })
+            .collect())
+    }
+
+    /// Attaches the metadata of one file to another, possibly in another replica.
+    /// The copy refers to the same content as the original, so no data is duplicated.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_namespace_id` - The ID of the replica containing the original file.
+    ///
+    /// * `from` - The path of the original file.
+    ///
+    /// * `to_namespace_id` - The ID of the replica containing the file to attach the metadata to.
+    ///
+    /// * `to` - The path of the file to attach the metadata to.
+    pub(crate) async fn copy_metadata(
+        &self,
+        from_namespace_id: NamespaceId,
+        from: PathBuf,
+        to_namespace_id: NamespaceId,
+        to: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Ok(entry) = self
+            .get_latest_entry(from_namespace_id, file_metadata_path(from))
+            .await
+        else {
+            return Ok(());
+        };
+        let document = self.open_document(to_namespace_id).await?;
+        document
+            .set_hash(
+                self.author_id,
+                self.entry_key(file_metadata_path(to)),
+                entry.content_hash(),
+                entry.content_len(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the metadata attached to a file, if it has any.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    pub(crate) async fn delete_metadata(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let metadata_path = file_metadata_path(path);
+        if self
+            .get_latest_entry(namespace_id, metadata_path.clone())
+            .await
+            .is_ok()
+        {
+            let document = self.open_document(namespace_id).await?;
+            document
+                .del(self.author_id, self.entry_key(metadata_path))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the metadata attached to every file within a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `path` - The path of the directory.
+    pub(crate) async fn delete_directory_metadata(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let metadata_prefix = self.entry_prefix(file_metadata_path(path));
+        let document = self.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::single_latest_per_key()
+            .key_prefix(metadata_prefix.clone())
+            .build();
+        if document.get_one(query).await?.is_some() {
+            self.delete_prefix(&document, self.author_id, metadata_prefix)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the metadata held in an entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry holding a file's metadata.
+    ///
+    /// # Returns
+    ///
+    /// The file's metadata.
+    async fn parse_metadata(
+        &self,
+        entry: &Entry,
+    ) -> Result<FileMetadata, Box<dyn Error + Send + Sync>> {
+        let metadata_bytes = self.read_entry_content(entry).await?;
+        Ok(toml::from_str(&String::from_utf8_lossy(&metadata_bytes))?)
+    }
+}
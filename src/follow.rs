@@ -0,0 +1,96 @@
+use crate::event::OkuFsEvent;
+use crate::fs::OkuFs;
+use crate::schedule::SyncPolicy;
+use futures::{stream::BoxStream, StreamExt};
+use iroh::{
+    client::{mem::Doc, LiveEvent},
+    sync::NamespaceId,
+};
+use std::error::Error;
+use tokio::task::JoinHandle;
+
+/// A replica kept synchronised with its peers, reporting the changes received from them.
+/// The replica stops being followed once the follower is dropped, unless its synchronisation policy keeps it synchronised.
+pub struct ReplicaFollower {
+    /// The file system holding the replica.
+    oku_fs: OkuFs,
+    /// The ID of the replica.
+    namespace_id: NamespaceId,
+    /// The document backing the replica, held so that the replica stays open while it is followed.
+    _document: Doc,
+    /// The events occurring in the replica.
+    events: BoxStream<'static, anyhow::Result<LiveEvent>>,
+    /// The task keeping the replica synchronised.
+    sync_task: JoinHandle<()>,
+}
+
+impl ReplicaFollower {
+    /// Gets the ID of the replica being followed.
+    pub fn namespace_id(&self) -> NamespaceId {
+        self.namespace_id
+    }
+
+    /// Waits for the next change received from the replica's peers.
+    ///
+    /// # Returns
+    ///
+    /// An event describing a file created, modified, deleted, or moved by a peer, or the completion of a synchronisation with a peer.
+    /// `None` is returned once the replica is closed, such as when it is deleted.
+    pub async fn next(&mut self) -> Option<OkuFsEvent> {
+        while let Some(event) = self.events.next().await {
+            match event {
+                Ok(LiveEvent::InsertRemote { entry, .. }) => {
+                    if let Some(event) = self.oku_fs.remote_entry_event(self.namespace_id, &entry) {
+                        return Some(event);
+                    }
+                }
+                Ok(LiveEvent::SyncFinished(_)) => {
+                    return Some(OkuFsEvent::SyncFinished(self.namespace_id));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!(namespace_id = %self.namespace_id, "{}", e);
+                    return None;
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Drop for ReplicaFollower {
+    fn drop(&mut self) {
+        self.sync_task.abort();
+    }
+}
+
+impl OkuFs {
+    /// Follows a replica, keeping it synchronised with its peers so that the local copy tracks the changes made by their authors without [`OkuFs::sync_replica`] being called.
+    /// A replica not held locally is fetched first. Failed synchronisations are retried after a delay which grows with each consecutive failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to follow.
+    ///
+    /// # Returns
+    ///
+    /// A follower reporting each change received from the replica's peers, which stops following the replica once dropped.
+    pub async fn follow_replica(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<ReplicaFollower, Box<dyn Error + Send + Sync>> {
+        if !self.list_replicas().await?.contains(&namespace_id) {
+            self.get_external_replica(namespace_id, None, true, false)
+                .await?;
+        }
+        let document = self.open_document(namespace_id).await?;
+        let events = document.subscribe().await?.boxed();
+        Ok(ReplicaFollower {
+            oku_fs: self.clone(),
+            namespace_id,
+            _document: document,
+            events,
+            sync_task: self.spawn_sync(namespace_id, SyncPolicy::Continuous),
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use iroh::sync::NamespaceId;
+use std::error::Error;
+
+impl OkuFs {
+    /// Makes a replica temporarily read-only, rejecting local writes until it is unfrozen.
+    /// Changes from peers continue to be synchronised into the replica.
+    /// Replicas are unfrozen when the file system restarts.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to freeze.
+    pub async fn freeze_replica(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.open_document(namespace_id).await?;
+        self.frozen_replicas.lock().unwrap().insert(namespace_id);
+        Ok(())
+    }
+
+    /// Allows local writes to a frozen replica again.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to unfreeze.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica was frozen.
+    pub fn unfreeze_replica(&self, namespace_id: NamespaceId) -> bool {
+        self.frozen_replicas.lock().unwrap().remove(&namespace_id)
+    }
+
+    /// Checks whether a replica is frozen.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether local writes to the replica are currently rejected.
+    pub fn is_replica_frozen(&self, namespace_id: NamespaceId) -> bool {
+        self.frozen_replicas.lock().unwrap().contains(&namespace_id)
+    }
+
+    /// Rejects a local write to a replica if the replica is frozen.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica being written to.
+    pub(crate) fn ensure_not_frozen(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.is_replica_frozen(namespace_id) {
+            return Err(OkuFsError::ReplicaFrozen(namespace_id.to_string()).into());
+        }
+        Ok(())
+    }
+}
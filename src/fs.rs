@@ -1,17 +1,38 @@
-use crate::discovery::{announce_replica, INITIAL_PUBLISH_DELAY, REPUBLISH_DELAY};
+use crate::coalesce::WriteCoalescer;
+use crate::compression::Compression;
+use crate::discovery::{AnnounceHealth, AnnounceSchedule};
 use crate::discovery::{
     PeerContentRequest, PeerContentResponse, PeerTicketResponse, DISCOVERY_PORT,
 };
-use crate::{discovery::ContentRequest, error::OkuFsError};
+use crate::event::{OkuFsEvent, EVENT_CHANNEL_CAPACITY};
+use crate::glob::Glob;
+use crate::integrity::{check_store_integrity, spawn_node, IntegrityCheck, StartupReport};
+use crate::ipc::RemoteControlConfig;
+use crate::limits::ResourceLimits;
+use crate::network::{PkarrRelayDiscovery, RelayServers};
+use crate::observer::OkuObserver;
+use crate::publish::announce_published_content;
+use crate::relay::{RelayRequest, RelayResponse};
+#[cfg(feature = "search")]
+use crate::search::{SearchIndex, SEARCH_INDEX_DIRECTORY};
+use crate::storage::{DiskStorage, OkuNode, StorageBackend};
+use crate::usage::UsageLevels;
+use crate::{
+    discovery::ContentRequest,
+    error::{OkuFsError, OkuRelayError},
+};
 use bytes::Bytes;
 use futures::{pin_mut, StreamExt};
 use iroh::client::Entry;
 use iroh::rpc_protocol::BlobDownloadRequest;
 use iroh::ticket::BlobTicket;
 use iroh::{
-    bytes::Hash,
-    net::discovery::{ConcurrentDiscovery, Discovery},
-    node::FsNode,
+    bytes::{Hash, Tag},
+    net::{
+        discovery::{ConcurrentDiscovery, Discovery},
+        key::SecretKey,
+        relay::RelayMode,
+    },
     rpc_protocol::ShareMode,
     sync::{Author, AuthorId, NamespaceId},
 };
@@ -19,27 +40,51 @@ use iroh_mainline_content_discovery::protocol::{Query, QueryFlags};
 use iroh_mainline_content_discovery::to_infohash;
 use iroh_pkarr_node_discovery::PkarrNodeDiscovery;
 use path_clean::PathClean;
+use pkarr::url::Url;
 use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
-use std::{error::Error, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
+use tokio::sync::{broadcast, Notify, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{instrument, Instrument};
 
-/// The path on disk where the file system is stored.
+/// The default path on disk where the file system is stored.
 pub const FS_PATH: &str = ".oku";
 
 /// The protocol identifier for exchanging document tickets.
 pub const ALPN_DOCUMENT_TICKET_FETCH: &[u8] = b"oku/document-ticket/fetch/v0";
 
+/// The protocol identifier for exchanging tickets to published content.
+pub const ALPN_BLOB_TICKET_FETCH: &[u8] = b"oku/blob-ticket/fetch/v0";
+
 /// The protocol identifier for initially connecting to relays.
 pub const ALPN_INITIAL_RELAY_CONNECTION: &[u8] = b"oku/relay/connect/v0";
 
 /// The protocol identifier for fetching its list of replicas.
 pub const ALPN_RELAY_FETCH: &[u8] = b"oku/relay/fetch/v0";
 
-fn normalise_path(path: PathBuf) -> PathBuf {
+/// The directory within a replica reserved for file system metadata.
+pub const METADATA_DIRECTORY: &str = "/.oku";
+
+/// The content of entries marking explicitly created directories.
+/// Entries with no content mark deletions, so markers cannot be empty.
+pub const DIRECTORY_MARKER: &[u8] = b"/";
+
+pub(crate) fn normalise_path(path: PathBuf) -> PathBuf {
     PathBuf::from("/").join(path).clean()
 }
 
@@ -59,11 +104,476 @@ pub fn path_to_entry_key(path: PathBuf) -> Bytes {
     path_bytes.into()
 }
 
+/// Converts the key of an entry in a file system replica back into a path.
+///
+/// # Arguments
+///
+/// * `key` - The key of an entry in a file system replica.
+///
+/// # Returns
+///
+/// The path represented by the key.
+pub fn entry_key_to_path(key: &[u8]) -> PathBuf {
+    let key = key.strip_suffix(b"\0").unwrap_or(key);
+    PathBuf::from(String::from_utf8_lossy(key).to_string())
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The scheme used to encode paths as the keys of entries in replicas.
+pub enum KeyCodec {
+    /// Paths are encoded as the operating system represents them, followed by a null byte.
+    #[default]
+    NullTerminated,
+    /// Paths are encoded as UTF-8 with `/` separators and no terminator, as is common among other users of Iroh documents.
+    Utf8,
+}
+
+impl KeyCodec {
+    /// Converts a path to a key for an entry in a file system replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to convert to a key.
+    ///
+    /// # Returns
+    ///
+    /// A byte string representing the path.
+    pub fn encode(&self, path: PathBuf) -> Bytes {
+        match self {
+            KeyCodec::NullTerminated => path_to_entry_key(path),
+            KeyCodec::Utf8 => utf8_path_string(path).into(),
+        }
+    }
+
+    /// Converts the path of a directory to a prefix shared by the keys of all entries within it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// A byte string prefixing the keys of the directory's entries.
+    pub fn encode_prefix(&self, path: PathBuf) -> Bytes {
+        let path = normalise_path(path).join(""); // Ensure path ends with a slash
+        match self {
+            KeyCodec::NullTerminated => path.into_os_string().into_encoded_bytes().into(),
+            KeyCodec::Utf8 => {
+                let mut prefix = utf8_path_string(path);
+                if !prefix.ends_with('/') {
+                    prefix.push('/');
+                }
+                prefix.into()
+            }
+        }
+    }
+
+    /// Converts the key of an entry in a file system replica back into a path.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of an entry in a file system replica.
+    ///
+    /// # Returns
+    ///
+    /// The path represented by the key.
+    pub fn decode(&self, key: &[u8]) -> PathBuf {
+        match self {
+            KeyCodec::NullTerminated => entry_key_to_path(key),
+            KeyCodec::Utf8 => PathBuf::from(String::from_utf8_lossy(key).to_string()),
+        }
+    }
+}
+
+/// Represents a path as UTF-8, separating its components with `/` regardless of platform.
+fn utf8_path_string(path: PathBuf) -> String {
+    let components: Vec<String> = normalise_path(path)
+        .components()
+        .filter(|component| !matches!(component, std::path::Component::RootDir))
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    format!("/{}", components.join("/"))
+}
+
+/// Converts a file's move from one path to another into the key of a rename hint.
+/// Both paths are held in the key, so that peers can interpret the hint without fetching its content.
+/// Rename hints always encode paths with [`KeyCodec::NullTerminated`], so that the two paths can be told apart.
+///
+/// # Arguments
+///
+/// * `from` - The original path of the file.
+///
+/// * `to` - The new path of the file.
+///
+/// # Returns
+///
+/// The key of an entry recording the file's move.
+pub fn rename_hint_key(from: PathBuf, to: PathBuf) -> Bytes {
+    let mut key_bytes = format!("{}/renames", METADATA_DIRECTORY).into_bytes();
+    key_bytes.extend_from_slice(&path_to_entry_key(from));
+    key_bytes.extend_from_slice(&path_to_entry_key(to));
+    key_bytes.into()
+}
+
+/// Recovers the original and new paths of a moved file from the key of a rename hint.
+///
+/// # Arguments
+///
+/// * `key` - The key of an entry in a file system replica.
+///
+/// # Returns
+///
+/// The original and new paths of the file, if the key is that of a rename hint.
+pub fn parse_rename_hint_key(key: &[u8]) -> Option<(PathBuf, PathBuf)> {
+    let paths = key.strip_prefix(format!("{}/renames", METADATA_DIRECTORY).as_bytes())?;
+    let separator = paths.iter().position(|byte| *byte == b'\0')?;
+    let (from, to) = paths.split_at(separator + 1);
+    if !from.starts_with(b"/") || !to.starts_with(b"/") {
+        return None;
+    }
+    Some((entry_key_to_path(from), entry_key_to_path(to)))
+}
+
+/// Determines whether an entry key lies within the directory reserved for file system metadata.
+///
+/// # Arguments
+///
+/// * `key` - The key of an entry in a file system replica.
+///
+/// # Returns
+///
+/// Whether the entry holds file system metadata rather than a user's file.
+pub fn is_metadata_key(key: &[u8]) -> bool {
+    key.starts_with(format!("{}/", METADATA_DIRECTORY).as_bytes())
+}
+
+/// Determines whether an entry key marks an explicitly created directory.
+/// Directory markers are keyed by the prefix shared by the directory's contents, so they are never mistaken for files.
+///
+/// # Arguments
+///
+/// * `key` - The key of an entry in a file system replica.
+///
+/// # Returns
+///
+/// Whether the entry marks a directory rather than holding a file.
+pub fn is_directory_marker(key: &[u8]) -> bool {
+    key.ends_with(b"/")
+}
+
+fn default_fs_path() -> PathBuf {
+    PathBuf::from(FS_PATH)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_quota_warning_ratio() -> f64 {
+    0.8
+}
+
+fn default_storage_backend() -> Arc<dyn StorageBackend> {
+    Arc::new(DiskStorage::default())
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 ///  The configuration of the file system.
 pub struct OkuFsConfig {
+    /// The path on disk where the file system is stored.
+    #[serde(skip, default = "default_fs_path")]
+    pub path: PathBuf,
     /// An optional address to facilitate communication behind NAT.
     pub relay_address: Option<String>,
+    /// The author to write as. If unspecified, the first author found on disk is used, or a new author is created.
+    #[serde(default)]
+    pub author_id: Option<AuthorId>,
+    /// Whether to publish the node's address and announce replicas to the mainline DHT.
+    #[serde(default = "default_true")]
+    pub discovery: bool,
+    /// Whether the file system starts offline, with all networking disabled, such as for air-gapped machines or tests. It can be brought online later with [`OkuFs::set_offline`].
+    /// The remote control is the one exception, and is served even while offline.
+    #[serde(default)]
+    pub offline: bool,
+    /// Whether moving a file records a hint, so that mirrors can distinguish a rename from a deletion and creation.
+    #[serde(default = "default_true")]
+    pub rename_hints: bool,
+    /// An optional limit, in bytes, on the size of each replica. Crossing it is reported as an event.
+    #[serde(default)]
+    pub replica_quota: Option<u64>,
+    /// The fraction of a replica's quota at which a warning is reported as an event.
+    #[serde(default = "default_quota_warning_ratio")]
+    pub quota_warning_ratio: f64,
+    /// An optional size, in bytes, of the local content store at which a warning is reported as an event.
+    #[serde(default)]
+    pub store_watermark: Option<u64>,
+    /// How the local store is checked for damage when the file system starts.
+    #[serde(default)]
+    pub integrity_check: IntegrityCheck,
+    /// The scheme used to encode paths as the keys of entries in replicas.
+    #[serde(default)]
+    pub key_codec: KeyCodec,
+    /// Limits on the resources used by the file system.
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Whether local writes to files must be authorised by a capability token recorded in the replica.
+    #[serde(default)]
+    pub enforce_capabilities: bool,
+    /// The addresses, as `host:port`, of the nodes used to join the mainline DHT. If unspecified, the DHT's default bootstrap nodes are used.
+    #[serde(default)]
+    pub dht_bootstrap: Option<Vec<String>>,
+    /// The pkarr relays the node's address is also published to, for networks where the mainline DHT cannot be reached directly.
+    #[serde(default)]
+    pub pkarr_relays: Vec<Url>,
+    /// The relay servers used to reach peers that cannot be connected to directly.
+    #[serde(default)]
+    pub relay_servers: RelayServers,
+    /// How long a network operation, such as fetching a replica, may take before it is abandoned. If unspecified, [`crate::timeout::DEFAULT_NETWORK_TIMEOUT`] is used.
+    #[serde(default)]
+    pub network_timeout: Option<Duration>,
+    /// When replicas are announced to the mainline DHT.
+    #[serde(default)]
+    pub announce_schedule: AnnounceSchedule,
+    /// Whether changes to the file system, made locally or received from peers, are recorded in the audit log.
+    #[serde(default = "default_true")]
+    pub audit_log: bool,
+    /// Whether deleted files are moved to the trash, from which they can be restored, rather than deleted outright.
+    #[serde(default)]
+    pub trash: bool,
+    /// How long files are kept in the trash before being deleted outright. If unspecified, files are kept until the trash is emptied.
+    #[serde(default)]
+    pub trash_retention: Option<Duration>,
+    /// The port and token of the node's remote control, through which it can be managed from other machines. If unspecified, the node cannot be managed remotely.
+    /// If specified, the remote control listens for connections whether or not the node is offline.
+    #[serde(default)]
+    pub remote_control: Option<RemoteControlConfig>,
+    /// The secret key the node is identified by, replacing any key held on disk. If unspecified, the key held on disk is used, or a new key is created.
+    #[serde(skip)]
+    pub node_secret_key: Option<SecretKey>,
+    /// Authors to hold, in addition to those held on disk. If no author to write as is specified, the first of these is written as.
+    #[serde(skip)]
+    pub author_keys: Vec<Author>,
+    /// Where the data of the node backing the file system is kept. If unspecified, it is kept on disk within the file system's path.
+    #[serde(skip, default = "default_storage_backend")]
+    pub storage_backend: Arc<dyn StorageBackend>,
+}
+
+impl Default for OkuFsConfig {
+    fn default() -> Self {
+        OkuFsConfig {
+            path: default_fs_path(),
+            relay_address: None,
+            author_id: None,
+            discovery: true,
+            offline: false,
+            rename_hints: true,
+            replica_quota: None,
+            quota_warning_ratio: default_quota_warning_ratio(),
+            store_watermark: None,
+            integrity_check: IntegrityCheck::default(),
+            key_codec: KeyCodec::default(),
+            limits: ResourceLimits::default(),
+            enforce_capabilities: false,
+            dht_bootstrap: None,
+            pkarr_relays: Vec::new(),
+            relay_servers: RelayServers::default(),
+            network_timeout: None,
+            announce_schedule: AnnounceSchedule::default(),
+            audit_log: true,
+            trash: false,
+            trash_retention: None,
+            remote_control: None,
+            node_secret_key: None,
+            author_keys: Vec::new(),
+            storage_backend: default_storage_backend(),
+        }
+    }
+}
+
+impl OkuFsConfig {
+    /// Creates a builder for the configuration of the file system.
+    ///
+    /// # Returns
+    ///
+    /// A builder starting from the default configuration.
+    pub fn builder() -> OkuFsConfigBuilder {
+        OkuFsConfigBuilder::default()
+    }
+
+    /// Describes the relay servers in the form the node is configured with, using none if the file system starts offline.
+    ///
+    /// # Returns
+    ///
+    /// The node's relay mode.
+    pub(crate) fn relay_mode(&self) -> Result<RelayMode, Box<dyn Error + Send + Sync>> {
+        match self.offline {
+            true => Ok(RelayMode::Disabled),
+            false => self.relay_servers.relay_mode(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// A builder for the configuration of the file system.
+pub struct OkuFsConfigBuilder {
+    config: OkuFsConfig,
+}
+
+impl OkuFsConfigBuilder {
+    /// Sets the path on disk where the file system is stored.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.path = path.into();
+        self
+    }
+
+    /// Sets the address of a relay to facilitate communication behind NAT.
+    pub fn relay_address(mut self, relay_address: impl Into<String>) -> Self {
+        self.config.relay_address = Some(relay_address.into());
+        self
+    }
+
+    /// Sets the author to write as.
+    pub fn author_id(mut self, author_id: AuthorId) -> Self {
+        self.config.author_id = Some(author_id);
+        self
+    }
+
+    /// Sets whether to publish the node's address and announce replicas to the mainline DHT.
+    pub fn discovery(mut self, discovery: bool) -> Self {
+        self.config.discovery = discovery;
+        self
+    }
+
+    /// Sets whether the file system starts offline, with all networking disabled.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.config.offline = offline;
+        self
+    }
+
+    /// Sets whether moving a file records a hint, so that mirrors can distinguish a rename from a deletion and creation.
+    pub fn rename_hints(mut self, rename_hints: bool) -> Self {
+        self.config.rename_hints = rename_hints;
+        self
+    }
+
+    /// Sets a limit, in bytes, on the size of each replica.
+    pub fn replica_quota(mut self, replica_quota: u64) -> Self {
+        self.config.replica_quota = Some(replica_quota);
+        self
+    }
+
+    /// Sets the fraction of a replica's quota at which a warning is reported.
+    pub fn quota_warning_ratio(mut self, quota_warning_ratio: f64) -> Self {
+        self.config.quota_warning_ratio = quota_warning_ratio;
+        self
+    }
+
+    /// Sets the size, in bytes, of the local content store at which a warning is reported.
+    pub fn store_watermark(mut self, store_watermark: u64) -> Self {
+        self.config.store_watermark = Some(store_watermark);
+        self
+    }
+
+    /// Sets how the local store is checked for damage when the file system starts.
+    pub fn integrity_check(mut self, integrity_check: IntegrityCheck) -> Self {
+        self.config.integrity_check = integrity_check;
+        self
+    }
+
+    /// Sets the scheme used to encode paths as the keys of entries in replicas.
+    pub fn key_codec(mut self, key_codec: KeyCodec) -> Self {
+        self.config.key_codec = key_codec;
+        self
+    }
+
+    /// Sets the limits on the resources used by the file system.
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    /// Sets whether local writes to files must be authorised by a capability token recorded in the replica.
+    pub fn enforce_capabilities(mut self, enforce_capabilities: bool) -> Self {
+        self.config.enforce_capabilities = enforce_capabilities;
+        self
+    }
+
+    /// Sets the addresses, as `host:port`, of the nodes used to join the mainline DHT.
+    pub fn dht_bootstrap(mut self, dht_bootstrap: Vec<String>) -> Self {
+        self.config.dht_bootstrap = Some(dht_bootstrap);
+        self
+    }
+
+    /// Sets the pkarr relays the node's address is also published to.
+    pub fn pkarr_relays(mut self, pkarr_relays: Vec<Url>) -> Self {
+        self.config.pkarr_relays = pkarr_relays;
+        self
+    }
+
+    /// Sets the relay servers used to reach peers that cannot be connected to directly.
+    pub fn relay_servers(mut self, relay_servers: RelayServers) -> Self {
+        self.config.relay_servers = relay_servers;
+        self
+    }
+
+    /// Sets how long a network operation may take before it is abandoned.
+    pub fn network_timeout(mut self, network_timeout: Duration) -> Self {
+        self.config.network_timeout = Some(network_timeout);
+        self
+    }
+
+    /// Sets when replicas are announced to the mainline DHT.
+    pub fn announce_schedule(mut self, announce_schedule: AnnounceSchedule) -> Self {
+        self.config.announce_schedule = announce_schedule;
+        self
+    }
+
+    /// Sets whether changes to the file system are recorded in the audit log.
+    pub fn audit_log(mut self, audit_log: bool) -> Self {
+        self.config.audit_log = audit_log;
+        self
+    }
+
+    /// Sets whether deleted files are moved to the trash rather than deleted outright.
+    pub fn trash(mut self, trash: bool) -> Self {
+        self.config.trash = trash;
+        self
+    }
+
+    /// Sets how long files are kept in the trash before being deleted outright.
+    pub fn trash_retention(mut self, trash_retention: Duration) -> Self {
+        self.config.trash_retention = Some(trash_retention);
+        self
+    }
+
+    /// Sets the port and token of the node's remote control, so that it can be managed from other machines.
+    pub fn remote_control(mut self, remote_control: RemoteControlConfig) -> Self {
+        self.config.remote_control = Some(remote_control);
+        self
+    }
+
+    /// Sets the secret key the node is identified by, such as one derived from a mnemonic or decrypted with [`crate::keys::decrypt_node_key`].
+    pub fn node_secret_key(mut self, node_secret_key: SecretKey) -> Self {
+        self.config.node_secret_key = Some(node_secret_key);
+        self
+    }
+
+    /// Adds an author to hold, such as one decrypted with [`crate::keys::decrypt_author_key`].
+    pub fn author_key(mut self, author_key: Author) -> Self {
+        self.config.author_keys.push(author_key);
+        self
+    }
+
+    /// Sets where the data of the node backing the file system is kept, such as in memory with [`crate::storage::MemoryStorage`].
+    pub fn storage_backend(mut self, storage_backend: impl StorageBackend + 'static) -> Self {
+        self.config.storage_backend = Arc::new(storage_backend);
+        self
+    }
+
+    /// Finishes building the configuration.
+    pub fn build(self) -> OkuFsConfig {
+        self.config
+    }
 }
 
 /// An instance of an Oku file system.
@@ -72,54 +582,143 @@ pub struct OkuFsConfig {
 #[derive(Clone, Debug)]
 pub struct OkuFs {
     /// An Iroh node responsible for storing replicas on the local machine, as well as joining swarms to fetch replicas from other nodes.
-    node: FsNode,
+    pub(crate) node: OkuNode,
     /// The public key of the author of the file system.
-    author_id: AuthorId,
+    pub(crate) author_id: AuthorId,
     /// The configuration of the file system.
-    config: OkuFsConfig,
+    pub(crate) config: OkuFsConfig,
+    /// A broadcast of events occurring in the file system.
+    pub(crate) event_sender: broadcast::Sender<OkuFsEvent>,
+    /// The most recently reported usage levels of the replicas and the local content store.
+    pub(crate) usage_levels: Arc<Mutex<UsageLevels>>,
+    /// A report of what happened when the file system started.
+    pub(crate) startup_report: Arc<StartupReport>,
+    /// The observers notified of operations performed by the file system.
+    pub(crate) observers: Arc<RwLock<Vec<Arc<dyn OkuObserver>>>>,
+    /// The replicas in which local writes are currently rejected.
+    pub(crate) frozen_replicas: Arc<Mutex<HashSet<NamespaceId>>>,
+    /// A signal that the node's network has changed, and the node should be republished.
+    pub(crate) network_changed: Arc<Notify>,
+    /// A summary of how announcements to the mainline DHT have gone.
+    pub(crate) announce_health: Arc<Mutex<AnnounceHealth>>,
+    /// The slots available for syncing replicas, as allowed by the configured limits.
+    pub(crate) sync_slots: Arc<Semaphore>,
+    /// The writes held back so that rapid successive writes to a file are recorded as one entry version.
+    pub(crate) write_coalescer: Arc<Mutex<WriteCoalescer>>,
+    /// The background tasks synchronising replicas as their policies require.
+    pub(crate) sync_schedules: Arc<Mutex<HashMap<NamespaceId, JoinHandle<()>>>>,
+    /// The replicas whose changes from peers are being broadcast as file system events.
+    pub(crate) forwarded_replicas: Arc<Mutex<HashSet<NamespaceId>>>,
+    /// Held while settings kept on disk are read, changed and written back, so that concurrent changes are not lost.
+    pub(crate) settings_lock: Arc<Mutex<()>>,
+    /// Whether networking is currently disabled.
+    pub(crate) offline: Arc<AtomicBool>,
+    /// Whether the node's background networking tasks, such as announcing replicas, have been started.
+    pub(crate) networking_started: Arc<AtomicBool>,
+    /// An index of the text files held in the replicas.
+    #[cfg(feature = "search")]
+    pub(crate) search_index: Arc<SearchIndex>,
 }
 
 impl OkuFs {
     /// Starts an instance of an Oku file system.
     /// In the background, an Iroh node is started, and the node's address is periodically announced to the mainline DHT.
     /// If no author credentials are found on disk, new credentials are generated.
+    /// Pinned replicas, and replicas whose policies require it, are synchronised with peers in the background, and interrupted imports of replicas are resumed.
+    /// If the file system is configured to start offline, none of this networking is done until it is brought online with [`OkuFs::set_offline`].
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the file system.
     ///
     /// # Returns
     ///
     /// A running instance of an Oku file system.
-    pub async fn start() -> Result<OkuFs, Box<dyn Error + Send + Sync>> {
-        let node_path = PathBuf::from(FS_PATH).join("node");
-        let node = FsNode::persistent(node_path).await?.spawn().await?;
+    /// If the store is checked for damage, the findings are available from [`OkuFs::startup_report`].
+    #[instrument(skip_all, fields(path = ?config.path), err)]
+    pub async fn start(config: &OkuFsConfig) -> Result<OkuFs, Box<dyn Error + Send + Sync>> {
+        let (node, mut startup_report) = spawn_node(config).await?;
+        if config.integrity_check != IntegrityCheck::Disabled {
+            check_store_integrity(&node, config.integrity_check, &mut startup_report).await?;
+        }
         let authors = node.authors.list().await?;
         futures::pin_mut!(authors);
-        let authors_count = authors.as_mut().count().await.to_owned();
-        let author_id = if authors_count == 0 {
-            node.authors.create().await?
-        } else {
-            let authors = node.authors.list().await?;
-            futures::pin_mut!(authors);
-            let authors_list: Vec<AuthorId> = authors.map(|author| author.unwrap()).collect().await;
-            authors_list[0]
+        let authors_list: Vec<AuthorId> = authors.map(|author| author.unwrap()).collect().await;
+        let author_id = match config.author_id {
+            Some(author_id) => {
+                if !authors_list.contains(&author_id) {
+                    return Err(OkuFsError::AuthorNotFound(author_id.to_string()).into());
+                }
+                author_id
+            }
+            None => match config
+                .author_keys
+                .first()
+                .map(|author| author.id())
+                .or(authors_list.first().copied())
+            {
+                Some(author_id) => author_id,
+                None => node.authors.create().await?,
+            },
         };
-        let config = load_or_create_config()?;
         let oku_fs = OkuFs {
             node,
             author_id,
-            config,
+            config: config.clone(),
+            event_sender: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            usage_levels: Arc::new(Mutex::new(UsageLevels::default())),
+            startup_report: Arc::new(startup_report),
+            observers: Arc::new(RwLock::new(Vec::new())),
+            frozen_replicas: Arc::new(Mutex::new(HashSet::new())),
+            network_changed: Arc::new(Notify::new()),
+            announce_health: Arc::new(Mutex::new(AnnounceHealth::default())),
+            sync_slots: Arc::new(config.limits.sync_slots()),
+            write_coalescer: Arc::new(Mutex::new(WriteCoalescer::default())),
+            sync_schedules: Arc::new(Mutex::new(HashMap::new())),
+            forwarded_replicas: Arc::new(Mutex::new(HashSet::new())),
+            settings_lock: Arc::new(Mutex::new(())),
+            offline: Arc::new(AtomicBool::new(config.offline)),
+            networking_started: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "search")]
+            search_index: Arc::new(SearchIndex::open(
+                &config.path.join(SEARCH_INDEX_DIRECTORY),
+                &config.limits,
+            )?),
         };
-        let oku_fs_clone = oku_fs.clone();
-        let node_addr = oku_fs.node.my_addr().await?;
-        let addr_info = node_addr.info;
-        let magic_endpoint = oku_fs.node.magic_endpoint();
-        let secret_key = magic_endpoint.secret_key();
-        let mut discovery_service = ConcurrentDiscovery::new();
-        let pkarr = PkarrNodeDiscovery::builder().secret_key(secret_key).build();
-        discovery_service.add(pkarr);
-        discovery_service.publish(&addr_info);
-        let docs_client = &oku_fs.node.docs;
-        let docs_client = docs_client.clone();
-        if let Some(relay_address) = oku_fs_clone.config.relay_address {
+        #[cfg(feature = "search")]
+        oku_fs.start_indexing();
+        for namespace_id in oku_fs.list_replicas().await? {
+            let document = oku_fs.open_document(namespace_id).await?;
+            oku_fs.forward_remote_events(document).await?;
+        }
+        // The remote control is served regardless of whether the node is offline, as it is how a headless node is brought back online.
+        if let Some(remote_control) = oku_fs.config.remote_control.clone() {
             let oku_fs_clone = oku_fs.clone();
+            tokio::spawn(async move {
+                if let Err(e) = oku_fs_clone.serve_remote(remote_control).await {
+                    tracing::error!("{}", e);
+                }
+            });
+        }
+        if !oku_fs.is_offline() {
+            oku_fs.start_networking().await?;
+        }
+        Ok(oku_fs)
+    }
+
+    /// Starts the networking of the file system, such as when it starts online or is brought online.
+    /// Pinned replicas, and replicas whose policies require it, are synchronised with peers in the background, and interrupted imports of replicas are resumed.
+    /// The first time networking is started, the node also begins listening for requests from peers, connecting to its relay, and announcing its replicas.
+    pub(crate) async fn start_networking(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.sync_pinned_replicas()?;
+        self.start_sync_schedules()?;
+        self.resume_pending_syncs().await?;
+        // The tasks below run until the file system shuts down, pausing while it is offline.
+        if self.networking_started.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Some(relay_address) = self.config.relay_address.clone() {
+            let oku_fs_clone = self.clone();
             tokio::spawn(async move {
                 oku_fs_clone
                     .connect_to_relay(relay_address.to_string())
@@ -127,26 +726,74 @@ impl OkuFs {
                     .unwrap();
             });
         }
-        let oku_fs_clone = oku_fs.clone();
+        let oku_fs_clone = self.clone();
         tokio::spawn(async move {
             oku_fs_clone
                 .listen_for_document_ticket_fetch_requests()
                 .await
                 .unwrap()
         });
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(INITIAL_PUBLISH_DELAY).await;
-                let replicas = docs_client.list().await.unwrap();
-                pin_mut!(replicas);
-                while let Some(replica) = replicas.next().await {
-                    let (namespace_id, _) = replica.unwrap();
-                    announce_replica(namespace_id).await.unwrap();
+        if self.config.discovery {
+            let discovery_service = self.create_discovery_service().await?;
+            self.watch_network_changes();
+            let oku_fs_clone = self.clone();
+            tokio::spawn(async move {
+                let dht = oku_fs_clone.mainline_dht();
+                let announce_schedule = oku_fs_clone.config.announce_schedule.clone();
+                loop {
+                    tokio::time::sleep(announce_schedule.initial_delay).await;
+                    if announce_schedule.enabled && !oku_fs_clone.is_offline() {
+                        match oku_fs_clone.announce(&dht).await {
+                            Ok(announced) => {
+                                let announce_health = oku_fs_clone.announce_health();
+                                match announce_health.failed.is_empty() {
+                                    true => tracing::info!(
+                                        announced = announced.len(),
+                                        "Announced replicas"
+                                    ),
+                                    false => tracing::warn!(
+                                        announced = announced.len(),
+                                        failed = announce_health.failed.len(),
+                                        consecutive_failures = announce_health.consecutive_failures,
+                                        "Some replicas could not be announced"
+                                    ),
+                                }
+                            }
+                            Err(e) => tracing::warn!("{}", e),
+                        }
+                        match oku_fs_clone.list_published() {
+                            Ok(published) if !published.is_empty() => {
+                                match announce_published_content(&dht, published).await {
+                                    Ok(announced) => tracing::info!(
+                                        announced = announced.len(),
+                                        "Announced published content"
+                                    ),
+                                    Err(e) => tracing::warn!("{}", e),
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(e) => tracing::warn!("{}", e),
+                        }
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(announce_schedule.next_delay()) => {}
+                        _ = oku_fs_clone.network_changed.notified(), if !oku_fs_clone.is_offline() => {
+                            // The node may be reachable at new addresses, so refresh its published record.
+                            if let Ok(node_addr) = oku_fs_clone.node.my_addr().await {
+                                discovery_service.publish(&node_addr.info);
+                            }
+                        }
+                    }
+                    if oku_fs_clone.is_offline() {
+                        continue;
+                    }
+                    if let Err(e) = oku_fs_clone.sync_pinned_replicas() {
+                        tracing::warn!("{}", e);
+                    }
                 }
-                tokio::time::sleep(REPUBLISH_DELAY - INITIAL_PUBLISH_DELAY).await;
-            }
-        });
-        Ok(oku_fs)
+            });
+        }
+        Ok(())
     }
 
     /// Create a mechanism for discovering other nodes on the network given their IDs.
@@ -162,27 +809,101 @@ impl OkuFs {
         let magic_endpoint = self.node.magic_endpoint();
         let secret_key = magic_endpoint.secret_key();
         let mut discovery_service = ConcurrentDiscovery::new();
-        let pkarr = PkarrNodeDiscovery::builder().secret_key(secret_key).build();
+        let pkarr = PkarrNodeDiscovery::builder()
+            .client(self.pkarr_client())
+            .secret_key(secret_key)
+            .build();
         discovery_service.add(pkarr);
+        if !self.config.pkarr_relays.is_empty() {
+            discovery_service.add(PkarrRelayDiscovery::new(
+                self.pkarr_client(),
+                self.config.pkarr_relays.clone(),
+                Some(secret_key),
+            ));
+        }
         discovery_service.publish(&addr_info);
         Ok(discovery_service)
     }
 
     /// Shuts down the Oku file system.
     pub fn shutdown(self) {
+        for (_, sync_schedule) in self.sync_schedules.lock().unwrap().drain() {
+            sync_schedule.abort();
+        }
         self.node.shutdown();
     }
 
+    /// Opens a replica in the file system.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to open.
+    ///
+    /// # Returns
+    ///
+    /// The document backing the replica.
+    pub(crate) async fn open_document(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<iroh::client::mem::Doc, Box<dyn Error + Send + Sync>> {
+        let docs_client = &self.node.docs;
+        Ok(docs_client
+            .open(namespace_id)
+            .await?
+            .ok_or(OkuFsError::FsEntryNotFound)?)
+    }
+
+    /// Converts a path to a key for an entry in a replica, using the configured key encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to convert to a key.
+    ///
+    /// # Returns
+    ///
+    /// A byte string representing the path.
+    pub(crate) fn entry_key(&self, path: PathBuf) -> Bytes {
+        self.config.key_codec.encode(path)
+    }
+
+    /// Converts the path of a directory to a prefix shared by the keys of all entries within it, using the configured key encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// A byte string prefixing the keys of the directory's entries.
+    pub(crate) fn entry_prefix(&self, path: PathBuf) -> Bytes {
+        self.config.key_codec.encode_prefix(path)
+    }
+
+    /// Converts the key of an entry in a replica back into a path, using the configured key encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of an entry in a replica.
+    ///
+    /// # Returns
+    ///
+    /// The path represented by the key.
+    pub(crate) fn entry_path(&self, key: &[u8]) -> PathBuf {
+        self.config.key_codec.decode(key)
+    }
+
     /// Creates a new replica in the file system.
     ///
     /// # Returns
     ///
     /// The ID of the new replica, being its public key.
+    #[instrument(skip_all, err)]
     pub async fn create_replica(&self) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
         let docs_client = &self.node.docs;
         let new_document = docs_client.create().await?;
         let document_id = new_document.id();
-        new_document.close().await?;
+        self.forward_remote_events(new_document).await?;
+        self.emit(OkuFsEvent::ReplicaCreated(document_id));
         Ok(document_id)
     }
 
@@ -191,12 +912,27 @@ impl OkuFs {
     /// # Arguments
     ///
     /// * `namespace_id` - The ID of the replica to delete.
+    #[instrument(skip_all, fields(%namespace_id), err)]
     pub async fn delete_replica(
         &self,
         namespace_id: NamespaceId,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
         let docs_client = &self.node.docs;
-        Ok(docs_client.drop_doc(namespace_id).await?)
+        docs_client.drop_doc(namespace_id).await?;
+        self.forwarded_replicas
+            .lock()
+            .unwrap()
+            .remove(&namespace_id);
+        self.remove_replica_alias(namespace_id)?;
+        self.remove_encryption_key(namespace_id)?;
+        self.remove_replica_privacy(namespace_id)?;
+        self.remove_replica_pin(namespace_id)?;
+        self.remove_sync_policy(namespace_id)?;
+        self.remove_download_policy(namespace_id)?;
+        self.remove_pending_sync(namespace_id)?;
+        self.emit(OkuFsEvent::ReplicaDeleted(namespace_id));
+        Ok(())
     }
 
     /// Lists all replicas in the file system.
@@ -213,65 +949,172 @@ impl OkuFs {
         Ok(replica_ids)
     }
 
-    /// Lists all files in a replica.
+    /// Gets the size of a replica.
     ///
     /// # Arguments
     ///
-    /// * `namespace_id` - The ID of the replica to list files in.
+    /// * `namespace_id` - The ID of the replica.
     ///
     /// # Returns
     ///
-    /// A list of all files in the replica.
-    pub async fn list_files(
+    /// The total size, in bytes, of the latest version of each file in the replica.
+    pub async fn get_size(
         &self,
         namespace_id: NamespaceId,
-    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
-        let docs_client = &self.node.docs;
-        let document = docs_client
-            .open(namespace_id)
-            .await?
-            .ok_or(OkuFsError::FsEntryNotFound)?;
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
         let query = iroh::sync::store::Query::single_latest_per_key().build();
         let entries = document.get_many(query).await?;
         pin_mut!(entries);
-        let files: Vec<Entry> = entries.map(|entry| entry.unwrap()).collect().await;
-        Ok(files)
+        let mut size = 0;
+        while let Some(entry) = entries.next().await {
+            size += entry?.content_len();
+        }
+        Ok(size)
     }
 
-    /// Creates a file (if it does not exist) or modifies an existing file.
+    /// Lists all files in a replica.
     ///
     /// # Arguments
     ///
-    /// * `namespace_id` - The ID of the replica containing the file to create or modify.
+    /// * `namespace_id` - The ID of the replica to list files in.
     ///
-    /// * `path` - The path of the file to create or modify.
+    /// # Returns
+    ///
+    /// A list of all files in the replica, along with markers of explicitly created directories, excluding file system metadata.
+    /// Directory markers can be told apart with [`is_directory_marker`].
+    /// To list only some files, use [`OkuFs::list_files_with_options`]; to list large replicas a piece at a time, use [`OkuFs::list_files_page`] or [`OkuFs::stream_files`].
+    #[instrument(skip_all, fields(%namespace_id), err)]
+    pub async fn list_files(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let entries = self.stream_files(namespace_id).await?;
+        pin_mut!(entries);
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next().await {
+            files.push(entry?);
+        }
+        Ok(files)
+    }
+
+    /// Creates a file (if it does not exist) or modifies an existing file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to create or modify.
+    ///
+    /// * `path` - The path of the file to create or modify.
     ///
     /// * `data` - The data to write to the file.
     ///
     /// # Returns
     ///
     /// The hash of the file.
+    /// If writes to the replica are coalesced, the file is not written until no further writes to it arrive within the window.
+    #[instrument(skip_all, fields(%namespace_id, ?path), err)]
     pub async fn create_or_modify_file(
         &self,
         namespace_id: NamespaceId,
         path: PathBuf,
         data: impl Into<Bytes>,
     ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
-        let file_key = path_to_entry_key(path);
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
         let data_bytes = data.into();
+        // Writes held back are checked now, rather than failing unseen once recorded.
+        self.config.limits.check_write(data_bytes.len() as u64)?;
+        if let Some(hash) = self.coalesce_write(namespace_id, path.clone(), &data_bytes) {
+            return Ok(hash);
+        }
+        self.write_file(namespace_id, path, data_bytes, self.author_id)
+            .await
+    }
+
+    /// Writes a file to a replica immediately, compressed as the replica's setting dictates.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to create or modify.
+    ///
+    /// * `path` - The path of the file to create or modify.
+    ///
+    /// * `data_bytes` - The data to write to the file.
+    ///
+    /// * `author_id` - The author to write the file as.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file.
+    pub(crate) async fn write_file(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data_bytes: Bytes,
+        author_id: AuthorId,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        let compression = self.replica_compression(namespace_id)?;
+        self.write_file_with_compression(namespace_id, path, data_bytes, author_id, compression)
+            .await
+    }
+
+    /// Writes a file to a replica immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to create or modify.
+    ///
+    /// * `path` - The path of the file to create or modify.
+    ///
+    /// * `data_bytes` - The data to write to the file.
+    ///
+    /// * `author_id` - The author to write the file as.
+    ///
+    /// * `compression` - How to compress the file.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file.
+    pub(crate) async fn write_file_with_compression(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data_bytes: Bytes,
+        author_id: AuthorId,
+        compression: Compression,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        let file_key = self.entry_key(path.clone());
         let docs_client = &self.node.docs;
         let document = docs_client
             .open(namespace_id)
             .await?
             .ok_or(OkuFsError::FsEntryNotFound)?;
-        let entry_hash = document
-            .set_bytes(self.author_id, file_key, data_bytes)
-            .await?;
-
+        let data_size = data_bytes.len() as u64;
+        self.config.limits.check_write(data_size)?;
+        let (data_bytes, compressed) = self.compress_content(&file_key, data_bytes, compression)?;
+        let data_bytes = self.encrypt_content(namespace_id, &file_key, data_bytes)?;
+        // The codec is recorded first, so that the content is never seen without it.
+        if compressed {
+            self.record_compressed_content(namespace_id, Hash::new(&data_bytes))
+                .await?;
+        }
+        let entry_hash = document.set_bytes(author_id, file_key, data_bytes).await?;
+        self.notify_observers(|observer| {
+            observer.on_write(namespace_id, &path, entry_hash, data_size)
+        });
+        self.emit(OkuFsEvent::EntryInserted {
+            namespace_id,
+            path: normalise_path(path),
+            hash: entry_hash,
+            author: author_id,
+        });
         Ok(entry_hash)
     }
 
     /// Deletes a file.
+    /// Only the version written by the file system's author is deleted; use [`OkuFs::purge_file`] to delete the versions of other authors.
+    /// If the trash is enabled in the configuration, the file is first moved to the trash, from which it can be restored with [`OkuFs::restore_from_trash`].
     ///
     /// # Arguments
     ///
@@ -282,22 +1125,99 @@ impl OkuFs {
     /// # Returns
     ///
     /// The number of entries deleted in the replica, which should be 1 if the file was successfully deleted.
+    #[instrument(skip_all, fields(%namespace_id, ?path), err)]
     pub async fn delete_file(
         &self,
         namespace_id: NamespaceId,
         path: PathBuf,
     ) -> Result<usize, Box<dyn Error + Send + Sync>> {
-        let file_key = path_to_entry_key(path);
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        if self.config.trash {
+            self.move_to_trash(namespace_id, path.clone()).await?;
+        }
+        self.remove_file(namespace_id, path, self.author_id).await
+    }
+
+    /// Deletes an author's version of a file, along with the file's metadata and any writes to it held back.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to delete.
+    ///
+    /// * `path` - The path of the file to delete.
+    ///
+    /// * `author_id` - The author to delete the file as.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries deleted in the replica.
+    pub(crate) async fn remove_file(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        author_id: AuthorId,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.discard_pending_writes(namespace_id, path.clone());
+        let file_key = self.entry_key(path.clone());
         let docs_client = &self.node.docs;
         let document = docs_client
             .open(namespace_id)
             .await?
             .ok_or(OkuFsError::FsEntryNotFound)?;
-        let entries_deleted = document.del(self.author_id, file_key).await?;
+        let entries_deleted = document.del(author_id, file_key).await?;
+        self.delete_metadata(namespace_id, path.clone()).await?;
+        self.emit(OkuFsEvent::EntryDeleted {
+            namespace_id,
+            path: normalise_path(path),
+            author: author_id,
+        });
         Ok(entries_deleted)
     }
 
-    /// Reads a file.
+    /// Deletes every entry an author has written under a prefix.
+    /// Deleting a prefix leaves the keys it removed in Iroh's index of entries by key, which cuts short later queries for the latest entry of each key; each removed key is therefore given a deletion entry of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The replica to delete the entries from.
+    ///
+    /// * `author_id` - The author whose entries should be deleted.
+    ///
+    /// * `prefix` - The prefix of the keys of the entries to delete.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries deleted.
+    pub(crate) async fn delete_prefix(
+        &self,
+        document: &iroh::client::mem::Doc,
+        author_id: AuthorId,
+        prefix: Bytes,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let query = iroh::sync::store::Query::author(author_id)
+            .key_prefix(prefix.clone())
+            .include_empty()
+            .build();
+        let entries = document.get_many(query).await?;
+        pin_mut!(entries);
+        let mut removed_keys = BTreeSet::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if entry.key() != prefix.as_ref() {
+                removed_keys.insert(entry.key().to_vec());
+            }
+        }
+        let entries_deleted = document.del(author_id, prefix).await?;
+        // Shorter keys are deleted first, so that deleting a key does not remove the deletion of a key it prefixes.
+        for key in removed_keys {
+            document.del(author_id, key).await?;
+        }
+        Ok(entries_deleted)
+    }
+
+    /// Reads the latest version of a file, whichever author wrote it, such as a file fetched from a peer.
     ///
     /// # Arguments
     ///
@@ -308,52 +1228,451 @@ impl OkuFs {
     /// # Returns
     ///
     /// The data read from the file.
+    #[instrument(skip_all, fields(%namespace_id, ?path), err)]
     pub async fn read_file(
         &self,
         namespace_id: NamespaceId,
         path: PathBuf,
     ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
-        let file_key = path_to_entry_key(path);
-        let docs_client = &self.node.docs;
-        let document = docs_client
-            .open(namespace_id)
+        if let Some(content) = self.pending_write(namespace_id, path.clone()) {
+            self.notify_observers(|observer| {
+                observer.on_read(namespace_id, &path, content.len() as u64)
+            });
+            return Ok(content);
+        }
+        let entry = self.get_latest_entry(namespace_id, path.clone()).await?;
+        let content = self.read_entry_content(&entry).await?;
+        self.notify_observers(|observer| {
+            observer.on_read(namespace_id, &path, content.len() as u64)
+        });
+        Ok(content)
+    }
+
+    /// Moves a file by pointing a new location at its content and deleting the original.
+    /// The content is neither read nor copied; a write to the file still held back by write coalescing is recorded first.
+    /// The original is deleted by every author, as with [`OkuFs::purge_file`], so that it does not remain visible when last written by another author.
+    /// Moving a file onto itself leaves it untouched.
+    /// Unless disabled in the configuration, a hint recording the move is written to the replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to move.
+    ///
+    /// * `from` - The path of the file to move.
+    ///
+    /// * `to` - The path to move the file to.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the hash of the file at the new destination and the number of replica entries deleted during the operation, which should be 1 if the file at the original path was deleted.
+    #[instrument(skip_all, fields(%namespace_id, ?from, ?to), err)]
+    pub async fn move_file(
+        &self,
+        namespace_id: NamespaceId,
+        from: PathBuf,
+        to: PathBuf,
+    ) -> Result<(Hash, usize), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, from.clone(), self.author_id)
+            .await?;
+        self.authorize_write(namespace_id, to.clone(), self.author_id)
+            .await?;
+        self.flush_write(namespace_id, from.clone()).await?;
+        // A write held back at the destination would otherwise overwrite the moved file once recorded.
+        self.flush_write(namespace_id, to.clone()).await?;
+        let entry = self.get_latest_entry(namespace_id, from.clone()).await?;
+        if normalise_path(from.clone()) == normalise_path(to.clone()) {
+            return Ok((entry.content_hash(), 0));
+        }
+        let hash = self
+            .set_entry_content(namespace_id, to.clone(), &entry)
+            .await?;
+        self.copy_metadata(namespace_id, from.clone(), namespace_id, to.clone())
+            .await?;
+        // The file lives on at its new path, so the original is not kept in the trash.
+        let entries_deleted = self
+            .purge_file(namespace_id, from.clone())
             .await?
-            .ok_or(OkuFsError::FsEntryNotFound)?;
-        let entry = document
-            .get_exact(self.author_id, file_key, false)
+            .entries_deleted;
+        self.record_rename(namespace_id, from, to, hash).await?;
+        Ok((hash, entries_deleted))
+    }
+
+    /// Records that a file has moved, writing a rename hint if enabled in the configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the moved file.
+    ///
+    /// * `from` - The original path of the file.
+    ///
+    /// * `to` - The new path of the file.
+    ///
+    /// * `hash` - The hash of the file's content.
+    pub(crate) async fn record_rename(
+        &self,
+        namespace_id: NamespaceId,
+        from: PathBuf,
+        to: PathBuf,
+        hash: Hash,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        if self.config.rename_hints {
+            let document = self.open_document(namespace_id).await?;
+            document
+                .set_bytes(
+                    self.author_id,
+                    rename_hint_key(from.clone(), to.clone()),
+                    hash.to_string(),
+                )
+                .await?;
+        }
+        self.emit(OkuFsEvent::EntryRenamed {
+            namespace_id,
+            from: normalise_path(from),
+            to: normalise_path(to),
+            author: self.author_id,
+        });
+        Ok(())
+    }
+
+    /// Gets the latest entry of a file, written by any author.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The latest entry of the file.
+    pub(crate) async fn get_latest_entry(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Entry, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::single_latest_per_key()
+            .key_exact(self.entry_key(path))
+            .build();
+        Ok(document
+            .get_one(query)
             .await?
-            .ok_or(OkuFsError::FsEntryNotFound)?;
-        Ok(entry.content_bytes(self.node.client()).await?)
+            .ok_or(OkuFsError::FsEntryNotFound)?)
+    }
+
+    /// Lists the latest entries of all files within a directory, written by any author.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// The latest entries of the files and directory markers within the directory, excluding file system metadata.
+    pub(crate) async fn list_directory_entries(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::single_latest_per_key()
+            .key_prefix(self.entry_prefix(path))
+            .build();
+        let entries = document.get_many(query).await?;
+        pin_mut!(entries);
+        let files: Vec<Entry> = entries
+            .map(|entry| entry.unwrap())
+            .filter(|entry| futures::future::ready(!is_metadata_key(entry.key())))
+            .collect()
+            .await;
+        Ok(files)
+    }
+
+    /// Copies a file, possibly into another replica.
+    /// The copy refers to the same content as the original, so no data is duplicated.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_namespace_id` - The ID of the replica containing the file to copy.
+    ///
+    /// * `from` - The path of the file to copy.
+    ///
+    /// * `to_namespace_id` - The ID of the replica to copy the file into.
+    ///
+    /// * `to` - The path to copy the file to.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file.
+    #[instrument(skip_all, fields(%from_namespace_id, ?from, %to_namespace_id, ?to), err)]
+    pub async fn copy_file(
+        &self,
+        from_namespace_id: NamespaceId,
+        from: PathBuf,
+        to_namespace_id: NamespaceId,
+        to: PathBuf,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(to_namespace_id)?;
+        self.authorize_write(to_namespace_id, to.clone(), self.author_id)
+            .await?;
+        self.flush_write(from_namespace_id, from.clone()).await?;
+        let entry = self
+            .get_latest_entry(from_namespace_id, from.clone())
+            .await?;
+        let hash = self
+            .set_entry_content(to_namespace_id, to.clone(), &entry)
+            .await?;
+        self.copy_metadata(from_namespace_id, from, to_namespace_id, to)
+            .await?;
+        Ok(hash)
+    }
+
+    /// Sets a file to refer to the content of an existing entry.
+    /// If the entry is in a replica encrypted differently, its content is copied instead.
+    /// Callers are expected to have authorised the write.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to create or modify.
+    ///
+    /// * `path` - The path of the file to create or modify.
+    ///
+    /// * `entry` - The entry whose content the file should refer to.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file.
+    pub(crate) async fn set_entry_content(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        entry: &Entry,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        // Content cannot be shared with a replica encrypted differently, so it is written anew.
+        if !is_metadata_key(entry.key())
+            && !self.shares_encryption(entry.id().namespace(), namespace_id)?
+        {
+            let content = self.read_entry_content(entry).await?;
+            return self
+                .write_file(namespace_id, path, content, self.author_id)
+                .await;
+        }
+        self.copy_codec(entry.id().namespace(), namespace_id, entry.content_hash())
+            .await?;
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_hash(
+                self.author_id,
+                self.entry_key(path.clone()),
+                entry.content_hash(),
+                entry.content_len(),
+            )
+            .await?;
+        self.notify_observers(|observer| {
+            observer.on_write(
+                namespace_id,
+                &path,
+                entry.content_hash(),
+                entry.content_len(),
+            )
+        });
+        self.emit(OkuFsEvent::EntryInserted {
+            namespace_id,
+            path: normalise_path(path),
+            hash: entry.content_hash(),
+            author: self.author_id,
+        });
+        Ok(entry.content_hash())
+    }
+
+    /// Creates an empty directory, which persists even while it holds no files.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to create the directory in.
+    ///
+    /// * `path` - The path of the directory to create.
+    #[instrument(skip_all, fields(%namespace_id, ?path), err)]
+    pub async fn create_directory(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        let path = normalise_path(path);
+        // The root directory always exists.
+        if path == Path::new("/") {
+            return Ok(());
+        }
+        let document = self.open_document(namespace_id).await?;
+        let marker_hash = document
+            .set_bytes(
+                self.author_id,
+                self.entry_prefix(path.clone()),
+                DIRECTORY_MARKER,
+            )
+            .await?;
+        self.emit(OkuFsEvent::EntryInserted {
+            namespace_id,
+            path: path.join(""),
+            hash: marker_hash,
+            author: self.author_id,
+        });
+        Ok(())
+    }
+
+    /// Copies a directory and all its contents, possibly into another replica.
+    /// The copies refer to the same content as the originals, so no data is duplicated.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_namespace_id` - The ID of the replica containing the directory to copy.
+    ///
+    /// * `from` - The path of the directory to copy.
+    ///
+    /// * `to_namespace_id` - The ID of the replica to copy the directory into.
+    ///
+    /// * `to` - The path to copy the directory to.
+    ///
+    /// # Returns
+    ///
+    /// The original and new paths of each copied file.
+    #[instrument(skip_all, fields(%from_namespace_id, ?from, %to_namespace_id, ?to), err)]
+    pub async fn copy_directory(
+        &self,
+        from_namespace_id: NamespaceId,
+        from: PathBuf,
+        to_namespace_id: NamespaceId,
+        to: PathBuf,
+    ) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(to_namespace_id)?;
+        self.authorize_write(to_namespace_id, to.clone(), self.author_id)
+            .await?;
+        let from = normalise_path(from);
+        let to = normalise_path(to);
+        self.flush_writes(from_namespace_id).await?;
+        // The contents are listed before any are copied, so a directory copied into itself is only copied once.
+        let entries = self
+            .list_directory_entries(from_namespace_id, from.clone())
+            .await?;
+        let mut copied = Vec::new();
+        for entry in entries {
+            let entry_path = self.entry_path(entry.key());
+            let relative_path = entry_path.strip_prefix(&from)?;
+            let destination = to.join(relative_path);
+            if is_directory_marker(entry.key()) {
+                self.create_directory(to_namespace_id, destination).await?;
+                continue;
+            }
+            self.set_entry_content(to_namespace_id, destination.clone(), &entry)
+                .await?;
+            self.copy_metadata(
+                from_namespace_id,
+                entry_path.clone(),
+                to_namespace_id,
+                destination.clone(),
+            )
+            .await?;
+            copied.push((entry_path, destination));
+        }
+        Ok(copied)
+    }
+
+    /// Moves a directory and all its contents by copying them to a new location and deleting the originals.
+    /// The layout of the directory is kept, so files nested within it remain nested at the new location.
+    /// The originals are deleted by every author, as with [`OkuFs::purge_directory`], so that files last written by other authors do not remain visible.
+    /// Unless disabled in the configuration, a hint recording the move of each file is written to the replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory to move.
+    ///
+    /// * `from` - The path of the directory to move.
+    ///
+    /// * `to` - The path to move the directory to.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the original and new paths of each moved file, and the number of replica entries deleted during the operation.
+    #[allow(clippy::type_complexity)]
+    #[instrument(skip_all, fields(%namespace_id, ?from, ?to), err)]
+    pub async fn move_directory(
+        &self,
+        namespace_id: NamespaceId,
+        from: PathBuf,
+        to: PathBuf,
+    ) -> Result<(Vec<(PathBuf, PathBuf)>, usize), Box<dyn Error + Send + Sync>> {
+        let (from, to) = (normalise_path(from), normalise_path(to));
+        if to.starts_with(&from) {
+            return Err(OkuFsError::MoveIntoSelf(
+                from.display().to_string(),
+                to.display().to_string(),
+            )
+            .into());
+        }
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, from.clone(), self.author_id)
+            .await?;
+        let moved = self
+            .copy_directory(namespace_id, from.clone(), namespace_id, to)
+            .await?;
+        // Every author's files were copied, so every author's originals are deleted, as with [`OkuFs::move_file`].
+        let entries_deleted = self
+            .purge_directory(namespace_id, from)
+            .await?
+            .entries_deleted;
+        for (original_path, new_path) in &moved {
+            let entry = self
+                .get_latest_entry(namespace_id, new_path.clone())
+                .await?;
+            self.record_rename(
+                namespace_id,
+                original_path.clone(),
+                new_path.clone(),
+                entry.content_hash(),
+            )
+            .await?;
+        }
+        Ok((moved, entries_deleted))
     }
 
-    /// Moves a file by copying it to a new location and deleting the original.
+    /// Deletes a directory and all its contents.
+    /// Only files written by the file system's author are deleted; use [`OkuFs::purge_directory`] to delete those of other authors.
+    /// If the trash is enabled in the configuration, the files within the directory are first moved to the trash, from which they can be restored with [`OkuFs::restore_from_trash`].
     ///
     /// # Arguments
     ///
-    /// * `namespace_id` - The ID of the replica containing the file to move.
-    ///
-    /// * `from` - The path of the file to move.
+    /// * `namespace_id` - The ID of the replica containing the directory to delete.
     ///
-    /// * `to` - The path to move the file to.
+    /// * `path` - The path of the directory to delete.
     ///
     /// # Returns
     ///
-    /// A tuple containing the hash of the file at the new destination and the number of replica entries deleted during the operation, which should be 1 if the file at the original path was deleted.
-    pub async fn move_file(
+    /// The number of entries deleted.
+    #[instrument(skip_all, fields(%namespace_id, ?path), err)]
+    pub async fn delete_directory(
         &self,
         namespace_id: NamespaceId,
-        from: PathBuf,
-        to: PathBuf,
-    ) -> Result<(Hash, usize), Box<dyn Error + Send + Sync>> {
-        let data = self.read_file(namespace_id, from.clone()).await?;
-        let hash = self
-            .create_or_modify_file(namespace_id, to.clone(), data)
+        path: PathBuf,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
             .await?;
-        let entries_deleted = self.delete_file(namespace_id, from).await?;
-        Ok((hash, entries_deleted))
+        if self.config.trash {
+            self.move_directory_to_trash(namespace_id, path.clone())
+                .await?;
+        }
+        self.remove_directory(namespace_id, path).await
     }
 
-    /// Deletes a directory and all its contents.
+    /// Deletes the file system author's files within a directory, along with their metadata and any writes to them held back.
     ///
     /// # Arguments
     ///
@@ -364,20 +1683,28 @@ impl OkuFs {
     /// # Returns
     ///
     /// The number of entries deleted.
-    pub async fn delete_directory(
+    pub(crate) async fn remove_directory(
         &self,
         namespace_id: NamespaceId,
         path: PathBuf,
     ) -> Result<usize, Box<dyn Error + Send + Sync>> {
         let path = normalise_path(path).join(""); // Ensure path ends with a slash
+        self.discard_pending_writes(namespace_id, path.clone());
         let docs_client = &self.node.docs;
         let document = docs_client
             .open(namespace_id)
             .await?
             .ok_or(OkuFsError::FsEntryNotFound)?;
-        let entries_deleted = document
-            .del(self.author_id, format!("{}", path.display()))
+        let entries_deleted = self
+            .delete_prefix(&document, self.author_id, self.entry_prefix(path.clone()))
+            .await?;
+        self.delete_directory_metadata(namespace_id, path.clone())
             .await?;
+        self.emit(OkuFsEvent::EntryDeleted {
+            namespace_id,
+            path,
+            author: self.author_id,
+        });
         Ok(entries_deleted)
     }
 
@@ -390,17 +1717,25 @@ impl OkuFs {
     /// # Returns
     ///
     /// A response containing a ticket for the content.
+    #[instrument(skip_all, fields(namespace_id = %request.namespace_id, path = ?request.path), err)]
     pub async fn respond_to_content_request(
         &self,
         request: PeerContentRequest,
     ) -> Result<PeerContentResponse, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!(
+            "Responding to a request for replica {}",
+            request.namespace_id
+        ))?;
+        if self.is_replica_private(request.namespace_id)? {
+            return Err(OkuFsError::ReplicaPrivate(request.namespace_id.to_string()).into());
+        }
         let docs_client = &self.node.docs;
         let document = docs_client
             .open(request.namespace_id)
             .await?
             .ok_or(OkuFsError::FsEntryNotFound)?;
-        match request.path {
-            None => {
+        match (request.path, request.patterns.is_empty()) {
+            (None, true) => {
                 let document_ticket = document.share(ShareMode::Read).await?;
                 let query = iroh::sync::store::Query::single_latest_per_key().build();
                 let entries = document.get_many(query).await?;
@@ -411,19 +1746,32 @@ impl OkuFs {
                     .await;
                 let content_length = file_sizes.iter().sum();
                 Ok(PeerContentResponse {
-                    ticket_response: PeerTicketResponse::Document(document_ticket),
+                    ticket_response: PeerTicketResponse::Document(Box::new(document_ticket)),
                     content_size: content_length,
                 })
             }
-            Some(blob_path) => {
+            (blob_path, _) => {
                 let blobs_client = &self.node.blobs;
-                let entry_prefix = path_to_entry_key(blob_path);
-                let query = iroh::sync::store::Query::single_latest_per_key()
-                    .key_prefix(entry_prefix)
-                    .build();
+                let query = match blob_path {
+                    Some(blob_path) => iroh::sync::store::Query::single_latest_per_key()
+                        .key_prefix(self.entry_key(blob_path))
+                        .build(),
+                    None => iroh::sync::store::Query::single_latest_per_key().build(),
+                };
                 let entries = document.get_many(query).await?;
                 pin_mut!(entries);
                 let entry_hashes_and_sizes: Vec<(Hash, u64)> = entries
+                    .filter(|entry| {
+                        let selected = request.patterns.is_empty()
+                            || entry.as_ref().is_ok_and(|entry| {
+                                let path = self.entry_path(entry.key());
+                                request
+                                    .patterns
+                                    .iter()
+                                    .any(|pattern| pattern.matches(&path))
+                            });
+                        futures::future::ready(selected)
+                    })
                     .map(|entry| {
                         (
                             entry.as_ref().unwrap().content_hash(),
@@ -456,7 +1804,7 @@ impl OkuFs {
     }
 
     /// Handles incoming requests for document tickets.
-    /// This function listens for incoming connections from peers and responds to requests for document tickets.
+    /// This function listens for incoming connections from peers and responds to requests for document tickets, and for tickets to published content.
     pub async fn listen_for_document_ticket_fetch_requests(
         &self,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -487,6 +1835,21 @@ impl OkuFs {
                             .write_all(peer_content_response_string.as_bytes())
                             .await?;
                         stream.flush().await?;
+                    } else if first_line == ALPN_BLOB_TICKET_FETCH {
+                        let remaining_lines: Vec<Vec<u8>> =
+                            incoming_lines.map(|x| x.to_owned()).collect();
+                        let blob_ticket_request_bytes = remaining_lines.concat();
+                        let content = serde_json::from_str(
+                            String::from_utf8_lossy(&blob_ticket_request_bytes).as_ref(),
+                        )?;
+                        if let Some(ticket) =
+                            self_clone.respond_to_blob_ticket_request(content).await?
+                        {
+                            stream
+                                .write_all(serde_json::to_string(&ticket)?.as_bytes())
+                                .await?;
+                            stream.flush().await?;
+                        }
                     }
                 }
                 Ok::<(), Box<dyn Error + Send + Sync>>(())
@@ -512,8 +1875,134 @@ impl OkuFs {
         partial: bool,
         verified: bool,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.get_external_replica_with_cancellation(
+            namespace_id,
+            path,
+            partial,
+            verified,
+            CancellationToken::new(),
+        )
+        .await
+    }
+
+    /// Joins a swarm to fetch the latest version of a replica and save it to the local machine, stopping early if cancelled.
+    /// Discovering peers, and requesting the replica from each peer, are abandoned after the configured network timeout.
+    /// If no discovered peer provides the replica and a relay is configured, the replica is requested through the relay instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to fetch.
+    ///
+    /// * `path` - An optional path of requested files within the replica.
+    ///
+    /// * `partial` - Whether to discover peers who claim to only have a partial copy of the replica.
+    ///
+    /// * `verified` - Whether to discover peers who have been verified to have the replica.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the fetch.
+    #[instrument(skip_all, fields(%namespace_id, ?path, partial, verified), err)]
+    pub async fn get_external_replica_with_cancellation(
+        &self,
+        namespace_id: NamespaceId,
+        path: Option<PathBuf>,
+        partial: bool,
+        verified: bool,
+        cancellation: CancellationToken,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Fetching replica {}", namespace_id))?;
+        let peer_content_request = PeerContentRequest {
+            namespace_id,
+            path,
+            patterns: Vec::new(),
+        };
+        self.request_from_peers(peer_content_request, partial, verified, cancellation)
+            .await
+    }
+
+    /// Joins a swarm to fetch the files of a replica matching any of several patterns, such as `/photos/**/*.jpg`.
+    /// Only the content of the matching files is fetched, rather than the replica itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica holding the files.
+    ///
+    /// * `patterns` - The patterns the paths of requested files must match.
+    ///
+    /// * `partial` - Whether to discover peers who claim to only have a partial copy of the replica.
+    ///
+    /// * `verified` - Whether to discover peers who have been verified to have the replica.
+    pub async fn get_external_files_matching(
+        &self,
+        namespace_id: NamespaceId,
+        patterns: Vec<Glob>,
+        partial: bool,
+        verified: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.get_external_files_matching_with_cancellation(
+            namespace_id,
+            patterns,
+            partial,
+            verified,
+            CancellationToken::new(),
+        )
+        .await
+    }
+
+    /// Joins a swarm to fetch the files of a replica matching any of several patterns, stopping early if cancelled.
+    /// Only the content of the matching files is fetched, rather than the replica itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica holding the files.
+    ///
+    /// * `patterns` - The patterns the paths of requested files must match.
+    ///
+    /// * `partial` - Whether to discover peers who claim to only have a partial copy of the replica.
+    ///
+    /// * `verified` - Whether to discover peers who have been verified to have the replica.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the fetch.
+    #[instrument(skip_all, fields(%namespace_id, ?patterns, partial, verified), err)]
+    pub async fn get_external_files_matching_with_cancellation(
+        &self,
+        namespace_id: NamespaceId,
+        patterns: Vec<Glob>,
+        partial: bool,
+        verified: bool,
+        cancellation: CancellationToken,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let peer_content_request = PeerContentRequest {
+            namespace_id,
+            path: None,
+            patterns,
+        };
+        self.request_from_peers(peer_content_request, partial, verified, cancellation)
+            .await
+    }
+
+    /// Discovers peers holding a replica, and requests content from them, falling back to the configured relay.
+    /// A whole replica not held locally is first requested from the preferred peers, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_content_request` - The request to send to each peer.
+    ///
+    /// * `partial` - Whether to discover peers who claim to only have a partial copy of the replica.
+    ///
+    /// * `verified` - Whether to discover peers who have been verified to have the replica.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the fetch.
+    async fn request_from_peers(
+        &self,
+        peer_content_request: PeerContentRequest,
+        partial: bool,
+        verified: bool,
+        cancellation: CancellationToken,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_online("Fetching from peers")?;
+        let namespace_id = peer_content_request.namespace_id;
         let content = ContentRequest::Hash(Hash::new(namespace_id));
-        let dht = mainline::Dht::default();
+        let dht = self.mainline_dht().as_async();
         let q = Query {
             content: content.hash_and_format(),
             flags: QueryFlags {
@@ -522,110 +2011,341 @@ impl OkuFs {
             },
         };
         let info_hash = to_infohash(q.content);
-        let peer_content_request = PeerContentRequest { namespace_id, path };
         let peer_content_request_string = serde_json::to_string(&peer_content_request)?;
-        let docs_client = &self.node.docs;
+
+        let whole_replica =
+            peer_content_request.path.is_none() && peer_content_request.patterns.is_empty();
+        if whole_replica
+            && !self.list_replicas().await?.contains(&namespace_id)
+            && self
+                .fetch_from_preferred_peers(namespace_id, &cancellation)
+                .await?
+        {
+            return Ok(());
+        }
 
         let mut addrs = dht.get_peers(info_hash);
-        for peer_response in &mut addrs {
-            if docs_client.open(namespace_id).await.is_ok() {
-                break;
-            }
-            let peer_content_request_string = peer_content_request_string.clone();
-            let docs_client = docs_client.clone();
-            let self_clone = self.clone();
-            tokio::spawn(async move {
-                let mut stream = TcpStream::connect(peer_response.peer).await?;
-                let mut request = Vec::new();
-                request.write_all(ALPN_DOCUMENT_TICKET_FETCH).await?;
-                request.write_all(b"\n").await?;
-                request
-                    .write_all(peer_content_request_string.as_bytes())
-                    .await?;
-                request.flush().await?;
-                stream.write_all(&request).await?;
-                stream.flush().await?;
-                let mut response_bytes = Vec::new();
-                stream.read_to_end(&mut response_bytes).await?;
-                let response: PeerContentResponse =
-                    serde_json::from_str(String::from_utf8_lossy(&response_bytes).as_ref())?;
-                match response.ticket_response {
-                    PeerTicketResponse::Document(document_ticket) => {
-                        if document_ticket.capability.id() != namespace_id {
-                            return Ok::<(), Box<dyn Error + Send + Sync>>(());
-                        }
-                        // let docs_client = &self.node.docs;
-                        docs_client.import(document_ticket).await?;
-                        Ok::<(), Box<dyn Error + Send + Sync>>(())
+        let mut requests = JoinSet::new();
+        let operation = format!("Discovering peers holding replica {}", namespace_id);
+        let discovered = self
+            .with_network_timeout(&operation, &cancellation, async {
+                while let Some(peer_response) = addrs.next_async().await {
+                    tracing::debug!(peer = %peer_response.peer, "Discovered peer");
+                    if self.fetch_satisfied(namespace_id, whole_replica).await {
+                        break;
                     }
-                    PeerTicketResponse::Entries(entry_tickets) => {
-                        let blobs_client = &self_clone.node.blobs;
-                        for blob_ticket in entry_tickets {
-                            let ticket_parts = blob_ticket.into_parts();
-                            let blob_download_request = BlobDownloadRequest {
-                                hash: ticket_parts.1,
-                                format: ticket_parts.2,
-                                peer: ticket_parts.0,
-                                tag: iroh::rpc_protocol::SetTagOption::Auto,
-                            };
-                            blobs_client.download(blob_download_request).await?;
-                            break;
+                    let peer_content_request_string = peer_content_request_string.clone();
+                    let self_clone = self.clone();
+                    let cancellation = cancellation.clone();
+                    requests.spawn(
+                        async move {
+                            let operation = format!(
+                                "Requesting replica {} from {}",
+                                namespace_id, peer_response.peer
+                            );
+                            self_clone
+                                .with_network_timeout(&operation, &cancellation, async {
+                                    self_clone
+                                        .request_external_replica(
+                                            namespace_id,
+                                            peer_response.peer,
+                                            peer_content_request_string,
+                                        )
+                                        .await
+                                })
+                                .await
                         }
-                        Ok::<(), Box<dyn Error + Send + Sync>>(())
-                    }
+                        .in_current_span(),
+                    );
                 }
-            });
+                Ok(())
+            })
+            .await;
+        let mut fetched = false;
+        while let Some(request) = requests.join_next().await {
+            fetched |= matches!(request, Ok(Ok(())));
+        }
+        if fetched {
+            return Ok(());
         }
 
-        Ok(())
+        // No peer could be reached directly, so the request is made through the relay, which reaches peers connected to it.
+        match &self.config.relay_address {
+            Some(relay_address) => {
+                let operation = format!(
+                    "Requesting replica {} through relay {}",
+                    namespace_id, relay_address
+                );
+                self.with_network_timeout(
+                    &operation,
+                    &cancellation,
+                    self.request_replica_through_relay(
+                        namespace_id,
+                        relay_address,
+                        peer_content_request_string,
+                    ),
+                )
+                .await
+            }
+            None => discovered,
+        }
+    }
+
+    /// Checks whether a fetch from peers has nothing left to do, as it asked for a whole replica that is now held.
+    /// Fetches of particular files within a replica are never satisfied this way, as a replica held locally may still lack their content.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica being fetched.
+    ///
+    /// * `whole_replica` - Whether the whole replica is being fetched.
+    ///
+    /// # Returns
+    ///
+    /// Whether no more peers need to be asked for the replica.
+    async fn fetch_satisfied(&self, namespace_id: NamespaceId, whole_replica: bool) -> bool {
+        whole_replica && matches!(self.node.docs.open(namespace_id).await, Ok(Some(_)))
+    }
+
+    /// Requests a replica, or files within it, from a peer discovered to hold it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to fetch.
+    ///
+    /// * `peer` - The address of the peer.
+    ///
+    /// * `peer_content_request_string` - The serialised request to send to the peer.
+    #[instrument(skip_all, fields(%namespace_id, %peer), err)]
+    async fn request_external_replica(
+        &self,
+        namespace_id: NamespaceId,
+        peer: SocketAddr,
+        peer_content_request_string: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let stream = TcpStream::connect(peer).await?;
+        let response_bytes = send_content_request(stream, &peer_content_request_string).await?;
+        let response: PeerContentResponse =
+            serde_json::from_str(String::from_utf8_lossy(&response_bytes).as_ref())?;
+        self.import_content_response(namespace_id, response).await
+    }
+
+    /// Requests a replica, or files within it, through a relay, which passes the request to a node connected to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to fetch.
+    ///
+    /// * `relay_address` - The address of the relay.
+    ///
+    /// * `peer_content_request_string` - The serialised request to send through the relay.
+    #[instrument(skip_all, fields(%namespace_id, relay = relay_address), err)]
+    async fn request_replica_through_relay(
+        &self,
+        namespace_id: NamespaceId,
+        relay_address: &str,
+        peer_content_request_string: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let relay_addr = relay_address.parse::<SocketAddr>()?;
+        let stream = TcpStream::connect(relay_addr).await.map_err(|e| {
+            OkuRelayError::ProblemConnecting(relay_address.to_string(), e.to_string())
+        })?;
+        let response_bytes = send_content_request(stream, &peer_content_request_string).await?;
+        // The relay closes the connection without responding when no node connected to it can satisfy the request.
+        if response_bytes.is_empty() {
+            return Err(OkuRelayError::CannotSatisfyRequest(namespace_id.to_string()).into());
+        }
+        let response: PeerContentResponse =
+            serde_json::from_str(String::from_utf8_lossy(&response_bytes).as_ref())?;
+        self.import_content_response(namespace_id, response).await
+    }
+
+    /// Saves the content a peer responded to a request with.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the requested replica.
+    ///
+    /// * `response` - The peer's response to the request.
+    #[instrument(skip_all, fields(%namespace_id), err)]
+    async fn import_content_response(
+        &self,
+        namespace_id: NamespaceId,
+        response: PeerContentResponse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match response.ticket_response {
+            PeerTicketResponse::Document(document_ticket) => {
+                if document_ticket.capability.id() != namespace_id {
+                    return Ok(());
+                }
+                let document = self.import_ticket(*document_ticket).await?;
+                self.notify_observers(|observer| observer.on_sync_start(namespace_id));
+                self.emit(OkuFsEvent::ReplicaImported(namespace_id));
+                self.forward_remote_events(document).await?;
+                Ok(())
+            }
+            PeerTicketResponse::Entries(entry_tickets) => {
+                let blobs_client = &self.node.blobs;
+                let _sync_slot = self.acquire_sync_slot().await?;
+                for blob_ticket in entry_tickets {
+                    let (peer, hash, format) = blob_ticket.into_parts();
+                    // The content is only tagged while downloading, as the replica refers to it once held.
+                    let tag = Tag::from(format!("oku-download-{}", hash));
+                    let blob_download_request = BlobDownloadRequest {
+                        hash,
+                        format,
+                        peer,
+                        tag: iroh::rpc_protocol::SetTagOption::Named(tag.clone()),
+                    };
+                    let outcome = blobs_client
+                        .download(blob_download_request)
+                        .await?
+                        .finish()
+                        .await;
+                    self.node.tags.delete(tag).await?;
+                    outcome?;
+                }
+                Ok(())
+            }
+        }
     }
 
     /// Connects to a relay to facilitate communication behind NAT.
-    /// Upon connecting, the file system will send a list of all replicas to the relay. Periodically, the relay will request the list of replicas again using the same connection.
+    /// Upon connecting, the file system will send a list of all replicas to the relay. The list is sent again whenever a replica is added, removed, or made private or public, and, using the same connection, the relay periodically requests the list again and passes on requests for content from external nodes.
     ///
     /// # Arguments
     ///
     /// * `relay_address` - The address of the relay to connect to.
+    #[instrument(skip_all, fields(relay = relay_address), err)]
     pub async fn connect_to_relay(
         &self,
         relay_address: String,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Connecting to relay {}", relay_address))?;
         let relay_addr = relay_address.parse::<SocketAddr>()?;
-        let mut stream = TcpStream::connect(relay_addr).await?;
-        let all_replicas = self.list_replicas().await?;
-        let all_replicas_str = serde_json::to_string(&all_replicas)?;
+        let stream = TcpStream::connect(relay_addr).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut events = self.subscribe();
+        let all_replicas = self.list_public_replicas().await?;
         let mut request = Vec::new();
         request.write_all(ALPN_INITIAL_RELAY_CONNECTION).await?;
         request.write_all(b"\n").await?;
-        request.write_all(all_replicas_str.as_bytes()).await?;
+        request
+            .write_all(serde_json::to_string(&RelayResponse::Replicas(all_replicas))?.as_bytes())
+            .await?;
+        request.write_all(b"\n").await?;
         request.flush().await?;
-        stream.write_all(&request).await?;
-        stream.flush().await?;
+        writer.write_all(&request).await?;
+        writer.flush().await?;
+        let mut lines = BufReader::new(reader).lines();
         loop {
-            let mut response_bytes = Vec::new();
-            stream.read_to_end(&mut response_bytes).await?;
-            if response_bytes == ALPN_RELAY_FETCH {
-                let all_replicas = self.list_replicas().await?;
-                let all_replicas_str = serde_json::to_string(&all_replicas)?;
-                stream.write_all(all_replicas_str.as_bytes()).await?;
-                stream.flush().await?;
-            }
+            let relay_response = tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line? else {
+                        break;
+                    };
+                    match serde_json::from_str(&line)? {
+                        RelayRequest::ListReplicas => {
+                            RelayResponse::Replicas(self.list_public_replicas().await?)
+                        }
+                        RelayRequest::Content(request_id, peer_content_request) => {
+                            match self.respond_to_content_request(peer_content_request).await {
+                                Ok(peer_content_response) => {
+                                    RelayResponse::Content(request_id, Box::new(peer_content_response))
+                                }
+                                Err(e) => RelayResponse::ContentError(request_id, e.to_string()),
+                            }
+                        }
+                    }
+                }
+                // The relay is told of replicas as they are added or removed, rather than only when it next asks.
+                event = events.recv() => match event {
+                    Ok(OkuFsEvent::ReplicaCreated(_))
+                    | Ok(OkuFsEvent::ReplicaImported(_))
+                    | Ok(OkuFsEvent::ReplicaDeleted(_))
+                    | Ok(OkuFsEvent::ReplicaPrivacyChanged { .. })
+                    | Err(broadcast::error::RecvError::Lagged(_)) => {
+                        RelayResponse::Replicas(self.list_public_replicas().await?)
+                    }
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            let mut relay_response_string = serde_json::to_string(&relay_response)?;
+            relay_response_string.push('\n');
+            writer.write_all(relay_response_string.as_bytes()).await?;
+            writer.flush().await?;
         }
         Ok(())
     }
 }
 
+/// Sends a request for content to a peer or relay, and waits for its response.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the peer or relay.
+///
+/// * `peer_content_request_string` - The serialised request.
+///
+/// # Returns
+///
+/// The bytes of the response, which are empty if the request could not be satisfied.
+pub(crate) async fn send_content_request(
+    stream: TcpStream,
+    peer_content_request_string: &str,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    send_request(
+        stream,
+        ALPN_DOCUMENT_TICKET_FETCH,
+        peer_content_request_string,
+    )
+    .await
+}
+
+/// Sends a request to a peer or relay using one of the file system's protocols, and waits for its response.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the peer or relay.
+///
+/// * `alpn` - The identifier of the protocol the request is made with.
+///
+/// * `request_string` - The serialised request.
+///
+/// # Returns
+///
+/// The bytes of the response, which are empty if the request could not be satisfied.
+pub(crate) async fn send_request(
+    mut stream: TcpStream,
+    alpn: &[u8],
+    request_string: &str,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut request = Vec::new();
+    request.write_all(alpn).await?;
+    request.write_all(b"\n").await?;
+    request.write_all(request_string.as_bytes()).await?;
+    request.flush().await?;
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+    let mut response_bytes = Vec::new();
+    stream.read_to_end(&mut response_bytes).await?;
+    Ok(response_bytes)
+}
+
 /// Imports the author credentials of the file system from disk, or creates new credentials if none exist.
 ///
 /// # Arguments
 ///
-/// * `path` - The path on disk of the file holding the author's credentials.
+/// * `path` - The path on disk where the file system is stored.
 ///
 /// # Returns
 ///
 /// The author credentials.
-pub fn load_or_create_author() -> Result<Author, Box<dyn Error + Send + Sync>> {
-    let path = PathBuf::from(FS_PATH).join("author");
+pub fn load_or_create_author(
+    path: impl AsRef<Path>,
+) -> Result<Author, Box<dyn Error + Send + Sync>> {
+    let path = path.as_ref().join("author");
     let author_file = std::fs::read(path.clone());
     match author_file {
         Ok(bytes) => Ok(Author::from_bytes(&bytes[..32].try_into()?)),
@@ -641,21 +2361,455 @@ pub fn load_or_create_author() -> Result<Author, Box<dyn Error + Send + Sync>> {
 
 /// Loads the configuration of the file system from disk, or creates a new configuration if none exists.
 ///
+/// # Arguments
+///
+/// * `path` - The path on disk where the file system is stored.
+///
 /// # Returns
 ///
 /// The configuration of the file system.
-pub fn load_or_create_config() -> Result<OkuFsConfig, Box<dyn Error + Send + Sync>> {
-    let path = PathBuf::from(FS_PATH).join("config");
-    let config_file_contents = std::fs::read_to_string(path.clone());
+pub fn load_or_create_config(
+    path: impl AsRef<Path>,
+) -> Result<OkuFsConfig, Box<dyn Error + Send + Sync>> {
+    let path = path.as_ref();
+    let config_path = path.join("config");
+    let config_file_contents = std::fs::read_to_string(config_path.clone());
     match config_file_contents {
-        Ok(config_file_toml) => Ok(toml::from_str(&config_file_toml)?),
+        Ok(config_file_toml) => {
+            let mut config: OkuFsConfig = toml::from_str(&config_file_toml)?;
+            config.path = path.to_path_buf();
+            Ok(config)
+        }
         Err(_) => {
-            let config = OkuFsConfig {
-                relay_address: None,
-            };
+            let config = OkuFsConfig::builder().path(path).build();
             let config_toml = toml::to_string(&config)?;
-            std::fs::write(path, config_toml)?;
+            std::fs::create_dir_all(path)?;
+            std::fs::write(config_path, config_toml)?;
             Ok(config)
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    /// Starts a file system kept in memory, without networking, which keeps deleted files in the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A name for the file system, unique among the tests.
+    ///
+    /// # Returns
+    ///
+    /// The file system.
+    pub(crate) async fn start_test_fs(name: &str) -> OkuFs {
+        let path =
+            std::env::temp_dir().join(format!("oku-fs-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        let config = OkuFsConfig::builder()
+            .path(path)
+            .offline(true)
+            .trash(true)
+            .storage_backend(MemoryStorage)
+            .build();
+        OkuFs::start(&config).await.unwrap()
+    }
+
+    /// Lists the paths of the files and directories within a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `oku_fs` - The file system.
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// The sorted paths of the files and directory markers that have not been deleted.
+    async fn list_paths(oku_fs: &OkuFs, namespace_id: NamespaceId, path: &str) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = oku_fs
+            .list_directory_entries(namespace_id, PathBuf::from(path))
+            .await
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.content_len() > 0)
+            .map(|entry| match is_directory_marker(entry.key()) {
+                true => oku_fs.entry_path(entry.key()).join(""),
+                false => oku_fs.entry_path(entry.key()),
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Writes a nested tree of files and an empty directory under `/a`.
+    ///
+    /// # Arguments
+    ///
+    /// * `oku_fs` - The file system.
+    ///
+    /// * `namespace_id` - The ID of the replica to write the tree to.
+    async fn write_nested_tree(oku_fs: &OkuFs, namespace_id: NamespaceId) {
+        for (path, content) in [
+            ("/a/top.txt", "top"),
+            ("/a/b/middle.txt", "middle"),
+            ("/a/b/c/bottom.txt", "bottom"),
+        ] {
+            oku_fs
+                .create_or_modify_file(namespace_id, PathBuf::from(path), content)
+                .await
+                .unwrap();
+        }
+        oku_fs
+            .create_directory(namespace_id, PathBuf::from("/a/b/empty"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn create_and_list_nested_directories() {
+        let oku_fs = start_test_fs("create").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        write_nested_tree(&oku_fs, namespace_id).await;
+        assert_eq!(
+            list_paths(&oku_fs, namespace_id, "/a").await,
+            [
+                "/a/b/c/bottom.txt",
+                "/a/b/empty/",
+                "/a/b/middle.txt",
+                "/a/top.txt"
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(
+            list_paths(&oku_fs, namespace_id, "/a/b/c").await,
+            [PathBuf::from("/a/b/c/bottom.txt")]
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn copy_nested_directory() {
+        let oku_fs = start_test_fs("copy").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        write_nested_tree(&oku_fs, namespace_id).await;
+        let copied = oku_fs
+            .copy_directory(
+                namespace_id,
+                PathBuf::from("/a"),
+                namespace_id,
+                PathBuf::from("/z"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(copied.len(), 3);
+        assert_eq!(
+            list_paths(&oku_fs, namespace_id, "/z").await,
+            [
+                "/z/b/c/bottom.txt",
+                "/z/b/empty/",
+                "/z/b/middle.txt",
+                "/z/top.txt"
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(
+            oku_fs
+                .read_file(namespace_id, PathBuf::from("/z/b/c/bottom.txt"))
+                .await
+                .unwrap(),
+            "bottom"
+        );
+        // The originals are left in place.
+        assert_eq!(list_paths(&oku_fs, namespace_id, "/a").await.len(), 4);
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn move_nested_directory() {
+        let oku_fs = start_test_fs("move").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        write_nested_tree(&oku_fs, namespace_id).await;
+        let (moved, _) = oku_fs
+            .move_directory(namespace_id, PathBuf::from("/a/b"), PathBuf::from("/y"))
+            .await
+            .unwrap();
+        assert_eq!(moved.len(), 2);
+        assert_eq!(
+            list_paths(&oku_fs, namespace_id, "/y").await,
+            ["/y/c/bottom.txt", "/y/empty/", "/y/middle.txt"].map(PathBuf::from)
+        );
+        assert_eq!(
+            list_paths(&oku_fs, namespace_id, "/a").await,
+            [PathBuf::from("/a/top.txt")]
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn delete_nested_directory() {
+        let oku_fs = start_test_fs("delete").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        write_nested_tree(&oku_fs, namespace_id).await;
+        oku_fs
+            .delete_directory(namespace_id, PathBuf::from("/a/b"))
+            .await
+            .unwrap();
+        assert_eq!(
+            list_paths(&oku_fs, namespace_id, "/a").await,
+            [PathBuf::from("/a/top.txt")]
+        );
+        assert!(oku_fs
+            .read_file(namespace_id, PathBuf::from("/a/b/c/bottom.txt"))
+            .await
+            .is_err());
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn move_file_onto_itself() {
+        let oku_fs = start_test_fs("move-self").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a.txt"), "a")
+            .await
+            .unwrap();
+        let (_, entries_deleted) = oku_fs
+            .move_file(
+                namespace_id,
+                PathBuf::from("/a.txt"),
+                PathBuf::from("a.txt"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(entries_deleted, 0);
+        assert_eq!(
+            oku_fs
+                .read_file(namespace_id, PathBuf::from("/a.txt"))
+                .await
+                .unwrap(),
+            "a"
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn move_file_written_by_another_author() {
+        let oku_fs = start_test_fs("move-author").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let author_id = oku_fs.create_author().await.unwrap();
+        oku_fs
+            .create_or_modify_file_as(namespace_id, PathBuf::from("/a.txt"), "a", author_id)
+            .await
+            .unwrap();
+        oku_fs
+            .move_file(
+                namespace_id,
+                PathBuf::from("/a.txt"),
+                PathBuf::from("/b.txt"),
+            )
+            .await
+            .unwrap();
+        assert!(!oku_fs
+            .exists(namespace_id, PathBuf::from("/a.txt"))
+            .await
+            .unwrap());
+        assert_eq!(
+            oku_fs
+                .read_file(namespace_id, PathBuf::from("/b.txt"))
+                .await
+                .unwrap(),
+            "a"
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn move_directory_written_by_another_author() {
+        let oku_fs = start_test_fs("move-directory-author").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let author_id = oku_fs.create_author().await.unwrap();
+        oku_fs
+            .create_or_modify_file_as(namespace_id, PathBuf::from("/a/b.txt"), "b", author_id)
+            .await
+            .unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a/c.txt"), "c")
+            .await
+            .unwrap();
+        oku_fs
+            .move_directory(namespace_id, PathBuf::from("/a"), PathBuf::from("/z"))
+            .await
+            .unwrap();
+        assert!(list_paths(&oku_fs, namespace_id, "/a").await.is_empty());
+        assert_eq!(
+            list_paths(&oku_fs, namespace_id, "/z").await,
+            ["/z/b.txt", "/z/c.txt"].map(PathBuf::from)
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_file_written_by_another_author() {
+        let oku_fs = start_test_fs("read-author").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let author_id = oku_fs.create_author().await.unwrap();
+        oku_fs
+            .create_or_modify_file_as(namespace_id, PathBuf::from("/a.txt"), "a", author_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            oku_fs
+                .read_file(namespace_id, PathBuf::from("/a.txt"))
+                .await
+                .unwrap(),
+            "a"
+        );
+        // The latest version is read, whoever wrote it.
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a.txt"), "b")
+            .await
+            .unwrap();
+        assert_eq!(
+            oku_fs
+                .read_file(namespace_id, PathBuf::from("/a.txt"))
+                .await
+                .unwrap(),
+            "b"
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fetch_files_from_held_replica() {
+        let oku_fs = start_test_fs("fetch").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        // A held replica need not be fetched again as a whole, but files matching patterns within it must still be requested from peers.
+        assert!(oku_fs.fetch_satisfied(namespace_id, true).await);
+        assert!(!oku_fs.fetch_satisfied(namespace_id, false).await);
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn trash_deleted_directory_but_not_moved_files() {
+        let oku_fs = start_test_fs("trash").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        write_nested_tree(&oku_fs, namespace_id).await;
+        oku_fs
+            .move_file(
+                namespace_id,
+                PathBuf::from("/a/top.txt"),
+                PathBuf::from("/top.txt"),
+            )
+            .await
+            .unwrap();
+        oku_fs
+            .move_directory(namespace_id, PathBuf::from("/a/b/c"), PathBuf::from("/c"))
+            .await
+            .unwrap();
+        assert!(oku_fs.list_trash(namespace_id).await.unwrap().is_empty());
+        oku_fs
+            .delete_directory(namespace_id, PathBuf::from("/a"))
+            .await
+            .unwrap();
+        let trashed: Vec<PathBuf> = oku_fs
+            .list_trash(namespace_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|item| item.path)
+            .collect();
+        assert_eq!(trashed, [PathBuf::from("/a/b/middle.txt")]);
+        oku_fs.shutdown();
+    }
+
+    #[test]
+    fn rename_hint_key_round_trip() {
+        let key = rename_hint_key(PathBuf::from("a/b.txt"), PathBuf::from("/c/./d.txt"));
+        assert!(is_metadata_key(&key));
+        assert_eq!(
+            parse_rename_hint_key(&key),
+            Some((PathBuf::from("/a/b.txt"), PathBuf::from("/c/d.txt")))
+        );
+        let renames = format!("{}/renames", METADATA_DIRECTORY);
+        for key in [
+            KeyCodec::NullTerminated.encode(PathBuf::from("/a.txt")),
+            Bytes::from(renames.clone()),
+            Bytes::from(format!("{}/a.txt", renames)),
+            Bytes::from(format!("{}a.txt\0/b.txt\0", renames)),
+            Bytes::from(format!("{}/a.txt\0b.txt\0", renames)),
+        ] {
+            assert_eq!(parse_rename_hint_key(&key), None, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn key_codec_round_trip() {
+        for key_codec in [KeyCodec::NullTerminated, KeyCodec::Utf8] {
+            for path in ["/", "/a.txt", "a/b/c.txt", "/a/./b/../c.txt", "/été/ü.txt"] {
+                let key = key_codec.encode(PathBuf::from(path));
+                assert_eq!(
+                    key_codec.decode(&key),
+                    normalise_path(PathBuf::from(path)),
+                    "{:?} {}",
+                    key_codec,
+                    path
+                );
+            }
+        }
+        assert_eq!(
+            KeyCodec::NullTerminated.encode(PathBuf::from("a/b")),
+            Bytes::from("/a/b\0")
+        );
+        assert_eq!(
+            KeyCodec::Utf8.encode(PathBuf::from("a/b")),
+            Bytes::from("/a/b")
+        );
+    }
+
+    #[test]
+    fn key_prefix_matches_directory_contents() {
+        for key_codec in [KeyCodec::NullTerminated, KeyCodec::Utf8] {
+            let prefix = key_codec.encode_prefix(PathBuf::from("/a"));
+            assert!(key_codec
+                .encode(PathBuf::from("/a/b.txt"))
+                .starts_with(&prefix));
+            assert!(key_codec
+                .encode(PathBuf::from("/a/b/c.txt"))
+                .starts_with(&prefix));
+            assert!(!key_codec
+                .encode(PathBuf::from("/ab.txt"))
+                .starts_with(&prefix));
+            assert!(!key_codec.encode(PathBuf::from("/a")).starts_with(&prefix));
+            let root_prefix = key_codec.encode_prefix(PathBuf::from("/"));
+            assert!(key_codec
+                .encode(PathBuf::from("/a.txt"))
+                .starts_with(&root_prefix));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refuse_dht_access_offline() {
+        let oku_fs = start_test_fs("offline-dht").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let error = oku_fs.announce_now().await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::Offline(_))
+        ));
+        let error = oku_fs
+            .get_external_files_matching(namespace_id, Vec::new(), false, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::Offline(_))
+        ));
+        oku_fs.shutdown();
+    }
+}
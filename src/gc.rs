@@ -0,0 +1,205 @@
+use crate::fs::OkuFs;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::{hashseq::HashSeq, BlobFormat, Hash},
+    client::Entry,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+/// The prefix of the names of tags created automatically by the local content store.
+const AUTO_TAG_PREFIX: &[u8] = b"auto-";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What was removed from the local content store by garbage collection.
+pub struct GcReport {
+    /// The number of pieces of content removed.
+    pub removed_blobs: usize,
+    /// The number of bytes reclaimed.
+    pub reclaimed_bytes: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// A description of the content held in the local content store.
+pub struct BlobStoreStats {
+    /// The number of pieces of content held in full.
+    pub blobs: usize,
+    /// The size, in bytes, of the content held in full.
+    pub bytes: u64,
+    /// The number of pieces of content held in part, such as while being downloaded.
+    pub incomplete_blobs: usize,
+    /// The number of bytes held of the content held in part.
+    pub incomplete_bytes: u64,
+    /// The number of pieces of content held in full that no version of any entry, snapshot, or tag refers to.
+    pub orphaned_blobs: usize,
+    /// The size, in bytes, of the orphaned content.
+    pub orphaned_bytes: u64,
+}
+
+impl OkuFs {
+    /// Removes content from the local content store that no entry needs any more, such as the content of deleted or overwritten files.
+    /// Content kept by a named tag or by publishing is never removed, nor is the content of any version of a file in a pinned replica.
+    /// Content written since the node started is kept by the local store until the node restarts, so is only removed by a later collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `history_depth` - The number of superseded versions of each entry whose content is kept, in addition to the latest version.
+    ///
+    /// # Returns
+    ///
+    /// The number of pieces of content removed, and the bytes reclaimed.
+    pub async fn gc(&self, history_depth: usize) -> Result<GcReport, Box<dyn Error + Send + Sync>> {
+        let referenced_hashes = self.referenced_hashes(history_depth).await?;
+        let unreferenced_blobs: Vec<(Hash, u64)> = self
+            .blob_sizes()
+            .await?
+            .into_iter()
+            .filter(|(hash, _)| !referenced_hashes.contains(hash))
+            .collect();
+        self.remove_blobs(unreferenced_blobs).await
+    }
+
+    /// Describes the content held in the local content store, including the content orphaned by no longer being referred to, so that the space it takes can be monitored.
+    ///
+    /// # Returns
+    ///
+    /// The number and size of the pieces of content held, in full and in part, and of those orphaned.
+    pub async fn blob_store_stats(&self) -> Result<BlobStoreStats, Box<dyn Error + Send + Sync>> {
+        let referenced_hashes = self.referenced_hashes(usize::MAX).await?;
+        let mut stats = BlobStoreStats::default();
+        for (hash, size) in self.blob_sizes().await? {
+            stats.blobs += 1;
+            stats.bytes += size;
+            if !referenced_hashes.contains(&hash) {
+                stats.orphaned_blobs += 1;
+                stats.orphaned_bytes += size;
+            }
+        }
+        let incomplete_blobs = self.node.blobs.list_incomplete().await?;
+        pin_mut!(incomplete_blobs);
+        while let Some(incomplete_blob) = incomplete_blobs.next().await {
+            stats.incomplete_blobs += 1;
+            stats.incomplete_bytes += incomplete_blob?.size;
+        }
+        Ok(stats)
+    }
+
+    /// Removes only the content orphaned by no longer being referred to by any version of any entry, by a snapshot, or by a tag.
+    /// Unlike [`OkuFs::gc`], the content of superseded versions is always kept, so no file's history is lost.
+    /// Content written since the node started is kept by the local store until the node restarts, so is only removed by a later cleanup.
+    ///
+    /// # Returns
+    ///
+    /// The number of pieces of content removed, and the bytes reclaimed.
+    pub async fn remove_orphaned_blobs(&self) -> Result<GcReport, Box<dyn Error + Send + Sync>> {
+        let referenced_hashes = self.referenced_hashes(usize::MAX).await?;
+        let orphaned_blobs: Vec<(Hash, u64)> = self
+            .blob_sizes()
+            .await?
+            .into_iter()
+            .filter(|(hash, _)| !referenced_hashes.contains(hash))
+            .collect();
+        self.remove_blobs(orphaned_blobs).await
+    }
+
+    /// Finds the content still needed by the file system.
+    ///
+    /// # Arguments
+    ///
+    /// * `history_depth` - The number of superseded versions of each entry whose content is needed, in addition to the latest version.
+    ///
+    /// # Returns
+    ///
+    /// The hashes of the content needed by entries, snapshots, and tags.
+    async fn referenced_hashes(
+        &self,
+        history_depth: usize,
+    ) -> Result<HashSet<Hash>, Box<dyn Error + Send + Sync>> {
+        let mut referenced_hashes = HashSet::new();
+        let pinned_replicas = self.list_pinned_replicas()?;
+        for namespace_id in self.list_replicas().await? {
+            let kept_versions = match pinned_replicas.contains(&namespace_id) {
+                true => usize::MAX,
+                false => history_depth.saturating_add(1),
+            };
+            let document = self.open_document(namespace_id).await?;
+            let query = iroh::sync::store::Query::all().include_empty().build();
+            let entries = document.get_many(query).await?;
+            pin_mut!(entries);
+            let mut versions: HashMap<Vec<u8>, Vec<Entry>> = HashMap::new();
+            while let Some(entry) = entries.next().await {
+                let entry = entry?;
+                versions
+                    .entry(entry.key().to_vec())
+                    .or_default()
+                    .push(entry);
+            }
+            for mut entry_versions in versions.into_values() {
+                entry_versions.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp()));
+                referenced_hashes.extend(
+                    entry_versions
+                        .iter()
+                        .take(kept_versions)
+                        .filter(|entry| entry.content_len() > 0)
+                        .map(|entry| entry.content_hash()),
+                );
+            }
+            referenced_hashes.extend(self.snapshot_hashes(namespace_id).await?);
+        }
+
+        let published_tags = self.published_tags()?;
+        let tags = self.node.tags.list().await?;
+        pin_mut!(tags);
+        while let Some(tag) = tags.next().await {
+            let tag = tag?;
+            // Tags created automatically only keep content while it is published; any others were left by content added or fetched before an entry referred to it.
+            let tag_name: &[u8] = tag.name.borrow();
+            if tag_name.starts_with(AUTO_TAG_PREFIX)
+                && !published_tags.contains(String::from_utf8_lossy(tag_name).as_ref())
+            {
+                continue;
+            }
+            referenced_hashes.insert(tag.hash);
+            // A tagged collection keeps every piece of content within it, not just the list of their hashes.
+            if tag.format == BlobFormat::HashSeq {
+                if let Ok(hash_seq) = self.node.blobs.read_to_bytes(tag.hash).await {
+                    referenced_hashes.extend(HashSeq::try_from(hash_seq)?);
+                }
+            }
+        }
+        Ok(referenced_hashes)
+    }
+
+    /// Removes content from the local content store.
+    ///
+    /// # Arguments
+    ///
+    /// * `blobs` - The hash and size of each piece of content to remove.
+    ///
+    /// # Returns
+    ///
+    /// The number of pieces of content removed, and the bytes reclaimed.
+    async fn remove_blobs(
+        &self,
+        blobs: Vec<(Hash, u64)>,
+    ) -> Result<GcReport, Box<dyn Error + Send + Sync>> {
+        for (hash, _) in blobs.iter() {
+            self.node.blobs.delete_blob(*hash).await?;
+        }
+
+        // The store declines to delete content written since the node started, so only content actually gone is reported.
+        let remaining_blobs = self.blob_sizes().await?;
+        let mut report = GcReport::default();
+        for (hash, size) in blobs {
+            if !remaining_blobs.contains_key(&hash) {
+                report.removed_blobs += 1;
+                report.reclaimed_bytes += size;
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,396 @@
+use crate::error::OkuFsError;
+use crate::fs::KeyCodec;
+use iroh::sync::store::FilterKind;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+/// A pattern matching the paths of files, such as `/photos/**/*.jpg`.
+///
+/// Within a path component, `*` matches any run of characters, `?` matches any one character, and `[...]` matches any one of the characters listed, which may include ranges such as `a-z` and may be negated with a leading `!`.
+/// A component of `**` matches any number of directories, including none, and `{a,b}` matches either alternative.
+/// Patterns are relative to the root of a replica, whether or not they begin with `/`.
+pub struct Glob {
+    /// The pattern as given.
+    pattern: String,
+    /// The path components of each alternative the pattern's braces expand to.
+    alternatives: Vec<Vec<String>>,
+}
+
+impl Glob {
+    /// Parses a glob pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern, such as `/photos/**/*.jpg`.
+    ///
+    /// # Returns
+    ///
+    /// The pattern, ready to match paths against.
+    pub fn new(pattern: &str) -> Result<Glob, OkuFsError> {
+        let invalid =
+            |reason: &str| OkuFsError::InvalidGlob(pattern.to_string(), reason.to_string());
+        let alternatives = expand_braces(pattern).ok_or_else(|| invalid("unbalanced braces"))?;
+        let alternatives: Vec<Vec<String>> = alternatives
+            .iter()
+            .map(|alternative| {
+                alternative
+                    .split('/')
+                    .filter(|component| !component.is_empty() && *component != ".")
+                    .map(|component| component.to_string())
+                    .collect()
+            })
+            .collect();
+        for component in alternatives.iter().flatten() {
+            if component == ".." {
+                return Err(invalid("patterns cannot refer to parent directories"));
+            }
+            if component.contains("**") && component != "**" {
+                return Err(invalid("`**` must be a whole path component"));
+            }
+            if !brackets_balanced(component) {
+                return Err(invalid("unbalanced brackets"));
+            }
+        }
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            alternatives,
+        })
+    }
+
+    /// Gets the pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Checks whether a path matches the pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of a file, relative to the root of a replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the path matches any alternative of the pattern.
+    pub fn matches(&self, path: &Path) -> bool {
+        let components: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        self.alternatives
+            .iter()
+            .any(|alternative| match_components(alternative, &components))
+    }
+
+    /// Converts the pattern into download filters selecting the entries which could match it.
+    /// Parts of the pattern with wildcards are selected by the prefix of the directory they lie beneath, so the filters may select more than the pattern matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_codec` - The scheme used to encode paths as the keys of entries.
+    ///
+    /// # Returns
+    ///
+    /// An exact filter for each alternative without wildcards, and a prefix filter for each other alternative.
+    pub fn filters(&self, key_codec: &KeyCodec) -> Vec<FilterKind> {
+        let mut filters = Vec::new();
+        for alternative in self.alternatives.iter() {
+            let literal_components: Vec<&String> = alternative
+                .iter()
+                .take_while(|component| !has_wildcards(component))
+                .collect();
+            let path: PathBuf = std::iter::once("/")
+                .chain(
+                    literal_components
+                        .iter()
+                        .map(|component| component.as_str()),
+                )
+                .collect();
+            let filter = match literal_components.len() == alternative.len() {
+                true => FilterKind::Exact(key_codec.encode(path)),
+                false => FilterKind::Prefix(key_codec.encode_prefix(path)),
+            };
+            if !filters.contains(&filter) {
+                filters.push(filter);
+            }
+        }
+        filters
+    }
+}
+
+impl FromStr for Glob {
+    type Err = OkuFsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Glob::new(s)
+    }
+}
+
+impl TryFrom<String> for Glob {
+    type Error = OkuFsError;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Glob::new(&pattern)
+    }
+}
+
+impl From<Glob> for String {
+    fn from(glob: Glob) -> Self {
+        glob.pattern
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+/// Expands the braces in a pattern into the alternatives they describe.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern.
+///
+/// # Returns
+///
+/// Each alternative, or `None` if the braces are unbalanced.
+fn expand_braces(pattern: &str) -> Option<Vec<String>> {
+    let Some(open) = pattern.find('{') else {
+        return match pattern.contains('}') {
+            true => None,
+            false => Some(vec![pattern.to_string()]),
+        };
+    };
+    let (before, rest) = pattern.split_at(open);
+    let mut depth = 0;
+    let mut options = Vec::new();
+    let mut option_start = 1;
+    for (index, character) in rest.char_indices() {
+        match character {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    options.push(&rest[option_start..index]);
+                    let after = &rest[index + 1..];
+                    let mut expanded = Vec::new();
+                    for option in options {
+                        let alternative = format!("{}{}{}", before, option, after);
+                        expanded.extend(expand_braces(&alternative)?);
+                    }
+                    return Some(expanded);
+                }
+            }
+            ',' if depth == 1 => {
+                options.push(&rest[option_start..index]);
+                option_start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Checks whether every bracket in a path component is closed.
+fn brackets_balanced(component: &str) -> bool {
+    let mut open = false;
+    for character in component.chars() {
+        match character {
+            '[' if !open => open = true,
+            ']' if open => open = false,
+            _ => {}
+        }
+    }
+    !open
+}
+
+/// Checks whether a path component of a pattern contains wildcards.
+fn has_wildcards(component: &str) -> bool {
+    component.contains(['*', '?', '['])
+}
+
+/// Matches the path components of a pattern against those of a path.
+///
+/// # Arguments
+///
+/// * `pattern` - The components of the pattern.
+///
+/// * `path` - The components of the path.
+///
+/// # Returns
+///
+/// Whether the path matches the pattern.
+fn match_components(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skipped| match_components(rest, &path[skipped..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((component, path_rest)) => {
+                let pattern_chars: Vec<char> = first.chars().collect();
+                let component_chars: Vec<char> = component.chars().collect();
+                match_component(&pattern_chars, &component_chars)
+                    && match_components(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Matches a single path component of a pattern against that of a path.
+///
+/// # Arguments
+///
+/// * `pattern` - The characters of the pattern's component.
+///
+/// * `name` - The characters of the path's component.
+///
+/// # Returns
+///
+/// Whether the name matches the pattern.
+fn match_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => {
+            (0..=name.len()).any(|skipped| match_component(rest, &name[skipped..]))
+        }
+        Some(('?', rest)) => !name.is_empty() && match_component(rest, &name[1..]),
+        Some(('[', rest)) => {
+            let Some(close) = rest.iter().skip(1).position(|c| *c == ']').map(|i| i + 1) else {
+                return false;
+            };
+            let Some((character, name_rest)) = name.split_first() else {
+                return false;
+            };
+            let (negated, class) = match rest[..close].split_first() {
+                Some(('!', class)) => (true, class),
+                _ => (false, &rest[..close]),
+            };
+            let mut in_class = false;
+            let mut index = 0;
+            while index < class.len() {
+                if index + 2 < class.len() && class[index + 1] == '-' {
+                    in_class |= (class[index]..=class[index + 2]).contains(character);
+                    index += 3;
+                } else {
+                    in_class |= class[index] == *character;
+                    index += 1;
+                }
+            }
+            in_class != negated && match_component(&rest[close + 1..], name_rest)
+        }
+        Some((literal, rest)) => name.first() == Some(literal) && match_component(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks which paths a pattern matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern.
+    ///
+    /// * `matching` - Paths the pattern should match.
+    ///
+    /// * `not_matching` - Paths the pattern should not match.
+    fn assert_matches(pattern: &str, matching: &[&str], not_matching: &[&str]) {
+        let glob = Glob::new(pattern).unwrap();
+        for path in matching {
+            assert!(glob.matches(Path::new(path)), "{} {}", pattern, path);
+        }
+        for path in not_matching {
+            assert!(!glob.matches(Path::new(path)), "{} {}", pattern, path);
+        }
+    }
+
+    #[test]
+    fn match_wildcards() {
+        assert_matches(
+            "/photos/*.jpg",
+            &["/photos/a.jpg", "/photos/.jpg", "photos/b.jpg"],
+            &["/photos/a.png", "/photos/a/b.jpg", "/a.jpg"],
+        );
+        assert_matches(
+            "photos/a?.txt",
+            &["/photos/ab.txt"],
+            &["/photos/a.txt", "/photos/abc.txt"],
+        );
+        assert_matches("/*", &["/a.txt"], &["/a/b.txt"]);
+    }
+
+    #[test]
+    fn match_any_number_of_directories() {
+        assert_matches(
+            "/photos/**/*.jpg",
+            &["/photos/a.jpg", "/photos/x/a.jpg", "/photos/x/y/a.jpg"],
+            &["/a.jpg", "/other/x/a.jpg", "/photos/x/a.png"],
+        );
+        assert_matches("/**", &["/a.txt", "/a/b/c.txt"], &[]);
+        assert!(Glob::new("/a**").is_err());
+    }
+
+    #[test]
+    fn match_character_classes() {
+        assert_matches(
+            "/[a-c]?.txt",
+            &["/a1.txt", "/c1.txt"],
+            &["/d1.txt", "/a.txt"],
+        );
+        assert_matches("/[!a-c].txt", &["/d.txt", "/-.txt"], &["/a.txt", "/b.txt"]);
+        assert_matches("/[]x].txt", &["/].txt", "/x.txt"], &["/y.txt"]);
+        assert_matches("/[a-].txt", &["/a.txt", "/-.txt"], &["/b.txt"]);
+        assert!(Glob::new("/[ab.txt").is_err());
+    }
+
+    #[test]
+    fn match_alternatives() {
+        assert_matches(
+            "/{docs,notes/*}.md",
+            &["/docs.md", "/notes/a.md"],
+            &["/notes.md", "/docs/a.md"],
+        );
+        assert_matches("/{a,{b,c}}.txt", &["/a.txt", "/c.txt"], &["/d.txt"]);
+        assert_matches("/a{,b}.txt", &["/a.txt", "/ab.txt"], &["/b.txt"]);
+        assert!(Glob::new("/{a,b.txt").is_err());
+        assert!(Glob::new("/a}.txt").is_err());
+    }
+
+    #[test]
+    fn reject_parent_directories() {
+        assert!(Glob::new("/a/../b.txt").is_err());
+        assert!(Glob::new("/{a,..}/b.txt").is_err());
+    }
+
+    #[test]
+    fn filter_by_literal_prefix() {
+        let key_codec = KeyCodec::default();
+        let glob = Glob::new("/photos/**/*.jpg").unwrap();
+        assert_eq!(
+            glob.filters(&key_codec),
+            [FilterKind::Prefix(
+                key_codec.encode_prefix(PathBuf::from("/photos"))
+            )]
+        );
+        let glob = Glob::new("{a.txt,b/*.txt,b/c?.txt}").unwrap();
+        assert_eq!(
+            glob.filters(&key_codec),
+            [
+                FilterKind::Exact(key_codec.encode(PathBuf::from("/a.txt"))),
+                FilterKind::Prefix(key_codec.encode_prefix(PathBuf::from("/b"))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::error::OkuFsError;
+use crate::event::OkuFsEvent;
+use crate::fs::{normalise_path, OkuFs};
+use bytes::Bytes;
+use futures::{pin_mut, StreamExt};
+use iroh::{bytes::Hash, client::Entry, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::PathBuf};
+
+/// The name of the file describing each version within an exported history archive.
+pub const HISTORY_INDEX_FILE_NAME: &str = "versions.json";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A description of one version of a file, as recorded in an exported history archive.
+pub struct FileVersionRecord {
+    /// The ID of the author who wrote this version.
+    pub author: String,
+    /// The hash of this version's content.
+    pub hash: String,
+    /// The time this version was written, in microseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The size, in bytes, of this version's content.
+    pub size: u64,
+    /// The path of this version's content within the archive, if its content was held locally.
+    pub content: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A way of identifying a previous version of a file.
+pub enum FileVersion {
+    /// The version with this content hash.
+    Hash(Hash),
+    /// The latest version written at or before this time, in microseconds since the Unix epoch.
+    Timestamp(u64),
+}
+
+impl OkuFs {
+    /// Lists the known versions of a file.
+    /// A replica holds the latest version written by each author, including deletions.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The versions of the file, from oldest to newest.
+    pub async fn file_history(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let file_key = self.entry_key(path);
+        let document = self.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::key_exact(file_key)
+            .include_empty()
+            .build();
+        let entries = document.get_many(query).await?;
+        pin_mut!(entries);
+        let mut versions: Vec<Entry> = entries.map(|entry| entry.unwrap()).collect().await;
+        versions.sort_by_key(|entry| entry.timestamp());
+        Ok(versions)
+    }
+
+    /// Bundles every known version of a file into a tar archive, so that its history can be kept offline.
+    /// The archive holds an index of the versions, along with the content of each version held locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the archive.
+    pub async fn export_history(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let versions = self.file_history(namespace_id, path).await?;
+        let mut archive = tar::Builder::new(Vec::new());
+        let mut records = Vec::new();
+        for version in versions {
+            let mut record = FileVersionRecord {
+                author: version.author().to_string(),
+                hash: version.content_hash().to_string(),
+                timestamp: version.timestamp(),
+                size: version.content_len(),
+                content: None,
+            };
+            if version.content_len() > 0 {
+                if let Ok(content) = self.read_entry_content(&version).await {
+                    let content_path = format!("versions/{}-{}", record.timestamp, record.author);
+                    let mut header = tar::Header::new_gnu();
+                    header.set_size(content.len() as u64);
+                    header.set_mtime(record.timestamp / 1_000_000);
+                    header.set_mode(0o644);
+                    archive.append_data(&mut header, &content_path, &content[..])?;
+                    record.content = Some(content_path);
+                }
+            }
+            records.push(record);
+        }
+        let index = serde_json::to_vec_pretty(&records)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(index.len() as u64);
+        header.set_mode(0o644);
+        archive.append_data(&mut header, HISTORY_INDEX_FILE_NAME, &index[..])?;
+        Ok(archive.into_inner()?.into())
+    }
+
+    /// Restores a file to the content of a previous version.
+    /// The restored content is written as a new version by this file system's author.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `version` - The version to restore, identified by its content hash or the time it was written. It must be listed in the file's history.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the restored content.
+    pub async fn revert_file(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        version: FileVersion,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        let versions = self.file_history(namespace_id, path.clone()).await?;
+        let (hash, size) = match version {
+            // Only versions of this file may be restored, not any content held by the node.
+            FileVersion::Hash(hash) => versions
+                .iter()
+                .find(|entry| entry.content_hash() == hash)
+                .map(|entry| (hash, entry.content_len()))
+                .ok_or(OkuFsError::FileVersionNotFound)?,
+            FileVersion::Timestamp(timestamp) => versions
+                .iter()
+                .rev()
+                .find(|entry| entry.timestamp() <= timestamp)
+                .map(|entry| (entry.content_hash(), entry.content_len()))
+                .ok_or(OkuFsError::FileVersionNotFound)?,
+        };
+        if size == 0 {
+            return Err(OkuFsError::FileVersionNotFound.into());
+        }
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_hash(self.author_id, self.entry_key(path.clone()), hash, size)
+            .await?;
+        self.notify_observers(|observer| observer.on_write(namespace_id, &path, hash, size));
+        self.emit(OkuFsEvent::EntryInserted {
+            namespace_id,
+            path: normalise_path(path),
+            hash,
+            author: self.author_id,
+        });
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::start_test_fs;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn revert_only_to_versions_of_the_file() {
+        let oku_fs = start_test_fs("revert").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let author_id = oku_fs.create_author().await.unwrap();
+        let hash = oku_fs
+            .create_or_modify_file_as(namespace_id, PathBuf::from("/a.txt"), "a", author_id)
+            .await
+            .unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a.txt"), "b")
+            .await
+            .unwrap();
+        let other_hash = oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/c.txt"), "c")
+            .await
+            .unwrap();
+        let error = oku_fs
+            .revert_file(
+                namespace_id,
+                PathBuf::from("/a.txt"),
+                FileVersion::Hash(other_hash),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::FileVersionNotFound)
+        ));
+        oku_fs
+            .revert_file(
+                namespace_id,
+                PathBuf::from("/a.txt"),
+                FileVersion::Hash(hash),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            oku_fs
+                .read_file(namespace_id, PathBuf::from("/a.txt"))
+                .await
+                .unwrap(),
+            "a"
+        );
+        oku_fs.shutdown();
+    }
+}
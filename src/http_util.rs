@@ -0,0 +1,89 @@
+#[cfg(any(feature = "webdav", feature = "api"))]
+use crate::error::OkuFsError;
+#[cfg(any(feature = "webdav", feature = "api"))]
+use bytes::Bytes;
+#[cfg(any(feature = "webdav", feature = "api"))]
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use sha2::{Digest, Sha256};
+#[cfg(any(feature = "webdav", feature = "api"))]
+use std::error::Error;
+
+/// Decodes a percent-encoded URL path.
+///
+/// # Arguments
+///
+/// * `path` - The percent-encoded path.
+///
+/// # Returns
+///
+/// The decoded path, with any invalid UTF-8 replaced.
+#[cfg(any(feature = "webdav", feature = "api"))]
+pub(crate) fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Checks whether a token presented by a connection matches the expected token, taking the same time however much of the token matches.
+///
+/// # Arguments
+///
+/// * `presented` - The token presented by the connection.
+///
+/// * `expected` - The expected token.
+///
+/// # Returns
+///
+/// Whether the tokens match.
+pub(crate) fn token_matches(presented: &str, expected: &str) -> bool {
+    let presented = Sha256::digest(presented.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    presented
+        .iter()
+        .zip(expected.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// Receives the body of a request, refusing bodies longer than a limit before they are held in memory.
+///
+/// # Arguments
+///
+/// * `body` - The body of the request.
+///
+/// * `limit` - The most bytes the body may hold.
+///
+/// # Returns
+///
+/// The body.
+#[cfg(any(feature = "webdav", feature = "api"))]
+pub(crate) async fn collect_limited_body(
+    body: hyper::body::Incoming,
+    limit: u64,
+) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+    match Limited::new(body, usize::try_from(limit).unwrap_or(usize::MAX))
+        .collect()
+        .await
+    {
+        Ok(collected) => Ok(collected.to_bytes()),
+        Err(e) if e.is::<LengthLimitError>() => {
+            Err(OkuFsError::WriteLimitExceeded(limit.saturating_add(1), limit).into())
+        }
+        Err(e) => Err(e),
+    }
+}
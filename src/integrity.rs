@@ -0,0 +1,182 @@
+use crate::error::OkuFsError;
+use crate::fs::{OkuFs, OkuFsConfig};
+use crate::storage::OkuNode;
+use futures::{pin_mut, StreamExt};
+use iroh::bytes::store::{ConsistencyCheckProgress, ReportLevel, ValidateProgress};
+use iroh::bytes::Hash;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, path::PathBuf};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// How the local store is checked for damage when the file system starts.
+pub enum IntegrityCheck {
+    /// The store is not checked.
+    #[default]
+    Disabled,
+    /// Problems are recorded in the startup report, and startup continues.
+    Report,
+    /// Problems cause startup to fail.
+    Fail,
+    /// Damaged data is set aside or removed, problems are recorded in the startup report, and startup continues.
+    Quarantine,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A problem found while checking the local store.
+pub struct IntegrityProblem {
+    /// The hash of the affected content, if the problem concerns specific content.
+    pub hash: Option<Hash>,
+    /// A description of the problem.
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// A report of what happened when the file system started.
+pub struct StartupReport {
+    /// Problems found while checking the local store.
+    pub problems: Vec<IntegrityProblem>,
+    /// The location the store was moved to, if it could not be opened and was quarantined.
+    pub quarantined_store: Option<PathBuf>,
+    /// Whether damaged content was removed from the store.
+    pub repaired: bool,
+}
+
+/// Starts the Iroh node backing the file system, quarantining its store if it cannot be opened and the configuration allows it.
+/// Only stores kept on disk can be quarantined.
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the file system.
+///
+/// # Returns
+///
+/// The running node, along with a report of any quarantining that took place.
+pub(crate) async fn spawn_node(
+    config: &OkuFsConfig,
+) -> Result<(OkuNode, StartupReport), Box<dyn Error + Send + Sync>> {
+    let storage_backend = &config.storage_backend;
+    let mut startup_report = StartupReport::default();
+    let node = match storage_backend.spawn_node(config).await {
+        Ok(node) => node,
+        Err(e) if config.integrity_check == IntegrityCheck::Quarantine => {
+            let node_path = storage_backend
+                .node_path(config)
+                .ok_or(OkuFsError::CannotStartNode(e.to_string()))?;
+            let quarantine_path = node_path.with_file_name(format!(
+                "{}.quarantined.{}",
+                node_path.file_name().unwrap_or_default().to_string_lossy(),
+                chrono::Utc::now().format("%Y%m%d%H%M%S")
+            ));
+            std::fs::rename(&node_path, &quarantine_path)?;
+            startup_report.problems.push(IntegrityProblem {
+                hash: None,
+                message: e.to_string(),
+            });
+            startup_report.quarantined_store = Some(quarantine_path);
+            storage_backend
+                .spawn_node(config)
+                .await
+                .map_err(|e| OkuFsError::CannotStartNode(e.to_string()))?
+        }
+        Err(e) => return Err(OkuFsError::CannotStartNode(e.to_string()).into()),
+    };
+    Ok((node, startup_report))
+}
+
+/// Checks the content held in the local store for damage, such as truncation or corruption from a crash.
+///
+/// # Arguments
+///
+/// * `node` - The node whose store should be checked.
+///
+/// * `integrity_check` - How the store should be checked.
+///
+/// * `startup_report` - The report to record problems in.
+pub(crate) async fn check_store_integrity(
+    node: &OkuNode,
+    integrity_check: IntegrityCheck,
+    startup_report: &mut StartupReport,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let repair = integrity_check == IntegrityCheck::Quarantine;
+    let mut problems = Vec::new();
+    let consistency_check = node.blobs.consistency_check(repair).await?;
+    pin_mut!(consistency_check);
+    while let Some(progress) = consistency_check.next().await {
+        match progress? {
+            ConsistencyCheckProgress::Update {
+                message,
+                entry,
+                level: ReportLevel::Warn | ReportLevel::Error,
+            } => problems.push(IntegrityProblem {
+                hash: entry,
+                message,
+            }),
+            ConsistencyCheckProgress::Abort(e) => problems.push(IntegrityProblem {
+                hash: None,
+                message: e.to_string(),
+            }),
+            _ => {}
+        }
+    }
+    problems.extend(validate_content(node, repair).await?);
+    if integrity_check == IntegrityCheck::Fail && !problems.is_empty() {
+        let messages: Vec<String> = problems.into_iter().map(|x| x.message).collect();
+        return Err(OkuFsError::CannotStartNode(messages.join("; ")).into());
+    }
+    startup_report.repaired = repair && !problems.is_empty();
+    startup_report.problems.extend(problems);
+    Ok(())
+}
+
+/// Checks that the content held in the local store matches its hashes.
+///
+/// # Arguments
+///
+/// * `node` - The node whose store should be checked.
+///
+/// * `repair` - Whether damaged content should be removed from the store.
+///
+/// # Returns
+///
+/// The problems found, naming the damaged content.
+pub(crate) async fn validate_content(
+    node: &OkuNode,
+    repair: bool,
+) -> Result<Vec<IntegrityProblem>, Box<dyn Error + Send + Sync>> {
+    let mut problems = Vec::new();
+    let validation = node.blobs.validate(repair).await?;
+    pin_mut!(validation);
+    let mut hashes_by_id = HashMap::new();
+    while let Some(progress) = validation.next().await {
+        match progress? {
+            ValidateProgress::Entry { id, hash, .. }
+            | ValidateProgress::PartialEntry { id, hash, .. } => {
+                hashes_by_id.insert(id, hash);
+            }
+            ValidateProgress::EntryDone {
+                id,
+                error: Some(message),
+            } => problems.push(IntegrityProblem {
+                hash: hashes_by_id.get(&id).copied(),
+                message,
+            }),
+            ValidateProgress::Abort(e) => problems.push(IntegrityProblem {
+                hash: None,
+                message: e.to_string(),
+            }),
+            _ => {}
+        }
+    }
+    Ok(problems)
+}
+
+impl OkuFs {
+    /// Gets the report of what happened when the file system started.
+    ///
+    /// # Returns
+    ///
+    /// The startup report, including any problems found in the local store.
+    pub fn startup_report(&self) -> &StartupReport {
+        &self.startup_report
+    }
+}
@@ -0,0 +1,2868 @@
+use crate::archive::ArchiveFormat;
+use crate::audit::{AuditQuery, AuditRecord};
+use crate::availability::{AvailabilitySummary, EntryAvailability};
+use crate::backup::RestoreReport;
+use crate::collection::{CollectionEntry, CollectionTicket};
+use crate::compression::Compression;
+use crate::conflict::{Conflict, ConflictResolution};
+use crate::diff::{Change, ReplicaState};
+use crate::discovery::{AnnounceHealth, AnnouncementStatus, ContentRequest};
+use crate::download::DownloadReport;
+use crate::error::OkuFsError;
+use crate::file_metadata::FileMetadata;
+use crate::fs::OkuFs;
+use crate::gc::{BlobStoreStats, GcReport};
+use crate::glob::Glob;
+use crate::history::FileVersion;
+use crate::http_util::token_matches;
+use crate::integrity::StartupReport;
+use crate::journal::OverwriteRecord;
+use crate::list::{ListOptions, ListPage, PageCursor};
+use crate::local::{LocalSyncReport, SyncDirection};
+use crate::merge::{MergeReport, MergeStrategy};
+use crate::network_stats::NetworkStats;
+use crate::peers::ReplicaPeer;
+use crate::profile::AuthorProfile;
+use crate::purge::PurgeReport;
+use crate::query::FileQuery;
+use crate::repair::RepairReport;
+use crate::resume::PendingSync;
+use crate::schedule::SyncPolicy;
+#[cfg(feature = "search")]
+use crate::search::SearchHit;
+use crate::shared_folder::SharedFolderMember;
+use crate::snapshot::Snapshot;
+use crate::stat::OkuFileInfo;
+use crate::template::{ReplicaManifest, ReplicaTemplate};
+use crate::ticket::AcceptPolicy;
+use crate::trash::TrashItem;
+use crate::tree::DirTree;
+use crate::ucan::{Capability, Revocation, Ucan};
+use crate::usage::StorageReport;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use iroh::{
+    bytes::{Hash, HashAndFormat},
+    client::Entry,
+    net::{magic_endpoint::get_remote_node_id, relay::RelayMode, MagicEndpoint, NodeAddr, NodeId},
+    rpc_protocol::ShareMode,
+    sync::{store::DownloadPolicy, AuthorId, NamespaceId},
+    ticket::{BlobTicket, DocTicket},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::{
+    error::Error,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::Mutex,
+};
+use tokio_util::sync::CancellationToken;
+
+/// The name of the socket, within the file system's directory, that a running node can be reached through.
+pub const IPC_SOCKET_FILE_NAME: &str = "oku.sock";
+
+/// The protocol identifier for managing a node through its remote control.
+pub const ALPN_REMOTE_CONTROL: &[u8] = b"oku/remote-control/v1";
+
+/// The longest frame, in bytes, sent over a connection.
+/// Longer messages, such as those carrying file contents, are sent as several frames, and a node accepts no more of them than its write limit allows.
+pub const MAX_IPC_FRAME_LENGTH: usize = 1024 * 1024;
+
+/// The longest token, in bytes, that a node accepts from a connection to its remote control.
+const MAX_REMOTE_CONTROL_TOKEN_LENGTH: u64 = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// The configuration of a node's remote control, through which it can be managed from other machines.
+pub struct RemoteControlConfig {
+    /// The UDP port to listen for connections on.
+    pub port: u16,
+    /// The token connections must present before sending requests.
+    pub token: String,
+    /// Whether connections may send requests reaching beyond the node's replicas, such as those reading or writing paths on the node's machine, or revealing its secret keys.
+    /// Otherwise, such requests are only carried out for processes on the node's machine.
+    #[serde(default)]
+    pub allow_host_access: bool,
+}
+
+/// A connection that requests can be sent over.
+trait IpcStream: AsyncRead + AsyncWrite + Debug + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Debug + Send + Unpin> IpcStream for T {}
+
+/// The type of a reply, which is nothing when none is given.
+macro_rules! ipc_reply_type {
+    () => {
+        ()
+    };
+    ($ret:ty) => {
+        $ret
+    };
+}
+
+/// Generates the requests other processes can send to a running node, how a node carries them out, and the methods of [`OkuFsClient`] sending them.
+///
+/// Each request is given as its variant of [`IpcRequest`], the name of the client method, its arguments and result, and how the file system carries it out.
+/// The arguments are sent as the variant's fields, in order.
+macro_rules! ipc_api {
+    ($(
+        $(#[doc = $doc:literal])*
+        $(#[cfg($cfg:meta)])?
+        $variant:ident => $method:ident($($arg:ident: $arg_ty:ty),* $(,)?) $(-> $ret:ty)? =
+            |$fs:ident| $body:expr;
+    )*) => {
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        /// A request sent by another process to a running node.
+        /// Each variant carries the arguments of the matching [`OkuFsClient`] method.
+        pub enum IpcRequest {
+            $(
+                $(#[doc = $doc])*
+                $(#[cfg($cfg)])?
+                $variant($($arg_ty),*),
+            )*
+        }
+
+        impl OkuFs {
+            /// Carries out a request sent by another process.
+            ///
+            /// # Arguments
+            ///
+            /// * `request` - The request.
+            ///
+            /// # Returns
+            ///
+            /// The encoded result of the request.
+            async fn carry_out_ipc_request(
+                &self,
+                request: IpcRequest,
+            ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+                match request {
+                    $(
+                        $(#[cfg($cfg)])?
+                        IpcRequest::$variant($($arg),*) => {
+                            let $fs = self;
+                            let result: Result<ipc_reply_type!($($ret)?), Box<dyn Error + Send + Sync>> =
+                                async { Ok($body) }.await;
+                            encode_reply(result)
+                        }
+                    )*
+                }
+            }
+        }
+
+        impl OkuFsClient {
+            $(
+                $(#[doc = $doc])*
+                $(#[cfg($cfg)])?
+                pub async fn $method(
+                    &self,
+                    $($arg: $arg_ty),*
+                ) -> Result<ipc_reply_type!($($ret)?), Box<dyn Error + Send + Sync>> {
+                    self.request(IpcRequest::$variant($($arg),*)).await
+                }
+            )*
+        }
+    };
+}
+
+ipc_api! {
+    /// Creates a replica in the node's file system.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica.
+    CreateReplica => create_replica() -> NamespaceId = |fs| fs.create_replica().await?;
+
+    /// Deletes a replica from the node's file system.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to delete.
+    DeleteReplica => delete_replica(namespace_id: NamespaceId) = |fs| {
+        fs.delete_replica(namespace_id).await?
+    };
+
+    /// Lists the replicas in the node's file system.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas.
+    ListReplicas => list_replicas() -> Vec<NamespaceId> = |fs| fs.list_replicas().await?;
+
+    /// Gets the size of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The size of the replica, in bytes.
+    GetSize => get_size(namespace_id: NamespaceId) -> u64 = |fs| fs.get_size(namespace_id).await?;
+
+    /// Lists the files in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The latest entries of the files in the replica.
+    ListFiles => list_files(namespace_id: NamespaceId) -> Vec<Entry> = |fs| {
+        fs.list_files(namespace_id).await?
+    };
+
+    /// Creates a file (if it does not exist) or modifies an existing file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `data` - The data to write to the file.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's content.
+    CreateOrModifyFile => create_or_modify_file(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data: Bytes,
+    ) -> Hash = |fs| fs.create_or_modify_file(namespace_id, path, data).await?;
+
+    /// Deletes a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries deleted.
+    DeleteFile => delete_file(namespace_id: NamespaceId, path: PathBuf) -> usize = |fs| {
+        fs.delete_file(namespace_id, path).await?
+    };
+
+    /// Reads a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The content of the file.
+    ReadFile => read_file(namespace_id: NamespaceId, path: PathBuf) -> Bytes = |fs| {
+        fs.read_file(namespace_id, path).await?
+    };
+
+    /// Moves a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `from` - The original path of the file.
+    ///
+    /// * `to` - The new path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's content and the number of entries deleted.
+    MoveFile => move_file(
+        namespace_id: NamespaceId,
+        from: PathBuf,
+        to: PathBuf,
+    ) -> (Hash, usize) = |fs| {
+        let (hash, entries_deleted) = fs.move_file(namespace_id, from, to).await?;
+        (hash, entries_deleted)
+    };
+
+    /// Copies a file, possibly into another replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `from` - The path of the file.
+    ///
+    /// * `to_namespace_id` - The ID of the replica to copy the file into.
+    ///
+    /// * `to` - The path of the copy.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's content.
+    CopyFile => copy_file(
+        from_namespace_id: NamespaceId,
+        from: PathBuf,
+        to_namespace_id: NamespaceId,
+        to: PathBuf,
+    ) -> Hash = |fs| fs.copy_file(from_namespace_id, from, to_namespace_id, to).await?;
+
+    /// Copies a directory, possibly into another replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `from` - The path of the directory.
+    ///
+    /// * `to_namespace_id` - The ID of the replica to copy the directory into.
+    ///
+    /// * `to` - The path of the copy.
+    ///
+    /// # Returns
+    ///
+    /// The original and new paths of the copied files.
+    CopyDirectory => copy_directory(
+        from_namespace_id: NamespaceId,
+        from: PathBuf,
+        to_namespace_id: NamespaceId,
+        to: PathBuf,
+    ) -> Vec<(PathBuf, PathBuf)> = |fs| {
+        fs.copy_directory(from_namespace_id, from, to_namespace_id, to).await?
+    };
+
+    /// Moves a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `from` - The original path of the directory.
+    ///
+    /// * `to` - The new path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// The original and new paths of the moved files, and the number of entries deleted.
+    MoveDirectory => move_directory(
+        namespace_id: NamespaceId,
+        from: PathBuf,
+        to: PathBuf,
+    ) -> (Vec<(PathBuf, PathBuf)>, usize) = |fs| {
+        let (moved, entries_deleted) = fs.move_directory(namespace_id, from, to).await?;
+        (moved, entries_deleted)
+    };
+
+    /// Creates an empty directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to create the directory in.
+    ///
+    /// * `path` - The path of the directory.
+    CreateDirectory => create_directory(namespace_id: NamespaceId, path: PathBuf) = |fs| {
+        fs.create_directory(namespace_id, path).await?
+    };
+
+    /// Deletes a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries deleted.
+    DeleteDirectory => delete_directory(namespace_id: NamespaceId, path: PathBuf) -> usize = |fs| {
+        fs.delete_directory(namespace_id, path).await?
+    };
+
+    /// Lists the known versions of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The entries of the file's versions.
+    FileHistory => file_history(namespace_id: NamespaceId, path: PathBuf) -> Vec<Entry> = |fs| {
+        fs.file_history(namespace_id, path).await?
+    };
+
+    /// Restores a previous version of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `version` - The version to restore.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the restored content.
+    RevertFile => revert_file(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        version: FileVersion,
+    ) -> Hash = |fs| fs.revert_file(namespace_id, path, version).await?;
+
+    /// Gives a replica an alias.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `alias` - The alias.
+    SetReplicaAlias => set_replica_alias(namespace_id: NamespaceId, alias: String) = |fs| {
+        fs.set_replica_alias(namespace_id, alias)?
+    };
+
+    /// Removes a replica's alias.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    RemoveReplicaAlias => remove_replica_alias(namespace_id: NamespaceId) = |fs| {
+        fs.remove_replica_alias(namespace_id)?
+    };
+
+    /// Finds the replica an alias refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The alias.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica, if the alias is in use.
+    ResolveAlias => resolve_alias(alias: String) -> Option<NamespaceId> = |fs| {
+        fs.resolve_alias(&alias)?
+    };
+
+    /// Finds the alias of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The alias of the replica, if it has one.
+    GetReplicaAlias => get_replica_alias(namespace_id: NamespaceId) -> Option<String> = |fs| {
+        fs.get_replica_alias(namespace_id)?
+    };
+
+    /// Lists the replicas in the node's file system, along with their aliases.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas, each with its alias if it has one.
+    ListReplicasWithAliases => list_replicas_with_aliases() -> Vec<(NamespaceId, Option<String>)> =
+        |fs| fs.list_replicas_with_aliases().await?;
+
+    /// Finds the operations recorded in the node's audit log.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The operations to find.
+    ///
+    /// # Returns
+    ///
+    /// The matching operations.
+    QueryAuditLog => query_audit_log(query: AuditQuery) -> Vec<AuditRecord> = |fs| {
+        fs.query_audit_log(&query)?
+    };
+
+    /// Gets the author that the node writes files as by default.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the author.
+    DefaultAuthor => default_author() -> AuthorId = |fs| fs.default_author();
+
+    /// Lists the authors held by the node.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the authors.
+    ListAuthors => list_authors() -> Vec<AuthorId> = |fs| fs.list_authors().await?;
+
+    /// Creates an author held by the node.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new author.
+    CreateAuthor => create_author() -> AuthorId = |fs| fs.create_author().await?;
+
+    /// Creates or modifies a file as a particular author.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `data` - The data to write to the file.
+    ///
+    /// * `author_id` - The author to write the file as.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's content.
+    CreateOrModifyFileAs => create_or_modify_file_as(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data: Bytes,
+        author_id: AuthorId,
+    ) -> Hash = |fs| fs.create_or_modify_file_as(namespace_id, path, data, author_id).await?;
+
+    /// Deletes a file as a particular author.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `author_id` - The author to delete the file as.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries deleted.
+    DeleteFileAs => delete_file_as(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        author_id: AuthorId,
+    ) -> usize = |fs| fs.delete_file_as(namespace_id, path, author_id).await?;
+
+    /// Describes how much of the content of the files under a path is held by the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the files.
+    ///
+    /// * `path` - The path of a file or directory.
+    ///
+    /// # Returns
+    ///
+    /// The availability of each file.
+    Availability => availability(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Vec<EntryAvailability> = |fs| fs.availability(namespace_id, path).await?;
+
+    /// Summarises how much of a replica's content is held by the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The availability of the replica's content.
+    AvailabilitySummary => availability_summary(
+        namespace_id: NamespaceId,
+    ) -> AvailabilitySummary = |fs| fs.availability_summary(namespace_id).await?;
+
+    /// Backs up the node's file system.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path, on the node's machine, to write the backup to.
+    ///
+    /// * `passphrase` - The passphrase to encrypt the backup with.
+    Backup => backup(path: PathBuf, passphrase: String) = |fs| {
+        fs.backup(path, &passphrase).await?
+    };
+
+    /// Restores the node's file system from a backup.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the backup on the node's machine.
+    ///
+    /// * `passphrase` - The passphrase the backup was encrypted with.
+    ///
+    /// # Returns
+    ///
+    /// The replicas restored, and the authors that could not be.
+    Restore => restore(path: PathBuf, passphrase: String) -> RestoreReport = |fs| {
+        fs.restore(path, &passphrase).await?
+    };
+
+    /// Reads a blob held by the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the blob.
+    ///
+    /// # Returns
+    ///
+    /// The content of the blob.
+    ReadBlob => read_blob(hash: Hash) -> Bytes = |fs| fs.read_blob(hash).await?;
+
+    /// Fetches a blob from other nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the blob.
+    ///
+    /// * `providers` - The nodes to fetch the blob from.
+    ///
+    /// # Returns
+    ///
+    /// The content of the blob.
+    FetchBlob => fetch_blob(hash: Hash, providers: Vec<NodeAddr>) -> Bytes = |fs| {
+        fs.fetch_blob(hash, providers).await?
+    };
+
+    /// Releases a blob fetched earlier, allowing the node to remove it.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the blob.
+    ReleaseBlob => release_blob(hash: Hash) = |fs| fs.release_blob(hash).await?;
+
+    /// Exports the latest version of every file in a replica as a bundle.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The number of files written into the bundle, and the bundle.
+    ExportBundle => export_bundle(namespace_id: NamespaceId) -> (usize, Bytes) = |fs| {
+        let mut bundle = Vec::new();
+        let files_written = fs.export_bundle(namespace_id, &mut bundle).await?;
+        (files_written, bundle.into())
+    };
+
+    /// Imports a bundle as a new replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `bundle` - The bundle.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica.
+    ImportBundle => import_bundle(bundle: Bytes) -> NamespaceId = |fs| {
+        fs.import_bundle(&bundle[..]).await?
+    };
+
+    /// Exports a replica, along with its history, as a bundle.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `include_write_key` - Whether the bundle should allow writing to the replica.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries written into the bundle, and the bundle.
+    ExportReplicaBundle => export_replica_bundle(
+        namespace_id: NamespaceId,
+        include_write_key: bool,
+    ) -> (usize, Bytes) = |fs| {
+        let mut bundle = Vec::new();
+        let entries_written = fs
+            .export_replica_bundle(namespace_id, include_write_key, &mut bundle)
+            .await?;
+        (entries_written, bundle.into())
+    };
+
+    /// Imports a replica exported along with its history.
+    ///
+    /// # Arguments
+    ///
+    /// * `bundle` - The bundle.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the imported replica.
+    ImportReplicaBundle => import_replica_bundle(bundle: Bytes) -> NamespaceId = |fs| {
+        fs.import_replica_bundle(&bundle[..]).await?
+    };
+
+    /// Exports the latest version of every file in a replica as an archive.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `format` - The format of the archive.
+    ///
+    /// # Returns
+    ///
+    /// The number of files written into the archive, and the archive.
+    ExportReplicaArchive => export_replica_archive(
+        namespace_id: NamespaceId,
+        format: ArchiveFormat,
+    ) -> (usize, Bytes) = |fs| {
+        let mut archive = Vec::new();
+        let files_written = fs
+            .export_replica_archive(namespace_id, &mut archive, format)
+            .await?;
+        (files_written, archive.into())
+    };
+
+    /// Exports the latest version of every file in a replica as a CAR file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The CID of the root directory, in its base32 form, and the CAR file.
+    ExportReplicaCar => export_replica_car(namespace_id: NamespaceId) -> (String, Bytes) = |fs| {
+        let mut car = Vec::new();
+        let root = fs.export_replica_car(namespace_id, &mut car).await?;
+        (root, car.into())
+    };
+
+    /// Imports the files in a CAR file into a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The directory to import the files into.
+    ///
+    /// * `data` - The CAR file.
+    ///
+    /// # Returns
+    ///
+    /// The number of files written into the replica.
+    ImportCar => import_car(namespace_id: NamespaceId, path: PathBuf, data: Bytes) -> usize = |fs| {
+        fs.import_car(namespace_id, path, &data[..]).await?
+    };
+
+    /// Coalesces writes to a replica made in quick succession.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `window` - How long writes are held back for.
+    SetWriteCoalescing => set_write_coalescing(
+        namespace_id: NamespaceId,
+        window: Duration,
+    ) = |fs| fs.set_write_coalescing(namespace_id, window).await?;
+
+    /// Stops coalescing writes to a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    DisableWriteCoalescing => disable_write_coalescing(namespace_id: NamespaceId) = |fs| {
+        fs.disable_write_coalescing(namespace_id).await?
+    };
+
+    /// Finds how long writes to a replica are held back for.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// How long writes are held back for, if they are coalesced.
+    WriteCoalescing => write_coalescing(namespace_id: NamespaceId) -> Option<Duration> = |fs| {
+        fs.write_coalescing(namespace_id)
+    };
+
+    /// Writes the writes held back for a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    FlushWrites => flush_writes(namespace_id: NamespaceId) = |fs| {
+        fs.flush_writes(namespace_id).await?
+    };
+
+    /// Creates a collection from several files.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The name and content of each file.
+    ///
+    /// # Returns
+    ///
+    /// A ticket for the collection.
+    CreateCollection => create_collection(files: Vec<(String, Bytes)>) -> CollectionTicket = |fs| {
+        fs.create_collection(files).await?
+    };
+
+    /// Creates a collection from the files in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The directory whose files should be collected, or the whole replica if there is none.
+    ///
+    /// # Returns
+    ///
+    /// A ticket for the collection.
+    CreateCollectionFromReplica => create_collection_from_replica(
+        namespace_id: NamespaceId,
+        path: Option<PathBuf>,
+    ) -> CollectionTicket = |fs| fs.create_collection_from_replica(namespace_id, path).await?;
+
+    /// Lists the files in a collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The collection.
+    ///
+    /// # Returns
+    ///
+    /// The files in the collection.
+    ListCollection => list_collection(content: ContentRequest) -> Vec<CollectionEntry> = |fs| {
+        fs.list_collection(content).await?
+    };
+
+    /// Reads a file in a collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The collection.
+    ///
+    /// * `name` - The name of the file.
+    ///
+    /// # Returns
+    ///
+    /// The content of the file.
+    ReadCollectionFile => read_collection_file(
+        content: ContentRequest,
+        name: String,
+    ) -> Bytes = |fs| fs.read_collection_file(content, &name).await?;
+
+    /// Sets how the files written to a replica are compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `compression` - The compression.
+    SetReplicaCompression => set_replica_compression(
+        namespace_id: NamespaceId,
+        compression: Compression,
+    ) = |fs| fs.set_replica_compression(namespace_id, compression)?;
+
+    /// Finds how the files written to a replica are compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The compression.
+    ReplicaCompression => replica_compression(namespace_id: NamespaceId) -> Compression = |fs| {
+        fs.replica_compression(namespace_id)?
+    };
+
+    /// Creates or modifies a file, compressing its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `data` - The data to write to the file.
+    ///
+    /// * `compression` - The compression.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's stored content.
+    CreateOrModifyFileWithCompression => create_or_modify_file_with_compression(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        data: Bytes,
+        compression: Compression,
+    ) -> Hash = |fs| {
+        fs.create_or_modify_file_with_compression(namespace_id, path, data, compression).await?
+    };
+
+    /// Lists the files in a replica with conflicting versions.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The conflicting versions of each file.
+    ListConflicts => list_conflicts(namespace_id: NamespaceId) -> Vec<Conflict> = |fs| {
+        fs.list_conflicts(namespace_id).await?
+    };
+
+    /// Resolves the conflicting versions of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `resolution` - How the conflict should be resolved.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the resolved content, if a new version was written.
+    ResolveConflict => resolve_conflict(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        resolution: ConflictResolution,
+    ) -> Option<Hash> = |fs| fs.resolve_conflict(namespace_id, path, resolution).await?;
+
+    /// Finds the changes made to a replica between two states.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `from` - The earlier state.
+    ///
+    /// * `to` - The later state.
+    ///
+    /// # Returns
+    ///
+    /// The changes.
+    Diff => diff(
+        namespace_id: NamespaceId,
+        from: ReplicaState,
+        to: ReplicaState,
+    ) -> Vec<Change> = |fs| fs.diff(namespace_id, &from, &to).await?;
+
+    /// Announces the node's replicas immediately.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas that could not be announced.
+    AnnounceNow => announce_now() -> Vec<NamespaceId> = |fs| fs.announce_now().await?;
+
+    /// Describes how announcing the node's replicas has gone.
+    ///
+    /// # Returns
+    ///
+    /// The outcome of recent announcements.
+    AnnounceHealth => announce_health() -> AnnounceHealth = |fs| fs.announce_health();
+
+    /// Checks whether a replica can be found by other nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The nodes found announcing the replica.
+    VerifyAnnouncement => verify_announcement(
+        namespace_id: NamespaceId,
+    ) -> AnnouncementStatus = |fs| fs.verify_announcement(namespace_id).await?;
+
+    /// Tells the node that its network has changed.
+    NetworkChange => network_change() = |fs| fs.network_change().await;
+
+    /// Finds the replica a DNS name refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The DNS name.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica.
+    ResolveDnsName => resolve_dns_name(name: String) -> NamespaceId = |fs| {
+        fs.resolve_dns_name(&name).await?
+    };
+
+    /// Publishes a replica under the node's Pkarr name.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The name the replica is published under.
+    PublishPkarrName => publish_pkarr_name(namespace_id: NamespaceId) -> String = |fs| {
+        fs.publish_pkarr_name(namespace_id).await?
+    };
+
+    /// Downloads the missing content of a replica from particular nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `peers` - The nodes to download from.
+    ///
+    /// # Returns
+    ///
+    /// The content downloaded, and the content that could not be.
+    DownloadFromPeers => download_from_peers(
+        namespace_id: NamespaceId,
+        peers: Vec<NodeAddr>,
+    ) -> DownloadReport = |fs| {
+        fs.download_from_peers(namespace_id, peers, CancellationToken::new()).await?
+    };
+
+    /// Finds which content of a replica is downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The download policy of the replica.
+    GetDownloadPolicy => get_download_policy(namespace_id: NamespaceId) -> DownloadPolicy = |fs| {
+        fs.get_download_policy(namespace_id)?
+    };
+
+    /// Sets which content of a replica is downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `policy` - The download policy.
+    SetDownloadPolicy => set_download_policy(
+        namespace_id: NamespaceId,
+        policy: DownloadPolicy,
+    ) = |fs| fs.set_download_policy(namespace_id, policy).await?;
+
+    /// Downloads only the content of the files in a replica matching some patterns.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `patterns` - The patterns.
+    SetDownloadPatterns => set_download_patterns(
+        namespace_id: NamespaceId,
+        patterns: Vec<Glob>,
+    ) = |fs| fs.set_download_patterns(namespace_id, &patterns).await?;
+
+    /// Creates an encrypted replica in the node's file system.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica.
+    CreateEncryptedReplica => create_encrypted_replica() -> NamespaceId = |fs| {
+        fs.create_encrypted_replica().await?
+    };
+
+    /// Checks whether a replica is encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica is encrypted.
+    IsReplicaEncrypted => is_replica_encrypted(namespace_id: NamespaceId) -> bool = |fs| {
+        fs.is_replica_encrypted(namespace_id)
+    };
+
+    /// Gets the key a replica is encrypted with.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The key, if the replica is encrypted.
+    EncryptionKey => encryption_key(namespace_id: NamespaceId) -> Option<[u8; 32]> = |fs| {
+        fs.encryption_key(namespace_id)?
+    };
+
+    /// Sets the key a replica is encrypted with.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `key` - The key.
+    SetEncryptionKey => set_encryption_key(namespace_id: NamespaceId, key: [u8; 32]) = |fs| {
+        fs.set_encryption_key(namespace_id, key)?
+    };
+
+    /// Sets the metadata of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `metadata` - The metadata.
+    SetMetadata => set_metadata(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        metadata: FileMetadata,
+    ) = |fs| fs.set_metadata(namespace_id, path, &metadata).await?;
+
+    /// Gets the metadata of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The metadata.
+    GetMetadata => get_metadata(namespace_id: NamespaceId, path: PathBuf) -> FileMetadata = |fs| {
+        fs.get_metadata(namespace_id, path).await?
+    };
+
+    /// Lists the files in a replica, along with their metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The latest entry and metadata of each file.
+    ListFilesWithMetadata => list_files_with_metadata(
+        namespace_id: NamespaceId,
+    ) -> Vec<(Entry, FileMetadata)> = |fs| fs.list_files_with_metadata(namespace_id).await?;
+
+    /// Prevents a replica from being modified.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    FreezeReplica => freeze_replica(namespace_id: NamespaceId) = |fs| {
+        fs.freeze_replica(namespace_id).await?
+    };
+
+    /// Allows a frozen replica to be modified again.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica was frozen.
+    UnfreezeReplica => unfreeze_replica(namespace_id: NamespaceId) -> bool = |fs| {
+        fs.unfreeze_replica(namespace_id)
+    };
+
+    /// Checks whether a replica is frozen.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica is frozen.
+    IsReplicaFrozen => is_replica_frozen(namespace_id: NamespaceId) -> bool = |fs| {
+        fs.is_replica_frozen(namespace_id)
+    };
+
+    /// Fetches a replica, or the files under a path in it, from other nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path of the files to fetch, or the whole replica if there is none.
+    ///
+    /// * `partial` - Whether only some of the replica's files are wanted.
+    ///
+    /// * `verified` - Whether the replica must come from a node that is known to hold it.
+    GetExternalReplica => get_external_replica(
+        namespace_id: NamespaceId,
+        path: Option<PathBuf>,
+        partial: bool,
+        verified: bool,
+    ) = |fs| fs.get_external_replica(namespace_id, path, partial, verified).await?;
+
+    /// Fetches the files in a replica matching some patterns from other nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `patterns` - The patterns.
+    ///
+    /// * `partial` - Whether only some of the replica's files are wanted.
+    ///
+    /// * `verified` - Whether the replica must come from a node that is known to hold it.
+    GetExternalFilesMatching => get_external_files_matching(
+        namespace_id: NamespaceId,
+        patterns: Vec<Glob>,
+        partial: bool,
+        verified: bool,
+    ) = |fs| {
+        fs.get_external_files_matching(namespace_id, patterns, partial, verified).await?
+    };
+
+    /// Connects the node to a relay.
+    ///
+    /// # Arguments
+    ///
+    /// * `relay_address` - The address of the relay.
+    ConnectToRelay => connect_to_relay(relay_address: String) = |fs| {
+        fs.connect_to_relay(relay_address).await?
+    };
+
+    /// Removes old versions of files and the content no longer referred to.
+    ///
+    /// # Arguments
+    ///
+    /// * `history_depth` - How many versions of each file to keep.
+    ///
+    /// # Returns
+    ///
+    /// The content removed.
+    Gc => gc(history_depth: usize) -> GcReport = |fs| fs.gc(history_depth).await?;
+
+    /// Describes the content in the node's blob store.
+    ///
+    /// # Returns
+    ///
+    /// The number and size of the blobs.
+    BlobStoreStats => blob_store_stats() -> BlobStoreStats = |fs| fs.blob_store_stats().await?;
+
+    /// Removes the content no longer referred to.
+    ///
+    /// # Returns
+    ///
+    /// The content removed.
+    RemoveOrphanedBlobs => remove_orphaned_blobs() -> GcReport = |fs| {
+        fs.remove_orphaned_blobs().await?
+    };
+
+    /// Exports the versions of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The exported versions.
+    ExportHistory => export_history(namespace_id: NamespaceId, path: PathBuf) -> Bytes = |fs| {
+        fs.export_history(namespace_id, path).await?
+    };
+
+    /// Describes the problems found when the node started.
+    ///
+    /// # Returns
+    ///
+    /// The node's startup report.
+    StartupReport => startup_report() -> StartupReport = |fs| fs.startup_report().clone();
+
+    /// Lists the files in a replica overwritten by other authors.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `since` - The time to list overwrites since, in microseconds since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// The overwrites.
+    ListOverwrites => list_overwrites(
+        namespace_id: NamespaceId,
+        since: u64,
+    ) -> Vec<OverwriteRecord> = |fs| fs.list_overwrites(namespace_id, since)?;
+
+    /// Exports the node's key, encrypted with a passphrase.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase.
+    ///
+    /// # Returns
+    ///
+    /// The encrypted key.
+    ExportNodeKey => export_node_key(passphrase: String) -> String = |fs| {
+        fs.export_node_key(&passphrase)?
+    };
+
+    /// Gets the size of an entry's content as it is read.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry.
+    ///
+    /// # Returns
+    ///
+    /// The size, in bytes.
+    ContentSize => content_size(entry: Entry) -> u64 = |fs| fs.content_size(&entry).await?;
+
+    /// Reads part of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `offset` - The offset to read from.
+    ///
+    /// * `len` - The number of bytes to read.
+    ///
+    /// # Returns
+    ///
+    /// The content read.
+    ReadFileRange => read_file_range(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        offset: u64,
+        len: usize,
+    ) -> Bytes = |fs| fs.read_file_range(namespace_id, path, offset, len).await?;
+
+    /// Lists the files in a replica matching some options.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `options` - The options.
+    ///
+    /// # Returns
+    ///
+    /// The latest entries of the matching files.
+    ListFilesWithOptions => list_files_with_options(
+        namespace_id: NamespaceId,
+        options: ListOptions,
+    ) -> Vec<Entry> = |fs| fs.list_files_with_options(namespace_id, &options).await?;
+
+    /// Lists a page of the files in a replica matching some options.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `options` - The options.
+    ///
+    /// * `after` - The cursor of the previous page, or none for the first page.
+    ///
+    /// * `limit` - The largest number of files in the page.
+    ///
+    /// # Returns
+    ///
+    /// The page of files.
+    ListFilesPage => list_files_page(
+        namespace_id: NamespaceId,
+        options: ListOptions,
+        after: Option<PageCursor>,
+        limit: usize,
+    ) -> ListPage = |fs| fs.list_files_page(namespace_id, &options, after.as_ref(), limit).await?;
+
+    /// Imports a directory on the node's machine into a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `local_path` - The path of the directory on the node's machine.
+    ///
+    /// * `replica_prefix` - The directory in the replica to import into.
+    ///
+    /// # Returns
+    ///
+    /// The paths of the imported files.
+    ImportDirectory => import_directory(
+        namespace_id: NamespaceId,
+        local_path: PathBuf,
+        replica_prefix: PathBuf,
+    ) -> Vec<PathBuf> = |fs| fs.import_directory(namespace_id, local_path, replica_prefix).await?;
+
+    /// Synchronises a replica with a directory on the node's machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `local_path` - The path of the directory on the node's machine.
+    ///
+    /// * `direction` - The direction to synchronise in.
+    ///
+    /// # Returns
+    ///
+    /// The files written and deleted.
+    SyncWithLocalDir => sync_with_local_dir(
+        namespace_id: NamespaceId,
+        local_path: PathBuf,
+        direction: SyncDirection,
+    ) -> LocalSyncReport = |fs| fs.sync_with_local_dir(namespace_id, local_path, direction).await?;
+
+    /// Merges the files in one replica into another.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The ID of the replica to merge from.
+    ///
+    /// * `destination` - The ID of the replica to merge into.
+    ///
+    /// * `strategy` - How files in both replicas are merged.
+    ///
+    /// # Returns
+    ///
+    /// The files merged and skipped.
+    MergeReplicas => merge_replicas(
+        source: NamespaceId,
+        destination: NamespaceId,
+        strategy: MergeStrategy,
+    ) -> MergeReport = |fs| fs.merge_replicas(source, destination, strategy).await?;
+
+    /// Describes the node's connections.
+    ///
+    /// # Returns
+    ///
+    /// The node's connections and the traffic over them.
+    NetworkStats => network_stats() -> NetworkStats = |fs| fs.network_stats().await?;
+
+    /// Disables or re-enables the node's networking.
+    ///
+    /// # Arguments
+    ///
+    /// * `offline` - Whether networking should be disabled.
+    SetOffline => set_offline(offline: bool) = |fs| fs.set_offline(offline).await?;
+
+    /// Checks whether the node's networking is disabled.
+    ///
+    /// # Returns
+    ///
+    /// Whether the node is offline.
+    IsOffline => is_offline() -> bool = |fs| fs.is_offline();
+
+    /// Lists the nodes a replica is synchronised with.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The nodes.
+    ListReplicaPeers => list_replica_peers(namespace_id: NamespaceId) -> Vec<ReplicaPeer> = |fs| {
+        fs.list_replica_peers(namespace_id).await?
+    };
+
+    /// Gets the ID the node is identified by.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the node.
+    NodeId => node_id() -> NodeId = |fs| fs.node_id();
+
+    /// Refuses connections from a node.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the node.
+    BlockPeer => block_peer(node_id: NodeId) = |fs| fs.block_peer(node_id)?;
+
+    /// Accepts connections from a blocked node again.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the node.
+    UnblockPeer => unblock_peer(node_id: NodeId) = |fs| fs.unblock_peer(node_id)?;
+
+    /// Lists the blocked nodes.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the blocked nodes.
+    ListBlockedPeers => list_blocked_peers() -> Vec<NodeId> = |fs| fs.list_blocked_peers()?;
+
+    /// Prefers a node when fetching content.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the node.
+    PreferPeer => prefer_peer(node_id: NodeId) = |fs| fs.prefer_peer(node_id)?;
+
+    /// Stops preferring a node.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the node.
+    UnpreferPeer => unprefer_peer(node_id: NodeId) = |fs| fs.unprefer_peer(node_id)?;
+
+    /// Lists the preferred nodes.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the preferred nodes.
+    ListPreferredPeers => list_preferred_peers() -> Vec<NodeId> = |fs| fs.list_preferred_peers()?;
+
+    /// Keeps a replica synchronised in full.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    PinReplica => pin_replica(namespace_id: NamespaceId) = |fs| {
+        fs.pin_replica(namespace_id).await?
+    };
+
+    /// Stops keeping a replica synchronised in full.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    UnpinReplica => unpin_replica(namespace_id: NamespaceId) = |fs| {
+        fs.unpin_replica(namespace_id).await?
+    };
+
+    /// Checks whether a replica is pinned.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica is pinned.
+    IsReplicaPinned => is_replica_pinned(namespace_id: NamespaceId) -> bool = |fs| {
+        fs.is_replica_pinned(namespace_id)?
+    };
+
+    /// Lists the pinned replicas.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the pinned replicas.
+    ListPinnedReplicas => list_pinned_replicas() -> Vec<NamespaceId> = |fs| {
+        fs.list_pinned_replicas()?
+    };
+
+    /// Hides a replica from other nodes, or stops hiding it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `private` - Whether the replica is private.
+    SetReplicaPrivate => set_replica_private(
+        namespace_id: NamespaceId,
+        private: bool,
+    ) = |fs| fs.set_replica_private(namespace_id, private).await?;
+
+    /// Checks whether a replica is private.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica is private.
+    IsReplicaPrivate => is_replica_private(namespace_id: NamespaceId) -> bool = |fs| {
+        fs.is_replica_private(namespace_id)?
+    };
+
+    /// Lists the replicas that are not private.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the public replicas.
+    ListPublicReplicas => list_public_replicas() -> Vec<NamespaceId> = |fs| {
+        fs.list_public_replicas().await?
+    };
+
+    /// Sets the profile of the author the node writes files as by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to record the profile in.
+    ///
+    /// * `profile` - The profile.
+    SetAuthorProfile => set_author_profile(
+        namespace_id: NamespaceId,
+        profile: AuthorProfile,
+    ) = |fs| fs.set_author_profile(namespace_id, &profile).await?;
+
+    /// Gets the profile of an author.
+    ///
+    /// # Arguments
+    ///
+    /// * `author_id` - The ID of the author.
+    ///
+    /// # Returns
+    ///
+    /// The profile, if the author has one.
+    GetAuthorProfile => get_author_profile(author_id: AuthorId) -> Option<AuthorProfile> = |fs| {
+        fs.get_author_profile(author_id).await?
+    };
+
+    /// Publishes some content.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content.
+    ///
+    /// # Returns
+    ///
+    /// A ticket for the content.
+    PublishBytes => publish_bytes(content: Bytes) -> BlobTicket = |fs| {
+        fs.publish_bytes(content).await?
+    };
+
+    /// Publishes a file or directory on the node's machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file or directory on the node's machine.
+    ///
+    /// # Returns
+    ///
+    /// A ticket for the content.
+    PublishPath => publish_path(path: PathBuf) -> BlobTicket = |fs| fs.publish_path(path).await?;
+
+    /// Stops publishing some content.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content.
+    Unpublish => unpublish(content: HashAndFormat) = |fs| fs.unpublish(content).await?;
+
+    /// Lists the content published by the node.
+    ///
+    /// # Returns
+    ///
+    /// The published content.
+    ListPublished => list_published() -> Vec<HashAndFormat> = |fs| fs.list_published()?;
+
+    /// Announces the content published by the node immediately.
+    ///
+    /// # Returns
+    ///
+    /// The content that could not be announced.
+    AnnouncePublishedNow => announce_published_now() -> Vec<HashAndFormat> = |fs| {
+        fs.announce_published_now().await?
+    };
+
+    /// Fetches content published by another node.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content.
+    ///
+    /// # Returns
+    ///
+    /// The content fetched.
+    FetchPublished => fetch_published(content: ContentRequest) -> HashAndFormat = |fs| {
+        fs.fetch_published(content).await?
+    };
+
+    /// Reads content published by another node.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content.
+    ///
+    /// # Returns
+    ///
+    /// The data of the content.
+    ReadPublished => read_published(content: ContentRequest) -> Bytes = |fs| {
+        fs.read_published(content).await?
+    };
+
+    /// Saves content published by another node to a path on the node's machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content.
+    ///
+    /// * `destination` - The path on the node's machine to save the content to.
+    ///
+    /// # Returns
+    ///
+    /// The content saved.
+    ExportPublished => export_published(
+        content: ContentRequest,
+        destination: PathBuf,
+    ) -> HashAndFormat = |fs| fs.export_published(content, destination).await?;
+
+    /// Deletes every version of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The entries deleted.
+    PurgeFile => purge_file(namespace_id: NamespaceId, path: PathBuf) -> PurgeReport = |fs| {
+        fs.purge_file(namespace_id, path).await?
+    };
+
+    /// Deletes every version of the files in a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// The entries deleted.
+    PurgeDirectory => purge_directory(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> PurgeReport = |fs| fs.purge_directory(namespace_id, path).await?;
+
+    /// Finds the files in a replica matching a query.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `query` - The query.
+    ///
+    /// # Returns
+    ///
+    /// The latest entries of the matching files.
+    QueryFiles => query_files(namespace_id: NamespaceId, query: FileQuery) -> Vec<Entry> = |fs| {
+        fs.query_files(namespace_id, &query).await?
+    };
+
+    /// Lists the files in a replica modified most recently.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `limit` - The largest number of files to list.
+    ///
+    /// # Returns
+    ///
+    /// The latest entries of the files, most recent first.
+    RecentlyModifiedFiles => recently_modified_files(
+        namespace_id: NamespaceId,
+        limit: usize,
+    ) -> Vec<Entry> = |fs| fs.recently_modified_files(namespace_id, limit).await?;
+
+    /// Finds and recovers the files in a replica whose content is damaged.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The files found damaged, and whether they were recovered.
+    RepairReplica => repair_replica(namespace_id: NamespaceId) -> RepairReport = |fs| {
+        fs.repair_replica(namespace_id).await?
+    };
+
+    /// Lists the imports that have not finished.
+    ///
+    /// # Returns
+    ///
+    /// The unfinished imports.
+    ListPendingSyncs => list_pending_syncs() -> Vec<PendingSync> = |fs| fs.list_pending_syncs()?;
+
+    /// Resumes the imports that have not finished.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas whose imports were resumed.
+    ResumePendingSyncs => resume_pending_syncs() -> Vec<NamespaceId> = |fs| {
+        fs.resume_pending_syncs().await?
+    };
+
+    /// Sets when a replica is synchronised.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `policy` - The policy.
+    SetSyncPolicy => set_sync_policy(namespace_id: NamespaceId, policy: SyncPolicy) = |fs| {
+        fs.set_sync_policy(namespace_id, policy).await?
+    };
+
+    /// Finds when a replica is synchronised.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The policy.
+    GetSyncPolicy => get_sync_policy(namespace_id: NamespaceId) -> SyncPolicy = |fs| {
+        fs.get_sync_policy(namespace_id)?
+    };
+
+    /// Synchronises a replica immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    SyncReplica => sync_replica(namespace_id: NamespaceId) = |fs| {
+        fs.sync_replica(namespace_id).await?
+    };
+
+    /// Searches the content of the files in the node's file system.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The search.
+    ///
+    /// # Returns
+    ///
+    /// The matching files, best first.
+    #[cfg(feature = "search")]
+    Search => search(query: String) -> Vec<SearchHit> = |fs| fs.search(&query)?;
+
+    /// Creates a shared folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the folder.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the shared folder's replica.
+    CreateSharedFolder => create_shared_folder(name: String) -> NamespaceId = |fs| {
+        fs.create_shared_folder(name).await?.namespace_id()
+    };
+
+    /// Invites a member to a shared folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the shared folder's replica.
+    ///
+    /// * `name` - The name of the member.
+    ///
+    /// * `writable` - Whether the member may write to the folder.
+    ///
+    /// # Returns
+    ///
+    /// A ticket for the member to join the folder with.
+    InviteToSharedFolder => invite_to_shared_folder(
+        namespace_id: NamespaceId,
+        name: String,
+        writable: bool,
+    ) -> String = |fs| fs.open_shared_folder(namespace_id).await?.invite(&name, writable).await?;
+
+    /// Lists the members of a shared folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the shared folder's replica.
+    ///
+    /// # Returns
+    ///
+    /// The members, by name.
+    ListSharedFolderMembers => list_shared_folder_members(
+        namespace_id: NamespaceId,
+    ) -> Vec<SharedFolderMember> = |fs| fs.open_shared_folder(namespace_id).await?.members().await?;
+
+    /// Removes a member from a shared folder, moving the folder to a new replica the member cannot reach.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the shared folder's replica.
+    ///
+    /// * `name` - The name of the member.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the shared folder's new replica, and a new ticket for each remaining member.
+    RemoveSharedFolderMember => remove_shared_folder_member(
+        namespace_id: NamespaceId,
+        name: String,
+    ) -> (NamespaceId, Vec<(SharedFolderMember, String)>) = |fs| {
+        let mut shared_folder = fs.open_shared_folder(namespace_id).await?;
+        let invitations = shared_folder.remove_member(&name).await?;
+        (shared_folder.namespace_id(), invitations)
+    };
+
+    /// Takes a snapshot of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot.
+    CreateSnapshot => create_snapshot(namespace_id: NamespaceId, label: String) -> Snapshot = |fs| {
+        fs.create_snapshot(namespace_id, &label).await?
+    };
+
+    /// Lists the snapshots of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The snapshots.
+    ListSnapshots => list_snapshots(namespace_id: NamespaceId) -> Vec<Snapshot> = |fs| {
+        fs.list_snapshots(namespace_id).await?
+    };
+
+    /// Gets a snapshot of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot.
+    GetSnapshot => get_snapshot(namespace_id: NamespaceId, label: String) -> Snapshot = |fs| {
+        fs.get_snapshot(namespace_id, &label).await?
+    };
+
+    /// Reads a file as it was when a snapshot was taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The content of the file.
+    ReadFileAtSnapshot => read_file_at_snapshot(
+        namespace_id: NamespaceId,
+        label: String,
+        path: PathBuf,
+    ) -> Bytes = |fs| fs.read_file_at_snapshot(namespace_id, &label, path).await?;
+
+    /// Restores the files under a path as they were when a snapshot was taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    ///
+    /// * `path` - The path of the files.
+    ///
+    /// # Returns
+    ///
+    /// The paths of the restored files.
+    RestoreSnapshot => restore_snapshot(
+        namespace_id: NamespaceId,
+        label: String,
+        path: PathBuf,
+    ) -> Vec<PathBuf> = |fs| fs.restore_snapshot(namespace_id, &label, path).await?;
+
+    /// Deletes a snapshot of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    DeleteSnapshot => delete_snapshot(namespace_id: NamespaceId, label: String) = |fs| {
+        fs.delete_snapshot(namespace_id, &label).await?
+    };
+
+    /// Describes a file or directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path of the file or directory.
+    ///
+    /// # Returns
+    ///
+    /// A description of the file or directory, if it exists.
+    Stat => stat(namespace_id: NamespaceId, path: PathBuf) -> Option<OkuFileInfo> = |fs| {
+        fs.stat(namespace_id, path).await?
+    };
+
+    /// Checks whether a file or directory exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path of the file or directory.
+    ///
+    /// # Returns
+    ///
+    /// Whether the file or directory exists.
+    Exists => exists(namespace_id: NamespaceId, path: PathBuf) -> bool = |fs| {
+        fs.exists(namespace_id, path).await?
+    };
+
+    /// Creates a replica from a template.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica.
+    CreateReplicaFromTemplate => create_replica_from_template(
+        template: ReplicaTemplate,
+    ) -> NamespaceId = |fs| fs.create_replica_from_template(&template).await?;
+
+    /// Reads the manifest of a replica created from a template.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The manifest, if the replica has one.
+    ReadReplicaManifest => read_replica_manifest(
+        namespace_id: NamespaceId,
+    ) -> Option<ReplicaManifest> = |fs| fs.read_replica_manifest(namespace_id).await?;
+
+    /// Imports a replica from a ticket.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket.
+    ///
+    /// * `policy` - The conditions the replica must meet.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the imported replica.
+    AcceptTicket => accept_ticket(ticket: String, policy: AcceptPolicy) -> NamespaceId = |fs| {
+        fs.accept_ticket(&ticket, policy).await?
+    };
+
+    /// Creates a ticket for a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `mode` - Whether the ticket allows writing to the replica.
+    ///
+    /// # Returns
+    ///
+    /// The ticket.
+    CreateReplicaTicket => create_replica_ticket(
+        namespace_id: NamespaceId,
+        mode: ShareMode,
+    ) -> DocTicket = |fs| fs.create_replica_ticket(namespace_id, mode).await?;
+
+    /// Creates a ticket for a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The ticket.
+    CreateFileTicket => create_file_ticket(
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> BlobTicket = |fs| fs.create_file_ticket(namespace_id, path).await?;
+
+    /// Fetches the file a ticket refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket.
+    ///
+    /// # Returns
+    ///
+    /// The content of the file.
+    FetchFileWithFileTicket => fetch_file_with_file_ticket(ticket: String) -> Bytes = |fs| {
+        fs.fetch_file_with_file_ticket(&ticket).await?
+    };
+
+    /// Saves the file a ticket refers to into a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket.
+    ///
+    /// * `namespace_id` - The ID of the replica to save the file into.
+    ///
+    /// * `path` - The path to save the file to.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's content.
+    SaveFileWithFileTicket => save_file_with_file_ticket(
+        ticket: String,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Hash = |fs| fs.save_file_with_file_ticket(&ticket, namespace_id, path).await?;
+
+    /// Lists the deleted files in a replica's trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The deleted files.
+    ListTrash => list_trash(namespace_id: NamespaceId) -> Vec<TrashItem> = |fs| {
+        fs.list_trash(namespace_id).await?
+    };
+
+    /// Restores a deleted file from a replica's trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `item` - The deleted file.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file's content.
+    RestoreFromTrash => restore_from_trash(
+        namespace_id: NamespaceId,
+        item: TrashItem,
+    ) -> Hash = |fs| fs.restore_from_trash(namespace_id, &item).await?;
+
+    /// Removes deleted files from a replica's trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `older_than` - How long ago files must have been deleted to be removed, or none to remove every file.
+    ///
+    /// # Returns
+    ///
+    /// The number of files removed.
+    EmptyTrash => empty_trash(
+        namespace_id: NamespaceId,
+        older_than: Option<Duration>,
+    ) -> usize = |fs| fs.empty_trash(namespace_id, older_than).await?;
+
+    /// Gets the tree of files and directories under a path.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// The tree.
+    GetTree => get_tree(namespace_id: NamespaceId, path: PathBuf) -> DirTree = |fs| {
+        fs.get_tree(namespace_id, path).await?
+    };
+
+    /// Delegates capabilities over a replica to another party.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `audience` - The DID of the party.
+    ///
+    /// * `capabilities` - The capabilities.
+    ///
+    /// * `expiry` - When the capabilities expire, if they do.
+    ///
+    /// # Returns
+    ///
+    /// A UCAN granting the capabilities.
+    DelegateCapability => delegate_capability(
+        namespace_id: NamespaceId,
+        audience: String,
+        capabilities: Vec<Capability>,
+        expiry: Option<DateTime<Utc>>,
+    ) -> Ucan = |fs| fs.delegate_capability(namespace_id, &audience, capabilities, expiry).await?;
+
+    /// Checks whether a UCAN grants a capability.
+    ///
+    /// # Arguments
+    ///
+    /// * `ucan` - The UCAN.
+    ///
+    /// * `action` - The capability.
+    VerifyCapability => verify_capability(ucan: Ucan, action: Capability) = |fs| {
+        fs.verify_capability(&ucan, &action).await?
+    };
+
+    /// Records a UCAN in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `ucan` - The UCAN.
+    ///
+    /// # Returns
+    ///
+    /// The CID of the UCAN.
+    AddCapability => add_capability(namespace_id: NamespaceId, ucan: Ucan) -> String = |fs| {
+        fs.add_capability(namespace_id, &ucan).await?
+    };
+
+    /// Lists the UCANs recorded in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The UCANs.
+    ListCapabilities => list_capabilities(namespace_id: NamespaceId) -> Vec<Ucan> = |fs| {
+        fs.list_capabilities(namespace_id).await?
+    };
+
+    /// Revokes a UCAN recorded in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `cid` - The CID of the UCAN.
+    RevokeCapability => revoke_capability(namespace_id: NamespaceId, cid: String) = |fs| {
+        fs.revoke_capability(namespace_id, &cid).await?
+    };
+
+    /// Records a revocation in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `revocation` - The revocation.
+    AddRevocation => add_revocation(
+        namespace_id: NamespaceId,
+        revocation: Revocation,
+    ) = |fs| fs.add_revocation(namespace_id, &revocation).await?;
+
+    /// Lists the revocations recorded in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The revocations.
+    ListRevocations => list_revocations(namespace_id: NamespaceId) -> Vec<Revocation> = |fs| {
+        fs.list_revocations(namespace_id).await?
+    };
+
+    /// Describes how the node's storage is used.
+    ///
+    /// # Returns
+    ///
+    /// The storage used by each replica, and by the blob store as a whole.
+    StorageReport => storage_report() -> StorageReport = |fs| fs.storage_report().await?;
+
+    /// Gets the size of the node's blob store.
+    ///
+    /// # Returns
+    ///
+    /// The size, in bytes.
+    GetStoreSize => get_store_size() -> u64 = |fs| fs.get_store_size().await?;
+}
+
+impl IpcRequest {
+    /// Whether the request reaches beyond the node's replicas, reading or writing paths on the node's machine, or revealing its secret keys.
+    /// Bundles and tickets granting write access to a replica hold the replica's secret key, and so are treated alike.
+    /// Such requests are refused over the remote control, unless it is configured to allow them.
+    ///
+    /// # Returns
+    ///
+    /// Whether the request needs access to the node's machine.
+    pub fn needs_host_access(&self) -> bool {
+        matches!(
+            self,
+            IpcRequest::Backup(..)
+                | IpcRequest::Restore(..)
+                | IpcRequest::ImportDirectory(..)
+                | IpcRequest::SyncWithLocalDir(..)
+                | IpcRequest::PublishPath(..)
+                | IpcRequest::ExportPublished(..)
+                | IpcRequest::ExportNodeKey(..)
+                | IpcRequest::EncryptionKey(..)
+                | IpcRequest::SetEncryptionKey(..)
+                | IpcRequest::ExportReplicaBundle(_, true)
+                | IpcRequest::CreateReplicaTicket(_, ShareMode::Write)
+                | IpcRequest::InviteToSharedFolder(_, _, true)
+        )
+    }
+}
+
+impl OkuFs {
+    #[cfg(unix)]
+    /// Serves the file system to other processes on this machine through a Unix domain socket, so they can share this node rather than starting their own.
+    /// A stale socket left behind by a node that is no longer running is replaced.
+    /// The socket may only be used by the user running the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the socket.
+    pub async fn serve_ipc(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = path.as_ref();
+        if path.exists() && UnixStream::connect(path).await.is_err() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        self.serve_ipc_listener(listener).await
+    }
+
+    #[cfg(unix)]
+    /// Serves the file system to other processes on this machine through a socket that is already listening, such as one passed in by a service manager.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The listening socket.
+    pub async fn serve_ipc_listener(
+        &self,
+        listener: UnixListener,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let self_clone = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = self_clone.serve_ipc_connection(stream, true).await {
+                    tracing::warn!("{}", e);
+                }
+            });
+        }
+    }
+
+    /// Serves the file system to other machines, so that a headless node can be managed remotely.
+    /// Connections are made directly to the node's ID, on the configured port, and are encrypted and authenticated as connections between peers are.
+    /// Each connection must first present the token, as a message of its own, before sending requests as it would through [`OkuFs::serve_ipc`].
+    /// Requests reaching beyond the node's replicas, as described by [`IpcRequest::needs_host_access`], are refused unless the remote control allows them.
+    /// Unlike the node's other networking, connections are accepted while the node is offline, so that it can be brought back online with [`OkuFs::set_offline`].
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_control` - The port to listen on, and the token connections must present.
+    pub async fn serve_remote(
+        &self,
+        remote_control: RemoteControlConfig,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // The node's own endpoint only accepts the protocols of the node, so the remote control is served through another with the same identity.
+        let endpoint = MagicEndpoint::builder()
+            .secret_key(self.node.magic_endpoint().secret_key().clone())
+            .alpns(vec![ALPN_REMOTE_CONTROL.to_vec()])
+            .relay_mode(RelayMode::Disabled)
+            .bind(remote_control.port)
+            .await?;
+        while let Some(connecting) = endpoint.accept().await {
+            let self_clone = self.clone();
+            let token = remote_control.token.clone();
+            let allow_host_access = remote_control.allow_host_access;
+            tokio::spawn(async move {
+                if let Err(e) = self_clone
+                    .serve_remote_connection(connecting, &token, allow_host_access)
+                    .await
+                {
+                    tracing::warn!("{}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Carries out the requests sent over a connection to the remote control, once it has presented the token.
+    ///
+    /// # Arguments
+    ///
+    /// * `connecting` - The incoming connection.
+    ///
+    /// * `token` - The token the connection must present.
+    ///
+    /// * `allow_host_access` - Whether the connection may send requests reaching beyond the node's replicas.
+    async fn serve_remote_connection(
+        &self,
+        connecting: quinn::Connecting,
+        token: &str,
+        allow_host_access: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let connection = connecting.await?;
+        let peer_node_id = get_remote_node_id(&connection)?;
+        let (send_stream, receive_stream) = connection.accept_bi().await?;
+        let mut stream = BufReader::new(tokio::io::join(receive_stream, send_stream));
+        let authenticated = match read_message(&mut stream, MAX_REMOTE_CONTROL_TOKEN_LENGTH).await {
+            Ok(Some(presented_token)) => {
+                token_matches(&String::from_utf8_lossy(&presented_token), token)
+            }
+            _ => false,
+        };
+        let result: Result<(), Box<dyn Error + Send + Sync>> = match authenticated {
+            true => Ok(()),
+            false => Err("invalid token".into()),
+        };
+        write_message(&mut stream, &encode_reply(result)?).await?;
+        if !authenticated {
+            tracing::warn!("Rejected remote control connection from {}", peer_node_id);
+            return Ok(());
+        }
+        self.serve_ipc_connection(stream, allow_host_access).await
+    }
+
+    /// Gets the location of the socket a node is served through by default.
+    ///
+    /// # Returns
+    ///
+    /// The path of the socket within the file system's directory.
+    pub fn ipc_socket_path(&self) -> PathBuf {
+        self.config.path.join(IPC_SOCKET_FILE_NAME)
+    }
+
+    /// Carries out the requests sent over a connection, one per message, until the connection closes.
+    /// Requests may be no longer than the node's write limit allows, leaving a frame's worth of room for the request's other arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The connection.
+    ///
+    /// * `allow_host_access` - Whether the connection may send requests reaching beyond the node's replicas.
+    async fn serve_ipc_connection(
+        &self,
+        stream: impl AsyncRead + AsyncWrite,
+        allow_host_access: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let max_request_length = self
+            .config
+            .limits
+            .received_size_limit()
+            .saturating_add(MAX_IPC_FRAME_LENGTH as u64);
+        while let Some(request) = read_message(&mut reader, max_request_length).await? {
+            let reply = match postcard::from_bytes(&request) {
+                Ok(request) => {
+                    self.respond_to_ipc_request(request, allow_host_access)
+                        .await?
+                }
+                Err(e) => encode_reply::<()>(Err(e.into()))?,
+            };
+            write_message(&mut writer, &reply).await?;
+        }
+        Ok(())
+    }
+
+    /// Carries out a request sent by another process, unless the connection it was sent over may not make it.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request.
+    ///
+    /// * `allow_host_access` - Whether requests reaching beyond the node's replicas may be carried out.
+    ///
+    /// # Returns
+    ///
+    /// The encoded result of the request.
+    async fn respond_to_ipc_request(
+        &self,
+        request: IpcRequest,
+        allow_host_access: bool,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        if !allow_host_access && request.needs_host_access() {
+            return encode_reply::<()>(Err(OkuFsError::IpcRequestRefused.into()));
+        }
+        self.carry_out_ipc_request(request).await
+    }
+}
+
+#[derive(Debug)]
+/// A connection to a node running in another process, on this machine or, through its remote control, on another.
+pub struct OkuFsClient {
+    /// The connection to the node.
+    stream: Mutex<BufReader<Box<dyn IpcStream>>>,
+    /// The endpoint a connection to a node's remote control is made through, kept open for as long as the connection.
+    _endpoint: Option<MagicEndpoint>,
+}
+
+impl OkuFsClient {
+    /// Connects to a node running in another process on this machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the socket the node is served through.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the node.
+    #[cfg(unix)]
+    pub async fn connect(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let stream: Box<dyn IpcStream> = Box::new(UnixStream::connect(path).await?);
+        Ok(OkuFsClient {
+            stream: Mutex::new(BufReader::new(stream)),
+            _endpoint: None,
+        })
+    }
+
+    /// Connects to a node on another machine through its remote control.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_addr` - The ID of the node, as given by [`OkuFs::node_id`], and the addresses its remote control listens on.
+    ///
+    /// * `token` - The token the node's remote control expects.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the node.
+    pub async fn connect_remote(
+        node_addr: NodeAddr,
+        token: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let endpoint = MagicEndpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind(0)
+            .await?;
+        let connection = endpoint.connect(node_addr, ALPN_REMOTE_CONTROL).await?;
+        let (send_stream, receive_stream) = connection.open_bi().await?;
+        let stream: Box<dyn IpcStream> = Box::new(tokio::io::join(receive_stream, send_stream));
+        let mut stream = BufReader::new(stream);
+        write_message(&mut stream, token.as_bytes()).await?;
+        read_reply::<()>(&mut stream).await?;
+        Ok(OkuFsClient {
+            stream: Mutex::new(stream),
+            _endpoint: Some(endpoint),
+        })
+    }
+
+    /// Sends a request to the node and waits for its result.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request.
+    ///
+    /// # Returns
+    ///
+    /// The result of the request, of the type returned by the [`OkuFsClient`] method matching it.
+    pub async fn request<T: DeserializeOwned>(
+        &self,
+        request: IpcRequest,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let request = postcard::to_stdvec(&request)?;
+        let mut stream = self.stream.lock().await;
+        write_message(&mut *stream, &request).await?;
+        read_reply(&mut *stream).await
+    }
+}
+
+/// Encodes the result of a request, to be sent back to the process that made it.
+///
+/// # Arguments
+///
+/// * `result` - The result of the request.
+///
+/// # Returns
+///
+/// The encoded result, with any error described as text.
+fn encode_reply<T: Serialize>(
+    result: Result<T, Box<dyn Error + Send + Sync>>,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    Ok(postcard::to_stdvec(&result.map_err(|e| e.to_string()))?)
+}
+
+/// Waits for a node to reply over a connection.
+/// Replies are not limited in length, as the node is trusted to send no more than was asked for.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the node.
+///
+/// # Returns
+///
+/// The result of the latest request sent over the connection.
+async fn read_reply<T: DeserializeOwned>(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    let reply = read_message(stream, u64::MAX)
+        .await?
+        .ok_or_else(|| OkuFsError::IpcRequestFailed(String::from("connection closed")))?;
+    let result: Result<T, String> = postcard::from_bytes(&reply)?;
+    result.map_err(|e| OkuFsError::IpcRequestFailed(e).into())
+}
+
+/// Sends a message over a connection, as frames no longer than [`MAX_IPC_FRAME_LENGTH`], each preceded by its length.
+/// A frame shorter than the limit ends the message, so a message whose length is a multiple of the limit is followed by an empty frame.
+///
+/// # Arguments
+///
+/// * `writer` - The connection.
+///
+/// * `message` - The message.
+async fn write_message(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &[u8],
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut remaining = message;
+    loop {
+        let (frame, rest) = remaining.split_at(remaining.len().min(MAX_IPC_FRAME_LENGTH));
+        writer.write_u32(frame.len() as u32).await?;
+        writer.write_all(frame).await?;
+        if frame.len() < MAX_IPC_FRAME_LENGTH {
+            break;
+        }
+        remaining = rest;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Receives a message from a connection, frame by frame, refusing frames longer than [`MAX_IPC_FRAME_LENGTH`] and messages longer than a limit.
+/// Memory is only taken up as frames arrive, rather than as much as a message claims to need.
+///
+/// # Arguments
+///
+/// * `reader` - The connection.
+///
+/// * `limit` - The length, in bytes, a message may not exceed.
+///
+/// # Returns
+///
+/// The message, or `None` if the connection closed.
+async fn read_message(
+    reader: &mut (impl AsyncRead + Unpin),
+    limit: u64,
+) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+    let mut message = Vec::new();
+    loop {
+        let frame_length = match reader.read_u32().await {
+            Ok(frame_length) => frame_length as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && message.is_empty() => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if frame_length > MAX_IPC_FRAME_LENGTH {
+            return Err(OkuFsError::IpcRequestFailed(format!(
+                "frame longer than {} bytes",
+                MAX_IPC_FRAME_LENGTH
+            ))
+            .into());
+        }
+        if (message.len() + frame_length) as u64 > limit {
+            return Err(OkuFsError::IpcRequestFailed(format!(
+                "message longer than {} bytes",
+                limit
+            ))
+            .into());
+        }
+        let start = message.len();
+        message.resize(start + frame_length, 0);
+        reader.read_exact(&mut message[start..]).await?;
+        if frame_length < MAX_IPC_FRAME_LENGTH {
+            return Ok(Some(message));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::start_test_fs;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    /// Sends a message over an in-memory connection, and receives it at the other end.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message.
+    ///
+    /// * `limit` - The length, in bytes, the received message may not exceed.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes sent, and the message received.
+    async fn send_and_receive(
+        message: &[u8],
+        limit: u64,
+    ) -> (usize, Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>>) {
+        let mut sent = Vec::new();
+        write_message(&mut sent, message).await.unwrap();
+        let received = read_message(&mut &sent[..], limit).await;
+        (sent.len(), received)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn frame_messages_at_frame_length_boundary() {
+        for length in [
+            0,
+            1,
+            MAX_IPC_FRAME_LENGTH - 1,
+            MAX_IPC_FRAME_LENGTH,
+            MAX_IPC_FRAME_LENGTH + 1,
+            MAX_IPC_FRAME_LENGTH * 2,
+        ] {
+            let message = vec![7u8; length];
+            let (sent_length, received) = send_and_receive(&message, u64::MAX).await;
+            assert_eq!(received.unwrap(), Some(message));
+            // A message filling its last frame is followed by an empty frame to end it.
+            let frame_count = length / MAX_IPC_FRAME_LENGTH + 1;
+            assert_eq!(sent_length, length + frame_count * 4);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_consecutive_messages() {
+        let mut sent = Vec::new();
+        let first = vec![1u8; MAX_IPC_FRAME_LENGTH];
+        write_message(&mut sent, &first).await.unwrap();
+        write_message(&mut sent, b"second").await.unwrap();
+        let mut reader = &sent[..];
+        assert_eq!(
+            read_message(&mut reader, u64::MAX).await.unwrap(),
+            Some(first)
+        );
+        assert_eq!(
+            read_message(&mut reader, u64::MAX).await.unwrap(),
+            Some(b"second".to_vec())
+        );
+        assert_eq!(read_message(&mut reader, u64::MAX).await.unwrap(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reject_messages_over_limit() {
+        let message = vec![7u8; MAX_IPC_FRAME_LENGTH + 1];
+        let (_, received) = send_and_receive(&message, message.len() as u64).await;
+        assert!(received.is_ok());
+        let (_, received) = send_and_receive(&message, message.len() as u64 - 1).await;
+        assert!(received.is_err());
+        // A frame claiming to be longer than allowed is refused before it is read.
+        let mut oversized_frame = Vec::new();
+        oversized_frame.extend_from_slice(&(MAX_IPC_FRAME_LENGTH as u32 + 1).to_be_bytes());
+        assert!(read_message(&mut &oversized_frame[..], u64::MAX)
+            .await
+            .is_err());
+        // A connection closing partway through a message is an error, not the end of the connection.
+        let mut truncated = Vec::new();
+        write_message(&mut truncated, &message).await.unwrap();
+        truncated.truncate(MAX_IPC_FRAME_LENGTH + 4);
+        assert!(read_message(&mut &truncated[..], u64::MAX).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refuse_host_access_unless_allowed() {
+        let oku_fs = start_test_fs("ipc-host-access").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        assert!(IpcRequest::ExportNodeKey(String::new()).needs_host_access());
+        assert!(IpcRequest::ExportReplicaBundle(namespace_id, true).needs_host_access());
+        assert!(!IpcRequest::ExportReplicaBundle(namespace_id, false).needs_host_access());
+        assert!(
+            IpcRequest::CreateReplicaTicket(namespace_id, ShareMode::Write).needs_host_access()
+        );
+        assert!(
+            !IpcRequest::CreateReplicaTicket(namespace_id, ShareMode::Read).needs_host_access()
+        );
+        assert!(
+            IpcRequest::InviteToSharedFolder(namespace_id, String::new(), true).needs_host_access()
+        );
+        assert!(
+            !IpcRequest::InviteToSharedFolder(namespace_id, String::new(), false)
+                .needs_host_access()
+        );
+        assert!(!IpcRequest::ListReplicas().needs_host_access());
+        let request = IpcRequest::EncryptionKey(namespace_id);
+        let reply = oku_fs
+            .respond_to_ipc_request(request.clone(), false)
+            .await
+            .unwrap();
+        let reply: Result<Option<[u8; 32]>, String> = postcard::from_bytes(&reply).unwrap();
+        assert_eq!(reply, Err(OkuFsError::IpcRequestRefused.to_string()));
+        let reply = oku_fs.respond_to_ipc_request(request, true).await.unwrap();
+        let reply: Result<Option<[u8; 32]>, String> = postcard::from_bytes(&reply).unwrap();
+        assert_eq!(reply, Ok(None));
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reject_remote_control_with_wrong_token() {
+        let oku_fs = start_test_fs("remote-token").await;
+        let port = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let remote_control = RemoteControlConfig {
+            port,
+            token: String::from("token"),
+            allow_host_access: false,
+        };
+        let serving_fs = oku_fs.clone();
+        let server = tokio::spawn(async move { serving_fs.serve_remote(remote_control).await });
+        let node_addr = NodeAddr::from_parts(
+            oku_fs.node_id(),
+            None,
+            vec![SocketAddr::from((Ipv4Addr::LOCALHOST, port))],
+        );
+        assert!(
+            OkuFsClient::connect_remote(node_addr.clone(), "wrong token")
+                .await
+                .is_err()
+        );
+        // Tokens longer than allowed are refused without being compared.
+        let long_token = "t".repeat(MAX_REMOTE_CONTROL_TOKEN_LENGTH as usize + 1);
+        assert!(OkuFsClient::connect_remote(node_addr.clone(), &long_token)
+            .await
+            .is_err());
+        let client = OkuFsClient::connect_remote(node_addr, "token")
+            .await
+            .unwrap();
+        let namespace_id = client.create_replica().await.unwrap();
+        assert!(client.encryption_key(namespace_id).await.is_err());
+        server.abort();
+        oku_fs.shutdown();
+    }
+}
@@ -0,0 +1,147 @@
+use crate::fs::{is_metadata_key, OkuFs};
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::Hash,
+    client::Entry,
+    sync::{AuthorId, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, io::Write, path::PathBuf};
+
+/// The directory, within the file system's directory, holding the journals of overwritten entries.
+pub const JOURNAL_DIRECTORY: &str = "journal";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A record of one version of a file being superseded by another during synchronisation.
+pub struct OverwriteRecord {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The ID of the author of the superseded version.
+    pub superseded_author: AuthorId,
+    /// The hash of the superseded version's content.
+    pub superseded_hash: Hash,
+    /// The time the superseded version was written, in microseconds since the Unix epoch.
+    pub superseded_timestamp: u64,
+    /// The ID of the author of the version that took precedence.
+    pub winning_author: AuthorId,
+    /// The hash of the content of the version that took precedence.
+    pub winning_hash: Hash,
+    /// The time the version that took precedence was written, in microseconds since the Unix epoch.
+    pub winning_timestamp: u64,
+    /// The time the overwrite was recorded, in microseconds since the Unix epoch.
+    pub recorded_at: u64,
+}
+
+impl OkuFs {
+    /// Lists the versions of files in a replica that were superseded during synchronisation.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `since` - The earliest time of interest, in microseconds since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// The overwrites recorded at or after the given time, from oldest to newest.
+    pub fn list_overwrites(
+        &self,
+        namespace_id: NamespaceId,
+        since: u64,
+    ) -> Result<Vec<OverwriteRecord>, Box<dyn Error + Send + Sync>> {
+        let journal = match std::fs::read_to_string(self.journal_path(namespace_id)) {
+            Ok(journal) => journal,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut records = Vec::new();
+        for line in journal.lines().filter(|line| !line.is_empty()) {
+            let record: OverwriteRecord = serde_json::from_str(line)?;
+            if record.recorded_at >= since {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Records an overwrite in a replica's journal if an entry received from a peer supersedes, or is superseded by, another author's version of the same file.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `entry` - The entry received from a peer.
+    pub(crate) async fn record_overwrite(
+        &self,
+        namespace_id: NamespaceId,
+        entry: &Entry,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if is_metadata_key(entry.key()) {
+            return Ok(());
+        }
+        let document = self.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::key_exact(entry.key())
+            .include_empty()
+            .build();
+        let versions = document.get_many(query).await?;
+        pin_mut!(versions);
+        let mut latest_other: Option<Entry> = None;
+        while let Some(version) = versions.next().await {
+            let version = version?;
+            if version.author() != entry.author()
+                && latest_other
+                    .as_ref()
+                    .is_none_or(|latest| version.timestamp() > latest.timestamp())
+            {
+                latest_other = Some(version);
+            }
+        }
+        let Some(other) = latest_other else {
+            return Ok(());
+        };
+        if other.content_hash() == entry.content_hash() {
+            return Ok(());
+        }
+        let (superseded, winning) = if entry.timestamp() > other.timestamp() {
+            (&other, entry)
+        } else {
+            (entry, &other)
+        };
+        let record = OverwriteRecord {
+            path: self.entry_path(entry.key()),
+            superseded_author: superseded.author(),
+            superseded_hash: superseded.content_hash(),
+            superseded_timestamp: superseded.timestamp(),
+            winning_author: winning.author(),
+            winning_hash: winning.content_hash(),
+            winning_timestamp: winning.timestamp(),
+            recorded_at: chrono::Utc::now().timestamp_micros() as u64,
+        };
+        let journal_path = self.journal_path(namespace_id);
+        if let Some(parent) = journal_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut journal = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(journal_path)?;
+        writeln!(journal, "{}", serde_json::to_string(&record)?)?;
+        Ok(())
+    }
+
+    /// Gets the location of a replica's journal of overwritten entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The path of the journal file.
+    fn journal_path(&self, namespace_id: NamespaceId) -> PathBuf {
+        self.config
+            .path
+            .join(JOURNAL_DIRECTORY)
+            .join(format!("{}.jsonl", namespace_id))
+    }
+}
@@ -0,0 +1,297 @@
+use crate::error::OkuFsError;
+use crate::fs::{OkuFs, OkuFsConfig};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crypto_secretbox::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Key, Nonce, XSalsa20Poly1305,
+};
+use iroh::{
+    net::key::SecretKey,
+    sync::{Author, AuthorId},
+    util::path::IrohPaths,
+};
+use rand_core::RngCore;
+use std::{error::Error, io::Write, path::Path};
+
+/// The length, in bytes, of the salt the key protecting an exported secret key is derived with.
+const SALT_LENGTH: usize = 16;
+
+/// The length, in bytes, of the nonce the exported secret key is encrypted with.
+const NONCE_LENGTH: usize = 24;
+
+/// Derives the key protecting an exported secret key from a passphrase.
+///
+/// # Arguments
+///
+/// * `passphrase` - The passphrase.
+///
+/// * `salt` - The salt the key is derived with.
+///
+/// # Returns
+///
+/// The key protecting the exported secret key.
+fn passphrase_key(
+    passphrase: &str,
+    salt: &[u8],
+) -> Result<XSalsa20Poly1305, Box<dyn Error + Send + Sync>> {
+    let mut key = [0u8; 32];
+    scrypt::scrypt(
+        passphrase.as_bytes(),
+        salt,
+        &scrypt::Params::recommended(),
+        &mut key,
+    )
+    .map_err(|e| OkuFsError::InvalidSecretKey(e.to_string()))?;
+    Ok(XSalsa20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// Encrypts data with a passphrase, so that it can be kept outside the file system.
+///
+/// # Arguments
+///
+/// * `data` - The data to encrypt.
+///
+/// * `passphrase` - The passphrase to encrypt the data with.
+///
+/// # Returns
+///
+/// The salt the key was derived with, the nonce, and the encrypted data.
+pub(crate) fn encrypt_with_passphrase(
+    data: &[u8],
+    passphrase: &str,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XSalsa20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = passphrase_key(passphrase, &salt)?
+        .encrypt(&nonce, data)
+        .map_err(|e| OkuFsError::InvalidSecretKey(e.to_string()))?;
+    Ok([&salt[..], &nonce[..], &ciphertext[..]].concat())
+}
+
+/// Decrypts data encrypted with [`encrypt_with_passphrase`].
+///
+/// # Arguments
+///
+/// * `encrypted_data` - The salt, nonce, and encrypted data.
+///
+/// * `passphrase` - The passphrase the data was encrypted with.
+///
+/// # Returns
+///
+/// The data.
+pub(crate) fn decrypt_with_passphrase(
+    encrypted_data: &[u8],
+    passphrase: &str,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    if encrypted_data.len() < SALT_LENGTH + NONCE_LENGTH {
+        return Err(OkuFsError::InvalidSecretKey("the key is too short".to_string()).into());
+    }
+    let (salt, encrypted_data) = encrypted_data.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = encrypted_data.split_at(NONCE_LENGTH);
+    Ok(passphrase_key(passphrase, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| OkuFsError::InvalidSecretKey("the passphrase is incorrect".to_string()))?)
+}
+
+/// Writes a file that only the user running the node may read, such as one holding secret keys.
+///
+/// # Arguments
+///
+/// * `path` - The path on disk of the file.
+///
+/// * `content` - The content of the file.
+pub(crate) fn write_private_file(
+    path: &Path,
+    content: &[u8],
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // The mode is only given to new files, so an existing file is restricted as well.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Encrypts a secret key with a passphrase, so that it can be kept outside the file system.
+///
+/// # Arguments
+///
+/// * `secret_key` - The secret key.
+///
+/// * `passphrase` - The passphrase to encrypt the secret key with.
+///
+/// # Returns
+///
+/// The encrypted secret key, encoded as base64.
+pub fn encrypt_secret_key(
+    secret_key: &[u8; 32],
+    passphrase: &str,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    Ok(STANDARD.encode(encrypt_with_passphrase(secret_key, passphrase)?))
+}
+
+/// Decrypts a secret key encrypted with [`encrypt_secret_key`].
+///
+/// # Arguments
+///
+/// * `encrypted_key` - The encrypted secret key, encoded as base64.
+///
+/// * `passphrase` - The passphrase the secret key was encrypted with.
+///
+/// # Returns
+///
+/// The secret key.
+pub fn decrypt_secret_key(
+    encrypted_key: &str,
+    passphrase: &str,
+) -> Result<[u8; 32], Box<dyn Error + Send + Sync>> {
+    let encrypted_key = STANDARD.decode(encrypted_key.trim())?;
+    let secret_key = decrypt_with_passphrase(&encrypted_key, passphrase)?;
+    Ok(secret_key
+        .as_slice()
+        .try_into()
+        .map_err(|_| OkuFsError::InvalidSecretKey("the key has the wrong length".to_string()))?)
+}
+
+/// Decrypts a node's secret key exported with [`OkuFs::export_node_key`], so that a file system can be started with it.
+///
+/// # Arguments
+///
+/// * `encrypted_key` - The encrypted secret key, encoded as base64.
+///
+/// * `passphrase` - The passphrase the secret key was encrypted with.
+///
+/// # Returns
+///
+/// The node's secret key, to be given to [`crate::fs::OkuFsConfigBuilder::node_secret_key`].
+pub fn decrypt_node_key(
+    encrypted_key: &str,
+    passphrase: &str,
+) -> Result<SecretKey, Box<dyn Error + Send + Sync>> {
+    Ok(SecretKey::from_bytes(&decrypt_secret_key(
+        encrypted_key,
+        passphrase,
+    )?))
+}
+
+/// Decrypts an author's secret key exported with [`OkuFs::export_author_key`], so that a file system can be started holding it.
+///
+/// # Arguments
+///
+/// * `encrypted_key` - The encrypted secret key, encoded as base64.
+///
+/// * `passphrase` - The passphrase the secret key was encrypted with.
+///
+/// # Returns
+///
+/// The author, to be given to [`crate::fs::OkuFsConfigBuilder::author_key`].
+pub fn decrypt_author_key(
+    encrypted_key: &str,
+    passphrase: &str,
+) -> Result<Author, Box<dyn Error + Send + Sync>> {
+    Ok(Author::from_bytes(&decrypt_secret_key(
+        encrypted_key,
+        passphrase,
+    )?))
+}
+
+impl OkuFs {
+    /// Exports the secret key the node is identified by, encrypted with a passphrase.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase to encrypt the secret key with.
+    ///
+    /// # Returns
+    ///
+    /// The encrypted secret key, encoded as base64.
+    pub fn export_node_key(
+        &self,
+        passphrase: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        encrypt_secret_key(
+            &self.node.magic_endpoint().secret_key().to_bytes(),
+            passphrase,
+        )
+    }
+
+    /// Exports the secret key of an author held by a file system, encrypted with a passphrase.
+    /// Author keys are only readable from the store while it is not open, so the file system must not be running.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the file system holding the author.
+    ///
+    /// * `author_id` - The ID of the author.
+    ///
+    /// * `passphrase` - The passphrase to encrypt the secret key with.
+    ///
+    /// # Returns
+    ///
+    /// The encrypted secret key, encoded as base64.
+    pub fn export_author_key(
+        config: &OkuFsConfig,
+        author_id: AuthorId,
+        passphrase: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let docs_store = iroh::sync::store::fs::Store::persistent(
+            IrohPaths::DocsDatabase.with_root(
+                config
+                    .storage_backend
+                    .node_path(config)
+                    .ok_or(OkuFsError::StorageNotOnDisk)?,
+            ),
+        )?;
+        let author = docs_store
+            .get_author(&author_id)?
+            .ok_or(OkuFsError::AuthorNotFound(author_id.to_string()))?;
+        encrypt_secret_key(&author.to_bytes(), passphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_key_round_trip() {
+        let secret_key = [7u8; 32];
+        let encrypted_key = encrypt_secret_key(&secret_key, "passphrase").unwrap();
+        // Keys are often pasted with surrounding whitespace.
+        assert_eq!(
+            decrypt_secret_key(&format!(" {}\n", encrypted_key), "passphrase").unwrap(),
+            secret_key
+        );
+        let error = decrypt_secret_key(&encrypted_key, "another passphrase").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::InvalidSecretKey(_))
+        ));
+    }
+
+    #[test]
+    fn reject_malformed_secret_key() {
+        assert!(decrypt_secret_key("not base64!", "passphrase").is_err());
+        let truncated = STANDARD.encode([0u8; SALT_LENGTH + NONCE_LENGTH - 1]);
+        let error = decrypt_secret_key(&truncated, "passphrase").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::InvalidSecretKey(_))
+        ));
+    }
+}
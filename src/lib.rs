@@ -2,9 +2,149 @@
 #![feature(doc_auto_cfg)]
 #![warn(missing_docs)]
 
+/// Human-readable names for replicas.
+pub mod alias;
+/// A JSON API over HTTP for applications using a running node.
+#[cfg(feature = "api")]
+pub mod api;
+/// Export of replicas to archives.
+pub mod archive;
+/// A local record of the changes made to the file system.
+pub mod audit;
+/// The authors a file system can write as.
+pub mod author;
+/// Reporting of how much of each file's content is held locally.
+pub mod availability;
+/// Backup and restoration of a node's identity and library.
+pub mod backup;
+/// Access to content by its hash.
+pub mod blob;
+/// Standalone bundles of replicas for offline distribution.
+pub mod bundle;
+/// Exchange of replicas with IPFS tooling as CAR files.
+pub mod car;
+/// Coalescing of rapid successive writes to the same file.
+pub mod coalesce;
+/// Groups of files published and fetched together under a single hash.
+pub mod collection;
+/// A unified view over several replicas.
+pub mod composite;
+/// Transparent compression of file contents.
+pub mod compression;
+/// Detection and resolution of files written differently by several authors.
+pub mod conflict;
+/// Running the file system as a system service.
+#[cfg(all(unix, feature = "daemon"))]
+pub mod daemon;
+/// Changes made to the files in replicas between states.
+pub mod diff;
 /// Content discovery and retrieval.
 pub mod discovery;
+/// Resolution and publication of domain names pointing to replicas.
+pub mod dns;
+/// Downloading of replica content from several peers at once.
+pub mod download;
+/// Choice of which files' content is downloaded into replicas.
+pub mod download_policy;
+/// Encryption of replica contents at rest.
+pub mod encryption;
 /// Errors originating in the Oku file system implementation.
 pub mod error;
+/// Events occurring in the file system.
+pub mod event;
+/// A C interface to the file system, for embedding it in applications not written in Rust.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// Metadata attached to files, held separately from their content.
+pub mod file_metadata;
+/// Following of replicas, keeping them synchronised with the changes made by their peers.
+pub mod follow;
+/// Temporary read-only freezing of replicas.
+pub mod freeze;
 /// An instance of an Oku file system.
 pub mod fs;
+/// Garbage collection of content no longer referenced by any replica.
+pub mod gc;
+/// Patterns matching the paths of files.
+pub mod glob;
+/// Access to the versions of files held in replicas.
+pub mod history;
+/// Helpers shared by the servers making the file system available to other programs.
+pub(crate) mod http_util;
+/// Checks of the local store's integrity.
+pub mod integrity;
+/// Sharing of a running node with other processes, on the same machine or on others.
+pub mod ipc;
+/// Journals of file versions superseded during synchronisation.
+pub mod journal;
+/// Export and import of the node's and authors' secret keys.
+pub mod keys;
+/// Limits on the resources used by the file system, for devices with little memory.
+pub mod limits;
+/// Filtered listings of the files in replicas.
+pub mod list;
+/// Exchange of files between replicas and directories on disk.
+pub mod local;
+/// Merging of one replica's files into another.
+pub mod merge;
+/// Configuration of how the node reaches, and is reached by, other nodes.
+pub mod network;
+/// Statistics describing what the node is doing on the network.
+pub mod network_stats;
+/// Hooks for observing file system operations.
+pub mod observer;
+/// Disabling of all networking, while keeping local access to replicas.
+pub mod offline;
+/// Listing, blocking, and preferring of the peers replicas are fetched from.
+pub mod peers;
+/// Replicas kept synchronised with peers without being asked to.
+pub mod pin;
+/// Replicas withheld from announcements, shared only with tickets.
+pub mod privacy;
+/// Profiles describing authors.
+pub mod profile;
+/// Publication of standalone content outside of any replica, to be fetched by its hash.
+pub mod publish;
+/// Deletion of every version of files, by every author.
+pub mod purge;
+/// Queries over the files in replicas, filtering and sorting them by their attributes.
+pub mod query;
+/// Relaying of requests for content to nodes unable to accept incoming connections.
+pub mod relay;
+/// Repair of replicas whose content is missing or damaged, by fetching it again from peers.
+pub mod repair;
+/// Resumption of imports of replicas interrupted before their content was fetched in full.
+pub mod resume;
+/// Background synchronisation of replicas on a schedule.
+pub mod schedule;
+/// Full-text search of the files held in replicas.
+#[cfg(feature = "search")]
+pub mod search;
+/// Access to replicas over SFTP.
+#[cfg(feature = "sftp")]
+pub mod sftp;
+/// Folders shared between several people, with a record of their members.
+pub mod shared_folder;
+/// Records of the files in replicas at moments in time.
+pub mod snapshot;
+/// Descriptions of the files and directories within replicas.
+pub mod stat;
+/// Places the data of the node backing the file system can be kept.
+pub mod storage;
+/// Templates for bootstrapping replica layouts.
+pub mod template;
+/// Inspection and acceptance of replica tickets.
+pub mod ticket;
+/// Timeouts and cancellation of network operations.
+pub mod timeout;
+/// Deleted files kept so that they can be restored.
+pub mod trash;
+/// Nested views of the directories and files within replicas.
+pub mod tree;
+/// Capability tokens authorising writes to replicas.
+pub mod ucan;
+/// Monitoring of storage usage against configured limits.
+pub mod usage;
+/// Access to replicas over WebDAV.
+#[cfg(feature = "webdav")]
+pub mod webdav;
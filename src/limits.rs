@@ -0,0 +1,342 @@
+use crate::encryption::ENCRYPTION_OVERHEAD;
+use crate::error::OkuFsError;
+use crate::fs::{is_directory_marker, is_metadata_key, OkuFs};
+use crate::list::ListOptions;
+use bytes::Bytes;
+use futures::StreamExt;
+use iroh::{
+    bytes::Hash,
+    client::{mem::Doc, Entry},
+    sync::NamespaceId,
+    ticket::DocTicket,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::PathBuf, time::Duration};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// How long a replica may hold one of the limited sync slots while waiting for its first sync to finish.
+pub const SYNC_SLOT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The largest file, in bytes, read into memory at once on constrained devices.
+pub const CONSTRAINED_MAX_READ_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The largest content, in bytes, decoded into memory from a form supplied by others, such as compressed content or CAR files, when no read limit is configured.
+/// Such content can expand far beyond its encoded size, so it is always limited.
+pub const DEFAULT_MAX_DECODED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// The largest content, in bytes, received into memory at once from clients of the file system's servers, such as the body of a request, when no write limit is configured.
+pub const DEFAULT_MAX_RECEIVED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// The largest file, in bytes, written at once on constrained devices.
+pub const CONSTRAINED_MAX_WRITE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The most replicas synced at once on constrained devices.
+pub const CONSTRAINED_MAX_CONCURRENT_SYNCS: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Limits on the resources used by the file system, for running on devices with little memory.
+pub struct ResourceLimits {
+    /// The most replicas fetched from peers at once. If unspecified, fetches are not limited.
+    #[serde(default)]
+    pub max_concurrent_syncs: Option<usize>,
+    /// Whether in-memory caches, such as those of the search index, are kept.
+    #[serde(default = "default_true")]
+    pub caches: bool,
+    /// The largest file, in bytes, read into memory at once. Larger files can still be read in ranges.
+    #[serde(default)]
+    pub max_read_size: Option<u64>,
+    /// The largest file, in bytes, written at once. If unspecified, writes are not limited, though content received by the file system's servers is still limited to [`DEFAULT_MAX_RECEIVED_SIZE`].
+    #[serde(default)]
+    pub max_write_size: Option<u64>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits {
+            max_concurrent_syncs: None,
+            caches: true,
+            max_read_size: None,
+            max_write_size: None,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// Limits suited to devices with little memory, such as single-board computers.
+    ///
+    /// # Returns
+    ///
+    /// Limits capping concurrent syncs, reads, and writes, with caches disabled.
+    pub fn constrained() -> Self {
+        ResourceLimits {
+            max_concurrent_syncs: Some(CONSTRAINED_MAX_CONCURRENT_SYNCS),
+            caches: false,
+            max_read_size: Some(CONSTRAINED_MAX_READ_SIZE),
+            max_write_size: Some(CONSTRAINED_MAX_WRITE_SIZE),
+        }
+    }
+
+    /// Creates the semaphore handing out slots for syncing replicas.
+    ///
+    /// # Returns
+    ///
+    /// A semaphore with one permit per replica allowed to sync at once.
+    pub(crate) fn sync_slots(&self) -> Semaphore {
+        Semaphore::new(
+            self.max_concurrent_syncs
+                .unwrap_or(Semaphore::MAX_PERMITS)
+                .clamp(1, Semaphore::MAX_PERMITS),
+        )
+    }
+
+    /// Checks that a read is within the configured limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The number of bytes to be read.
+    pub(crate) fn check_read(&self, len: u64) -> Result<(), OkuFsError> {
+        match self.max_read_size {
+            Some(max_read_size) if len > max_read_size => {
+                Err(OkuFsError::ReadLimitExceeded(len, max_read_size))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a write is within the configured limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The number of bytes to be written.
+    pub(crate) fn check_write(&self, len: u64) -> Result<(), OkuFsError> {
+        match self.max_write_size {
+            Some(max_write_size) if len > max_write_size => {
+                Err(OkuFsError::WriteLimitExceeded(len, max_write_size))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Gets the most content, in bytes, that may be received into memory from a client of the file system's servers.
+    ///
+    /// # Returns
+    ///
+    /// The write limit, or [`DEFAULT_MAX_RECEIVED_SIZE`] if none is configured.
+    pub(crate) fn received_size_limit(&self) -> u64 {
+        self.max_write_size.unwrap_or(DEFAULT_MAX_RECEIVED_SIZE)
+    }
+
+    /// Gets the most content, in bytes, that may be decoded into memory from a form supplied by others.
+    ///
+    /// # Returns
+    ///
+    /// The read limit, or [`DEFAULT_MAX_DECODED_SIZE`] if none is configured.
+    pub(crate) fn decoded_size_limit(&self) -> u64 {
+        self.max_read_size.unwrap_or(DEFAULT_MAX_DECODED_SIZE)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl OkuFs {
+    /// Waits for a free slot to sync a replica, as allowed by the configured limits.
+    ///
+    /// # Returns
+    ///
+    /// A permit occupying the slot until dropped.
+    pub(crate) async fn acquire_sync_slot(
+        &self,
+    ) -> Result<OwnedSemaphorePermit, Box<dyn Error + Send + Sync>> {
+        Ok(self.sync_slots.clone().acquire_owned().await?)
+    }
+
+    /// Imports a replica from a ticket once a sync slot is free.
+    /// The slot is held until the replica's first sync finishes, or until [`SYNC_SLOT_TIMEOUT`] elapses.
+    /// Blocked peers are removed from the ticket, and preferred peers are synchronised with first.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket of the replica to import.
+    ///
+    /// # Returns
+    ///
+    /// The imported replica.
+    pub(crate) async fn import_ticket(
+        &self,
+        mut ticket: DocTicket,
+    ) -> Result<Doc, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Importing replica {}", ticket.capability.id()))?;
+        ticket.nodes = self.order_peers(ticket.nodes)?;
+        let sync_slot = self.acquire_sync_slot().await?;
+        let document = self.node.docs.import(ticket).await?;
+        self.forward_remote_events(document.clone()).await?;
+        let events = document.subscribe().await?;
+        tokio::spawn(async move {
+            let _ = tokio::time::timeout(SYNC_SLOT_TIMEOUT, async {
+                tokio::pin!(events);
+                while let Some(Ok(event)) = events.next().await {
+                    if let iroh::client::LiveEvent::SyncFinished(_) = event {
+                        break;
+                    }
+                }
+            })
+            .await;
+            drop(sync_slot);
+        });
+        Ok(document)
+    }
+
+    /// Reads the content of an entry into memory, as allowed by the configured limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry whose content should be read.
+    ///
+    /// # Returns
+    ///
+    /// The content of the entry.
+    pub(crate) async fn read_entry_content(
+        &self,
+        entry: &Entry,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.read_content(
+            entry.id().namespace(),
+            entry.key(),
+            entry.content_hash(),
+            entry.content_len(),
+        )
+        .await
+    }
+
+    /// Reads content stored under a key in a replica into memory, as allowed by the configured limits, whether or not an entry still refers to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica the content was stored in.
+    ///
+    /// * `entry_key` - The key the content was stored under.
+    ///
+    /// * `hash` - The hash of the content as stored.
+    ///
+    /// * `size` - The size, in bytes, of the content as stored.
+    ///
+    /// # Returns
+    ///
+    /// The content, decrypted and decompressed.
+    pub(crate) async fn read_content(
+        &self,
+        namespace_id: NamespaceId,
+        entry_key: &[u8],
+        hash: Hash,
+        size: u64,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.config.limits.check_read(size)?;
+        let content = self.node.blobs.read_to_bytes(hash).await?;
+        let content = self.decrypt_content(namespace_id, entry_key, content)?;
+        self.decompress_content(namespace_id, entry_key, hash, content)
+            .await
+    }
+
+    /// Gets the size of a file's content as it is read, rather than as it is stored.
+    /// Entries report the size of their stored content, which differs for encrypted or compressed files.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry holding the file.
+    ///
+    /// # Returns
+    ///
+    /// The size, in bytes, of the file's content once decrypted and decompressed.
+    pub async fn content_size(&self, entry: &Entry) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        if is_metadata_key(entry.key()) || is_directory_marker(entry.key()) {
+            return Ok(entry.content_len());
+        }
+        let namespace_id = entry.id().namespace();
+        if self
+            .is_content_compressed(namespace_id, entry.content_hash())
+            .await?
+        {
+            return Ok(self.read_entry_content(entry).await?.len() as u64);
+        }
+        if self.is_replica_encrypted(namespace_id) {
+            return Ok(entry.content_len().saturating_sub(ENCRYPTION_OVERHEAD));
+        }
+        Ok(entry.content_len())
+    }
+
+    /// Reads part of a file.
+    /// Unlike [`OkuFs::read_file`], this allows files larger than the configured read limit to be read piece by piece, unless the file is encrypted or compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to read.
+    ///
+    /// * `path` - The path of the file to read.
+    ///
+    /// * `offset` - The position in the file to begin reading at.
+    ///
+    /// * `len` - The most bytes to read.
+    ///
+    /// # Returns
+    ///
+    /// The data read from the file, which is shorter than requested if the end of the file is reached.
+    pub async fn read_file_range(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        offset: u64,
+        len: usize,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.config.limits.check_read(len as u64)?;
+        let entry = self.get_latest_entry(namespace_id, path.clone()).await?;
+        // Encrypted or compressed content can only be decoded as a whole.
+        if self.is_replica_encrypted(namespace_id)
+            || self
+                .is_content_compressed(namespace_id, entry.content_hash())
+                .await?
+        {
+            let content = self.read_entry_content(&entry).await?;
+            let start = (offset as usize).min(content.len());
+            let content = content.slice(start..(start + len).min(content.len()));
+            self.notify_observers(|observer| {
+                observer.on_read(namespace_id, &path, content.len() as u64)
+            });
+            return Ok(content);
+        }
+        let len = len.min(entry.content_len().saturating_sub(offset) as usize);
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+        let content = self
+            .node
+            .blobs
+            .read_at_to_bytes(entry.content_hash(), offset, Some(len))
+            .await?;
+        self.notify_observers(|observer| {
+            observer.on_read(namespace_id, &path, content.len() as u64)
+        });
+        Ok(content)
+    }
+
+    /// Lists the files in a replica one at a time, without holding the whole listing in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to list files in.
+    ///
+    /// # Returns
+    ///
+    /// A stream of the files in the replica, excluding file system metadata.
+    pub async fn stream_files(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<
+        impl futures::Stream<Item = Result<Entry, Box<dyn Error + Send + Sync>>>,
+        Box<dyn Error + Send + Sync>,
+    > {
+        self.stream_files_with_options(namespace_id, ListOptions::default())
+            .await
+    }
+}
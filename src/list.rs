@@ -0,0 +1,232 @@
+use crate::fs::{is_directory_marker, is_metadata_key, KeyCodec, OkuFs};
+use crate::glob::Glob;
+use bytes::Bytes;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    client::Entry,
+    sync::{
+        store::{FilterKind, Query},
+        AuthorId, NamespaceId,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// Criteria selecting the files listed in a replica. Files must meet every criterion given.
+pub struct ListOptions {
+    /// A directory the files must be within.
+    pub path: Option<PathBuf>,
+    /// Patterns, one of which the path of each file must match.
+    pub patterns: Vec<Glob>,
+    /// Extensions, one of which each file must have, such as `jpg`. Case is ignored.
+    pub extensions: Vec<String>,
+    /// Authors, one of whom must have written the latest version of each file.
+    pub authors: Vec<AuthorId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A position within a listing, from which the next page of files continues.
+/// Cursors remain valid while the replica changes, as they record the key of the last file listed rather than a count of files.
+pub struct PageCursor(Vec<u8>);
+
+impl PageCursor {
+    /// Gets the key of the last file listed before the cursor.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// One page of a listing of the files in a replica.
+pub struct ListPage {
+    /// The files on this page, in the order of their keys.
+    pub entries: Vec<Entry>,
+    /// The position from which the next page continues, or `None` if this is the last page.
+    pub next: Option<PageCursor>,
+}
+
+impl ListOptions {
+    /// Finds a prefix shared by the keys of every entry which could meet the criteria, so that only those entries are queried.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_codec` - The scheme used to encode paths as the keys of entries.
+    ///
+    /// # Returns
+    ///
+    /// The prefix, if the criteria narrow the listing to one.
+    fn key_prefix(&self, key_codec: &KeyCodec) -> Option<Bytes> {
+        if let Some(path) = &self.path {
+            return Some(key_codec.encode_prefix(path.clone()));
+        }
+        let mut filters: Vec<FilterKind> = Vec::new();
+        for filter in self
+            .patterns
+            .iter()
+            .flat_map(|pattern| pattern.filters(key_codec))
+        {
+            if !filters.contains(&filter) {
+                filters.push(filter);
+            }
+        }
+        match filters.as_slice() {
+            [FilterKind::Prefix(prefix)] | [FilterKind::Exact(prefix)] => Some(prefix.clone()),
+            _ => None,
+        }
+    }
+
+    /// Checks whether an entry meets the criteria not already applied by the query.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the entry.
+    ///
+    /// * `entry` - The entry.
+    ///
+    /// # Returns
+    ///
+    /// Whether the entry is listed.
+    fn selects(&self, path: &Path, entry: &Entry) -> bool {
+        // Directory markers have neither names to match nor extensions.
+        if is_directory_marker(entry.key())
+            && (!self.patterns.is_empty() || !self.extensions.is_empty())
+        {
+            return false;
+        }
+        let extension_matches = self.extensions.is_empty()
+            || path.extension().is_some_and(|extension| {
+                let extension = extension.to_string_lossy();
+                self.extensions.iter().any(|wanted| {
+                    wanted
+                        .trim_start_matches('.')
+                        .eq_ignore_ascii_case(&extension)
+                })
+            });
+        extension_matches
+            && (self.patterns.is_empty()
+                || self.patterns.iter().any(|pattern| pattern.matches(path)))
+            && (self.authors.is_empty() || self.authors.contains(&entry.author()))
+    }
+}
+
+impl OkuFs {
+    /// Lists the files in a replica meeting several criteria, such as matching a pattern or having a given extension.
+    /// Only the entries which could meet the criteria are read from the replica where possible, rather than every entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to list files in.
+    ///
+    /// * `options` - The criteria selecting files.
+    ///
+    /// # Returns
+    ///
+    /// The files meeting the criteria, excluding file system metadata.
+    /// Markers of explicitly created directories are included unless patterns or extensions are given.
+    pub async fn list_files_with_options(
+        &self,
+        namespace_id: NamespaceId,
+        options: &ListOptions,
+    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let entries = self
+            .stream_files_with_options(namespace_id, options.clone())
+            .await?;
+        pin_mut!(entries);
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next().await {
+            files.push(entry?);
+        }
+        Ok(files)
+    }
+
+    /// Lists the files in a replica meeting several criteria one at a time, without holding the whole listing in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to list files in.
+    ///
+    /// * `options` - The criteria selecting files.
+    ///
+    /// # Returns
+    ///
+    /// A stream of the files meeting the criteria, excluding file system metadata.
+    pub async fn stream_files_with_options(
+        &self,
+        namespace_id: NamespaceId,
+        options: ListOptions,
+    ) -> Result<
+        impl futures::Stream<Item = Result<Entry, Box<dyn Error + Send + Sync>>>,
+        Box<dyn Error + Send + Sync>,
+    > {
+        let document = self.open_document(namespace_id).await?;
+        let query = match options.key_prefix(&self.config.key_codec) {
+            Some(prefix) => Query::single_latest_per_key().key_prefix(prefix).build(),
+            None => Query::single_latest_per_key().build(),
+        };
+        let entries = document.get_many(query).await?;
+        let key_codec = self.config.key_codec;
+        Ok(entries.filter_map(move |entry| {
+            futures::future::ready(match entry {
+                Ok(entry) if is_metadata_key(entry.key()) => None,
+                Ok(entry) if !options.selects(&key_codec.decode(entry.key()), &entry) => None,
+                Ok(entry) => Some(Ok(entry)),
+                Err(e) => Some(Err(e.into())),
+            })
+        }))
+    }
+
+    /// Lists one page of the files in a replica meeting several criteria, so that large replicas can be listed a piece at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to list files in.
+    ///
+    /// * `options` - The criteria selecting files.
+    ///
+    /// * `after` - The cursor returned with the previous page, or `None` to list the first page.
+    ///
+    /// * `limit` - The most files to list on the page.
+    ///
+    /// # Returns
+    ///
+    /// Up to `limit` files meeting the criteria, following those listed before the cursor, and a cursor for the next page if any files remain.
+    pub async fn list_files_page(
+        &self,
+        namespace_id: NamespaceId,
+        options: &ListOptions,
+        after: Option<&PageCursor>,
+        limit: usize,
+    ) -> Result<ListPage, Box<dyn Error + Send + Sync>> {
+        let entries = self
+            .stream_files_with_options(namespace_id, options.clone())
+            .await?;
+        pin_mut!(entries);
+        let mut page: Vec<Entry> = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            // Entries are listed in the order of their keys, so those up to the cursor were on earlier pages.
+            if after.is_some_and(|cursor| entry.key() <= cursor.as_bytes()) {
+                continue;
+            }
+            if page.len() == limit {
+                let next = page
+                    .last()
+                    .map(|entry| PageCursor(entry.key().to_vec()))
+                    .or_else(|| after.cloned());
+                return Ok(ListPage {
+                    entries: page,
+                    next,
+                });
+            }
+            page.push(entry);
+        }
+        Ok(ListPage {
+            entries: page,
+            next: None,
+        })
+    }
+}
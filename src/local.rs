@@ -0,0 +1,397 @@
+use crate::fs::{is_directory_marker, is_metadata_key, normalise_path, OkuFs};
+use iroh::{bytes::Hash, client::Entry, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::task::JoinHandle;
+
+/// The directory, within the file system's directory, holding the state of synchronisations with directories on disk.
+pub const LOCAL_SYNC_STATE_DIRECTORY: &str = "local_sync";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The direction in which changes flow when synchronising a replica with a directory on disk.
+pub enum SyncDirection {
+    /// The directory on disk is mirrored into the replica.
+    ToReplica,
+    /// The replica is mirrored into the directory on disk.
+    ToLocal,
+    /// Changes on either side are applied to the other.
+    /// When a file has changed on both sides, the most recent change is kept.
+    #[default]
+    Bidirectional,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The changes made while synchronising a replica with a directory on disk.
+pub struct LocalSyncReport {
+    /// The paths of files written to the replica.
+    pub written_to_replica: Vec<PathBuf>,
+    /// The paths of files deleted from the replica.
+    pub deleted_from_replica: Vec<PathBuf>,
+    /// The paths of files written to the directory on disk.
+    pub written_to_local: Vec<PathBuf>,
+    /// The paths of files deleted from the directory on disk.
+    pub deleted_from_local: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+/// The files held on both sides at the end of the previous synchronisation, used to tell deletions apart from new files.
+struct LocalSyncState {
+    /// The hash of each file's content, keyed by the file's path relative to the synchronised directory.
+    files: BTreeMap<PathBuf, String>,
+}
+
+/// A version of a file on one side of a synchronisation.
+struct SyncedFile {
+    /// The hash of the file's content.
+    hash: Hash,
+    /// The time the file was last modified, in microseconds since the Unix epoch.
+    timestamp: u64,
+    /// The entry of the file, if it is held in the replica.
+    entry: Option<Entry>,
+}
+
+/// Lists the files within a directory on disk, including those within its subdirectories.
+///
+/// # Arguments
+///
+/// * `directory` - The path of the directory on disk.
+///
+/// # Returns
+///
+/// The paths of the files, relative to the directory.
+fn list_local_files(directory: &Path) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync>> {
+    let mut files = Vec::new();
+    let mut directories = vec![PathBuf::new()];
+    while let Some(relative_directory) = directories.pop() {
+        for dir_entry in std::fs::read_dir(directory.join(&relative_directory))? {
+            let dir_entry = dir_entry?;
+            let relative_path = relative_directory.join(dir_entry.file_name());
+            let file_type = dir_entry.file_type()?;
+            if file_type.is_dir() {
+                directories.push(relative_path);
+            } else if file_type.is_file() {
+                files.push(relative_path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Checks that a path lies within the directory it is relative to, so that joining it onto the directory cannot escape it.
+///
+/// # Arguments
+///
+/// * `relative_path` - The path, relative to a directory.
+///
+/// # Returns
+///
+/// Whether the path is non-empty and made only of normal components, without parent directories, roots, or prefixes.
+fn is_contained_path(relative_path: &Path) -> bool {
+    relative_path.components().next().is_some()
+        && relative_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+impl OkuFs {
+    /// Imports the files within a directory on disk into a replica, including those within its subdirectories.
+    /// Files whose content matches the latest version in the replica are left untouched.
+    /// Empty files are skipped, as a replica cannot hold empty content.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to import the files into.
+    ///
+    /// * `local_path` - The path of the directory on disk.
+    ///
+    /// * `replica_prefix` - The path of the directory in the replica to import the files into.
+    ///
+    /// # Returns
+    ///
+    /// The paths in the replica of the files that were created or modified.
+    pub async fn import_directory(
+        &self,
+        namespace_id: NamespaceId,
+        local_path: impl AsRef<Path>,
+        replica_prefix: PathBuf,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync>> {
+        let local_path = local_path.as_ref();
+        let replica_prefix = normalise_path(replica_prefix);
+        let existing_hashes: HashMap<PathBuf, Hash> = self
+            .list_directory_entries(namespace_id, replica_prefix.clone())
+            .await?
+            .iter()
+            .map(|entry| (self.entry_path(entry.key()), entry.content_hash()))
+            .collect();
+        let mut imported = Vec::new();
+        for relative_path in list_local_files(local_path)? {
+            let data = tokio::fs::read(local_path.join(&relative_path)).await?;
+            if data.is_empty() {
+                continue;
+            }
+            let replica_path = replica_prefix.join(&relative_path);
+            if existing_hashes.get(&replica_path) == Some(&Hash::new(&data)) {
+                continue;
+            }
+            self.create_or_modify_file(namespace_id, replica_path.clone(), data)
+                .await?;
+            imported.push(replica_path);
+        }
+        Ok(imported)
+    }
+
+    /// Synchronises a replica with a directory on disk, mirroring created, modified, and deleted files.
+    /// Deletions are recognised by comparing both sides against their state at the end of the previous synchronisation.
+    /// Empty files are skipped, as a replica cannot hold empty content, as is the replica's metadata directory.
+    /// Files in the replica whose paths would lie outside the directory on disk are also skipped.
+    /// Unless changes only flow into the replica, explicitly created directories are also created on disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to synchronise.
+    ///
+    /// * `local_path` - The path of the directory on disk.
+    ///
+    /// * `direction` - The direction in which changes flow.
+    ///
+    /// # Returns
+    ///
+    /// The changes made on each side.
+    pub async fn sync_with_local_dir(
+        &self,
+        namespace_id: NamespaceId,
+        local_path: impl AsRef<Path>,
+        direction: SyncDirection,
+    ) -> Result<LocalSyncReport, Box<dyn Error + Send + Sync>> {
+        let local_path = local_path.as_ref();
+        std::fs::create_dir_all(local_path)?;
+        let state_path = self.local_sync_state_path(namespace_id, local_path)?;
+        let previous_state: LocalSyncState = match std::fs::read_to_string(&state_path) {
+            Ok(state) => serde_json::from_str(&state)?,
+            Err(_) => LocalSyncState::default(),
+        };
+
+        let mut local_files = HashMap::new();
+        for relative_path in list_local_files(local_path)? {
+            if is_metadata_key(&self.entry_key(relative_path.clone())) {
+                continue;
+            }
+            let file_path = local_path.join(&relative_path);
+            let data = tokio::fs::read(&file_path).await?;
+            if data.is_empty() {
+                continue;
+            }
+            let timestamp = std::fs::metadata(&file_path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)?
+                .as_micros() as u64;
+            local_files.insert(
+                relative_path,
+                SyncedFile {
+                    hash: Hash::new(&data),
+                    timestamp,
+                    entry: None,
+                },
+            );
+        }
+        let mut replica_files = HashMap::new();
+        for entry in self.list_files(namespace_id).await? {
+            let entry_path = self.entry_path(entry.key());
+            let relative_path = match entry_path.strip_prefix("/") {
+                Ok(relative_path) if is_contained_path(relative_path) => {
+                    relative_path.to_path_buf()
+                }
+                _ => {
+                    tracing::warn!(
+                        %namespace_id,
+                        "Skipping {:?}, which lies outside the synchronised directory",
+                        entry_path
+                    );
+                    continue;
+                }
+            };
+            if is_directory_marker(entry.key()) {
+                if direction != SyncDirection::ToReplica {
+                    tokio::fs::create_dir_all(local_path.join(&relative_path)).await?;
+                }
+                continue;
+            }
+            replica_files.insert(
+                relative_path,
+                SyncedFile {
+                    hash: entry.content_hash(),
+                    timestamp: entry.timestamp(),
+                    entry: Some(entry),
+                },
+            );
+        }
+
+        let paths: BTreeSet<PathBuf> = local_files
+            .keys()
+            .chain(replica_files.keys())
+            .chain(previous_state.files.keys())
+            .filter(|relative_path| is_contained_path(relative_path))
+            .cloned()
+            .collect();
+        let mut report = LocalSyncReport::default();
+        let mut state = LocalSyncState::default();
+        for relative_path in paths {
+            let local_file = local_files.get(&relative_path);
+            let replica_file = replica_files.get(&relative_path);
+            let local_hash = local_file.map(|file| file.hash);
+            let replica_hash = replica_file.map(|file| file.hash);
+            let previous_hash = previous_state
+                .files
+                .get(&relative_path)
+                .and_then(|hash| hash.parse::<Hash>().ok());
+            let push_to_replica = local_hash != replica_hash
+                && match direction {
+                    SyncDirection::ToReplica => true,
+                    SyncDirection::ToLocal => false,
+                    SyncDirection::Bidirectional => {
+                        let local_changed = local_hash != previous_hash;
+                        let replica_changed = replica_hash != previous_hash;
+                        match (local_file, replica_file) {
+                            _ if !replica_changed => true,
+                            _ if !local_changed => false,
+                            (Some(local_file), Some(replica_file)) => {
+                                local_file.timestamp > replica_file.timestamp
+                            }
+                            // When one side deleted a file the other side changed, the changed file is kept.
+                            (local_file, _) => local_file.is_some(),
+                        }
+                    }
+                };
+            let pull_to_local = local_hash != replica_hash && !push_to_replica;
+            let replica_path = normalise_path(relative_path.clone());
+            let synced_hash = if push_to_replica {
+                match local_file {
+                    Some(_) => {
+                        let data = tokio::fs::read(local_path.join(&relative_path)).await?;
+                        self.create_or_modify_file(namespace_id, replica_path.clone(), data)
+                            .await?;
+                        report.written_to_replica.push(replica_path);
+                    }
+                    None => {
+                        self.delete_file(namespace_id, replica_path.clone()).await?;
+                        report.deleted_from_replica.push(replica_path);
+                    }
+                }
+                local_hash
+            } else if pull_to_local {
+                let file_path = local_path.join(&relative_path);
+                match replica_file.and_then(|file| file.entry.as_ref()) {
+                    Some(entry) => {
+                        let content = self.read_entry_content(entry).await?;
+                        if let Some(parent) = file_path.parent() {
+                            tokio::fs::create_dir_all(parent).await?;
+                        }
+                        tokio::fs::write(&file_path, content).await?;
+                        report.written_to_local.push(file_path);
+                    }
+                    None => {
+                        tokio::fs::remove_file(&file_path).await?;
+                        report.deleted_from_local.push(file_path);
+                    }
+                }
+                replica_hash
+            } else {
+                local_hash
+            };
+            if let Some(synced_hash) = synced_hash {
+                state.files.insert(relative_path, synced_hash.to_string());
+            }
+        }
+
+        if let Some(parent) = state_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&state_path, serde_json::to_string(&state)?)?;
+        Ok(report)
+    }
+
+    /// Repeatedly synchronises a replica with a directory on disk in the background.
+    /// Problems encountered during a synchronisation are reported, and the next synchronisation is attempted as scheduled.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to synchronise.
+    ///
+    /// * `local_path` - The path of the directory on disk.
+    ///
+    /// * `direction` - The direction in which changes flow.
+    ///
+    /// * `interval` - The time between synchronisations.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the background task, which can be aborted to stop synchronising.
+    pub fn schedule_sync_with_local_dir(
+        &self,
+        namespace_id: NamespaceId,
+        local_path: impl Into<PathBuf>,
+        direction: SyncDirection,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        let self_clone = self.clone();
+        let local_path = local_path.into();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self_clone
+                    .sync_with_local_dir(namespace_id, &local_path, direction)
+                    .await
+                {
+                    tracing::warn!(%namespace_id, "{}", e);
+                }
+            }
+        })
+    }
+
+    /// Gets the location of the state recorded for synchronisations between a replica and a directory on disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `local_path` - The path of the directory on disk.
+    ///
+    /// # Returns
+    ///
+    /// The path of the file holding the synchronisation state.
+    fn local_sync_state_path(
+        &self,
+        namespace_id: NamespaceId,
+        local_path: &Path,
+    ) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+        let local_path = local_path.canonicalize()?;
+        let local_path_hash = Hash::new(local_path.to_string_lossy().as_bytes());
+        Ok(self
+            .config
+            .path
+            .join(LOCAL_SYNC_STATE_DIRECTORY)
+            .join(format!("{}-{}.json", namespace_id, local_path_hash)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contained_paths_stay_within_directory() {
+        for path in ["a.txt", "a/b/c.txt", "a/./b.txt"] {
+            assert!(is_contained_path(Path::new(path)), "{}", path);
+        }
+        for path in ["", ".", "..", "../a.txt", "a/../../b.txt", "/a.txt", "/"] {
+            assert!(!is_contained_path(Path::new(path)), "{}", path);
+        }
+    }
+}
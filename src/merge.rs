@@ -0,0 +1,93 @@
+use crate::fs::{is_directory_marker, OkuFs};
+use iroh::{client::Entry, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, path::PathBuf};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// How files existing in both replicas are treated when merging one replica into another.
+pub enum MergeStrategy {
+    /// Keep the destination's file.
+    #[default]
+    Skip,
+    /// Replace the destination's file with the source's.
+    Overwrite,
+    /// Keep whichever file was written most recently.
+    NewestWins,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What was changed in a replica by merging another into it.
+pub struct MergeReport {
+    /// The paths of the files written to the destination.
+    pub merged: Vec<PathBuf>,
+    /// The paths of the files differing between the replicas that were left as they were in the destination.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl OkuFs {
+    /// Copies the files of one replica into another, such as to consolidate a forked replica back into the original.
+    /// The copies refer to the same content as the originals, so no data is duplicated.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The ID of the replica to merge from.
+    ///
+    /// * `destination` - The ID of the replica to merge into.
+    ///
+    /// * `strategy` - How files existing in both replicas with different content are treated.
+    ///
+    /// # Returns
+    ///
+    /// The files written to the destination, and the differing files left as they were.
+    pub async fn merge_replicas(
+        &self,
+        source: NamespaceId,
+        destination: NamespaceId,
+        strategy: MergeStrategy,
+    ) -> Result<MergeReport, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(destination)?;
+        self.flush_writes(source).await?;
+        self.flush_writes(destination).await?;
+        let existing: HashMap<Vec<u8>, Entry> = self
+            .list_directory_entries(destination, PathBuf::from("/"))
+            .await?
+            .into_iter()
+            .map(|entry| (entry.key().to_vec(), entry))
+            .collect();
+        let mut report = MergeReport::default();
+        for entry in self
+            .list_directory_entries(source, PathBuf::from("/"))
+            .await?
+        {
+            let path = self.entry_path(entry.key());
+            if is_directory_marker(entry.key()) {
+                if !existing.contains_key(entry.key()) {
+                    self.create_directory(destination, path).await?;
+                }
+                continue;
+            }
+            if let Some(existing_entry) = existing.get(entry.key()) {
+                if existing_entry.content_hash() == entry.content_hash() {
+                    continue;
+                }
+                let replace = match strategy {
+                    MergeStrategy::Skip => false,
+                    MergeStrategy::Overwrite => true,
+                    MergeStrategy::NewestWins => entry.timestamp() > existing_entry.timestamp(),
+                };
+                if !replace {
+                    report.skipped.push(path);
+                    continue;
+                }
+            }
+            self.authorize_write(destination, path.clone(), self.author_id)
+                .await?;
+            self.set_entry_content(destination, path.clone(), &entry)
+                .await?;
+            self.copy_metadata(source, path.clone(), destination, path.clone())
+                .await?;
+            report.merged.push(path);
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,257 @@
+use crate::discovery::{INITIAL_PUBLISH_DELAY, REPUBLISH_DELAY};
+use crate::fs::OkuFs;
+use futures::{stream::BoxStream, StreamExt};
+use iroh::net::{
+    discovery::{Discovery, DiscoveryItem},
+    key::SecretKey,
+    relay::{RelayMap, RelayMode, RelayNode},
+    util::AbortingJoinHandle,
+    AddrInfo, MagicEndpoint, NodeId,
+};
+use pkarr::{
+    dns::{
+        rdata::{RData, TXT},
+        Name, Packet, ResourceRecord, CLASS,
+    },
+    url::Url,
+    Keypair, PkarrClient, SignedPacket,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+/// The name of the record holding a node's home relay, matching the records published to the mainline DHT.
+const RELAY_URL_KEY: &str = "_relay_url.iroh.";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The relay servers the node uses to reach peers it cannot connect to directly, and to be reached by them.
+///
+/// Relaying every connection, without attempting direct connections, is not supported.
+pub enum RelayServers {
+    /// The relay servers run by the developers of Iroh.
+    #[default]
+    Default,
+    /// No relay servers. Only peers reachable directly can be connected to.
+    Disabled,
+    /// The given relay servers, such as self-hosted ones.
+    Custom(Vec<RelayNode>),
+}
+
+impl RelayServers {
+    /// Describes the relay servers in the form the node is configured with.
+    ///
+    /// # Returns
+    ///
+    /// The node's relay mode.
+    pub(crate) fn relay_mode(&self) -> Result<RelayMode, Box<dyn Error + Send + Sync>> {
+        Ok(match self {
+            RelayServers::Default => RelayMode::Default,
+            RelayServers::Disabled => RelayMode::Disabled,
+            RelayServers::Custom(relay_nodes) => {
+                RelayMode::Custom(RelayMap::from_nodes(relay_nodes.iter().cloned())?)
+            }
+        })
+    }
+}
+
+impl OkuFs {
+    /// Gets the ID the node backing the file system is identified by, which peers and clients of its remote control connect to.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the node.
+    pub fn node_id(&self) -> NodeId {
+        self.node.node_id()
+    }
+
+    /// Connects to the mainline DHT, through the configured bootstrap nodes if any are given.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the mainline DHT.
+    pub(crate) fn mainline_dht(&self) -> mainline::Dht {
+        match &self.config.dht_bootstrap {
+            Some(dht_bootstrap) => mainline::Dht::builder().bootstrap(dht_bootstrap).build(),
+            None => mainline::Dht::default(),
+        }
+    }
+
+    /// Creates a client for publishing and resolving signed records, joining the mainline DHT through the configured bootstrap nodes if any are given.
+    ///
+    /// # Returns
+    ///
+    /// A pkarr client.
+    pub(crate) fn pkarr_client(&self) -> PkarrClient {
+        match &self.config.dht_bootstrap {
+            Some(dht_bootstrap) => PkarrClient::builder().bootstrap(dht_bootstrap).build(),
+            None => PkarrClient::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A mechanism for publishing and resolving node addresses through pkarr relays, for networks where the mainline DHT cannot be reached directly.
+pub struct PkarrRelayDiscovery(Arc<PkarrRelayDiscoveryInner>);
+
+#[derive(Debug)]
+struct PkarrRelayDiscoveryInner {
+    /// The client used to contact the relays.
+    pkarr: PkarrClient,
+    /// The relays to publish to and resolve from, in order of preference.
+    relays: Vec<Url>,
+    /// The key signing published records. Without it, addresses are only resolved.
+    keypair: Option<Keypair>,
+    /// The background task periodically publishing the node's address.
+    task: Mutex<Option<AbortingJoinHandle<()>>>,
+}
+
+impl PkarrRelayDiscovery {
+    /// Creates a mechanism for publishing and resolving node addresses through pkarr relays.
+    ///
+    /// # Arguments
+    ///
+    /// * `pkarr` - The client used to contact the relays.
+    ///
+    /// * `relays` - The relays to publish to and resolve from, in order of preference.
+    ///
+    /// * `secret_key` - The node's secret key, if its own address should be published.
+    ///
+    /// # Returns
+    ///
+    /// A discovery mechanism using the given relays.
+    pub fn new(pkarr: PkarrClient, relays: Vec<Url>, secret_key: Option<&SecretKey>) -> Self {
+        PkarrRelayDiscovery(Arc::new(PkarrRelayDiscoveryInner {
+            pkarr,
+            relays,
+            keypair: secret_key.map(|secret_key| Keypair::from_secret_key(&secret_key.to_bytes())),
+            task: Mutex::new(None),
+        }))
+    }
+}
+
+impl Discovery for PkarrRelayDiscovery {
+    fn publish(&self, info: &AddrInfo) {
+        let Some(keypair) = &self.0.keypair else {
+            return;
+        };
+        let Ok(signed_packet) = addr_info_to_packet(keypair, info) else {
+            return;
+        };
+        let this = self.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(INITIAL_PUBLISH_DELAY).await;
+                for relay in &this.0.relays {
+                    if let Err(e) = this.0.pkarr.relay_put(relay, &signed_packet).await {
+                        tracing::warn!(%relay, "Problem publishing to pkarr relay: {}", e);
+                    }
+                }
+                tokio::time::sleep(REPUBLISH_DELAY - INITIAL_PUBLISH_DELAY).await;
+            }
+        });
+        *self.0.task.lock().unwrap() = Some(task.into());
+    }
+
+    fn resolve(
+        &self,
+        _endpoint: MagicEndpoint,
+        node_id: NodeId,
+    ) -> Option<BoxStream<'_, anyhow::Result<DiscoveryItem>>> {
+        let this = self.clone();
+        let resolution = async move {
+            let public_key = pkarr::PublicKey::try_from(*node_id.as_bytes())?;
+            for relay in &this.0.relays {
+                if let Ok(Some(signed_packet)) =
+                    this.0.pkarr.relay_get(relay, public_key.clone()).await
+                {
+                    return Ok(DiscoveryItem {
+                        provenance: "pkarr-relay",
+                        last_updated: Some(*signed_packet.timestamp()),
+                        addr_info: packet_to_addr_info(&signed_packet)?,
+                    });
+                }
+            }
+            anyhow::bail!("Node {} not found on any pkarr relay.", node_id)
+        };
+        Some(futures::stream::once(resolution).boxed())
+    }
+}
+
+/// Extracts the text of a TXT record.
+///
+/// # Arguments
+///
+/// * `record` - The record.
+///
+/// # Returns
+///
+/// The text of the record, if it is a TXT record.
+pub(crate) fn record_text(record: &ResourceRecord) -> Option<String> {
+    match &record.rdata {
+        RData::TXT(txt) if record.class == CLASS::IN => String::try_from(txt.clone()).ok(),
+        _ => None,
+    }
+}
+
+/// Reads a node's address from a signed record.
+///
+/// # Arguments
+///
+/// * `signed_packet` - The signed record.
+///
+/// # Returns
+///
+/// The node's home relay and direct addresses.
+fn packet_to_addr_info(signed_packet: &SignedPacket) -> anyhow::Result<AddrInfo> {
+    let direct_addresses = signed_packet
+        .resource_records("@")
+        .filter_map(record_text)
+        .map(|address| Ok(address.parse()?))
+        .collect::<anyhow::Result<BTreeSet<SocketAddr>>>()?;
+    let relay_url = signed_packet
+        .resource_records(RELAY_URL_KEY)
+        .filter_map(record_text)
+        .map(|url| anyhow::Ok(Url::parse(&url)?.into()))
+        .next()
+        .transpose()?;
+    Ok(AddrInfo {
+        relay_url,
+        direct_addresses,
+    })
+}
+
+/// Creates a signed record of a node's address.
+///
+/// # Arguments
+///
+/// * `keypair` - The node's key.
+///
+/// * `info` - The node's home relay and direct addresses.
+///
+/// # Returns
+///
+/// The signed record.
+fn addr_info_to_packet(keypair: &Keypair, info: &AddrInfo) -> pkarr::Result<SignedPacket> {
+    let mut packet = Packet::new_reply(0);
+    for address in &info.direct_addresses {
+        packet.answers.push(ResourceRecord::new(
+            Name::new("@")?,
+            CLASS::IN,
+            0,
+            RData::TXT(TXT::try_from(address.to_string().as_str())?.into_owned()),
+        ));
+    }
+    if let Some(relay_url) = &info.relay_url {
+        packet.answers.push(ResourceRecord::new(
+            Name::new(RELAY_URL_KEY)?,
+            CLASS::IN,
+            0,
+            RData::TXT(TXT::try_from(relay_url.as_str())?.into_owned()),
+        ));
+    }
+    SignedPacket::from_packet(keypair, &packet)
+}
@@ -0,0 +1,106 @@
+use crate::fs::OkuFs;
+use iroh::net::{magicsock::ConnectionType, metrics::MagicsockMetrics, relay::RelayUrl, NodeId};
+use iroh_metrics::core::Metric;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// How the node reaches a peer.
+pub enum PeerConnectionKind {
+    /// The peer is reached directly.
+    Direct,
+    /// The peer is reached through a relay server.
+    Relay,
+    /// The peer is reached both directly and through a relay server, as the direct address has not recently been confirmed to work.
+    Mixed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A peer the node is connected to.
+pub struct PeerStats {
+    /// The ID of the peer.
+    pub node_id: NodeId,
+    /// How the peer is reached.
+    pub connection: PeerConnectionKind,
+    /// The address the peer is reached at directly, if any.
+    pub address: Option<SocketAddr>,
+    /// The relay server the peer is reached through, if any.
+    pub relay_url: Option<RelayUrl>,
+    /// The round-trip time to the peer, if it has been measured.
+    pub latency: Option<Duration>,
+    /// The time since the connection to the peer was last used.
+    pub last_used: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// A description of what the node is doing on the network.
+pub struct NetworkStats {
+    /// The peers the node is connected to.
+    pub peers: Vec<PeerStats>,
+    /// The number of peers reached directly.
+    pub direct_peers: usize,
+    /// The number of peers reached through a relay server.
+    pub relayed_peers: usize,
+    /// The number of peers reached both directly and through a relay server.
+    pub mixed_peers: usize,
+    /// The number of replicas being synchronised with peers.
+    pub active_syncs: usize,
+    /// The number of bytes of data sent to peers.
+    pub bytes_sent: u64,
+    /// The number of bytes of data received from peers through relay servers.
+    pub bytes_received_relayed: u64,
+    /// The number of QUIC datagrams received from peers, whether directly or through relay servers.
+    pub datagrams_received: u64,
+}
+
+impl OkuFs {
+    /// Describes what the node is doing on the network, such as for showing on a dashboard.
+    ///
+    /// Iroh does not count the data exchanged with each peer, nor the bytes received from peers directly, so only totals of the data sent, the bytes received through relay servers, and the datagrams received are given.
+    /// The totals are kept for the whole process since it started, and include the traffic of any other nodes running in it.
+    ///
+    /// # Returns
+    ///
+    /// The peers the node is connected to, how they are reached, the replicas being synchronised, and the data exchanged with peers.
+    pub async fn network_stats(&self) -> Result<NetworkStats, Box<dyn Error + Send + Sync>> {
+        let mut stats = NetworkStats::default();
+        for connection_info in self.node.magic_endpoint().connection_infos() {
+            let (connection, address, relay_url) = match connection_info.conn_type {
+                ConnectionType::Direct(address) => {
+                    (PeerConnectionKind::Direct, Some(address), None)
+                }
+                ConnectionType::Relay(relay_url) => {
+                    (PeerConnectionKind::Relay, None, Some(relay_url))
+                }
+                ConnectionType::Mixed(address, relay_url) => {
+                    (PeerConnectionKind::Mixed, Some(address), Some(relay_url))
+                }
+                ConnectionType::None => continue,
+            };
+            match connection {
+                PeerConnectionKind::Direct => stats.direct_peers += 1,
+                PeerConnectionKind::Relay => stats.relayed_peers += 1,
+                PeerConnectionKind::Mixed => stats.mixed_peers += 1,
+            }
+            stats.peers.push(PeerStats {
+                node_id: connection_info.node_id,
+                connection,
+                address,
+                relay_url,
+                latency: connection_info.latency,
+                last_used: connection_info.last_used,
+            });
+        }
+        for namespace_id in self.list_replicas().await? {
+            if self.open_document(namespace_id).await?.status().await?.sync {
+                stats.active_syncs += 1;
+            }
+        }
+        if let Some(metrics) = MagicsockMetrics::try_get() {
+            stats.bytes_sent = metrics.send_data.get();
+            stats.bytes_received_relayed = metrics.recv_data_relay.get();
+            stats.datagrams_received = metrics.recv_datagrams.get();
+        }
+        Ok(stats)
+    }
+}
@@ -0,0 +1,78 @@
+use crate::fs::OkuFs;
+use iroh::{bytes::Hash, sync::NamespaceId};
+use std::{fmt::Debug, path::Path, sync::Arc};
+
+/// A receiver of notifications about operations performed by the file system, for feeding telemetry or user interfaces.
+///
+/// Every method does nothing by default, so implementors need only handle the operations they are interested in.
+/// Methods are called inline with the operation being observed, and so should return quickly.
+pub trait OkuObserver: Debug + Send + Sync {
+    /// Called after a file is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `hash` - The hash of the file's content.
+    ///
+    /// * `size` - The size, in bytes, of the file's content.
+    fn on_write(&self, _namespace_id: NamespaceId, _path: &Path, _hash: Hash, _size: u64) {}
+
+    /// Called after a file is read.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `size` - The size, in bytes, of the data read.
+    fn on_read(&self, _namespace_id: NamespaceId, _path: &Path, _size: u64) {}
+
+    /// Called when a replica begins synchronising with peers.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    fn on_sync_start(&self, _namespace_id: NamespaceId) {}
+
+    /// Called when a replica finishes synchronising with a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `result` - The outcome of the synchronisation, with a description of the problem if it failed.
+    fn on_sync_finish(&self, _namespace_id: NamespaceId, _result: &Result<(), String>) {}
+
+    /// Called after a replica is announced to the mainline DHT.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    fn on_announce(&self, _namespace_id: NamespaceId) {}
+}
+
+impl OkuFs {
+    /// Registers an observer to be notified of operations performed by the file system.
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - The observer to register.
+    pub fn add_observer(&self, observer: impl OkuObserver + 'static) {
+        self.observers.write().unwrap().push(Arc::new(observer));
+    }
+
+    /// Notifies each registered observer of an operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `notify` - The notification to deliver to each observer.
+    pub(crate) fn notify_observers(&self, notify: impl Fn(&dyn OkuObserver)) {
+        for observer in self.observers.read().unwrap().iter() {
+            notify(observer.as_ref());
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use std::{error::Error, sync::atomic::Ordering};
+
+impl OkuFs {
+    /// Disables or re-enables all networking, while keeping full local access to replicas.
+    /// While offline, replicas are neither announced, discovered, nor synchronised, requests from peers are refused, and network operations fail with [`OkuFsError::Offline`].
+    /// Bringing the file system online resumes synchronising the replicas that are pinned or whose policies require it, as well as interrupted imports.
+    /// A file system started offline does not use relay servers to reach peers until it is restarted online.
+    /// The remote control, if configured, keeps accepting connections while offline, so that a headless node taken offline can still be managed and brought back online.
+    /// If networking cannot be started, the file system stays offline.
+    ///
+    /// # Arguments
+    ///
+    /// * `offline` - Whether networking should be disabled.
+    pub async fn set_offline(&self, offline: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.offline.swap(offline, Ordering::SeqCst) == offline {
+            return Ok(());
+        }
+        if offline {
+            return self.stop_networking().await;
+        }
+        // Networking is started while online, as the tasks it starts refuse to run offline.
+        if let Err(e) = self.start_networking().await {
+            self.offline.store(true, Ordering::SeqCst);
+            if let Err(stop_error) = self.stop_networking().await {
+                tracing::warn!("{}", stop_error);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Stops the networking of the file system, such as when it is taken offline.
+    /// Scheduled synchronisation stops, and replicas are no longer synchronised with peers.
+    async fn stop_networking(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for (_, sync_schedule) in self.sync_schedules.lock().unwrap().drain() {
+            sync_schedule.abort();
+        }
+        for namespace_id in self.list_replicas().await? {
+            self.open_document(namespace_id).await?.leave().await?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether networking is disabled.
+    ///
+    /// # Returns
+    ///
+    /// Whether the file system is currently offline.
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::SeqCst)
+    }
+
+    /// Rejects a network operation if the file system is offline.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - A description of the operation, for reporting why it was rejected.
+    pub(crate) fn ensure_online(
+        &self,
+        operation: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.is_offline() {
+            return Err(OkuFsError::Offline(operation.to_string()).into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+use crate::event::OkuFsEvent;
+use crate::fs::OkuFs;
+use futures::StreamExt;
+use iroh::{
+    client::LiveEvent,
+    net::{magicsock::ConnectionType, NodeAddr, NodeId},
+    sync::{Capability, NamespaceId},
+    ticket::DocTicket,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, str::FromStr};
+use tokio_util::sync::CancellationToken;
+
+/// The name of the file listing blocked and preferred peers, within the path on disk where the file system is stored.
+pub const PEER_PREFERENCES_FILE_NAME: &str = "peers";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A peer a replica has been synchronised with.
+pub struct ReplicaPeer {
+    /// The ID of the peer.
+    pub node_id: NodeId,
+    /// Whether the node is currently connected to the peer.
+    pub connected: bool,
+    /// Whether the peer is blocked.
+    pub blocked: bool,
+    /// Whether the peer is preferred.
+    pub preferred: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The blocked and preferred peers, as saved on disk.
+struct PeerPreferences {
+    /// The IDs of the peers never contacted when fetching replicas.
+    #[serde(default)]
+    blocked: Vec<String>,
+    /// The IDs of the peers tried first when fetching replicas, in order of preference.
+    #[serde(default)]
+    preferred: Vec<String>,
+}
+
+/// Parses the IDs of peers saved on disk.
+///
+/// # Arguments
+///
+/// * `node_ids` - The IDs of the peers, in their textual form.
+///
+/// # Returns
+///
+/// The IDs of the peers.
+fn parse_node_ids(node_ids: &[String]) -> Result<Vec<NodeId>, Box<dyn Error + Send + Sync>> {
+    node_ids
+        .iter()
+        .map(|node_id| Ok(NodeId::from_str(node_id)?))
+        .collect()
+}
+
+impl OkuFs {
+    /// Loads the blocked and preferred peers from disk.
+    ///
+    /// # Returns
+    ///
+    /// The blocked and preferred peers.
+    fn load_peer_preferences(&self) -> Result<PeerPreferences, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(PEER_PREFERENCES_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(peer_preferences_toml) => Ok(toml::from_str(&peer_preferences_toml)?),
+            Err(_) => Ok(PeerPreferences::default()),
+        }
+    }
+
+    /// Saves the blocked and preferred peers to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_preferences` - The blocked and preferred peers.
+    fn save_peer_preferences(
+        &self,
+        peer_preferences: &PeerPreferences,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        std::fs::write(
+            self.config.path.join(PEER_PREFERENCES_FILE_NAME),
+            toml::to_string(peer_preferences)?,
+        )?;
+        Ok(())
+    }
+
+    /// Lists the peers a replica has been synchronised with, along with whether they are connected, blocked, or preferred.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The peers the replica has recently been synchronised with, most useful first.
+    pub async fn list_replica_peers(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<ReplicaPeer>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let blocked = self.list_blocked_peers()?;
+        let preferred = self.list_preferred_peers()?;
+        let connected: Vec<NodeId> = self
+            .node
+            .magic_endpoint()
+            .connection_infos()
+            .into_iter()
+            .filter(|connection_info| !matches!(connection_info.conn_type, ConnectionType::None))
+            .map(|connection_info| connection_info.node_id)
+            .collect();
+        document
+            .get_sync_peers()
+            .await?
+            .unwrap_or_default()
+            .iter()
+            .map(|peer| {
+                let node_id = NodeId::from_bytes(peer)?;
+                Ok(ReplicaPeer {
+                    node_id,
+                    connected: connected.contains(&node_id),
+                    blocked: blocked.contains(&node_id),
+                    preferred: preferred.contains(&node_id),
+                })
+            })
+            .collect()
+    }
+
+    /// Blocks a peer, so that it is never contacted when fetching replicas. A blocked peer is no longer preferred.
+    ///
+    /// Iroh does not allow refusing synchronisations started by other nodes, so a blocked peer can still synchronise with replicas it already knows to be held by this node.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the peer.
+    pub fn block_peer(&self, node_id: NodeId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut peer_preferences = self.load_peer_preferences()?;
+        let node_id = node_id.to_string();
+        peer_preferences.preferred.retain(|peer| *peer != node_id);
+        if !peer_preferences.blocked.contains(&node_id) {
+            peer_preferences.blocked.push(node_id);
+        }
+        self.save_peer_preferences(&peer_preferences)
+    }
+
+    /// Unblocks a peer, so that it may be contacted again when fetching replicas.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the peer.
+    pub fn unblock_peer(&self, node_id: NodeId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut peer_preferences = self.load_peer_preferences()?;
+        let node_id = node_id.to_string();
+        peer_preferences.blocked.retain(|peer| *peer != node_id);
+        self.save_peer_preferences(&peer_preferences)
+    }
+
+    /// Lists the blocked peers.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the peers never contacted when fetching replicas.
+    pub fn list_blocked_peers(&self) -> Result<Vec<NodeId>, Box<dyn Error + Send + Sync>> {
+        parse_node_ids(&self.load_peer_preferences()?.blocked)
+    }
+
+    /// Prefers a peer, so that it is tried before any other when fetching replicas. A preferred peer is no longer blocked.
+    /// Peers are tried in the order they were preferred in, and are reached at their last known addresses or found through node discovery.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the peer.
+    pub fn prefer_peer(&self, node_id: NodeId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut peer_preferences = self.load_peer_preferences()?;
+        let node_id = node_id.to_string();
+        peer_preferences.blocked.retain(|peer| *peer != node_id);
+        if !peer_preferences.preferred.contains(&node_id) {
+            peer_preferences.preferred.push(node_id);
+        }
+        self.save_peer_preferences(&peer_preferences)
+    }
+
+    /// Stops preferring a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The ID of the peer.
+    pub fn unprefer_peer(&self, node_id: NodeId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut peer_preferences = self.load_peer_preferences()?;
+        let node_id = node_id.to_string();
+        peer_preferences.preferred.retain(|peer| *peer != node_id);
+        self.save_peer_preferences(&peer_preferences)
+    }
+
+    /// Lists the preferred peers.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the peers tried first when fetching replicas, in order of preference.
+    pub fn list_preferred_peers(&self) -> Result<Vec<NodeId>, Box<dyn Error + Send + Sync>> {
+        parse_node_ids(&self.load_peer_preferences()?.preferred)
+    }
+
+    /// Orders the peers a replica is to be fetched from, removing blocked peers and putting preferred peers first.
+    /// Preferred peers not among the given peers are added, so that they are tried too.
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The peers the replica is to be fetched from.
+    ///
+    /// # Returns
+    ///
+    /// The preferred peers, followed by the other peers not blocked.
+    pub(crate) fn order_peers(
+        &self,
+        peers: Vec<NodeAddr>,
+    ) -> Result<Vec<NodeAddr>, Box<dyn Error + Send + Sync>> {
+        let blocked = self.list_blocked_peers()?;
+        let preferred = self.list_preferred_peers()?;
+        let mut ordered_peers: Vec<NodeAddr> = preferred
+            .iter()
+            .map(|node_id| {
+                peers
+                    .iter()
+                    .find(|peer| peer.node_id == *node_id)
+                    .cloned()
+                    .unwrap_or_else(|| NodeAddr::new(*node_id))
+            })
+            .collect();
+        ordered_peers.extend(
+            peers.into_iter().filter(|peer| {
+                !blocked.contains(&peer.node_id) && !preferred.contains(&peer.node_id)
+            }),
+        );
+        Ok(ordered_peers)
+    }
+
+    /// Fetches a replica from the preferred peers, before looking for it elsewhere.
+    /// The replica is only kept if a preferred peer synchronises it within the configured network timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to fetch.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the fetch.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica was fetched from a preferred peer.
+    pub(crate) async fn fetch_from_preferred_peers(
+        &self,
+        namespace_id: NamespaceId,
+        cancellation: &CancellationToken,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let preferred_peers = self.order_peers(Vec::new())?;
+        if preferred_peers.is_empty() {
+            return Ok(false);
+        }
+        let _sync_slot = self.acquire_sync_slot().await?;
+        let document = self
+            .node
+            .docs
+            .import(DocTicket {
+                capability: Capability::Read(namespace_id),
+                nodes: Vec::new(),
+            })
+            .await?;
+        let events = document.subscribe().await?;
+        let peer_count = preferred_peers.len();
+        document.start_sync(preferred_peers).await?;
+        let operation = format!("Fetching replica {} from preferred peers", namespace_id);
+        let fetched = self
+            .with_network_timeout(&operation, cancellation, async {
+                tokio::pin!(events);
+                let mut failures = 0;
+                while let Some(event) = events.next().await {
+                    if let LiveEvent::SyncFinished(sync_event) = event? {
+                        match sync_event.result {
+                            Ok(()) => return Ok(true),
+                            Err(e) => {
+                                tracing::debug!(peer = %sync_event.peer, "Sync failed: {}", e);
+                                failures += 1;
+                                if failures == peer_count {
+                                    return Ok(false);
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(false)
+            })
+            .await;
+        if let Ok(true) = fetched {
+            self.emit(OkuFsEvent::ReplicaImported(namespace_id));
+            self.forward_remote_events(document).await?;
+            return Ok(true);
+        }
+        document.leave().await?;
+        drop(document);
+        self.node.docs.drop_doc(namespace_id).await?;
+        match fetched {
+            Err(e) if cancellation.is_cancelled() => Err(e),
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::event::OkuFsEvent;
+use crate::fs::OkuFs;
+use iroh::sync::NamespaceId;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, error::Error, str::FromStr};
+
+/// The name of the file listing pinned replicas, within the path on disk where the file system is stored.
+pub const PINNED_REPLICAS_FILE_NAME: &str = "pinned_replicas";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The pinned replicas, as saved on disk.
+struct PinnedReplicas {
+    /// The IDs of the pinned replicas.
+    pinned: BTreeSet<String>,
+}
+
+impl OkuFs {
+    /// Loads the set of pinned replicas from disk.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the pinned replicas.
+    fn load_pinned_replicas(&self) -> Result<BTreeSet<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(PINNED_REPLICAS_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(pinned_replicas_toml) => {
+                let pinned_replicas: PinnedReplicas = toml::from_str(&pinned_replicas_toml)?;
+                pinned_replicas
+                    .pinned
+                    .iter()
+                    .map(|namespace_id| Ok(NamespaceId::from_str(namespace_id)?))
+                    .collect()
+            }
+            Err(_) => Ok(BTreeSet::new()),
+        }
+    }
+
+    /// Saves the set of pinned replicas to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `pinned_replicas` - The IDs of the pinned replicas.
+    fn save_pinned_replicas(
+        &self,
+        pinned_replicas: &BTreeSet<NamespaceId>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let pinned_replicas = PinnedReplicas {
+            pinned: pinned_replicas
+                .iter()
+                .map(|namespace_id| namespace_id.to_string())
+                .collect(),
+        };
+        std::fs::write(
+            self.config.path.join(PINNED_REPLICAS_FILE_NAME),
+            toml::to_string(&pinned_replicas)?,
+        )?;
+        Ok(())
+    }
+
+    /// Pins a replica, keeping it synchronised with peers without being asked to.
+    /// Pinned replicas are synchronised when the file system starts and, if discovery is enabled, again on the announcement schedule, and every version of their files is kept by garbage collection.
+    /// A replica need not be held locally to be pinned; it is fetched from peers in the background.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to pin.
+    pub async fn pin_replica(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut pinned_replicas = self.load_pinned_replicas()?;
+        if pinned_replicas.insert(namespace_id) {
+            self.save_pinned_replicas(&pinned_replicas)?;
+            self.emit(OkuFsEvent::ReplicaPinChanged {
+                namespace_id,
+                pinned: true,
+            });
+        }
+        self.spawn_pinned_replica_sync(namespace_id);
+        Ok(())
+    }
+
+    /// Unpins a replica, so that it is only synchronised when asked to.
+    /// The replica is kept locally; use [`OkuFs::delete_replica`] to remove it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to unpin.
+    pub async fn unpin_replica(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut pinned_replicas = self.load_pinned_replicas()?;
+        if pinned_replicas.remove(&namespace_id) {
+            self.save_pinned_replicas(&pinned_replicas)?;
+            self.emit(OkuFsEvent::ReplicaPinChanged {
+                namespace_id,
+                pinned: false,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks whether a replica is pinned.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica is kept synchronised without being asked to.
+    pub fn is_replica_pinned(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(self.load_pinned_replicas()?.contains(&namespace_id))
+    }
+
+    /// Lists the pinned replicas, including those yet to be fetched from peers.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the pinned replicas.
+    pub fn list_pinned_replicas(&self) -> Result<Vec<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        Ok(self.load_pinned_replicas()?.into_iter().collect())
+    }
+
+    /// Synchronises a pinned replica with peers in the background.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    fn spawn_pinned_replica_sync(&self, namespace_id: NamespaceId) {
+        let self_clone = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = self_clone.sync_replica(namespace_id).await {
+                tracing::warn!(%namespace_id, "{}", e);
+            }
+        });
+    }
+
+    /// Synchronises every pinned replica with peers in the background.
+    pub(crate) fn sync_pinned_replicas(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for namespace_id in self.load_pinned_replicas()? {
+            self.spawn_pinned_replica_sync(namespace_id);
+        }
+        Ok(())
+    }
+
+    /// Forgets whether a replica was pinned, such as when it is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub(crate) fn remove_replica_pin(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut pinned_replicas = self.load_pinned_replicas()?;
+        if pinned_replicas.remove(&namespace_id) {
+            self.save_pinned_replicas(&pinned_replicas)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use crate::event::OkuFsEvent;
+use crate::fs::OkuFs;
+use iroh::sync::NamespaceId;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, error::Error, str::FromStr};
+
+/// The name of the file listing private replicas, within the path on disk where the file system is stored.
+pub const PRIVATE_REPLICAS_FILE_NAME: &str = "private_replicas";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The private replicas, as saved on disk.
+struct PrivateReplicas {
+    /// The IDs of the private replicas.
+    private: BTreeSet<String>,
+}
+
+impl OkuFs {
+    /// Loads the set of private replicas from disk.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the private replicas.
+    fn load_private_replicas(&self) -> Result<BTreeSet<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(PRIVATE_REPLICAS_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(private_replicas_toml) => {
+                let private_replicas: PrivateReplicas = toml::from_str(&private_replicas_toml)?;
+                private_replicas
+                    .private
+                    .iter()
+                    .map(|namespace_id| Ok(NamespaceId::from_str(namespace_id)?))
+                    .collect()
+            }
+            Err(_) => Ok(BTreeSet::new()),
+        }
+    }
+
+    /// Saves the set of private replicas to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `private_replicas` - The IDs of the private replicas.
+    fn save_private_replicas(
+        &self,
+        private_replicas: &BTreeSet<NamespaceId>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let private_replicas = PrivateReplicas {
+            private: private_replicas
+                .iter()
+                .map(|namespace_id| namespace_id.to_string())
+                .collect(),
+        };
+        std::fs::write(
+            self.config.path.join(PRIVATE_REPLICAS_FILE_NAME),
+            toml::to_string(&private_replicas)?,
+        )?;
+        Ok(())
+    }
+
+    /// Marks a replica as private or public.
+    /// Private replicas are never announced to the mainline DHT or to a relay, and requests for them from peers are refused, so they can only be shared with tickets.
+    /// Replicas are public unless marked otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `private` - Whether the replica should be private.
+    pub async fn set_replica_private(
+        &self,
+        namespace_id: NamespaceId,
+        private: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.open_document(namespace_id).await?;
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut private_replicas = self.load_private_replicas()?;
+        let changed = match private {
+            true => private_replicas.insert(namespace_id),
+            false => private_replicas.remove(&namespace_id),
+        };
+        if changed {
+            self.save_private_replicas(&private_replicas)?;
+            self.emit(OkuFsEvent::ReplicaPrivacyChanged {
+                namespace_id,
+                private,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks whether a replica is private.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// Whether the replica is withheld from announcements and from requests by peers.
+    pub fn is_replica_private(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(self.load_private_replicas()?.contains(&namespace_id))
+    }
+
+    /// Lists the replicas which are not private, and so may be announced.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the public replicas.
+    pub async fn list_public_replicas(
+        &self,
+    ) -> Result<Vec<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        let private_replicas = self.load_private_replicas()?;
+        Ok(self
+            .list_replicas()
+            .await?
+            .into_iter()
+            .filter(|namespace_id| !private_replicas.contains(namespace_id))
+            .collect())
+    }
+
+    /// Forgets whether a replica was private, such as when it is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub(crate) fn remove_replica_privacy(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut private_replicas = self.load_private_replicas()?;
+        if private_replicas.remove(&namespace_id) {
+            self.save_private_replicas(&private_replicas)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use crate::fs::{OkuFs, METADATA_DIRECTORY};
+use iroh::{
+    bytes::Hash,
+    client::Entry,
+    sync::{AuthorId, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::PathBuf};
+
+/// The directory holding author profiles, within the directory reserved for file system metadata.
+pub const PROFILES_DIRECTORY_NAME: &str = "profiles";
+
+/// Gets the path of an author's profile.
+///
+/// # Arguments
+///
+/// * `author_id` - The ID of the author.
+///
+/// # Returns
+///
+/// The path of the author's profile within a replica.
+pub fn author_profile_path(author_id: AuthorId) -> PathBuf {
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(PROFILES_DIRECTORY_NAME)
+        .join(format!("{}.toml", author_id))
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// Information an author publishes about themselves, so that they can be recognised by something other than their key.
+pub struct AuthorProfile {
+    /// The name the author wishes to be shown as.
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// The hash of an image representing the author.
+    #[serde(default)]
+    pub avatar: Option<Hash>,
+    /// A decentralised identifier belonging to the author.
+    #[serde(default)]
+    pub did: Option<String>,
+}
+
+impl OkuFs {
+    /// Publishes this file system's author profile in a replica, so that peers holding the replica can resolve it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to publish the profile in.
+    ///
+    /// * `profile` - The profile to publish.
+    pub async fn set_author_profile(
+        &self,
+        namespace_id: NamespaceId,
+        profile: &AuthorProfile,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_bytes(
+                self.author_id,
+                self.entry_key(author_profile_path(self.author_id)),
+                toml::to_string_pretty(profile)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Finds an author's profile in the replicas held locally.
+    /// Only profiles written by the author themselves are considered; if several replicas hold one, the most recently written is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `author_id` - The ID of the author.
+    ///
+    /// # Returns
+    ///
+    /// The author's profile, if one was found.
+    pub async fn get_author_profile(
+        &self,
+        author_id: AuthorId,
+    ) -> Result<Option<AuthorProfile>, Box<dyn Error + Send + Sync>> {
+        let profile_key = self.entry_key(author_profile_path(author_id));
+        let mut latest_entry = None;
+        for namespace_id in self.list_replicas().await? {
+            let document = self.open_document(namespace_id).await?;
+            if let Some(entry) = document
+                .get_exact(author_id, profile_key.clone(), false)
+                .await?
+            {
+                if latest_entry
+                    .as_ref()
+                    .is_none_or(|latest: &Entry| entry.timestamp() > latest.timestamp())
+                {
+                    latest_entry = Some(entry);
+                }
+            }
+        }
+        let Some(entry) = latest_entry else {
+            return Ok(None);
+        };
+        let profile_bytes = self.read_entry_content(&entry).await?;
+        Ok(Some(toml::from_str(&String::from_utf8_lossy(
+            &profile_bytes,
+        ))?))
+    }
+}
@@ -0,0 +1,563 @@
+use crate::blob::blob_tag;
+use crate::discovery::{ContentRequest, ANNOUNCE_PARALLELISM, DISCOVERY_PORT};
+use crate::error::{OkuDiscoveryError, OkuFsError};
+use crate::fs::{send_request, OkuFs, ALPN_BLOB_TICKET_FETCH};
+use bytes::Bytes;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::{
+        store::{ExportFormat, ExportMode},
+        BlobFormat, HashAndFormat, Tag,
+    },
+    client::ShareTicketOptions,
+    net::NodeAddr,
+    rpc_protocol::{BlobDownloadRequest, SetTagOption, WrapOption},
+    ticket::BlobTicket,
+};
+use iroh_mainline_content_discovery::{announce_dht, to_infohash};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    collections::{BTreeSet, HashSet},
+    error::Error,
+    net::SocketAddr,
+    path::Path,
+};
+use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
+use tracing::instrument;
+
+/// The name of the file listing published content, within the path on disk where the file system is stored.
+pub const PUBLISHED_CONTENT_FILE_NAME: &str = "published";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A piece of published content, as saved on disk.
+struct PublishedEntry {
+    /// The hash and format of the content.
+    content: HashAndFormat,
+    /// The name of the tag keeping the content from being garbage collected.
+    tag: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The published content, as saved on disk.
+struct PublishedContent {
+    /// The content published, in the order it was published in.
+    #[serde(default)]
+    published: Vec<PublishedEntry>,
+}
+
+impl OkuFs {
+    /// Loads the published content from disk.
+    ///
+    /// # Returns
+    ///
+    /// The published content.
+    fn load_published_content(&self) -> Result<PublishedContent, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(PUBLISHED_CONTENT_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(published_content_toml) => Ok(toml::from_str(&published_content_toml)?),
+            Err(_) => Ok(PublishedContent::default()),
+        }
+    }
+
+    /// Lists the tags keeping published content from being garbage collected.
+    ///
+    /// # Returns
+    ///
+    /// The names of the tags kept on published content.
+    pub(crate) fn published_tags(&self) -> Result<HashSet<String>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .load_published_content()?
+            .published
+            .into_iter()
+            .map(|entry| entry.tag)
+            .collect())
+    }
+
+    /// Saves the published content to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `published_content` - The published content.
+    fn save_published_content(
+        &self,
+        published_content: &PublishedContent,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        std::fs::write(
+            self.config.path.join(PUBLISHED_CONTENT_FILE_NAME),
+            toml::to_string(published_content)?,
+        )?;
+        Ok(())
+    }
+
+    /// Records content added to the local store as published, and announces it if discovery is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The hash and format of the content.
+    ///
+    /// * `tag` - The tag keeping the content from being garbage collected.
+    ///
+    /// # Returns
+    ///
+    /// A ticket pointing to the content and this node.
+    pub(crate) async fn record_published_content(
+        &self,
+        content: HashAndFormat,
+        tag: Tag,
+    ) -> Result<BlobTicket, Box<dyn Error + Send + Sync>> {
+        let already_published = {
+            let _settings_guard = self.settings_lock.lock().unwrap();
+            let mut published_content = self.load_published_content()?;
+            let already_published = published_content
+                .published
+                .iter()
+                .any(|entry| entry.content == content);
+            if !already_published {
+                let tag: &[u8] = tag.borrow();
+                published_content.published.push(PublishedEntry {
+                    content,
+                    tag: String::from_utf8_lossy(tag).to_string(),
+                });
+                self.save_published_content(&published_content)?;
+            }
+            already_published
+        };
+        // The content was already published, so the tag created by adding it again is not needed.
+        if already_published {
+            self.node.tags.delete(tag).await?;
+        }
+        if self.config.discovery && !self.is_offline() {
+            let self_clone = self.clone();
+            tokio::spawn(async move {
+                let dht = self_clone.mainline_dht();
+                if let Err(e) = announce_published_content(&dht, [content]).await {
+                    tracing::warn!("{}", e);
+                }
+            });
+        }
+        Ok(self
+            .node
+            .blobs
+            .share(
+                content.hash,
+                content.format,
+                ShareTicketOptions::RelayAndAddresses,
+            )
+            .await?)
+    }
+
+    /// Publishes content outside of any replica, so that it can be fetched by its hash.
+    /// Published content is immutable, is kept by garbage collection until unpublished, and is announced to the mainline DHT along with the replicas.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content to publish.
+    ///
+    /// # Returns
+    ///
+    /// A ticket pointing to the content and this node.
+    pub async fn publish_bytes(
+        &self,
+        content: impl Into<Bytes>,
+    ) -> Result<BlobTicket, Box<dyn Error + Send + Sync>> {
+        let outcome = self.node.blobs.add_bytes(content).await?;
+        self.record_published_content(HashAndFormat::raw(outcome.hash), outcome.tag)
+            .await
+    }
+
+    /// Publishes a file or directory on disk outside of any replica, so that it can be fetched by its hash.
+    /// A file is published as a single piece of content, while a directory is published as a collection of the files within it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file or directory.
+    ///
+    /// # Returns
+    ///
+    /// A ticket pointing to the content and this node.
+    pub async fn publish_path(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<BlobTicket, Box<dyn Error + Send + Sync>> {
+        // The node reads the path itself, so it must not depend on this process's working directory.
+        let path = std::fs::canonicalize(path)?;
+        let outcome = self
+            .node
+            .blobs
+            .add_from_path(path, false, SetTagOption::Auto, WrapOption::NoWrap)
+            .await?
+            .finish()
+            .await?;
+        self.record_published_content(
+            HashAndFormat {
+                hash: outcome.hash,
+                format: outcome.format,
+            },
+            outcome.tag,
+        )
+        .await
+    }
+
+    /// Stops publishing content, so that it is no longer announced or given to peers, and may be removed by garbage collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The hash and format of the content.
+    pub async fn unpublish(
+        &self,
+        content: HashAndFormat,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let unpublished = {
+            let _settings_guard = self.settings_lock.lock().unwrap();
+            let mut published_content = self.load_published_content()?;
+            let (unpublished, published): (Vec<_>, Vec<_>) = published_content
+                .published
+                .into_iter()
+                .partition(|entry| entry.content == content);
+            published_content.published = published;
+            self.save_published_content(&published_content)?;
+            unpublished
+        };
+        for entry in unpublished {
+            self.node.tags.delete(Tag::from(entry.tag)).await?;
+        }
+        Ok(())
+    }
+
+    /// Lists the published content.
+    ///
+    /// # Returns
+    ///
+    /// The hash and format of each piece of published content, in the order it was published in.
+    pub fn list_published(&self) -> Result<Vec<HashAndFormat>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .load_published_content()?
+            .published
+            .into_iter()
+            .map(|entry| entry.content)
+            .collect())
+    }
+
+    /// Announces all published content to the mainline DHT immediately, rather than waiting for the next scheduled announcement.
+    ///
+    /// # Returns
+    ///
+    /// The content that was successfully announced.
+    #[instrument(skip_all, err)]
+    pub async fn announce_published_now(
+        &self,
+    ) -> Result<Vec<HashAndFormat>, Box<dyn Error + Send + Sync>> {
+        self.ensure_online("Announcing published content")?;
+        announce_published_content(&self.mainline_dht(), self.list_published()?).await
+    }
+
+    /// Responds to a peer asking for a ticket to published content.
+    /// Only published content is given out, so that the content of replicas cannot be fetched by its hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The hash and format of the content.
+    ///
+    /// # Returns
+    ///
+    /// A ticket pointing to the content and this node, if the content is published.
+    pub async fn respond_to_blob_ticket_request(
+        &self,
+        content: HashAndFormat,
+    ) -> Result<Option<BlobTicket>, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Responding to a request for content {}", content))?;
+        if !self.list_published()?.contains(&content) {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.node
+                .blobs
+                .share(
+                    content.hash,
+                    content.format,
+                    ShareTicketOptions::RelayAndAddresses,
+                )
+                .await?,
+        ))
+    }
+
+    /// Fetches published content so that it can be read locally, such as from a ticket, or by its hash from the peers announcing it on the mainline DHT.
+    /// Fetched content is kept by garbage collection until released with [`OkuFs::release_blob`].
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content to fetch, as a hash, a hash and format, or a ticket.
+    ///
+    /// # Returns
+    ///
+    /// The hash and format of the fetched content.
+    pub async fn fetch_published(
+        &self,
+        content: ContentRequest,
+    ) -> Result<HashAndFormat, Box<dyn Error + Send + Sync>> {
+        self.fetch_published_with_cancellation(content, CancellationToken::new())
+            .await
+    }
+
+    /// Fetches published content so that it can be read locally, stopping early if cancelled.
+    /// Discovering peers, and fetching the content from each of them, are abandoned after the configured network timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content to fetch, as a hash, a hash and format, or a ticket.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the fetch.
+    ///
+    /// # Returns
+    ///
+    /// The hash and format of the fetched content.
+    #[instrument(skip_all, fields(content = %content.hash_and_format()), err)]
+    pub async fn fetch_published_with_cancellation(
+        &self,
+        content: ContentRequest,
+        cancellation: CancellationToken,
+    ) -> Result<HashAndFormat, Box<dyn Error + Send + Sync>> {
+        let hash_and_format = content.hash_and_format();
+        if self.is_published_content_held(hash_and_format).await? {
+            return Ok(hash_and_format);
+        }
+        let providers = match content {
+            ContentRequest::Ticket(ticket) => vec![ticket.node_addr().clone()],
+            _ => {
+                self.discover_published_providers(hash_and_format, &cancellation)
+                    .await?
+            }
+        };
+        for provider in self.order_peers(providers)? {
+            let operation = format!(
+                "Fetching content {} from {}",
+                hash_and_format, provider.node_id
+            );
+            let blob_download_request = BlobDownloadRequest {
+                hash: hash_and_format.hash,
+                format: hash_and_format.format,
+                peer: provider,
+                tag: SetTagOption::Named(blob_tag(hash_and_format.hash)),
+            };
+            let outcome = self
+                .with_network_timeout(&operation, &cancellation, async {
+                    self.node
+                        .blobs
+                        .download(blob_download_request)
+                        .await?
+                        .finish()
+                        .await?;
+                    Ok(())
+                })
+                .await;
+            match outcome {
+                Ok(()) => return Ok(hash_and_format),
+                Err(e) if cancellation.is_cancelled() => return Err(e),
+                Err(e) => tracing::warn!(content = %hash_and_format, "{}", e),
+            }
+        }
+        Err(OkuFsError::BlobUnavailable(hash_and_format.to_string()).into())
+    }
+
+    /// Reads published content, fetching it first if it is not held locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content to read, as a hash, a hash and format, or a ticket.
+    ///
+    /// # Returns
+    ///
+    /// The content, which must be a single piece of content rather than a collection.
+    pub async fn read_published(
+        &self,
+        content: ContentRequest,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let hash_and_format = self.fetch_published(content).await?;
+        if hash_and_format.format == BlobFormat::HashSeq {
+            return Err(OkuFsError::PublishedCollection(hash_and_format.to_string()).into());
+        }
+        self.read_blob(hash_and_format.hash).await
+    }
+
+    /// Saves published content to disk, fetching it first if it is not held locally.
+    /// A single piece of content is saved as a file, while a collection is saved as a directory holding its files.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content to save, as a hash, a hash and format, or a ticket.
+    ///
+    /// * `destination` - The path of the file or directory to save the content to.
+    ///
+    /// # Returns
+    ///
+    /// The hash and format of the saved content.
+    pub async fn export_published(
+        &self,
+        content: ContentRequest,
+        destination: impl AsRef<Path>,
+    ) -> Result<HashAndFormat, Box<dyn Error + Send + Sync>> {
+        let hash_and_format = self.fetch_published(content).await?;
+        let destination = std::path::absolute(destination)?;
+        let format = match hash_and_format.format {
+            BlobFormat::Raw => ExportFormat::Blob,
+            BlobFormat::HashSeq => ExportFormat::Collection,
+        };
+        self.node
+            .blobs
+            .export(hash_and_format.hash, destination, format, ExportMode::Copy)
+            .await?
+            .finish()
+            .await?;
+        Ok(hash_and_format)
+    }
+
+    /// Checks whether published content is held in full locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The hash and format of the content.
+    ///
+    /// # Returns
+    ///
+    /// Whether the content, and every piece of content in it if it is a collection, is held in full.
+    async fn is_published_content_held(
+        &self,
+        content: HashAndFormat,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let mut hashes = vec![content.hash];
+        if content.format == BlobFormat::HashSeq {
+            match self.read_blob(content.hash).await {
+                Ok(hash_seq) => hashes.extend(iroh::bytes::hashseq::HashSeq::try_from(hash_seq)?),
+                Err(_) => return Ok(false),
+            }
+        }
+        for hash in hashes {
+            match self.node.blobs.read(hash).await {
+                Ok(reader) if reader.is_complete() => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Finds the nodes providing published content by asking each peer announcing it on the mainline DHT for a ticket to it.
+    /// Asking peers is abandoned after the configured network timeout, keeping the nodes found until then.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The hash and format of the content.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the search.
+    ///
+    /// # Returns
+    ///
+    /// The nodes listed in the tickets the peers responded with, other than this node.
+    async fn discover_published_providers(
+        &self,
+        content: HashAndFormat,
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<NodeAddr>, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!("Discovering peers providing content {}", content))?;
+        let request_string = serde_json::to_string(&content)?;
+        let mut addrs = self
+            .mainline_dht()
+            .as_async()
+            .get_peers(to_infohash(content));
+        let own_node_id = self.node.node_id();
+        let mut providers: Vec<NodeAddr> = Vec::new();
+        let operation = format!("Discovering peers providing content {}", content);
+        let discovered = self
+            .with_network_timeout(&operation, cancellation, async {
+                while let Some(peer_response) = addrs.next_async().await {
+                    match request_blob_ticket(content, peer_response.peer, &request_string).await {
+                        Ok(Some(ticket)) => {
+                            let provider = ticket.node_addr();
+                            if provider.node_id != own_node_id
+                                && !providers
+                                    .iter()
+                                    .any(|known| known.node_id == provider.node_id)
+                            {
+                                providers.push(provider.clone());
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::debug!(peer = %peer_response.peer, "{}", e),
+                    }
+                }
+                Ok(())
+            })
+            .await;
+        if cancellation.is_cancelled() {
+            discovered?;
+        }
+        Ok(providers)
+    }
+}
+
+/// Announces published content to the mainline DHT, so that peers can find the nodes providing it by its hash.
+///
+/// # Arguments
+///
+/// * `dht` - The connection to the mainline DHT to announce through.
+///
+/// * `content` - The hash and format of each piece of content to announce.
+///
+/// # Returns
+///
+/// The content that was successfully announced.
+pub(crate) async fn announce_published_content(
+    dht: &mainline::Dht,
+    content: impl IntoIterator<Item = HashAndFormat>,
+) -> Result<Vec<HashAndFormat>, Box<dyn Error + Send + Sync>> {
+    let content: BTreeSet<HashAndFormat> = content.into_iter().collect();
+    let announce_stream = announce_dht(dht.clone(), content, DISCOVERY_PORT, ANNOUNCE_PARALLELISM);
+    pin_mut!(announce_stream);
+    let mut announced = Vec::new();
+    while let Some((content, res)) = announce_stream.next().await {
+        match res {
+            Ok(_) => {
+                tracing::debug!(%content, "Announced published content");
+                announced.push(content);
+            }
+            Err(e) => tracing::warn!(
+                "{}",
+                OkuDiscoveryError::ProblemAnnouncingContent(content.to_string(), e.to_string())
+            ),
+        }
+    }
+    Ok(announced)
+}
+
+/// Asks a peer announcing published content for a ticket to it.
+///
+/// # Arguments
+///
+/// * `content` - The hash and format of the content.
+///
+/// * `peer` - The address of the peer.
+///
+/// * `request_string` - The serialised request for the content.
+///
+/// # Returns
+///
+/// The ticket the peer responded with, if it provides the content.
+async fn request_blob_ticket(
+    content: HashAndFormat,
+    peer: SocketAddr,
+    request_string: &str,
+) -> Result<Option<BlobTicket>, Box<dyn Error + Send + Sync>> {
+    let stream = TcpStream::connect(peer).await?;
+    let response_bytes = send_request(stream, ALPN_BLOB_TICKET_FETCH, request_string).await?;
+    // Peers close the connection without responding when they do not publish the content.
+    if response_bytes.is_empty() {
+        return Ok(None);
+    }
+    let ticket: BlobTicket =
+        serde_json::from_str(String::from_utf8_lossy(&response_bytes).as_ref())?;
+    match ticket.hash() == content.hash && ticket.format() == content.format {
+        true => Ok(Some(ticket)),
+        false => Ok(None),
+    }
+}
@@ -0,0 +1,152 @@
+use crate::event::OkuFsEvent;
+use crate::fs::{normalise_path, OkuFs};
+use futures::{pin_mut, StreamExt};
+use iroh::sync::{store::Query, AuthorId, NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashSet},
+    error::Error,
+    path::PathBuf,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What was removed from a replica by purging files.
+pub struct PurgeReport {
+    /// The number of entries removed, across all authors held on this node.
+    pub entries_deleted: usize,
+    /// The number of entries written by authors not held on this node.
+    /// These cannot be removed, as only their authors can sign a deletion, so they are hidden behind a newer deletion instead.
+    pub hidden_entries: usize,
+}
+
+impl OkuFs {
+    /// Deletes every version of a file, by every author.
+    ///
+    /// A replica holds the latest version of a file written by each author, and deleting a file only removes the deleting author's version, so other authors' versions remain and reappear once synchronised.
+    /// Purging deletes the file as each author held on this node. Versions by other authors cannot be removed, since only their authors can sign a deletion; instead, the file is deleted once more as the file system's author, so that the deletion is the latest version and the file stays hidden.
+    /// Should another author write the file again after the purge, their write is newer, and the file reappears.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file to purge.
+    ///
+    /// * `path` - The path of the file to purge.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries removed, and the number of entries by other authors hidden instead.
+    pub async fn purge_file(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<PurgeReport, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        self.discard_pending_writes(namespace_id, path.clone());
+        let document = self.open_document(namespace_id).await?;
+        let file_key = self.entry_key(path.clone());
+        let local_authors: HashSet<AuthorId> = self.list_authors().await?.into_iter().collect();
+        let mut authors = BTreeSet::new();
+        let mut hidden_entries = 0;
+        let entries = document
+            .get_many(Query::key_exact(&file_key).build())
+            .await?;
+        pin_mut!(entries);
+        while let Some(entry) = entries.next().await {
+            let author = entry?.author();
+            match local_authors.contains(&author) {
+                true => {
+                    authors.insert(author);
+                }
+                false => hidden_entries += 1,
+            }
+        }
+        let mut entries_deleted = 0;
+        // The file system's author deletes last, so that its deletion is the latest version of the file.
+        authors.remove(&self.author_id);
+        for author in authors.into_iter().chain(std::iter::once(self.author_id)) {
+            entries_deleted += document.del(author, file_key.clone()).await?;
+        }
+        self.delete_metadata(namespace_id, path.clone()).await?;
+        self.emit(OkuFsEvent::EntryDeleted {
+            namespace_id,
+            path: normalise_path(path),
+            author: self.author_id,
+        });
+        Ok(PurgeReport {
+            entries_deleted,
+            hidden_entries,
+        })
+    }
+
+    /// Deletes every version of every file within a directory, by every author.
+    ///
+    /// Deleting a directory only removes the deleting author's files within it, leaving other authors' files in place.
+    /// Purging deletes the directory as each author held on this node, then hides each remaining file written by other authors behind a newer deletion by the file system's author, as in [`OkuFs::purge_file`].
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory to purge.
+    ///
+    /// * `path` - The path of the directory to purge.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries removed, and the number of entries by other authors hidden instead.
+    pub async fn purge_directory(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<PurgeReport, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        let path = normalise_path(path).join(""); // Ensure path ends with a slash
+        self.discard_pending_writes(namespace_id, path.clone());
+        let document = self.open_document(namespace_id).await?;
+        let prefix = self.entry_prefix(path.clone());
+        let local_authors: HashSet<AuthorId> = self.list_authors().await?.into_iter().collect();
+        let mut authors = BTreeSet::new();
+        let mut remote_keys = BTreeSet::new();
+        let mut hidden_entries = 0;
+        let entries = document
+            .get_many(Query::key_prefix(&prefix).build())
+            .await?;
+        pin_mut!(entries);
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            match local_authors.contains(&entry.author()) {
+                true => {
+                    authors.insert(entry.author());
+                }
+                false => {
+                    hidden_entries += 1;
+                    remote_keys.insert(entry.key().to_vec());
+                }
+            }
+        }
+        let mut entries_deleted = 0;
+        authors.insert(self.author_id);
+        for author in authors {
+            entries_deleted += self
+                .delete_prefix(&document, author, prefix.clone())
+                .await?;
+        }
+        // Deletions of single files are newer than the deletion of the directory, so they are not removed by it.
+        for key in remote_keys {
+            document.del(self.author_id, key).await?;
+        }
+        self.delete_directory_metadata(namespace_id, path.clone())
+            .await?;
+        self.emit(OkuFsEvent::EntryDeleted {
+            namespace_id,
+            path,
+            author: self.author_id,
+        });
+        Ok(PurgeReport {
+            entries_deleted,
+            hidden_entries,
+        })
+    }
+}
@@ -0,0 +1,228 @@
+use crate::fs::{is_directory_marker, is_metadata_key, OkuFs};
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    client::Entry,
+    sync::{
+        store::{Query, SortDirection},
+        AuthorId, NamespaceId,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, error::Error, path::PathBuf};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// An attribute of files to sort query results by.
+pub enum FileSort {
+    /// Sort by path, in the order of the files' keys.
+    #[default]
+    Path,
+    /// Sort by the time the latest version of each file was written.
+    Modified,
+    /// Sort by the size of each file's content, as stored.
+    Size,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+/// A query over the files in a replica, filtering them by their attributes and sorting the results.
+///
+/// Filters on path and author, and sorting by path, are applied by the replica's store; the remaining filters and sorts are applied while reading the matching entries, holding no more than twice the requested results in memory.
+pub struct FileQuery {
+    /// A directory the files must be within.
+    path: Option<PathBuf>,
+    /// The author who must have written the files.
+    author: Option<AuthorId>,
+    /// The earliest time the files may have been modified, in microseconds since the Unix epoch.
+    modified_after: Option<u64>,
+    /// The latest time the files may have been modified, in microseconds since the Unix epoch.
+    modified_before: Option<u64>,
+    /// The smallest size, in bytes, the files' content may have.
+    min_size: Option<u64>,
+    /// The largest size, in bytes, the files' content may have.
+    max_size: Option<u64>,
+    /// The attribute to sort the files by.
+    sort: FileSort,
+    /// The direction to sort the files in.
+    direction: SortDirection,
+    /// The most files to return.
+    limit: Option<usize>,
+    /// The number of files to skip before returning any.
+    offset: usize,
+}
+
+impl FileQuery {
+    /// Creates a query matching every file in a replica, sorted by path.
+    pub fn new() -> Self {
+        FileQuery::default()
+    }
+
+    /// Only matches files within a directory.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Only matches files written by an author, describing each by the latest version the author wrote.
+    pub fn author(mut self, author: AuthorId) -> Self {
+        self.author = Some(author);
+        self
+    }
+
+    /// Only matches files modified at or after a time, in microseconds since the Unix epoch.
+    pub fn modified_after(mut self, timestamp: u64) -> Self {
+        self.modified_after = Some(timestamp);
+        self
+    }
+
+    /// Only matches files modified at or before a time, in microseconds since the Unix epoch.
+    pub fn modified_before(mut self, timestamp: u64) -> Self {
+        self.modified_before = Some(timestamp);
+        self
+    }
+
+    /// Only matches files whose stored content is at least a given size, in bytes.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// Only matches files whose stored content is at most a given size, in bytes.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Sorts the matching files by an attribute, in a given direction.
+    pub fn sort_by(mut self, sort: FileSort, direction: SortDirection) -> Self {
+        self.sort = sort;
+        self.direction = direction;
+        self
+    }
+
+    /// Returns no more than a given number of files.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skips a given number of files before returning any.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Checks whether an entry meets the filters not applied by the replica's store.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry.
+    ///
+    /// # Returns
+    ///
+    /// Whether the entry matches the query.
+    fn selects(&self, entry: &Entry) -> bool {
+        !is_metadata_key(entry.key())
+            && !is_directory_marker(entry.key())
+            && self
+                .modified_after
+                .is_none_or(|after| entry.timestamp() >= after)
+            && self
+                .modified_before
+                .is_none_or(|before| entry.timestamp() <= before)
+            && self.min_size.is_none_or(|min| entry.content_len() >= min)
+            && self.max_size.is_none_or(|max| entry.content_len() <= max)
+    }
+
+    /// Compares two entries in the order the query sorts them.
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        let ordering = match self.sort {
+            FileSort::Path => a.key().cmp(b.key()),
+            FileSort::Modified => a.timestamp().cmp(&b.timestamp()),
+            FileSort::Size => a.content_len().cmp(&b.content_len()),
+        }
+        .then_with(|| a.key().cmp(b.key()));
+        match self.direction {
+            SortDirection::Asc => ordering,
+            SortDirection::Desc => ordering.reverse(),
+        }
+    }
+}
+
+impl OkuFs {
+    /// Finds the files in a replica matching a query, such as the most recently modified files within a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to search.
+    ///
+    /// * `query` - The query to match files against.
+    ///
+    /// # Returns
+    ///
+    /// The latest entries of the matching files, in the order requested, excluding file system metadata and directory markers.
+    pub async fn query_files(
+        &self,
+        namespace_id: NamespaceId,
+        query: &FileQuery,
+    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let mut store_query = Query::single_latest_per_key();
+        if let Some(path) = &query.path {
+            store_query = store_query.key_prefix(self.entry_prefix(path.clone()));
+        }
+        if let Some(author) = query.author {
+            store_query = store_query.author(author);
+        }
+        let sorted_by_store = query.sort == FileSort::Path;
+        if sorted_by_store {
+            store_query = store_query.sort_direction(query.direction);
+        }
+        // Results sorted by the store are read lazily, so reading stops once enough files are found.
+        let wanted = query.limit.map(|limit| query.offset + limit);
+        let entries = document.get_many(store_query.build()).await?;
+        pin_mut!(entries);
+        let mut files: Vec<Entry> = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if !query.selects(&entry) {
+                continue;
+            }
+            files.push(entry);
+            match wanted {
+                Some(wanted) if sorted_by_store && files.len() >= wanted => break,
+                Some(wanted) if files.len() >= wanted.max(1) * 2 => {
+                    files.sort_by(|a, b| query.compare(a, b));
+                    files.truncate(wanted);
+                }
+                _ => {}
+            }
+        }
+        files.sort_by(|a, b| query.compare(a, b));
+        Ok(files
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    /// Lists the files in a replica most recently modified.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `limit` - The most files to list.
+    ///
+    /// # Returns
+    ///
+    /// The latest entries of the most recently modified files, newest first.
+    pub async fn recently_modified_files(
+        &self,
+        namespace_id: NamespaceId,
+        limit: usize,
+    ) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let query = FileQuery::new()
+            .sort_by(FileSort::Modified, SortDirection::Desc)
+            .limit(limit);
+        self.query_files(namespace_id, &query).await
+    }
+}
@@ -1,167 +1,315 @@
-use ahash::AHashMap;
-use iroh::sync::NamespaceId;
-use lazy_static::lazy_static;
-use oku_fs::{
-    discovery::{
-        announce_replica, PeerContentRequest, PeerContentResponse, DISCOVERY_PORT,
-        INITIAL_PUBLISH_DELAY, REPUBLISH_DELAY,
-    },
-    error::OkuRelayError,
-    fs::{ALPN_DOCUMENT_TICKET_FETCH, ALPN_INITIAL_RELAY_CONNECTION, ALPN_RELAY_FETCH},
+use crate::discovery::{
+    announce_replicas, PeerContentRequest, PeerContentResponse, INITIAL_PUBLISH_DELAY,
+    REPUBLISH_DELAY,
 };
+use crate::error::OkuRelayError;
+use crate::fs::{ALPN_DOCUMENT_TICKET_FETCH, ALPN_INITIAL_RELAY_CONNECTION};
+use iroh::sync::NamespaceId;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::{HashMap, HashSet},
     error::Error,
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    net::SocketAddr,
+    sync::Arc,
 };
-use tokio::{io::AsyncReadExt, net::TcpStream};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::TcpListener,
-    sync::RwLock,
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot, RwLock},
 };
 
-lazy_static! {
-    static ref REPLICAS_BY_NODE: RwLock<AHashMap<NamespaceId, SocketAddr>> =
-        RwLock::new(AHashMap::new());
+/// The number of requests from external nodes that may wait to be passed to a connected node.
+pub const RELAY_REQUEST_BUFFER: usize = 64;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A message sent by a relay to a node connected to it.
+pub(crate) enum RelayRequest {
+    /// Asks the node for the replicas it holds.
+    ListReplicas,
+    /// Passes on a request for content made by an external node, to be answered with the same identifier.
+    Content(u64, PeerContentRequest),
 }
 
-#[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
-    // Listen for node connections.
-    // 1. A node connects to the relay, creating a new thread.
-    // 2. The relay receives a list of replicas held by the node.
-    // 3. The connection stays open, and the relay periodically updates the list of replicas held by the node.
-    tokio::spawn(async move {
-        handle_node_connections().await?;
-        Ok::<(), Box<dyn Error + Send + Sync>>(())
-    });
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A message sent by a node connected to a relay, in reply to the relay.
+pub(crate) enum RelayResponse {
+    /// The replicas held by the node.
+    Replicas(Vec<NamespaceId>),
+    /// The response to the request for content with the given identifier.
+    Content(u64, Box<PeerContentResponse>),
+    /// The reason the request for content with the given identifier could not be satisfied.
+    ContentError(u64, String),
+}
 
-    // Announce replicas held by connected nodes.
-    tokio::spawn(async move {
-        loop {
-            // let responses = responses.clone();
-            tokio::time::sleep(INITIAL_PUBLISH_DELAY).await;
-            for replica_by_node in REPLICAS_BY_NODE.read().await.iter() {
-                announce_replica(*replica_by_node.0).await?;
-            }
-            tokio::time::sleep(REPUBLISH_DELAY - INITIAL_PUBLISH_DELAY).await;
-        }
-        Ok::<(), Box<dyn Error + Send + Sync>>(())
-    });
+/// A request for content waiting to be passed to a connected node, along with where to send the node's response.
+type PendingRequest = (
+    PeerContentRequest,
+    oneshot::Sender<Result<PeerContentResponse, String>>,
+);
+
+#[derive(Debug)]
+/// A node behind NAT connected to a relay.
+struct ConnectedNode {
+    /// The replicas the node last reported holding.
+    replicas: HashSet<NamespaceId>,
+    /// Requests for content to be passed to the node.
+    requests: mpsc::Sender<PendingRequest>,
+}
+
+#[derive(Clone, Debug, Default)]
+/// A relay for nodes unable to accept incoming connections, such as those behind NAT.
+///
+/// Nodes connect to the relay and keep their connection open. The relay announces the replicas they hold to the mainline DHT on their behalf, and passes requests for those replicas from external nodes to them over their connection.
+pub struct OkuRelay {
+    /// The nodes connected to the relay, by the address they connected from.
+    nodes: Arc<RwLock<HashMap<SocketAddr, ConnectedNode>>>,
+}
+
+impl OkuRelay {
+    /// Creates a relay with no connected nodes.
+    ///
+    /// # Returns
+    ///
+    /// A relay, yet to accept connections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lists the replicas held by the nodes connected to the relay.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas the relay can satisfy requests for.
+    pub async fn list_replicas(&self) -> Vec<NamespaceId> {
+        let replicas: HashSet<NamespaceId> = self
+            .nodes
+            .read()
+            .await
+            .values()
+            .flat_map(|node| node.replicas.iter().copied())
+            .collect();
+        replicas.into_iter().collect()
+    }
 
-    // Listen for content requests from external nodes.
-    // 1. Get request from external node.
-    // 2. Find IP of node that can satisfy this request.
-    // 3. Pass the request to the node behind NAT and get its response.
-    // 4. Pass the response to the external node.
-    tokio::spawn(async move {
-        let socket = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT);
-        let listener = TcpListener::bind(socket).await?;
+    /// Accepts connections from nodes and requests from external nodes, announcing the replicas held by connected nodes until the relay is stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on. External nodes expect relays to listen on the discovery port.
+    pub async fn run(&self, address: SocketAddr) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let listener = TcpListener::bind(address).await?;
+        // A single connection to the DHT is shared by the relay's announcements, rather than one being made for each.
+        let dht = mainline::Dht::default();
+        let self_clone = self.clone();
+        let announce_dht = dht.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(INITIAL_PUBLISH_DELAY).await;
+                let replicas = self_clone.list_replicas().await;
+                if let Err(e) = announce_replicas(&announce_dht, replicas).await {
+                    tracing::warn!("{}", e);
+                }
+                tokio::time::sleep(REPUBLISH_DELAY - INITIAL_PUBLISH_DELAY).await;
+            }
+        });
         loop {
-            let (mut stream, _) = listener.accept().await?;
+            let (stream, peer_address) = listener.accept().await?;
+            let self_clone = self.clone();
+            let dht = dht.clone();
             tokio::spawn(async move {
-                tokio::spawn(async move {
-                    let mut buf_reader = BufReader::new(&mut stream);
-                    let received: Vec<u8> = buf_reader.fill_buf().await?.to_vec();
-                    buf_reader.consume(received.len());
-                    let mut incoming_lines = received.split(|x| *x == 10);
-                    if let Some(first_line) = incoming_lines.next() {
-                        if first_line == ALPN_DOCUMENT_TICKET_FETCH {
-                            let remaining_lines: Vec<Vec<u8>> =
-                                incoming_lines.map(|x| x.to_owned()).collect();
-                            let peer_content_request_bytes = remaining_lines.concat();
-                            let peer_content_request_str =
-                                String::from_utf8_lossy(&peer_content_request_bytes).to_string();
-                            let peer_content_request =
-                                serde_json::from_str(&peer_content_request_str)?;
-                            let peer_content_response =
-                                respond_to_content_request(peer_content_request).await?;
-                            let peer_content_response_string =
-                                serde_json::to_string(&peer_content_response)?;
-                            stream
-                                .write_all(peer_content_response_string.as_bytes())
-                                .await?;
-                            stream.flush().await?;
-                        }
-                    }
-                    Ok::<(), Box<dyn Error + Send + Sync>>(())
-                });
+                if let Err(e) = self_clone
+                    .handle_connection(stream, peer_address, dht)
+                    .await
+                {
+                    tracing::warn!(%peer_address, "{}", e);
+                }
             });
         }
-        Ok::<(), Box<dyn Error + Send + Sync>>(())
-    });
-    Ok(())
-}
+    }
 
-async fn respond_to_content_request(
-    peer_content_request: PeerContentRequest,
-) -> Result<PeerContentResponse, Box<dyn Error + Send + Sync>> {
-    let replicas_by_node_reader = REPLICAS_BY_NODE.read().await;
-    let replica_ip = replicas_by_node_reader
-        .get(&peer_content_request.namespace_id)
-        .ok_or(OkuRelayError::CannotSatisfyRequest(
-            peer_content_request.namespace_id.to_string(),
-        ))?;
-    let peer_content_request_string = serde_json::to_string(&peer_content_request)?;
-    let mut stream = TcpStream::connect(*replica_ip).await?;
-    let mut request = Vec::new();
-    request.write_all(ALPN_DOCUMENT_TICKET_FETCH).await?;
-    request.write_all(b"\n").await?;
-    request
-        .write_all(peer_content_request_string.as_bytes())
-        .await?;
-    request.flush().await?;
-    stream.write_all(&request).await?;
-    stream.flush().await?;
-    let mut response_bytes = Vec::new();
-    stream.read_to_end(&mut response_bytes).await?;
-    let response: PeerContentResponse =
-        serde_json::from_str(String::from_utf8_lossy(&response_bytes).as_ref())?;
-    Ok(response)
-}
+    /// Handles a connection to the relay, either from a node behind NAT or from an external node requesting content.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The connection.
+    ///
+    /// * `peer_address` - The address the connection was made from.
+    ///
+    /// * `dht` - The connection to the mainline DHT to announce replicas through.
+    async fn handle_connection(
+        &self,
+        stream: TcpStream,
+        peer_address: SocketAddr,
+        dht: mainline::Dht,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut buf_reader = BufReader::new(stream);
+        let mut first_line = Vec::new();
+        buf_reader.read_until(b'\n', &mut first_line).await?;
+        if first_line.last() == Some(&b'\n') {
+            first_line.pop();
+        }
+        if first_line == ALPN_INITIAL_RELAY_CONNECTION {
+            self.handle_node(buf_reader, peer_address, dht).await
+        } else if first_line == ALPN_DOCUMENT_TICKET_FETCH {
+            let peer_content_request_bytes = buf_reader.fill_buf().await?.to_vec();
+            let peer_content_request: PeerContentRequest = serde_json::from_str(
+                String::from_utf8_lossy(&peer_content_request_bytes).as_ref(),
+            )?;
+            let peer_content_response = self
+                .respond_to_content_request(peer_content_request)
+                .await?;
+            let mut stream = buf_reader.into_inner();
+            stream
+                .write_all(serde_json::to_string(&peer_content_response)?.as_bytes())
+                .await?;
+            stream.flush().await?;
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
 
-async fn handle_node_connections() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let socket = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT);
-    let listener = TcpListener::bind(socket).await?;
-    loop {
-        let (mut stream, _) = listener.accept().await?;
-        let node_ip = stream.peer_addr()?;
-        tokio::spawn(async move {
-            let mut buf_reader = BufReader::new(&mut stream);
-            let received: Vec<u8> = buf_reader.fill_buf().await?.to_vec();
-            buf_reader.consume(received.len());
-            let mut incoming_lines = received.split(|x| *x == 10);
-            if let Some(first_line) = incoming_lines.next() {
-                if first_line == ALPN_INITIAL_RELAY_CONNECTION {
-                    let remaining_lines: Vec<Vec<u8>> =
-                        incoming_lines.map(|x| x.to_owned()).collect();
-                    let replica_list_bytes = remaining_lines.concat();
-                    let replica_list_str = String::from_utf8_lossy(&replica_list_bytes).to_string();
-                    let replica_list: Vec<NamespaceId> = serde_json::from_str(&replica_list_str)?;
-                    for replica in &replica_list {
-                        REPLICAS_BY_NODE.write().await.insert(*replica, node_ip);
-                    }
-                    // Periodically update list while connection remains alive
-                    loop {
-                        tokio::time::sleep(INITIAL_PUBLISH_DELAY).await;
-                        // Clear out list for this node
-                        REPLICAS_BY_NODE.write().await.retain(|_k, v| *v != node_ip);
-                        // Get updated list from node
-                        stream.write_all(ALPN_RELAY_FETCH).await?;
-                        stream.flush().await?;
-                        let mut response_bytes = Vec::new();
-                        stream.read_to_end(&mut response_bytes).await?;
-                        let response: Vec<NamespaceId> = serde_json::from_str(
-                            String::from_utf8_lossy(&response_bytes).as_ref(),
-                        )?;
-                        for replica in &response {
-                            REPLICAS_BY_NODE.write().await.insert(*replica, node_ip);
+    /// Passes a request for content to a connected node holding the requested replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_content_request` - A request for content made by an external node.
+    ///
+    /// # Returns
+    ///
+    /// The connected node's response to the request.
+    async fn respond_to_content_request(
+        &self,
+        peer_content_request: PeerContentRequest,
+    ) -> Result<PeerContentResponse, Box<dyn Error + Send + Sync>> {
+        let namespace_id = peer_content_request.namespace_id;
+        let requests = self
+            .nodes
+            .read()
+            .await
+            .values()
+            .find(|node| node.replicas.contains(&namespace_id))
+            .map(|node| node.requests.clone())
+            .ok_or(OkuRelayError::CannotSatisfyRequest(
+                namespace_id.to_string(),
+            ))?;
+        let (response_sender, response_receiver) = oneshot::channel();
+        requests
+            .send((peer_content_request, response_sender))
+            .await
+            .map_err(|_| OkuRelayError::NodeDisconnected(namespace_id.to_string()))?;
+        match response_receiver.await {
+            Ok(Ok(peer_content_response)) => Ok(peer_content_response),
+            Ok(Err(e)) => {
+                Err(OkuRelayError::ProblemSatisfyingRequest(namespace_id.to_string(), e).into())
+            }
+            Err(_) => Err(OkuRelayError::NodeDisconnected(namespace_id.to_string()).into()),
+        }
+    }
+
+    /// Serves a node connected to the relay until it disconnects, keeping track of the replicas it holds and passing requests for content to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf_reader` - The connection to the node, after the line identifying it as a node.
+    ///
+    /// * `peer_address` - The address the node connected from.
+    ///
+    /// * `dht` - The connection to the mainline DHT to announce the node's replicas through.
+    async fn handle_node(
+        &self,
+        buf_reader: BufReader<TcpStream>,
+        peer_address: SocketAddr,
+        dht: mainline::Dht,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (requests, mut request_receiver) = mpsc::channel(RELAY_REQUEST_BUFFER);
+        self.nodes.write().await.insert(
+            peer_address,
+            ConnectedNode {
+                replicas: HashSet::new(),
+                requests,
+            },
+        );
+        let (reader, mut writer) = buf_reader.into_inner().into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut pending_responses = HashMap::new();
+        let mut next_request_id = 0;
+        let mut refresh = tokio::time::interval(REPUBLISH_DELAY);
+        // The first tick completes immediately, but the node sends its replicas upon connecting.
+        refresh.tick().await;
+        let served = async {
+            loop {
+                let relay_request = tokio::select! {
+                    line = lines.next_line() => {
+                        let Some(line) = line? else {
+                            break;
+                        };
+                        match serde_json::from_str(&line)? {
+                            RelayResponse::Replicas(replicas) => {
+                                let new_replicas = self.update_replicas(peer_address, replicas).await;
+                                if !new_replicas.is_empty() {
+                                    let dht = dht.clone();
+                                    tokio::spawn(async move {
+                                        announce_replicas(&dht, new_replicas).await
+                                    });
+                                }
+                            }
+                            RelayResponse::Content(request_id, peer_content_response) => {
+                                if let Some(response_sender) = pending_responses.remove(&request_id) {
+                                    let _ = oneshot::Sender::send(response_sender, Ok(*peer_content_response));
+                                }
+                            }
+                            RelayResponse::ContentError(request_id, e) => {
+                                if let Some(response_sender) = pending_responses.remove(&request_id) {
+                                    let _ = oneshot::Sender::send(response_sender, Err(e));
+                                }
+                            }
                         }
-                        tokio::time::sleep(REPUBLISH_DELAY - INITIAL_PUBLISH_DELAY).await;
+                        continue;
                     }
-                }
+                    Some((peer_content_request, response_sender)) = request_receiver.recv() => {
+                        let request_id = next_request_id;
+                        next_request_id += 1;
+                        pending_responses.insert(request_id, response_sender);
+                        RelayRequest::Content(request_id, peer_content_request)
+                    }
+                    _ = refresh.tick() => RelayRequest::ListReplicas,
+                };
+                let mut relay_request_string = serde_json::to_string(&relay_request)?;
+                relay_request_string.push('\n');
+                writer.write_all(relay_request_string.as_bytes()).await?;
+                writer.flush().await?;
             }
             Ok::<(), Box<dyn Error + Send + Sync>>(())
-        });
+        };
+        let outcome = served.await;
+        self.nodes.write().await.remove(&peer_address);
+        outcome
+    }
+
+    /// Records the replicas a connected node holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_address` - The address the node connected from.
+    ///
+    /// * `replicas` - The replicas the node holds.
+    ///
+    /// # Returns
+    ///
+    /// The replicas the node did not previously report holding.
+    async fn update_replicas(
+        &self,
+        peer_address: SocketAddr,
+        replicas: Vec<NamespaceId>,
+    ) -> Vec<NamespaceId> {
+        let mut nodes = self.nodes.write().await;
+        let Some(node) = nodes.get_mut(&peer_address) else {
+            return Vec::new();
+        };
+        let replicas: HashSet<NamespaceId> = replicas.into_iter().collect();
+        let new_replicas = replicas.difference(&node.replicas).copied().collect();
+        node.replicas = replicas;
+        new_replicas
     }
 }
@@ -0,0 +1,11 @@
+use oku_fs::{discovery::DISCOVERY_PORT, relay::OkuRelay};
+use std::{
+    error::Error,
+    net::{Ipv4Addr, SocketAddrV4},
+};
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let address = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT);
+    OkuRelay::new().run(address.into()).await
+}
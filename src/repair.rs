@@ -0,0 +1,236 @@
+use crate::availability::Availability;
+use crate::discovery::{PeerContentRequest, PeerContentResponse, PeerTicketResponse};
+use crate::fs::{send_content_request, OkuFs};
+use crate::integrity::validate_content;
+use iroh::{
+    bytes::{Hash, HashAndFormat},
+    net::{NodeAddr, NodeId},
+    sync::NamespaceId,
+};
+use iroh_mainline_content_discovery::to_infohash;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, error::Error, net::SocketAddr, path::PathBuf};
+use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
+use tracing::instrument;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What was found and fetched while repairing a replica.
+pub struct RepairReport {
+    /// The files whose content was damaged, and was removed so that it could be fetched again.
+    pub damaged: Vec<PathBuf>,
+    /// The files whose missing or damaged content was fetched from peers.
+    pub recovered: Vec<PathBuf>,
+    /// The files whose missing or damaged content no peer could provide.
+    pub unavailable: Vec<PathBuf>,
+}
+
+impl OkuFs {
+    /// Repairs a replica by fetching the content of its files that is missing or damaged locally from its peers.
+    /// The content is first asked of the peers the replica was last synchronised with, then of the peers announcing the replica on the mainline DHT.
+    /// Content excluded by the replica's download policy is not fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The files whose content was damaged, recovered, or could not be recovered.
+    pub async fn repair_replica(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<RepairReport, Box<dyn Error + Send + Sync>> {
+        self.repair_replica_with_cancellation(namespace_id, CancellationToken::new())
+            .await
+    }
+
+    /// Repairs a replica by fetching the content of its files that is missing or damaged locally from its peers, stopping early if cancelled.
+    /// Looking for peers announcing the replica is abandoned after the configured network timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the repair.
+    ///
+    /// # Returns
+    ///
+    /// The files whose content was damaged, recovered, or could not be recovered.
+    #[instrument(skip_all, fields(%namespace_id), err)]
+    pub async fn repair_replica_with_cancellation(
+        &self,
+        namespace_id: NamespaceId,
+        cancellation: CancellationToken,
+    ) -> Result<RepairReport, Box<dyn Error + Send + Sync>> {
+        let mut report = RepairReport::default();
+        let entries = self.list_files(namespace_id).await?;
+        let damaged_hashes: HashSet<Hash> = validate_content(&self.node, false)
+            .await?
+            .into_iter()
+            .filter_map(|problem| problem.hash)
+            .collect();
+        let mut removed_hashes = HashSet::new();
+        for entry in entries.iter() {
+            let hash = entry.content_hash();
+            if damaged_hashes.contains(&hash) {
+                if removed_hashes.insert(hash) {
+                    self.node.blobs.delete_blob(hash).await?;
+                }
+                report.damaged.push(self.entry_path(entry.key()));
+            }
+        }
+
+        let missing_entries: Vec<_> = self
+            .entry_availabilities(namespace_id, entries)
+            .await?
+            .into_iter()
+            .filter(|entry_availability| {
+                matches!(
+                    entry_availability.availability,
+                    Availability::Pending | Availability::Partial { .. }
+                )
+            })
+            .map(|entry_availability| entry_availability.entry)
+            .collect();
+        if missing_entries.is_empty() {
+            return Ok(report);
+        }
+
+        let document = self.open_document(namespace_id).await?;
+        let sync_peers = document
+            .get_sync_peers()
+            .await?
+            .unwrap_or_default()
+            .iter()
+            .map(|peer| Ok(NodeAddr::new(NodeId::from_bytes(peer)?)))
+            .collect::<Result<Vec<_>, Box<dyn Error + Send + Sync>>>()?;
+        let sync_peers = self.order_peers(sync_peers)?;
+        let download_report = self
+            .download_from_peers(namespace_id, sync_peers.clone(), cancellation.clone())
+            .await?;
+        if !download_report.failed.is_empty() && !cancellation.is_cancelled() {
+            let announced_peers: Vec<NodeAddr> = self
+                .discover_replica_nodes(namespace_id, &cancellation)
+                .await?
+                .into_iter()
+                .filter(|peer| {
+                    !sync_peers
+                        .iter()
+                        .any(|sync_peer| sync_peer.node_id == peer.node_id)
+                })
+                .collect();
+            self.download_from_peers(
+                namespace_id,
+                self.order_peers(announced_peers)?,
+                cancellation,
+            )
+            .await?;
+        }
+
+        for entry_availability in self
+            .entry_availabilities(namespace_id, missing_entries)
+            .await?
+        {
+            match entry_availability.availability {
+                Availability::Local => report.recovered.push(entry_availability.path),
+                _ => report.unavailable.push(entry_availability.path),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Finds the nodes holding a replica by asking each peer announcing it on the mainline DHT for a ticket to it.
+    /// Asking peers is abandoned after the configured network timeout, keeping the nodes found until then.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the search.
+    ///
+    /// # Returns
+    ///
+    /// The nodes listed in the tickets the peers responded with, other than this node.
+    async fn discover_replica_nodes(
+        &self,
+        namespace_id: NamespaceId,
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<NodeAddr>, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(&format!(
+            "Discovering nodes holding replica {}",
+            namespace_id
+        ))?;
+        let peer_content_request_string = serde_json::to_string(&PeerContentRequest {
+            namespace_id,
+            path: None,
+            patterns: Vec::new(),
+        })?;
+        let info_hash = to_infohash(HashAndFormat::raw(Hash::new(namespace_id)));
+        let mut addrs = self.mainline_dht().as_async().get_peers(info_hash);
+        let own_node_id = self.node.node_id();
+        let mut nodes: Vec<NodeAddr> = Vec::new();
+        let operation = format!("Discovering nodes holding replica {}", namespace_id);
+        let discovered = self
+            .with_network_timeout(&operation, cancellation, async {
+                while let Some(peer_response) = addrs.next_async().await {
+                    let peer_nodes = request_replica_nodes(
+                        namespace_id,
+                        peer_response.peer,
+                        &peer_content_request_string,
+                    )
+                    .await;
+                    match peer_nodes {
+                        Ok(peer_nodes) => {
+                            for peer_node in peer_nodes {
+                                if peer_node.node_id != own_node_id
+                                    && !nodes.iter().any(|node| node.node_id == peer_node.node_id)
+                                {
+                                    nodes.push(peer_node);
+                                }
+                            }
+                        }
+                        Err(e) => tracing::debug!(peer = %peer_response.peer, "{}", e),
+                    }
+                }
+                Ok(())
+            })
+            .await;
+        if cancellation.is_cancelled() {
+            discovered?;
+        }
+        Ok(nodes)
+    }
+}
+
+/// Asks a peer announcing a replica for a ticket to it.
+///
+/// # Arguments
+///
+/// * `namespace_id` - The ID of the replica.
+///
+/// * `peer` - The address of the peer.
+///
+/// * `peer_content_request_string` - The serialised request for the replica.
+///
+/// # Returns
+///
+/// The nodes listed in the ticket the peer responded with, if it points to the replica.
+async fn request_replica_nodes(
+    namespace_id: NamespaceId,
+    peer: SocketAddr,
+    peer_content_request_string: &str,
+) -> Result<Vec<NodeAddr>, Box<dyn Error + Send + Sync>> {
+    let stream = TcpStream::connect(peer).await?;
+    let response_bytes = send_content_request(stream, peer_content_request_string).await?;
+    let response: PeerContentResponse =
+        serde_json::from_str(String::from_utf8_lossy(&response_bytes).as_ref())?;
+    match response.ticket_response {
+        PeerTicketResponse::Document(document_ticket)
+            if document_ticket.capability.id() == namespace_id =>
+        {
+            Ok(document_ticket.nodes)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
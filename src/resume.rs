@@ -0,0 +1,375 @@
+use crate::fs::OkuFs;
+use crate::ticket::AcceptPolicy;
+use iroh::{net::NodeAddr, sync::NamespaceId, ticket::DocTicket};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio_util::sync::CancellationToken;
+
+/// The name of the file listing replicas whose import has not yet finished, within the path on disk where the file system is stored.
+pub const PENDING_SYNCS_FILE_NAME: &str = "pending_syncs";
+
+/// The time between checks of how much of a replica being imported is held.
+pub const PENDING_SYNC_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The number of checks in a row without progress after which an import is left to be resumed when the file system next starts.
+pub const MAX_STALLED_CHECKS: u32 = 6;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A replica imported from a ticket whose content has not yet been fetched in full.
+pub struct PendingSync {
+    /// The ID of the replica.
+    pub namespace_id: NamespaceId,
+    /// The ticket the replica was imported from, with the access kept by the acceptance policy.
+    pub ticket: DocTicket,
+    /// The largest size, in bytes, the replica may have, as set by the acceptance policy.
+    pub max_size: Option<u64>,
+    /// Whether the replica met its acceptance policy, so that only its content is left to fetch.
+    pub accepted: bool,
+    /// When the import started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// The number of times the import has been resumed.
+    pub resumptions: u32,
+    /// The number of bytes of the replica's content held when last checked.
+    pub local_bytes: u64,
+    /// The size of the replica's content, in bytes, when last checked.
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A replica whose import has not yet finished, as saved on disk.
+struct SavedPendingSync {
+    /// The ticket the replica was imported from, in its textual form.
+    ticket: String,
+    /// The largest size, in bytes, the replica may have.
+    max_size: Option<u64>,
+    /// How long to spend learning the replica's size from peers.
+    probe_timeout: Duration,
+    /// Whether the replica met its acceptance policy.
+    accepted: bool,
+    /// When the import started, in seconds since the Unix epoch.
+    started_at: u64,
+    /// The number of times the import has been resumed.
+    #[serde(default)]
+    resumptions: u32,
+    /// The number of bytes of the replica's content held when last checked.
+    #[serde(default)]
+    local_bytes: u64,
+    /// The size of the replica's content, in bytes, when last checked.
+    #[serde(default)]
+    total_bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The replicas whose import has not yet finished, as saved on disk.
+struct PendingSyncs {
+    /// The unfinished imports, by replica ID.
+    pending: BTreeMap<String, SavedPendingSync>,
+}
+
+impl OkuFs {
+    /// Loads the replicas whose import has not yet finished from disk.
+    ///
+    /// # Returns
+    ///
+    /// The unfinished imports, by replica ID.
+    fn load_pending_syncs(
+        &self,
+    ) -> Result<BTreeMap<String, SavedPendingSync>, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(PENDING_SYNCS_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(pending_syncs_toml) => {
+                let pending_syncs: PendingSyncs = toml::from_str(&pending_syncs_toml)?;
+                Ok(pending_syncs.pending)
+            }
+            Err(_) => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Saves the replicas whose import has not yet finished to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The unfinished imports, by replica ID.
+    fn save_pending_syncs(
+        &self,
+        pending: BTreeMap<String, SavedPendingSync>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        std::fs::write(
+            self.config.path.join(PENDING_SYNCS_FILE_NAME),
+            toml::to_string(&PendingSyncs { pending })?,
+        )?;
+        Ok(())
+    }
+
+    /// Changes the saved state of an unfinished import, if it is still pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `update` - The change to make to the saved state.
+    fn update_pending_sync(
+        &self,
+        namespace_id: NamespaceId,
+        update: impl FnOnce(&mut SavedPendingSync),
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut pending = self.load_pending_syncs()?;
+        if let Some(pending_sync) = pending.get_mut(&namespace_id.to_string()) {
+            update(pending_sync);
+            self.save_pending_syncs(pending)?;
+        }
+        Ok(())
+    }
+
+    /// Records that a replica is being imported from a ticket, so that the import can be resumed if it is interrupted.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket the replica is imported from.
+    ///
+    /// * `policy` - The conditions the ticket must meet to be accepted.
+    pub(crate) fn record_pending_sync(
+        &self,
+        ticket: &DocTicket,
+        policy: &AcceptPolicy,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut pending = self.load_pending_syncs()?;
+        let namespace_id = ticket.capability.id().to_string();
+        // An import being resumed keeps when it first started, and how often it has been resumed.
+        let (started_at, resumptions) = match pending.get(&namespace_id) {
+            Some(pending_sync) => (pending_sync.started_at, pending_sync.resumptions),
+            None => (SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(), 0),
+        };
+        pending.insert(
+            namespace_id,
+            SavedPendingSync {
+                ticket: ticket.to_string(),
+                max_size: policy.max_size,
+                probe_timeout: policy.probe_timeout,
+                accepted: false,
+                started_at,
+                resumptions,
+                local_bytes: 0,
+                total_bytes: 0,
+            },
+        );
+        self.save_pending_syncs(pending)
+    }
+
+    /// Forgets an unfinished import, such as once it finishes, or when the replica is rejected or deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub(crate) fn remove_pending_sync(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut pending = self.load_pending_syncs()?;
+        if pending.remove(&namespace_id.to_string()).is_some() {
+            self.save_pending_syncs(pending)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the replicas imported from tickets whose content has not yet been fetched in full, along with how far each import has got.
+    ///
+    /// # Returns
+    ///
+    /// The unfinished imports.
+    pub fn list_pending_syncs(&self) -> Result<Vec<PendingSync>, Box<dyn Error + Send + Sync>> {
+        self.load_pending_syncs()?
+            .into_iter()
+            .map(|(namespace_id, pending_sync)| {
+                Ok(PendingSync {
+                    namespace_id: NamespaceId::from_str(&namespace_id)?,
+                    ticket: DocTicket::from_str(&pending_sync.ticket)?,
+                    max_size: pending_sync.max_size,
+                    accepted: pending_sync.accepted,
+                    started_at: pending_sync.started_at,
+                    resumptions: pending_sync.resumptions,
+                    local_bytes: pending_sync.local_bytes,
+                    total_bytes: pending_sync.total_bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// Resumes the imports of replicas interrupted before their content was fetched in full, such as by the process exiting or the network dropping.
+    /// This is done when the file system starts. Each import is resumed in the background: a replica which had not yet met its acceptance policy is imported again from its ticket, while the missing content of an accepted replica is fetched from the nodes listed in its ticket.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas whose imports were resumed.
+    pub async fn resume_pending_syncs(
+        &self,
+    ) -> Result<Vec<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        self.ensure_online("Resuming imports")?;
+        let replicas = self.list_replicas().await?;
+        let mut resumed = Vec::new();
+        for (namespace_id, pending_sync) in self.load_pending_syncs()? {
+            let namespace_id = NamespaceId::from_str(&namespace_id)?;
+            let ticket = DocTicket::from_str(&pending_sync.ticket)?;
+            self.update_pending_sync(namespace_id, |pending_sync| pending_sync.resumptions += 1)?;
+            let self_clone = self.clone();
+            let held = replicas.contains(&namespace_id);
+            tokio::spawn(async move {
+                if let Err(e) = self_clone
+                    .resume_pending_sync(ticket, pending_sync, held)
+                    .await
+                {
+                    tracing::warn!(%namespace_id, "Could not resume import: {}", e);
+                }
+            });
+            resumed.push(namespace_id);
+        }
+        Ok(resumed)
+    }
+
+    /// Resumes an unfinished import.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket the replica was imported from.
+    ///
+    /// * `pending_sync` - The saved state of the import.
+    ///
+    /// * `held` - Whether the replica is held locally.
+    async fn resume_pending_sync(
+        &self,
+        ticket: DocTicket,
+        pending_sync: SavedPendingSync,
+        held: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let namespace_id = ticket.capability.id();
+        if !held || !pending_sync.accepted {
+            // The replica's size may not have been checked against the policy yet, so it is imported again from scratch.
+            if held {
+                let document = self.open_document(namespace_id).await?;
+                document.leave().await?;
+                drop(document);
+                self.node.docs.drop_doc(namespace_id).await?;
+            }
+            // The saved ticket already carries only the access the policy kept.
+            let mut policy = AcceptPolicy::default()
+                .allow_write(true)
+                .probe_timeout(pending_sync.probe_timeout);
+            if let Some(max_size) = pending_sync.max_size {
+                policy = policy.max_size(max_size);
+            }
+            self.accept_ticket(&ticket.to_string(), policy).await?;
+            return Ok(());
+        }
+        let document = self.open_document(namespace_id).await?;
+        // An import interrupted while its content was downloaded from several peers may have left content excluded.
+        self.restore_download_policy(&document).await?;
+        let peers = self.order_peers(ticket.nodes)?;
+        document.start_sync(peers.clone()).await?;
+        self.track_pending_sync(namespace_id, peers, CancellationToken::new());
+        Ok(())
+    }
+
+    /// Marks an import as having met its acceptance policy, and follows its progress in the background until its content is held in full.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `peers` - The nodes listed in the ticket the replica was imported from.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops following the import.
+    pub(crate) fn accept_pending_sync(
+        &self,
+        namespace_id: NamespaceId,
+        peers: Vec<NodeAddr>,
+        cancellation: CancellationToken,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update_pending_sync(namespace_id, |pending_sync| pending_sync.accepted = true)?;
+        self.track_pending_sync(namespace_id, peers, cancellation);
+        Ok(())
+    }
+
+    /// Follows the progress of an import in the background, saving how much of the replica's content is held.
+    /// The import is forgotten once the replica has been synchronised and its content is held in full.
+    /// If no content arrives for a while, the missing content is asked of the nodes listed in the ticket; if they cannot provide it either, the import is left to be resumed when the file system next starts.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `peers` - The nodes listed in the ticket the replica was imported from.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops following the import.
+    fn track_pending_sync(
+        &self,
+        namespace_id: NamespaceId,
+        peers: Vec<NodeAddr>,
+        cancellation: CancellationToken,
+    ) {
+        let self_clone = self.clone();
+        tokio::spawn(async move {
+            let mut last_local_bytes = 0;
+            let mut stalled_checks = 0;
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(PENDING_SYNC_CHECK_INTERVAL) => {}
+                    _ = cancellation.cancelled() => break,
+                }
+                if !self_clone.list_replicas().await?.contains(&namespace_id) {
+                    self_clone.remove_pending_sync(namespace_id)?;
+                    break;
+                }
+                // Until the replica has been synchronised with a peer, its entries are not known.
+                let document = self_clone.open_document(namespace_id).await?;
+                if document
+                    .get_sync_peers()
+                    .await?
+                    .unwrap_or_default()
+                    .is_empty()
+                {
+                    continue;
+                }
+                let summary = self_clone.availability_summary(namespace_id).await?;
+                if summary.pending_files == 0 && summary.partial_files == 0 {
+                    self_clone.remove_pending_sync(namespace_id)?;
+                    tracing::info!(%namespace_id, "Finished importing replica");
+                    break;
+                }
+                self_clone.update_pending_sync(namespace_id, |pending_sync| {
+                    pending_sync.local_bytes = summary.local_bytes;
+                    pending_sync.total_bytes = summary.total_bytes;
+                })?;
+                if summary.local_bytes != last_local_bytes {
+                    last_local_bytes = summary.local_bytes;
+                    stalled_checks = 0;
+                    continue;
+                }
+                // Content interrupted before a restart is not fetched again by synchronising, so it is asked for directly.
+                let report = self_clone
+                    .download_from_peers(namespace_id, peers.clone(), cancellation.clone())
+                    .await?;
+                match report.downloaded {
+                    0 => stalled_checks += 1,
+                    _ => stalled_checks = 0,
+                }
+                if stalled_checks >= MAX_STALLED_CHECKS {
+                    tracing::warn!(
+                        %namespace_id,
+                        missing = summary.pending_files + summary.partial_files,
+                        "Import of replica stalled; it will be resumed when the file system next starts"
+                    );
+                    break;
+                }
+            }
+            Ok::<(), Box<dyn Error + Send + Sync>>(())
+        });
+    }
+}
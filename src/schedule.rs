@@ -0,0 +1,305 @@
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use futures::StreamExt;
+use iroh::{client::LiveEvent, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, str::FromStr, time::Duration};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// The name of the file listing how replicas are synchronised, within the path on disk where the file system is stored.
+pub const SYNC_POLICIES_FILE_NAME: &str = "sync_policies";
+
+/// The delay before retrying a failed synchronisation for the first time.
+pub const INITIAL_SYNC_BACKOFF: Duration = Duration::from_secs(5);
+
+/// The longest delay between retries of a repeatedly failing synchronisation.
+pub const MAX_SYNC_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// When a replica is synchronised with peers in the background.
+pub enum SyncPolicy {
+    /// The replica is only synchronised when asked to.
+    #[default]
+    Manual,
+    /// The replica is synchronised repeatedly, waiting the given time after each synchronisation.
+    Periodic(Duration),
+    /// The replica is kept synchronised, rejoining its peers whenever a synchronisation fails.
+    Continuous,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The synchronisation policies of replicas, as saved on disk.
+struct SyncPolicies {
+    /// The policies of replicas not synchronised manually, by replica ID.
+    policies: BTreeMap<String, SyncPolicy>,
+}
+
+/// Picks how long to wait before retrying a failing synchronisation.
+///
+/// # Arguments
+///
+/// * `failures` - The number of consecutive failed synchronisations.
+///
+/// # Returns
+///
+/// A delay doubling with each failure, up to [`MAX_SYNC_BACKOFF`].
+fn sync_backoff(failures: u32) -> Duration {
+    INITIAL_SYNC_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_SYNC_BACKOFF)
+}
+
+impl OkuFs {
+    /// Loads the synchronisation policies of replicas from disk.
+    ///
+    /// # Returns
+    ///
+    /// The policies of replicas not synchronised manually.
+    fn load_sync_policies(
+        &self,
+    ) -> Result<BTreeMap<NamespaceId, SyncPolicy>, Box<dyn Error + Send + Sync>> {
+        let path = self.config.path.join(SYNC_POLICIES_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(sync_policies_toml) => {
+                let sync_policies: SyncPolicies = toml::from_str(&sync_policies_toml)?;
+                sync_policies
+                    .policies
+                    .iter()
+                    .map(|(namespace_id, policy)| {
+                        Ok((NamespaceId::from_str(namespace_id)?, *policy))
+                    })
+                    .collect()
+            }
+            Err(_) => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Saves the synchronisation policies of replicas to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `sync_policies` - The policies of replicas not synchronised manually.
+    fn save_sync_policies(
+        &self,
+        sync_policies: &BTreeMap<NamespaceId, SyncPolicy>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let sync_policies = SyncPolicies {
+            policies: sync_policies
+                .iter()
+                .map(|(namespace_id, policy)| (namespace_id.to_string(), *policy))
+                .collect(),
+        };
+        std::fs::write(
+            self.config.path.join(SYNC_POLICIES_FILE_NAME),
+            toml::to_string(&sync_policies)?,
+        )?;
+        Ok(())
+    }
+
+    /// Sets when a replica is synchronised with peers in the background.
+    /// The policy is kept across restarts. Failed synchronisations are retried after a delay which grows with each consecutive failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `policy` - When the replica should be synchronised.
+    pub async fn set_sync_policy(
+        &self,
+        namespace_id: NamespaceId,
+        policy: SyncPolicy,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut sync_policies = self.load_sync_policies()?;
+        match policy {
+            SyncPolicy::Manual => sync_policies.remove(&namespace_id),
+            _ => sync_policies.insert(namespace_id, policy),
+        };
+        self.save_sync_policies(&sync_policies)?;
+        self.schedule_sync(namespace_id, policy);
+        Ok(())
+    }
+
+    /// Gets when a replica is synchronised with peers in the background.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The replica's synchronisation policy, which is manual unless set otherwise.
+    pub fn get_sync_policy(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<SyncPolicy, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .load_sync_policies()?
+            .get(&namespace_id)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    /// Synchronises a replica with peers, waiting for the synchronisation to finish.
+    /// Replicas held locally rejoin the peers they last synchronised with; others are fetched from peers found on the mainline DHT.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub async fn sync_replica(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(document) = self.node.docs.open(namespace_id).await? else {
+            return self
+                .get_external_replica(namespace_id, None, true, false)
+                .await;
+        };
+        let operation = format!("Synchronising replica {}", namespace_id);
+        self.ensure_online(&operation)?;
+        let events = document.subscribe().await?;
+        document.start_sync(Vec::new()).await?;
+        self.with_network_timeout(&operation, &CancellationToken::new(), async {
+            tokio::pin!(events);
+            while let Some(event) = events.next().await {
+                if let LiveEvent::SyncFinished(sync_event) = event? {
+                    return sync_event
+                        .result
+                        .map_err(|e| OkuFsError::SyncFailed(namespace_id.to_string(), e).into());
+                }
+            }
+            Err(
+                OkuFsError::SyncFailed(namespace_id.to_string(), "replica closed".to_string())
+                    .into(),
+            )
+        })
+        .await
+    }
+
+    /// Waits for a synchronisation of a replica with one of its peers to fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The reason the synchronisation failed.
+    async fn wait_for_failed_sync(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Box<dyn Error + Send + Sync> {
+        let document = match self.open_document(namespace_id).await {
+            Ok(document) => document,
+            Err(e) => return e,
+        };
+        let events = match document.subscribe().await {
+            Ok(events) => events,
+            Err(e) => return e.into(),
+        };
+        tokio::pin!(events);
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(LiveEvent::SyncFinished(sync_event)) => {
+                    if let Err(e) = sync_event.result {
+                        return OkuFsError::SyncFailed(namespace_id.to_string(), e).into();
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => return e.into(),
+            }
+        }
+        OkuFsError::SyncFailed(namespace_id.to_string(), "replica closed".to_string()).into()
+    }
+
+    /// Synchronises a replica in the background as its policy requires, replacing any synchronisation already scheduled for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `policy` - When the replica should be synchronised.
+    fn schedule_sync(&self, namespace_id: NamespaceId, policy: SyncPolicy) {
+        let mut sync_schedules = self.sync_schedules.lock().unwrap();
+        if let Some(previous) = sync_schedules.remove(&namespace_id) {
+            previous.abort();
+        }
+        // The policy is kept, so synchronising starts once the file system is brought online.
+        if policy == SyncPolicy::Manual || self.is_offline() {
+            return;
+        }
+        sync_schedules.insert(namespace_id, self.spawn_sync(namespace_id, policy));
+    }
+
+    /// Starts synchronising a replica in the background as a policy other than [`SyncPolicy::Manual`] requires.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `policy` - When the replica should be synchronised.
+    ///
+    /// # Returns
+    ///
+    /// The task synchronising the replica, which runs until aborted.
+    pub(crate) fn spawn_sync(
+        &self,
+        namespace_id: NamespaceId,
+        policy: SyncPolicy,
+    ) -> JoinHandle<()> {
+        let self_clone = self.clone();
+        tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                let outcome = match self_clone.sync_replica(namespace_id).await {
+                    Ok(()) if policy == SyncPolicy::Continuous => {
+                        Err(self_clone.wait_for_failed_sync(namespace_id).await)
+                    }
+                    outcome => outcome,
+                };
+                let delay = match outcome {
+                    Ok(()) => {
+                        failures = 0;
+                        match policy {
+                            SyncPolicy::Periodic(interval) => interval,
+                            _ => Duration::ZERO,
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!(%namespace_id, "{}", e);
+                        failures += 1;
+                        sync_backoff(failures)
+                    }
+                };
+                tokio::time::sleep(delay).await;
+            }
+        })
+    }
+
+    /// Starts synchronising in the background the replicas whose policies require it.
+    pub(crate) fn start_sync_schedules(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for (namespace_id, policy) in self.load_sync_policies()? {
+            self.schedule_sync(namespace_id, policy);
+        }
+        Ok(())
+    }
+
+    /// Stops synchronising a replica in the background and forgets its policy, such as when it is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    pub(crate) fn remove_sync_policy(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.schedule_sync(namespace_id, SyncPolicy::Manual);
+        let _settings_guard = self.settings_lock.lock().unwrap();
+        let mut sync_policies = self.load_sync_policies()?;
+        if sync_policies.remove(&namespace_id).is_some() {
+            self.save_sync_policies(&sync_policies)?;
+        }
+        Ok(())
+    }
+}
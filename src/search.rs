@@ -0,0 +1,488 @@
+use crate::event::OkuFsEvent;
+use crate::fs::{is_directory_marker, normalise_path, OkuFs, METADATA_DIRECTORY};
+use crate::limits::ResourceLimits;
+use futures::{pin_mut, StreamExt};
+use iroh::{bytes::Hash, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+use tantivy::{
+    collector::{DocSetCollector, TopDocs},
+    directory::MmapDirectory,
+    doc,
+    query::{AllQuery, QueryParser, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, Value, STORED, STRING, TEXT},
+    Index, IndexReader, IndexWriter, ReloadPolicy, SnippetGenerator, TantivyDocument, Term,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+/// The directory, within the file system's directory, holding the search index.
+pub const SEARCH_INDEX_DIRECTORY: &str = "search_index";
+
+/// The largest file, in bytes, whose content is indexed.
+pub const MAX_INDEXED_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The most hits returned by a search.
+pub const MAX_SEARCH_HITS: usize = 100;
+
+/// The memory, in bytes, the search index may use while indexing.
+const INDEX_WRITER_MEMORY_BUDGET: usize = 32 * 1024 * 1024;
+
+/// The memory, in bytes, the search index may use while indexing when caches are disabled.
+const CONSTRAINED_INDEX_WRITER_MEMORY_BUDGET: usize = 15_000_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A file matching a search.
+pub struct SearchHit {
+    /// The ID of the replica containing the file.
+    pub namespace_id: NamespaceId,
+    /// The path of the file.
+    pub path: PathBuf,
+    /// An excerpt of the file's content surrounding the matched terms.
+    pub snippet: String,
+    /// How closely the file matches the search, relative to other hits.
+    pub score: f32,
+}
+
+/// An index of the text files held in the file system's replicas.
+pub(crate) struct SearchIndex {
+    /// The writer adding files to, and removing files from, the index.
+    writer: Mutex<IndexWriter>,
+    /// The reader used to search the index.
+    reader: IndexReader,
+    /// The ID of the replica containing a file.
+    namespace_id_field: Field,
+    /// The path of a file.
+    path_field: Field,
+    /// The replica ID and path of a file, identifying it across replicas.
+    entry_field: Field,
+    /// The replica ID and path of each directory containing a file.
+    ancestors_field: Field,
+    /// The hash of a file's content.
+    hash_field: Field,
+    /// The content of a file.
+    content_field: Field,
+}
+
+impl std::fmt::Debug for SearchIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchIndex").finish_non_exhaustive()
+    }
+}
+
+impl SearchIndex {
+    /// Opens the search index, creating it if it does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory holding the search index.
+    ///
+    /// * `limits` - The limits on the resources the index may use.
+    ///
+    /// # Returns
+    ///
+    /// The search index.
+    pub(crate) fn open(
+        path: &Path,
+        limits: &ResourceLimits,
+    ) -> Result<SearchIndex, Box<dyn Error + Send + Sync>> {
+        let mut schema_builder = Schema::builder();
+        let namespace_id_field = schema_builder.add_text_field("namespace_id", STRING | STORED);
+        let path_field = schema_builder.add_text_field("path", STRING | STORED);
+        let entry_field = schema_builder.add_text_field("entry", STRING);
+        let ancestors_field = schema_builder.add_text_field("ancestors", STRING);
+        let hash_field = schema_builder.add_text_field("hash", STRING | STORED);
+        let content_field = schema_builder.add_text_field("content", TEXT | STORED);
+        let schema = schema_builder.build();
+        std::fs::create_dir_all(path)?;
+        let index = Index::open_or_create(MmapDirectory::open(path)?, schema)?;
+        let (writer, reader) = if limits.caches {
+            (
+                index.writer(INDEX_WRITER_MEMORY_BUDGET)?,
+                index
+                    .reader_builder()
+                    .reload_policy(ReloadPolicy::Manual)
+                    .try_into()?,
+            )
+        } else {
+            (
+                index.writer_with_num_threads(1, CONSTRAINED_INDEX_WRITER_MEMORY_BUDGET)?,
+                index
+                    .reader_builder()
+                    .reload_policy(ReloadPolicy::Manual)
+                    .doc_store_cache_num_blocks(0)
+                    .try_into()?,
+            )
+        };
+        Ok(SearchIndex {
+            writer: Mutex::new(writer),
+            reader,
+            namespace_id_field,
+            path_field,
+            entry_field,
+            ancestors_field,
+            hash_field,
+            content_field,
+        })
+    }
+
+    /// Adds a file to the index, replacing any earlier version of it.
+    /// The change is not visible to searches until committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `hash` - The hash of the file's content.
+    ///
+    /// * `content` - The file's content.
+    fn insert(
+        &self,
+        namespace_id: NamespaceId,
+        path: &Path,
+        hash: Hash,
+        content: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let id = entry_id(namespace_id, path);
+        let mut document = doc!(
+            self.namespace_id_field => namespace_id.to_string(),
+            self.path_field => path.to_string_lossy().to_string(),
+            self.entry_field => id.clone(),
+            self.hash_field => hash.to_string(),
+            self.content_field => content
+        );
+        for ancestor in path.ancestors().skip(1) {
+            document.add_text(self.ancestors_field, entry_id(namespace_id, ancestor));
+        }
+        let writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(self.entry_field, &id));
+        writer.add_document(document)?;
+        Ok(())
+    }
+
+    /// Removes a file, or every file within a directory, from the index.
+    /// The change is not visible to searches until committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file or directory.
+    ///
+    /// * `path` - The path of the file or directory.
+    fn remove(&self, namespace_id: NamespaceId, path: &Path) {
+        let id = entry_id(namespace_id, path);
+        let writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(self.entry_field, &id));
+        writer.delete_term(Term::from_field_text(self.ancestors_field, &id));
+    }
+
+    /// Removes every file in a replica from the index.
+    /// The change is not visible to searches until committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    fn remove_replica(&self, namespace_id: NamespaceId) {
+        self.writer
+            .lock()
+            .unwrap()
+            .delete_term(Term::from_field_text(
+                self.namespace_id_field,
+                &namespace_id.to_string(),
+            ));
+    }
+
+    /// Makes the changes to the index visible to searches.
+    fn commit(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.writer.lock().unwrap().commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    /// Lists the replicas with files held in the index.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the replicas.
+    fn indexed_replicas(&self) -> Result<HashSet<NamespaceId>, Box<dyn Error + Send + Sync>> {
+        let searcher = self.reader.searcher();
+        let mut replicas = HashSet::new();
+        for address in searcher.search(&AllQuery, &DocSetCollector)? {
+            let document: TantivyDocument = searcher.doc(address)?;
+            if let Some(namespace_id) = document
+                .get_first(self.namespace_id_field)
+                .and_then(|value| value.as_str())
+            {
+                replicas.insert(NamespaceId::from_str(namespace_id)?);
+            }
+        }
+        Ok(replicas)
+    }
+
+    /// Lists the files of a replica held in the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the indexed content of each file, by path.
+    fn indexed_files(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<HashMap<PathBuf, Hash>, Box<dyn Error + Send + Sync>> {
+        let searcher = self.reader.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(self.namespace_id_field, &namespace_id.to_string()),
+            IndexRecordOption::Basic,
+        );
+        let mut files = HashMap::new();
+        for address in searcher.search(&query, &DocSetCollector)? {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let (Some(path), Some(hash)) = (
+                document
+                    .get_first(self.path_field)
+                    .and_then(|value| value.as_str()),
+                document
+                    .get_first(self.hash_field)
+                    .and_then(|value| value.as_str()),
+            ) else {
+                continue;
+            };
+            files.insert(PathBuf::from(path), Hash::from_str(hash)?);
+        }
+        Ok(files)
+    }
+}
+
+impl OkuFs {
+    /// Searches the content of the text files held in the file system's replicas.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The search query. Terms may be combined with `AND`, `OR`, and `NOT`, and phrases enclosed in quotes.
+    ///
+    /// # Returns
+    ///
+    /// The files matching the query, from the best match to the worst.
+    pub fn search(&self, query: &str) -> Result<Vec<SearchHit>, Box<dyn Error + Send + Sync>> {
+        let index = &self.search_index;
+        let searcher = index.reader.searcher();
+        let query_parser = QueryParser::for_index(searcher.index(), vec![index.content_field]);
+        let query = query_parser.parse_query(query)?;
+        let snippet_generator = SnippetGenerator::create(&searcher, &*query, index.content_field)?;
+        let mut hits = Vec::new();
+        for (score, address) in searcher.search(&query, &TopDocs::with_limit(MAX_SEARCH_HITS))? {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let (Some(namespace_id), Some(path)) = (
+                document
+                    .get_first(index.namespace_id_field)
+                    .and_then(|value| value.as_str()),
+                document
+                    .get_first(index.path_field)
+                    .and_then(|value| value.as_str()),
+            ) else {
+                continue;
+            };
+            hits.push(SearchHit {
+                namespace_id: NamespaceId::from_str(namespace_id)?,
+                path: PathBuf::from(path),
+                snippet: snippet_generator
+                    .snippet_from_doc(&document)
+                    .fragment()
+                    .to_string(),
+                score,
+            });
+        }
+        Ok(hits)
+    }
+
+    /// Keeps the search index up to date with the file system's replicas.
+    /// The index is first brought up to date with every replica, then updated as file system events occur.
+    pub(crate) fn start_indexing(&self) {
+        let mut events = self.subscribe();
+        let self_clone = self.clone();
+        tokio::spawn(async move {
+            // Files whose content has yet to be downloaded, by the hash of their content.
+            let mut pending = HashMap::new();
+            if let Err(e) = self_clone.reindex_replicas(&mut pending).await {
+                tracing::warn!("{}", e);
+            }
+            loop {
+                let result = match events.recv().await {
+                    Ok(event) => self_clone.index_event(event, &mut pending).await,
+                    // Events were missed, so the index may no longer reflect the replicas.
+                    Err(RecvError::Lagged(_)) => self_clone.reindex_replicas(&mut pending).await,
+                    Err(RecvError::Closed) => break,
+                };
+                if let Err(e) = result {
+                    tracing::warn!("{}", e);
+                }
+            }
+        });
+    }
+
+    /// Updates the search index in response to a file system event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that occurred.
+    ///
+    /// * `pending` - The files whose content has yet to be downloaded.
+    async fn index_event(
+        &self,
+        event: OkuFsEvent,
+        pending: &mut HashMap<Hash, Vec<(NamespaceId, PathBuf)>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match event {
+            OkuFsEvent::EntryInserted {
+                namespace_id,
+                path,
+                hash,
+                ..
+            } => self.index_file(namespace_id, path, hash, pending).await?,
+            OkuFsEvent::EntryDeleted {
+                namespace_id, path, ..
+            } => self.search_index.remove(namespace_id, &path),
+            OkuFsEvent::ContentReady { hash, .. } => {
+                for (namespace_id, path) in pending.remove(&hash).unwrap_or_default() {
+                    self.index_file(namespace_id, path, hash, pending).await?;
+                }
+            }
+            OkuFsEvent::ReplicaImported(namespace_id) => {
+                return self.reindex_replica(namespace_id, pending).await
+            }
+            OkuFsEvent::ReplicaDeleted(namespace_id) => {
+                self.search_index.remove_replica(namespace_id)
+            }
+            _ => return Ok(()),
+        }
+        self.search_index.commit()
+    }
+
+    /// Indexes a file if its content is text, replacing any earlier version of it in the index.
+    /// The change is not visible to searches until the index is committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `hash` - The hash of the file's content.
+    ///
+    /// * `pending` - The files whose content has yet to be downloaded; the file is added if its content is missing.
+    async fn index_file(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        hash: Hash,
+        pending: &mut HashMap<Hash, Vec<(NamespaceId, PathBuf)>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Directory markers are keyed, and reported, with a trailing slash.
+        if path.starts_with(METADATA_DIRECTORY)
+            || is_directory_marker(path.as_os_str().as_encoded_bytes())
+        {
+            return Ok(());
+        }
+        // Indexing encrypted files would leave their text on disk unencrypted.
+        if self.is_replica_encrypted(namespace_id) {
+            self.search_index.remove(namespace_id, &path);
+            return Ok(());
+        }
+        let mut reader = self.node.blobs.read(hash).await?;
+        if !reader.is_complete() {
+            pending.entry(hash).or_default().push((namespace_id, path));
+            return Ok(());
+        }
+        if reader.size() > MAX_INDEXED_FILE_SIZE
+            || self.config.limits.check_read(reader.size()).is_err()
+        {
+            self.search_index.remove(namespace_id, &path);
+            return Ok(());
+        }
+        let content = reader.read_to_bytes().await?;
+        match std::str::from_utf8(&content) {
+            Ok(text) => self.search_index.insert(namespace_id, &path, hash, text)?,
+            // Files that are not text are left out of the index.
+            Err(_) => self.search_index.remove(namespace_id, &path),
+        }
+        Ok(())
+    }
+
+    /// Brings the search index up to date with a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `pending` - The files whose content has yet to be downloaded.
+    async fn reindex_replica(
+        &self,
+        namespace_id: NamespaceId,
+        pending: &mut HashMap<Hash, Vec<(NamespaceId, PathBuf)>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut indexed_files = self.search_index.indexed_files(namespace_id)?;
+        let files = self.stream_files(namespace_id).await?;
+        pin_mut!(files);
+        while let Some(file) = files.next().await {
+            let file = file?;
+            let path = self.entry_path(file.key());
+            if indexed_files.remove(&path) != Some(file.content_hash()) {
+                self.index_file(namespace_id, path, file.content_hash(), pending)
+                    .await?;
+            }
+        }
+        for path in indexed_files.keys() {
+            self.search_index.remove(namespace_id, path);
+        }
+        self.search_index.commit()
+    }
+
+    /// Brings the search index up to date with every replica, removing replicas that are no longer held.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The files whose content has yet to be downloaded.
+    async fn reindex_replicas(
+        &self,
+        pending: &mut HashMap<Hash, Vec<(NamespaceId, PathBuf)>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let replicas = self.list_replicas().await?;
+        for namespace_id in self.search_index.indexed_replicas()? {
+            if !replicas.contains(&namespace_id) {
+                self.search_index.remove_replica(namespace_id);
+            }
+        }
+        for namespace_id in replicas {
+            self.reindex_replica(namespace_id, pending).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a file or directory across replicas.
+///
+/// # Arguments
+///
+/// * `namespace_id` - The ID of the replica containing the file or directory.
+///
+/// * `path` - The path of the file or directory.
+///
+/// # Returns
+///
+/// The replica ID followed by the normalised path.
+fn entry_id(namespace_id: NamespaceId, path: &Path) -> String {
+    format!(
+        "{}:{}",
+        namespace_id,
+        normalise_path(path.to_path_buf()).to_string_lossy()
+    )
+}
@@ -0,0 +1,782 @@
+use crate::error::OkuFsError;
+use crate::fs::{normalise_path, OkuFs, METADATA_DIRECTORY};
+use async_trait::async_trait;
+use iroh::{client::Entry, sync::NamespaceId};
+use russh::{
+    server::{Auth, Config, Handler, Msg, Server, Session},
+    Channel, ChannelId, MethodSet,
+};
+use russh_keys::key::{KeyPair, PublicKey};
+use russh_sftp::protocol::{
+    Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+impl OkuFs {
+    /// Serves the replicas in the file system over SFTP, for hosts where mounting them is not possible.
+    /// Each replica appears as a top-level directory named after its ID; replicas may also be reached through their aliases.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen for SSH clients on.
+    ///
+    /// * `host_key` - The key identifying the server to clients.
+    ///
+    /// * `authorized_keys` - The public keys of the clients permitted to connect.
+    pub async fn serve_sftp(
+        &self,
+        address: SocketAddr,
+        host_key: KeyPair,
+        authorized_keys: Vec<PublicKey>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let config = Config {
+            methods: MethodSet::PUBLICKEY,
+            keys: vec![host_key],
+            ..Default::default()
+        };
+        let mut server = SftpServer {
+            fs: self.clone(),
+            authorized_keys: Arc::new(authorized_keys),
+        };
+        server.run_on_address(Arc::new(config), address).await?;
+        Ok(())
+    }
+}
+
+/// Accepts SSH connections on behalf of the file system.
+struct SftpServer {
+    /// The file system being served.
+    fs: OkuFs,
+    /// The public keys of the clients permitted to connect.
+    authorized_keys: Arc<Vec<PublicKey>>,
+}
+
+impl Server for SftpServer {
+    type Handler = SftpConnection;
+
+    fn new_client(&mut self, _peer_address: Option<SocketAddr>) -> Self::Handler {
+        SftpConnection {
+            fs: self.fs.clone(),
+            authorized_keys: self.authorized_keys.clone(),
+            channels: HashMap::new(),
+        }
+    }
+
+    fn handle_session_error(&mut self, error: russh::Error) {
+        tracing::warn!("{}", error);
+    }
+}
+
+/// An SSH connection from a client, offering only the SFTP subsystem.
+struct SftpConnection {
+    /// The file system being served.
+    fs: OkuFs,
+    /// The public keys of the clients permitted to connect.
+    authorized_keys: Arc<Vec<PublicKey>>,
+    /// The channels opened by the client that have not yet requested a subsystem.
+    channels: HashMap<ChannelId, Channel<Msg>>,
+}
+
+impl SftpConnection {
+    /// Decides whether a client may authenticate with a public key.
+    fn authorise(&self, public_key: &PublicKey) -> Auth {
+        if self.authorized_keys.contains(public_key) {
+            Auth::Accept
+        } else {
+            Auth::Reject {
+                proceed_with_methods: None,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for SftpConnection {
+    type Error = russh::Error;
+
+    async fn auth_publickey_offered(
+        &mut self,
+        _user: &str,
+        public_key: &PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        Ok(self.authorise(public_key))
+    }
+
+    async fn auth_publickey(
+        &mut self,
+        _user: &str,
+        public_key: &PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        Ok(self.authorise(public_key))
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
+
+    async fn channel_eof(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.close(channel);
+        Ok(())
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        channel_id: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        match self.channels.remove(&channel_id) {
+            Some(channel) if name == "sftp" => {
+                session.channel_success(channel_id);
+                let sftp_session = SftpSession {
+                    fs: self.fs.clone(),
+                    handles: HashMap::new(),
+                    next_handle: 0,
+                };
+                russh_sftp::server::run(channel.into_stream(), sftp_session).await;
+            }
+            _ => session.channel_failure(channel_id),
+        }
+        Ok(())
+    }
+}
+
+/// A file or directory opened by an SFTP client.
+enum SftpHandle {
+    /// A directory, with the listing yet to be sent to the client.
+    Directory(Option<Vec<File>>),
+    /// A file, with its content held until the handle is closed.
+    File {
+        /// The ID of the replica holding the file.
+        namespace_id: NamespaceId,
+        /// The path of the file within the replica.
+        path: PathBuf,
+        /// The content of the file.
+        content: Vec<u8>,
+        /// Whether the content has changed since the file was opened.
+        modified: bool,
+    },
+}
+
+/// The SFTP subsystem running on one channel of an SSH connection.
+struct SftpSession {
+    /// The file system being served.
+    fs: OkuFs,
+    /// The files and directories currently opened by the client.
+    handles: HashMap<String, SftpHandle>,
+    /// The number to use for the next handle.
+    next_handle: u64,
+}
+
+impl SftpSession {
+    /// Registers an opened file or directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The opened file or directory.
+    ///
+    /// # Returns
+    ///
+    /// The name the client refers to the handle by.
+    fn insert_handle(&mut self, handle: SftpHandle) -> String {
+        let name = self.next_handle.to_string();
+        self.next_handle += 1;
+        self.handles.insert(name.clone(), handle);
+        name
+    }
+
+    /// Finds the replica and path an SFTP path refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path given by the client.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica and the path within it, or `None` if the path refers to the root of the server.
+    fn resolve_sftp_path(&self, path: &str) -> Result<Option<(NamespaceId, PathBuf)>, StatusCode> {
+        let path = normalise_path(PathBuf::from(path));
+        let mut components = path
+            .components()
+            .skip(1)
+            .map(|component| component.as_os_str().to_string_lossy().to_string());
+        let Some(replica) = components.next() else {
+            return Ok(None);
+        };
+        let namespace_id = match NamespaceId::from_str(&replica) {
+            Ok(namespace_id) => namespace_id,
+            Err(_) => self
+                .fs
+                .resolve_alias(&replica)
+                .map_err(status_code)?
+                .ok_or(StatusCode::NoSuchFile)?,
+        };
+        let path = normalise_path(components.collect::<PathBuf>());
+        if path.starts_with(METADATA_DIRECTORY) {
+            return Err(StatusCode::NoSuchFile);
+        }
+        Ok(Some((namespace_id, path)))
+    }
+
+    /// Describes a file or directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path given by the client.
+    ///
+    /// # Returns
+    ///
+    /// The attributes of the file or directory.
+    async fn attributes(&self, path: &str) -> Result<FileAttributes, StatusCode> {
+        let Some((namespace_id, path)) = self.resolve_sftp_path(path)? else {
+            return Ok(directory_attributes());
+        };
+        if let Ok(entry) = self.fs.get_latest_entry(namespace_id, path.clone()).await {
+            let size = self.fs.content_size(&entry).await.map_err(status_code)?;
+            return Ok(file_attributes(&entry, size));
+        }
+        let entries = self
+            .fs
+            .list_directory_entries(namespace_id, path.clone())
+            .await
+            .map_err(status_code)?;
+        if entries.is_empty() && path != Path::new("/") {
+            return Err(StatusCode::NoSuchFile);
+        }
+        Ok(directory_attributes())
+    }
+
+    /// Lists the contents of a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path given by the client.
+    ///
+    /// # Returns
+    ///
+    /// The files and directories immediately within the directory.
+    async fn list_sftp_directory(&self, path: &str) -> Result<Vec<File>, StatusCode> {
+        let Some((namespace_id, path)) = self.resolve_sftp_path(path)? else {
+            return Ok(self
+                .fs
+                .list_replicas()
+                .await
+                .map_err(status_code)?
+                .into_iter()
+                .map(|namespace_id| File::new(namespace_id.to_string(), directory_attributes()))
+                .collect());
+        };
+        let tree = self
+            .fs
+            .get_tree(namespace_id, path)
+            .await
+            .map_err(status_code)?;
+        let mut children = BTreeMap::new();
+        for name in tree.directories.into_keys() {
+            children.insert(name, directory_attributes());
+        }
+        for (name, entry) in tree.files {
+            let size = self.fs.content_size(&entry).await.map_err(status_code)?;
+            children.insert(name, file_attributes(&entry, size));
+        }
+        Ok(children
+            .into_iter()
+            .map(|(name, attributes)| File::new(name, attributes))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl russh_sftp::server::Handler for SftpSession {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        let (namespace_id, path) = self
+            .resolve_sftp_path(&filename)?
+            .ok_or(StatusCode::PermissionDenied)?;
+        // The latest version is read whichever author wrote it, as `stat` describes.
+        let existing = match self.fs.read_file(namespace_id, path.clone()).await {
+            Ok(content) => Some(content),
+            Err(e) => match e.downcast_ref::<OkuFsError>() {
+                Some(OkuFsError::FsEntryNotFound) => None,
+                _ => return Err(status_code(e)),
+            },
+        };
+        let truncate = pflags.contains(OpenFlags::TRUNCATE);
+        let (content, modified) = match existing {
+            Some(_) if pflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUDE) => {
+                return Err(StatusCode::Failure)
+            }
+            Some(_) if truncate => (Vec::new(), true),
+            Some(content) => (content.to_vec(), false),
+            None if pflags.contains(OpenFlags::CREATE) => (Vec::new(), true),
+            None => return Err(StatusCode::NoSuchFile),
+        };
+        let handle = self.insert_handle(SftpHandle::File {
+            namespace_id,
+            path,
+            content,
+            modified,
+        });
+        Ok(Handle { id, handle })
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        match self.handles.remove(&handle) {
+            Some(SftpHandle::File {
+                namespace_id,
+                path,
+                content,
+                modified: true,
+            }) => {
+                // Entries with no content mark deletions, so empty files cannot be stored.
+                if content.is_empty() {
+                    return Err(StatusCode::PermissionDenied);
+                }
+                self.fs
+                    .create_or_modify_file(namespace_id, path, content)
+                    .await
+                    .map_err(status_code)?;
+            }
+            Some(_) => (),
+            None => return Err(StatusCode::Failure),
+        }
+        Ok(ok_status(id))
+    }
+
+    async fn read(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let Some(SftpHandle::File { content, .. }) = self.handles.get(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        let start = offset as usize;
+        if start >= content.len() {
+            return Err(StatusCode::Eof);
+        }
+        let end = content.len().min(start.saturating_add(len as usize));
+        Ok(Data {
+            id,
+            data: content[start..end].to_vec(),
+        })
+    }
+
+    async fn write(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        let size_limit = self.fs.config.limits.received_size_limit();
+        let Some(SftpHandle::File {
+            content, modified, ..
+        }) = self.handles.get_mut(&handle)
+        else {
+            return Err(StatusCode::Failure);
+        };
+        // The client chooses where to write, so files may not grow past the size the node accepts from clients.
+        let start = usize::try_from(offset).map_err(|_| StatusCode::Failure)?;
+        let end = start
+            .checked_add(data.len())
+            .filter(|end| *end as u64 <= size_limit)
+            .ok_or(StatusCode::Failure)?;
+        if content.len() < end {
+            content.resize(end, 0);
+        }
+        content[start..end].copy_from_slice(&data);
+        *modified = true;
+        Ok(ok_status(id))
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        self.stat(id, path).await
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        match self.handles.get(&handle) {
+            Some(SftpHandle::File { content, .. }) => {
+                let mut attrs = FileAttributes {
+                    size: Some(content.len() as u64),
+                    permissions: Some(0o644),
+                    ..Default::default()
+                };
+                attrs.set_regular(true);
+                Ok(Attrs { id, attrs })
+            }
+            Some(SftpHandle::Directory(_)) => Ok(Attrs {
+                id,
+                attrs: directory_attributes(),
+            }),
+            None => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn setstat(
+        &mut self,
+        id: u32,
+        _path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        // Replicas do not record ownership or permissions, so there is nothing to change.
+        Ok(ok_status(id))
+    }
+
+    async fn fsetstat(
+        &mut self,
+        id: u32,
+        _handle: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        Ok(ok_status(id))
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        let listing = self.list_sftp_directory(&path).await?;
+        let handle = self.insert_handle(SftpHandle::Directory(Some(listing)));
+        Ok(Handle { id, handle })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        let Some(SftpHandle::Directory(listing)) = self.handles.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        match listing.take() {
+            Some(files) if !files.is_empty() => Ok(Name { id, files }),
+            _ => Err(StatusCode::Eof),
+        }
+    }
+
+    async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+        let (namespace_id, path) = self
+            .resolve_sftp_path(&filename)?
+            .ok_or(StatusCode::PermissionDenied)?;
+        let entries_deleted = self
+            .fs
+            .delete_file(namespace_id, path)
+            .await
+            .map_err(status_code)?;
+        if entries_deleted == 0 {
+            return Err(StatusCode::NoSuchFile);
+        }
+        Ok(ok_status(id))
+    }
+
+    async fn mkdir(
+        &mut self,
+        id: u32,
+        path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        let (namespace_id, path) = self
+            .resolve_sftp_path(&path)?
+            .ok_or(StatusCode::PermissionDenied)?;
+        self.fs
+            .create_directory(namespace_id, path)
+            .await
+            .map_err(status_code)?;
+        Ok(ok_status(id))
+    }
+
+    async fn rmdir(&mut self, id: u32, path: String) -> Result<Status, Self::Error> {
+        let (namespace_id, path) = self
+            .resolve_sftp_path(&path)?
+            .ok_or(StatusCode::PermissionDenied)?;
+        self.fs
+            .delete_directory(namespace_id, path)
+            .await
+            .map_err(status_code)?;
+        Ok(ok_status(id))
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let path = normalise_path(PathBuf::from(path));
+        Ok(Name {
+            id,
+            files: vec![File::dummy(path.to_string_lossy())],
+        })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: self.attributes(&path).await?,
+        })
+    }
+
+    async fn rename(
+        &mut self,
+        id: u32,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        let (from_namespace_id, from) = self
+            .resolve_sftp_path(&oldpath)?
+            .ok_or(StatusCode::PermissionDenied)?;
+        let (to_namespace_id, to) = self
+            .resolve_sftp_path(&newpath)?
+            .ok_or(StatusCode::PermissionDenied)?;
+        if from_namespace_id != to_namespace_id {
+            return Err(StatusCode::OpUnsupported);
+        }
+        self.fs
+            .move_file(from_namespace_id, from, to)
+            .await
+            .map_err(status_code)?;
+        Ok(ok_status(id))
+    }
+}
+
+/// Converts a file system error into the status reported to SFTP clients.
+fn status_code(error: Box<dyn Error + Send + Sync>) -> StatusCode {
+    match error.downcast_ref::<OkuFsError>() {
+        Some(OkuFsError::FsEntryNotFound) => StatusCode::NoSuchFile,
+        Some(OkuFsError::ReplicaFrozen(_)) => StatusCode::PermissionDenied,
+        _ => StatusCode::Failure,
+    }
+}
+
+/// Creates the status reported to SFTP clients when a request succeeds.
+fn ok_status(id: u32) -> Status {
+    Status {
+        id,
+        status_code: StatusCode::Ok,
+        error_message: String::from("Ok"),
+        language_tag: String::from("en-US"),
+    }
+}
+
+/// Describes a directory to SFTP clients.
+fn directory_attributes() -> FileAttributes {
+    let mut attrs = FileAttributes {
+        permissions: Some(0o755),
+        ..Default::default()
+    };
+    attrs.set_dir(true);
+    attrs
+}
+
+/// Describes a file to SFTP clients.
+///
+/// # Arguments
+///
+/// * `entry` - The latest entry of the file.
+///
+/// * `size` - The size of the file's content.
+fn file_attributes(entry: &Entry, size: u64) -> FileAttributes {
+    let mut attrs = FileAttributes {
+        size: Some(size),
+        permissions: Some(0o644),
+        mtime: Some((entry.timestamp() / 1_000_000) as u32),
+        ..Default::default()
+    };
+    attrs.set_regular(true);
+    attrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::start_test_fs;
+    use russh_sftp::server::Handler as SftpHandler;
+
+    /// Starts an SFTP session serving a file system, without an SSH connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `oku_fs` - The file system.
+    ///
+    /// # Returns
+    ///
+    /// The session.
+    fn start_test_session(oku_fs: &OkuFs) -> SftpSession {
+        SftpSession {
+            fs: oku_fs.clone(),
+            handles: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    /// Reads a whole file through an SFTP session.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The content of the file.
+    async fn read_sftp_file(session: &mut SftpSession, path: &str) -> Vec<u8> {
+        let handle = session
+            .open(
+                0,
+                path.to_string(),
+                OpenFlags::READ,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        let data = session.read(0, handle.clone(), 0, 1024).await.unwrap().data;
+        session.close(0, handle).await.unwrap();
+        data
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_and_read_files() {
+        let oku_fs = start_test_fs("sftp-files").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let mut session = start_test_session(&oku_fs);
+        let path = format!("/{}/d/a.txt", namespace_id);
+        let handle = session
+            .open(
+                0,
+                path.clone(),
+                OpenFlags::CREATE | OpenFlags::WRITE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        session
+            .write(0, handle.clone(), 0, b"hello".to_vec())
+            .await
+            .unwrap();
+        session
+            .write(0, handle.clone(), 6, b"world".to_vec())
+            .await
+            .unwrap();
+        session.close(0, handle).await.unwrap();
+        assert_eq!(read_sftp_file(&mut session, &path).await, b"hello\0world");
+        assert_eq!(session.stat(0, path).await.unwrap().attrs.size, Some(11));
+        let listing = session
+            .list_sftp_directory(&format!("/{}", namespace_id))
+            .await
+            .unwrap();
+        assert_eq!(
+            listing
+                .iter()
+                .map(|file| file.filename.clone())
+                .collect::<Vec<_>>(),
+            ["d"]
+        );
+        // Files last written by another author are read as they are listed.
+        let author_id = oku_fs.create_author().await.unwrap();
+        oku_fs
+            .create_or_modify_file_as(namespace_id, PathBuf::from("/b.txt"), "b", author_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            read_sftp_file(&mut session, &format!("/{}/b.txt", namespace_id)).await,
+            b"b"
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hide_metadata_from_clients() {
+        let oku_fs = start_test_fs("sftp-metadata").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let mut session = start_test_session(&oku_fs);
+        let path = format!("/{}/.oku/file_metadata/a.txt", namespace_id);
+        assert_eq!(
+            session.stat(0, path.clone()).await.unwrap_err(),
+            StatusCode::NoSuchFile
+        );
+        assert_eq!(
+            session
+                .open(
+                    0,
+                    path,
+                    OpenFlags::CREATE | OpenFlags::WRITE,
+                    FileAttributes::default()
+                )
+                .await
+                .unwrap_err(),
+            StatusCode::NoSuchFile
+        );
+        assert_eq!(
+            session
+                .mkdir(
+                    0,
+                    format!("/{}/.oku/d", namespace_id),
+                    FileAttributes::default()
+                )
+                .await
+                .unwrap_err(),
+            StatusCode::NoSuchFile
+        );
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refuse_writes_past_write_limit() {
+        let mut oku_fs = start_test_fs("sftp-limit").await;
+        oku_fs.config.limits.max_write_size = Some(8);
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        let mut session = start_test_session(&oku_fs);
+        let handle = session
+            .open(
+                0,
+                format!("/{}/a.txt", namespace_id),
+                OpenFlags::CREATE | OpenFlags::WRITE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        session
+            .write(0, handle.clone(), 4, b"abcd".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(
+            session
+                .write(0, handle.clone(), 5, b"abcd".to_vec())
+                .await
+                .unwrap_err(),
+            StatusCode::Failure
+        );
+        assert_eq!(
+            session
+                .write(0, handle.clone(), u64::MAX, b"a".to_vec())
+                .await
+                .unwrap_err(),
+            StatusCode::Failure
+        );
+        let attrs = session.fstat(0, handle).await.unwrap().attrs;
+        assert_eq!(attrs.size, Some(8));
+        oku_fs.shutdown();
+    }
+}
@@ -0,0 +1,254 @@
+use crate::error::OkuFsError;
+use crate::fs::{OkuFs, METADATA_DIRECTORY};
+use crate::template::{manifest_path, ReplicaManifest, ReplicaTemplate};
+use futures::{pin_mut, StreamExt};
+use iroh::{rpc_protocol::ShareMode, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::PathBuf};
+
+/// The directory holding the members of a shared folder, within the directory reserved for file system metadata.
+pub const MEMBERS_DIRECTORY_NAME: &str = "members";
+
+/// Gets the path of a shared folder member's record.
+///
+/// # Arguments
+///
+/// * `name` - The name of the member.
+///
+/// # Returns
+///
+/// The path of the member's record within a replica.
+pub fn member_path(name: &str) -> PathBuf {
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(MEMBERS_DIRECTORY_NAME)
+        .join(format!("{}.toml", name))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Someone invited to a shared folder.
+pub struct SharedFolderMember {
+    /// The name the member was invited under.
+    pub name: String,
+    /// Whether the member was invited to write to the folder, rather than only read it.
+    pub writable: bool,
+    /// The time the member was invited, in microseconds since the Unix epoch.
+    pub invited_at: u64,
+}
+
+/// A replica shared between several people, with a record of who it was shared with.
+#[derive(Clone, Debug)]
+pub struct SharedFolder {
+    /// The file system holding the folder.
+    fs: OkuFs,
+    /// The ID of the replica holding the folder.
+    namespace_id: NamespaceId,
+}
+
+impl OkuFs {
+    /// Creates a folder to be shared with others.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the folder, recorded in its manifest.
+    ///
+    /// # Returns
+    ///
+    /// The new shared folder.
+    pub async fn create_shared_folder(
+        &self,
+        name: impl Into<String>,
+    ) -> Result<SharedFolder, Box<dyn Error + Send + Sync>> {
+        let template = ReplicaTemplate::shared_folder().metadata("name", name);
+        let namespace_id = self.create_replica_from_template(&template).await?;
+        Ok(SharedFolder {
+            fs: self.clone(),
+            namespace_id,
+        })
+    }
+
+    /// Opens a folder previously created with [`OkuFs::create_shared_folder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica holding the folder.
+    ///
+    /// # Returns
+    ///
+    /// The shared folder.
+    pub async fn open_shared_folder(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<SharedFolder, Box<dyn Error + Send + Sync>> {
+        self.open_document(namespace_id).await?;
+        Ok(SharedFolder {
+            fs: self.clone(),
+            namespace_id,
+        })
+    }
+}
+
+impl SharedFolder {
+    /// Gets the ID of the replica holding the folder.
+    pub fn namespace_id(&self) -> NamespaceId {
+        self.namespace_id
+    }
+
+    /// Reads the folder's manifest, holding its name and any other metadata.
+    ///
+    /// # Returns
+    ///
+    /// The folder's manifest, if it has one.
+    pub async fn manifest(&self) -> Result<Option<ReplicaManifest>, Box<dyn Error + Send + Sync>> {
+        self.fs.read_replica_manifest(self.namespace_id).await
+    }
+
+    /// Invites someone to the folder, recording them as a member.
+    /// Inviting an existing member again replaces their record, such as to change their access.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to record the member under.
+    ///
+    /// * `writable` - Whether the member may write to the folder, rather than only read it.
+    ///
+    /// # Returns
+    ///
+    /// A ticket to send to the member, with which they can import the folder.
+    pub async fn invite(
+        &self,
+        name: &str,
+        writable: bool,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        if name.is_empty() || name.contains('/') {
+            return Err(OkuFsError::InvalidMemberName(name.to_string()).into());
+        }
+        let member = SharedFolderMember {
+            name: name.to_string(),
+            writable,
+            invited_at: chrono::Utc::now().timestamp_micros() as u64,
+        };
+        self.record_member(&member).await?;
+        self.ticket(writable).await
+    }
+
+    /// Lists the folder's members.
+    ///
+    /// # Returns
+    ///
+    /// The members invited to the folder, ordered by name.
+    pub async fn members(&self) -> Result<Vec<SharedFolderMember>, Box<dyn Error + Send + Sync>> {
+        let document = self.fs.open_document(self.namespace_id).await?;
+        let query = iroh::sync::store::Query::single_latest_per_key()
+            .key_prefix(
+                self.fs
+                    .entry_prefix(PathBuf::from(METADATA_DIRECTORY).join(MEMBERS_DIRECTORY_NAME)),
+            )
+            .build();
+        let entries = document.get_many(query).await?;
+        pin_mut!(entries);
+        let mut members = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let content = self.fs.read_entry_content(&entry?).await?;
+            members.push(toml::from_str::<SharedFolderMember>(
+                &String::from_utf8_lossy(&content),
+            )?);
+        }
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(members)
+    }
+
+    /// Removes a member from the folder.
+    /// Access to a replica cannot be withdrawn once granted, so the folder is moved to a new replica that the removed member was never given, and the old replica is removed from this node.
+    /// The remaining members must be sent the returned tickets to follow the folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the member to remove.
+    ///
+    /// # Returns
+    ///
+    /// A new ticket for each remaining member.
+    pub async fn remove_member(
+        &mut self,
+        name: &str,
+    ) -> Result<Vec<(SharedFolderMember, String)>, Box<dyn Error + Send + Sync>> {
+        let members = self.members().await?;
+        if !members.iter().any(|member| member.name == name) {
+            return Err(OkuFsError::MemberNotFound(name.to_string()).into());
+        }
+        let old_namespace_id = self.namespace_id;
+        let new_namespace_id = self.fs.create_replica().await?;
+        if let Some(key) = self.fs.encryption_key(old_namespace_id)? {
+            self.fs.set_encryption_key(new_namespace_id, key)?;
+        }
+        self.fs
+            .copy_directory(
+                old_namespace_id,
+                PathBuf::from("/"),
+                new_namespace_id,
+                PathBuf::from("/"),
+            )
+            .await?;
+        if let Ok(manifest) = self
+            .fs
+            .get_latest_entry(old_namespace_id, manifest_path())
+            .await
+        {
+            self.fs
+                .set_entry_content(new_namespace_id, manifest_path(), &manifest)
+                .await?;
+        }
+        let alias = self.fs.get_replica_alias(old_namespace_id)?;
+        self.fs.delete_replica(old_namespace_id).await?;
+        if let Some(alias) = alias {
+            self.fs.remove_replica_alias(old_namespace_id)?;
+            self.fs.set_replica_alias(new_namespace_id, alias)?;
+        }
+        self.namespace_id = new_namespace_id;
+        let mut tickets = Vec::new();
+        for member in members.into_iter().filter(|member| member.name != name) {
+            self.record_member(&member).await?;
+            let ticket = self.ticket(member.writable).await?;
+            tickets.push((member, ticket));
+        }
+        Ok(tickets)
+    }
+
+    /// Records a member of the folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `member` - The member to record.
+    async fn record_member(
+        &self,
+        member: &SharedFolderMember,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.fs
+            .create_or_modify_file(
+                self.namespace_id,
+                member_path(&member.name),
+                toml::to_string(member)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Creates a ticket granting access to the folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `writable` - Whether the ticket grants write access, rather than only read access.
+    ///
+    /// # Returns
+    ///
+    /// The ticket, in its textual form.
+    async fn ticket(&self, writable: bool) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let document = self.fs.open_document(self.namespace_id).await?;
+        let mode = if writable {
+            ShareMode::Write
+        } else {
+            ShareMode::Read
+        };
+        Ok(document.share(mode).await?.to_string())
+    }
+}
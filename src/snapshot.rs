@@ -0,0 +1,314 @@
+use crate::error::OkuFsError;
+use crate::event::OkuFsEvent;
+use crate::fs::{is_directory_marker, normalise_path, OkuFs, METADATA_DIRECTORY};
+use bytes::Bytes;
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::Hash,
+    sync::{store::Query, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The directory holding the snapshots of a replica, within the directory reserved for file system metadata.
+pub const SNAPSHOTS_DIRECTORY_NAME: &str = "snapshots";
+
+/// Gets the path of a snapshot's record.
+///
+/// # Arguments
+///
+/// * `label` - The label of the snapshot.
+///
+/// # Returns
+///
+/// The path of the snapshot's record within a replica.
+pub fn snapshot_path(label: &str) -> PathBuf {
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(SNAPSHOTS_DIRECTORY_NAME)
+        .join(format!("{}.toml", label))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A file as it was when a snapshot was taken.
+pub struct SnapshotFile {
+    /// The hash of the file's content, as stored.
+    pub hash: Hash,
+    /// The size, in bytes, of the file's content, as stored.
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// The files of a replica at a moment in time.
+pub struct Snapshot {
+    /// The label the snapshot was taken under.
+    pub label: String,
+    /// The time the snapshot was taken, in microseconds since the Unix epoch.
+    pub created_at: u64,
+    /// The files in the replica when the snapshot was taken, by path.
+    pub files: BTreeMap<PathBuf, SnapshotFile>,
+}
+
+impl OkuFs {
+    /// Records the files of a replica as they are now, so that they can be read or restored later.
+    /// Snapshots are held within the replica, so they are shared with its peers, and the content they refer to is kept by garbage collection until they are deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label to take the snapshot under, replacing any snapshot with the same label.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot taken.
+    pub async fn create_snapshot(
+        &self,
+        namespace_id: NamespaceId,
+        label: &str,
+    ) -> Result<Snapshot, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        if label.is_empty() || label.contains('/') {
+            return Err(OkuFsError::InvalidSnapshotLabel(label.to_string()).into());
+        }
+        self.flush_writes(namespace_id).await?;
+        let entries = self.stream_files(namespace_id).await?;
+        pin_mut!(entries);
+        let mut files = BTreeMap::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if is_directory_marker(entry.key()) {
+                continue;
+            }
+            files.insert(
+                self.entry_path(entry.key()),
+                SnapshotFile {
+                    hash: entry.content_hash(),
+                    size: entry.content_len(),
+                },
+            );
+        }
+        let snapshot = Snapshot {
+            label: label.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)?
+                .as_micros()
+                .try_into()?,
+            files,
+        };
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_bytes(
+                self.author_id,
+                self.entry_key(snapshot_path(label)),
+                toml::to_string(&snapshot)?,
+            )
+            .await?;
+        Ok(snapshot)
+    }
+
+    /// Lists the snapshots taken of a replica, by any author.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The snapshots of the replica, oldest first.
+    pub async fn list_snapshots(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<Snapshot>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let prefix =
+            self.entry_prefix(PathBuf::from(METADATA_DIRECTORY).join(SNAPSHOTS_DIRECTORY_NAME));
+        let entries = document
+            .get_many(Query::single_latest_per_key().key_prefix(prefix).build())
+            .await?;
+        pin_mut!(entries);
+        let mut snapshots = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let content = self.read_entry_content(&entry?).await?;
+            snapshots.push(toml::from_str::<Snapshot>(&String::from_utf8_lossy(
+                &content,
+            ))?);
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.created_at);
+        Ok(snapshots)
+    }
+
+    /// Gets a snapshot of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot with the given label.
+    pub async fn get_snapshot(
+        &self,
+        namespace_id: NamespaceId,
+        label: &str,
+    ) -> Result<Snapshot, Box<dyn Error + Send + Sync>> {
+        let entry = self
+            .get_latest_entry(namespace_id, snapshot_path(label))
+            .await
+            .map_err(|_| OkuFsError::SnapshotNotFound(label.to_string()))?;
+        let content = self.read_entry_content(&entry).await?;
+        Ok(toml::from_str(&String::from_utf8_lossy(&content))?)
+    }
+
+    /// Reads a file as it was when a snapshot was taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The content the file had when the snapshot was taken.
+    pub async fn read_file_at_snapshot(
+        &self,
+        namespace_id: NamespaceId,
+        label: &str,
+        path: PathBuf,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let path = normalise_path(path);
+        let snapshot = self.get_snapshot(namespace_id, label).await?;
+        let file = snapshot
+            .files
+            .get(&path)
+            .ok_or(OkuFsError::FsEntryNotFound)?;
+        self.read_content(namespace_id, &self.entry_key(path), file.hash, file.size)
+            .await
+    }
+
+    /// Returns a directory to how it was when a snapshot was taken.
+    /// Files changed since are set back to their earlier content, files deleted since are recreated, and files created since are deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    ///
+    /// * `path` - The path of the directory to restore; `/` restores the whole replica.
+    ///
+    /// # Returns
+    ///
+    /// The paths of the files changed by the restoration.
+    pub async fn restore_snapshot(
+        &self,
+        namespace_id: NamespaceId,
+        label: &str,
+        path: PathBuf,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, path.clone(), self.author_id)
+            .await?;
+        let path = normalise_path(path);
+        let snapshot = self.get_snapshot(namespace_id, label).await?;
+        self.flush_writes(namespace_id).await?;
+        let mut current = BTreeMap::new();
+        for entry in self
+            .list_directory_entries(namespace_id, path.clone())
+            .await?
+        {
+            if !is_directory_marker(entry.key()) {
+                current.insert(self.entry_path(entry.key()), entry);
+            }
+        }
+        let document = self.open_document(namespace_id).await?;
+        let mut changed = Vec::new();
+        for (file_path, file) in snapshot
+            .files
+            .iter()
+            .filter(|(file_path, _)| file_path.starts_with(&path))
+        {
+            if current
+                .get(file_path)
+                .is_some_and(|entry| entry.content_hash() == file.hash)
+            {
+                continue;
+            }
+            document
+                .set_hash(
+                    self.author_id,
+                    self.entry_key(file_path.clone()),
+                    file.hash,
+                    file.size,
+                )
+                .await?;
+            self.emit(OkuFsEvent::EntryInserted {
+                namespace_id,
+                path: file_path.clone(),
+                hash: file.hash,
+                author: self.author_id,
+            });
+            changed.push(file_path.clone());
+        }
+        for file_path in current.into_keys() {
+            if !snapshot.files.contains_key(&file_path) {
+                self.remove_file(namespace_id, file_path.clone(), self.author_id)
+                    .await?;
+                changed.push(file_path);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Deletes a snapshot of a replica, allowing the content only it refers to to be garbage collected.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `label` - The label of the snapshot.
+    pub async fn delete_snapshot(
+        &self,
+        namespace_id: NamespaceId,
+        label: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.get_snapshot(namespace_id, label).await?;
+        let document = self.open_document(namespace_id).await?;
+        document
+            .del(self.author_id, self.entry_key(snapshot_path(label)))
+            .await?;
+        Ok(())
+    }
+
+    /// Collects the content referred to by the snapshots of a replica, so that it is kept by garbage collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The hashes of the content referred to by any snapshot.
+    pub(crate) async fn snapshot_hashes(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<HashSet<Hash>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .list_snapshots(namespace_id)
+            .await?
+            .into_iter()
+            .flat_map(|snapshot| snapshot.files.into_values())
+            .map(|file| file.hash)
+            .collect())
+    }
+}
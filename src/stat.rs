@@ -0,0 +1,151 @@
+use crate::fs::{is_metadata_key, normalise_path, OkuFs};
+use iroh::{
+    bytes::Hash,
+    client::Entry,
+    sync::{store::Query, AuthorId, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A description of a file or directory within a replica.
+pub struct OkuFileInfo {
+    /// The path of the file or directory.
+    pub path: PathBuf,
+    /// Whether this is a directory rather than a file.
+    pub is_dir: bool,
+    /// The size, in bytes, of the file's content as it is read. Directories have no size.
+    pub size: u64,
+    /// The hash of the file's content, or `None` for directories.
+    pub hash: Option<Hash>,
+    /// The author of the latest version of the file, or of the directory's marker if it was created explicitly.
+    pub author: AuthorId,
+    /// The time the earliest version held of the file was written, in microseconds since the Unix epoch.
+    pub created: u64,
+    /// The time the latest version of the file was written, in microseconds since the Unix epoch.
+    pub modified: u64,
+}
+
+impl OkuFs {
+    /// Describes a file or directory within a replica.
+    /// Directories are described by their marker if they were created explicitly, or otherwise by a file within them.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path of the file or directory.
+    ///
+    /// # Returns
+    ///
+    /// A description of the file or directory, or `None` if nothing exists at the path.
+    pub async fn stat(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Option<OkuFileInfo>, Box<dyn Error + Send + Sync>> {
+        let path = normalise_path(path);
+        if let Some(entry) = self.stat_entry(namespace_id, &path, false).await? {
+            let created = self
+                .file_history(namespace_id, path.clone())
+                .await?
+                .iter()
+                .filter(|version| version.content_len() > 0)
+                .map(|version| version.timestamp())
+                .min()
+                .unwrap_or(entry.timestamp());
+            return Ok(Some(OkuFileInfo {
+                path,
+                is_dir: false,
+                size: self.content_size(&entry).await?,
+                hash: Some(entry.content_hash()),
+                author: entry.author(),
+                created,
+                modified: entry.timestamp(),
+            }));
+        }
+        if path == Path::new("/") {
+            return Ok(None);
+        }
+        Ok(self
+            .stat_entry(namespace_id, &path, true)
+            .await?
+            .map(|entry| OkuFileInfo {
+                path,
+                is_dir: true,
+                size: 0,
+                hash: None,
+                author: entry.author(),
+                created: entry.timestamp(),
+                modified: entry.timestamp(),
+            }))
+    }
+
+    /// Checks whether a file or directory exists within a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path of the file or directory.
+    ///
+    /// # Returns
+    ///
+    /// Whether a file or directory exists at the path.
+    pub async fn exists(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let path = normalise_path(path);
+        Ok(self.stat_entry(namespace_id, &path, false).await?.is_some()
+            || (path != Path::new("/")
+                && self.stat_entry(namespace_id, &path, true).await?.is_some()))
+    }
+
+    /// Finds the entry describing a file, or an entry within a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path of the file or directory.
+    ///
+    /// * `directory` - Whether to look for a directory rather than a file.
+    ///
+    /// # Returns
+    ///
+    /// The latest entry of the file, or the directory's marker if it has one and otherwise the latest entry of any file within it.
+    async fn stat_entry(
+        &self,
+        namespace_id: NamespaceId,
+        path: &Path,
+        directory: bool,
+    ) -> Result<Option<Entry>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        if !directory {
+            let query = Query::single_latest_per_key()
+                .key_exact(self.entry_key(path.to_path_buf()))
+                .build();
+            return Ok(document
+                .get_one(query)
+                .await?
+                .filter(|entry| !is_metadata_key(entry.key())));
+        }
+        let prefix = self.entry_prefix(path.to_path_buf());
+        let query = Query::single_latest_per_key()
+            .key_exact(prefix.clone())
+            .build();
+        if let Some(marker) = document.get_one(query).await? {
+            return Ok(Some(marker));
+        }
+        let query = Query::single_latest_per_key().key_prefix(prefix).build();
+        Ok(document
+            .get_one(query)
+            .await?
+            .filter(|entry| !is_metadata_key(entry.key())))
+    }
+}
@@ -0,0 +1,230 @@
+use crate::error::OkuFsError;
+use crate::fs::{OkuFs, OkuFsConfig};
+use futures::future::BoxFuture;
+use iroh::{
+    bytes::store::Store as BaoStore,
+    client::mem::{Iroh, RpcClient},
+    net::{MagicEndpoint, NodeAddr, NodeId},
+    node::{Builder, FsNode, Node, StorageConfig},
+    util::path::IrohPaths,
+};
+use std::{error::Error, ops::Deref, path::PathBuf};
+use tokio_util::sync::CancellationToken;
+
+/// The name of the directory holding the node's data, within the path on disk where the file system is stored.
+pub const NODE_DIRECTORY_NAME: &str = "node";
+
+/// A place the data of the Iroh node backing the file system is kept, such as a directory on disk or memory.
+/// Backends keeping data elsewhere, such as in an encrypted container or an object store, can be written by implementing this trait, typically with [`spawn_node_with_stores`].
+pub trait StorageBackend: std::fmt::Debug + Send + Sync {
+    /// Starts an Iroh node keeping its data in the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the file system, giving the node's secret key, authors, and relay servers.
+    ///
+    /// # Returns
+    ///
+    /// The running node.
+    fn spawn_node<'a>(
+        &'a self,
+        config: &'a OkuFsConfig,
+    ) -> BoxFuture<'a, Result<OkuNode, Box<dyn Error + Send + Sync>>>;
+
+    /// Gets the directory on disk holding the node's data, if the backend keeps it on disk.
+    /// The directory is moved aside if the node cannot be started and the integrity check is set to quarantine, and holds the node's secret key for backups.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the file system.
+    ///
+    /// # Returns
+    ///
+    /// The directory holding the node's data, or `None` if it is not kept on disk.
+    fn node_path(&self, config: &OkuFsConfig) -> Option<PathBuf> {
+        let _ = config;
+        None
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Keeps the node's data in a directory on disk.
+pub struct DiskStorage {
+    /// The directory holding the node's data. If unspecified, the `node` directory within the file system's path is used.
+    pub path: Option<PathBuf>,
+}
+
+impl DiskStorage {
+    /// Keeps the node's data in a given directory, rather than within the file system's path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory holding the node's data.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        DiskStorage {
+            path: Some(path.into()),
+        }
+    }
+}
+
+impl StorageBackend for DiskStorage {
+    fn spawn_node<'a>(
+        &'a self,
+        config: &'a OkuFsConfig,
+    ) -> BoxFuture<'a, Result<OkuNode, Box<dyn Error + Send + Sync>>> {
+        Box::pin(async move {
+            let node_path = self.node_path(config).unwrap_or_default();
+            if !config.author_keys.is_empty() {
+                // Authors can only be added to the store while no node has it open.
+                std::fs::create_dir_all(&node_path)?;
+                let docs_store = iroh::sync::store::fs::Store::persistent(
+                    IrohPaths::DocsDatabase.with_root(&node_path),
+                )?;
+                for author in &config.author_keys {
+                    docs_store.import_author(author.clone())?;
+                }
+            }
+            let mut builder = FsNode::persistent(node_path)
+                .await?
+                .relay_mode(config.relay_mode()?);
+            if let Some(node_secret_key) = &config.node_secret_key {
+                builder = builder.secret_key(node_secret_key.clone());
+            }
+            Ok(builder.spawn().await?.into())
+        })
+    }
+
+    fn node_path(&self, config: &OkuFsConfig) -> Option<PathBuf> {
+        Some(
+            self.path
+                .clone()
+                .unwrap_or_else(|| config.path.join(NODE_DIRECTORY_NAME)),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Keeps the node's data in memory, so that it is lost once the file system stops, such as for tests or short-lived nodes.
+/// Settings such as aliases and pins are still kept within the file system's path.
+pub struct MemoryStorage;
+
+impl StorageBackend for MemoryStorage {
+    fn spawn_node<'a>(
+        &'a self,
+        config: &'a OkuFsConfig,
+    ) -> BoxFuture<'a, Result<OkuNode, Box<dyn Error + Send + Sync>>> {
+        Box::pin(spawn_node_with_stores(
+            iroh::bytes::store::mem::Store::default(),
+            iroh::sync::store::fs::Store::memory(),
+            StorageConfig::Mem,
+            config,
+        ))
+    }
+}
+
+/// Starts an Iroh node keeping its data in the given stores, as configured for the file system.
+///
+/// # Arguments
+///
+/// * `blobs_store` - The store holding the content of files.
+///
+/// * `docs_store` - The store holding replicas and authors.
+///
+/// * `storage` - Where the node keeps the rest of its data, such as its record of peers.
+///
+/// * `config` - The configuration of the file system, giving the node's secret key, authors, and relay servers.
+///
+/// # Returns
+///
+/// The running node.
+pub async fn spawn_node_with_stores<D: BaoStore>(
+    blobs_store: D,
+    docs_store: iroh::sync::store::fs::Store,
+    storage: StorageConfig,
+    config: &OkuFsConfig,
+) -> Result<OkuNode, Box<dyn Error + Send + Sync>> {
+    for author in &config.author_keys {
+        docs_store.import_author(author.clone())?;
+    }
+    let mut builder = Builder::with_db_and_store(blobs_store, docs_store, storage)
+        .relay_mode(config.relay_mode()?);
+    if let Some(node_secret_key) = &config.node_secret_key {
+        builder = builder.secret_key(node_secret_key.clone());
+    }
+    Ok(builder.spawn().await?.into())
+}
+
+#[derive(Clone, Debug)]
+/// A running Iroh node backing the file system, whichever storage backend holds its data.
+/// The node's clients for replicas, content, tags, and authors are reached through it.
+pub struct OkuNode {
+    /// A client of the node.
+    client: Iroh,
+    /// A client sending requests to the node directly, for what the node's other clients do not offer.
+    controller: RpcClient,
+    /// The endpoint the node connects to peers through.
+    magic_endpoint: MagicEndpoint,
+    /// A token which, once cancelled, stops the node.
+    cancel_token: CancellationToken,
+}
+
+impl<D: BaoStore> From<Node<D>> for OkuNode {
+    fn from(node: Node<D>) -> Self {
+        OkuNode {
+            client: node.client().clone(),
+            controller: node.controller(),
+            magic_endpoint: node.magic_endpoint().clone(),
+            cancel_token: node.cancel_token(),
+        }
+    }
+}
+
+impl OkuNode {
+    /// Gets the endpoint the node connects to peers through.
+    pub fn magic_endpoint(&self) -> &MagicEndpoint {
+        &self.magic_endpoint
+    }
+
+    /// Gets a client sending requests to the node directly.
+    pub fn controller(&self) -> &RpcClient {
+        &self.controller
+    }
+
+    /// Gets the ID the node is identified by.
+    pub fn node_id(&self) -> NodeId {
+        self.magic_endpoint.node_id()
+    }
+
+    /// Gets the address the node can be reached at.
+    pub async fn my_addr(&self) -> anyhow::Result<NodeAddr> {
+        self.magic_endpoint.my_addr().await
+    }
+
+    /// Stops the node.
+    pub fn shutdown(&self) {
+        self.cancel_token.cancel();
+    }
+}
+
+impl Deref for OkuNode {
+    type Target = Iroh;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl OkuFs {
+    /// Gets the directory on disk holding the data of the node backing the file system.
+    ///
+    /// # Returns
+    ///
+    /// The directory holding the node's data, or an error if the storage backend does not keep it on disk.
+    pub(crate) fn node_path(&self) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .config
+            .storage_backend
+            .node_path(&self.config)
+            .ok_or(OkuFsError::StorageNotOnDisk)?)
+    }
+}
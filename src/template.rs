@@ -0,0 +1,172 @@
+use crate::error::OkuFsError;
+use crate::fs::{OkuFs, METADATA_DIRECTORY};
+use bytes::Bytes;
+use iroh::sync::NamespaceId;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, path::PathBuf};
+
+/// The name of the file holding a replica's manifest, within the directory reserved for file system metadata.
+pub const MANIFEST_FILE_NAME: &str = "manifest.toml";
+
+/// Gets the path of a replica's manifest.
+///
+/// # Returns
+///
+/// The path of the manifest within a replica.
+pub fn manifest_path() -> PathBuf {
+    PathBuf::from(METADATA_DIRECTORY).join(MANIFEST_FILE_NAME)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// Metadata describing the layout and purpose of a replica.
+pub struct ReplicaManifest {
+    /// The name of the template the replica was created from.
+    pub template: String,
+    /// The directories that make up the replica's initial layout.
+    #[serde(default)]
+    pub directories: Vec<PathBuf>,
+    /// Application-defined metadata about the replica.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+/// A template defining the initial layout of a replica.
+pub struct ReplicaTemplate {
+    /// The name of the template.
+    pub name: String,
+    /// The directories that make up the replica's initial layout.
+    pub directories: Vec<PathBuf>,
+    /// Metadata to record in the replica's manifest.
+    pub metadata: BTreeMap<String, String>,
+    /// Files to create in the replica, keyed by their paths.
+    pub files: BTreeMap<PathBuf, Bytes>,
+}
+
+impl ReplicaTemplate {
+    /// Creates an empty template.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the template.
+    ///
+    /// # Returns
+    ///
+    /// A template with no directories, metadata, or files.
+    pub fn new(name: impl Into<String>) -> Self {
+        ReplicaTemplate {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a directory to the template.
+    pub fn directory(mut self, path: impl Into<PathBuf>) -> Self {
+        self.directories.push(path.into());
+        self
+    }
+
+    /// Adds a metadata field to the template's manifest.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Adds a file to the template.
+    pub fn file(mut self, path: impl Into<PathBuf>, data: impl Into<Bytes>) -> Self {
+        self.files.insert(path.into(), data.into());
+        self
+    }
+
+    /// A template for a static website.
+    pub fn website() -> Self {
+        ReplicaTemplate::new("website")
+            .directory("/assets")
+            .metadata("kind", "website")
+            .file(
+                "/index.html",
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<link rel=\"stylesheet\" href=\"/assets/style.css\">\n</head>\n<body>\n</body>\n</html>\n",
+            )
+            .file("/assets/style.css", "body {}\n")
+    }
+
+    /// A template for a collection of notes.
+    pub fn notes() -> Self {
+        ReplicaTemplate::new("notes")
+            .directory("/notes")
+            .metadata("kind", "notes")
+            .file("/README.md", "# Notes\n")
+    }
+
+    /// A template for a folder shared between several authors.
+    pub fn shared_folder() -> Self {
+        ReplicaTemplate::new("shared-folder")
+            .directory("/shared")
+            .metadata("kind", "shared-folder")
+    }
+
+    /// Gets the manifest describing replicas created from this template.
+    pub fn manifest(&self) -> ReplicaManifest {
+        ReplicaManifest {
+            template: self.name.clone(),
+            directories: self.directories.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+impl OkuFs {
+    /// Creates a new replica in the file system, laid out according to a template.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template defining the replica's initial directories, manifest metadata, and files.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new replica, being its public key.
+    pub async fn create_replica_from_template(
+        &self,
+        template: &ReplicaTemplate,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        let namespace_id = self.create_replica().await?;
+        let manifest_toml = toml::to_string(&template.manifest())?;
+        self.create_or_modify_file(namespace_id, manifest_path(), manifest_toml)
+            .await?;
+        for path in &template.directories {
+            self.create_directory(namespace_id, path.clone()).await?;
+        }
+        for (path, data) in &template.files {
+            self.create_or_modify_file(namespace_id, path.clone(), data.clone())
+                .await?;
+        }
+        Ok(namespace_id)
+    }
+
+    /// Reads the manifest of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica whose manifest should be read.
+    ///
+    /// # Returns
+    ///
+    /// The replica's manifest, if it has one.
+    pub async fn read_replica_manifest(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Option<ReplicaManifest>, Box<dyn Error + Send + Sync>> {
+        // The manifest may have been written by any author, such as the one who created the replica.
+        let entry = match self.get_latest_entry(namespace_id, manifest_path()).await {
+            Ok(entry) => entry,
+            Err(e) => match e.downcast_ref::<OkuFsError>() {
+                Some(OkuFsError::FsEntryNotFound) => return Ok(None),
+                _ => return Err(e),
+            },
+        };
+        let manifest_bytes = self.read_entry_content(&entry).await?;
+        Ok(Some(toml::from_str(&String::from_utf8_lossy(
+            &manifest_bytes,
+        ))?))
+    }
+}
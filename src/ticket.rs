@@ -0,0 +1,337 @@
+use crate::error::OkuFsError;
+use crate::event::OkuFsEvent;
+use crate::fs::OkuFs;
+use bytes::Bytes;
+use futures::StreamExt;
+use iroh::{
+    bytes::{BlobFormat, Hash},
+    client::{LiveEvent, ShareTicketOptions},
+    net::{relay::RelayUrl, NodeId},
+    rpc_protocol::ShareMode,
+    sync::{store::DownloadPolicy, Capability, CapabilityKind, NamespaceId},
+    ticket::{BlobTicket, DocTicket},
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// The default time to spend learning a replica's size before deciding whether to accept it.
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+/// A description of what a replica ticket grants, and where it points.
+pub struct TicketInfo {
+    /// The ID of the replica the ticket grants access to.
+    pub namespace_id: NamespaceId,
+    /// Whether the ticket grants read-only or writable access to the replica.
+    pub capability: CapabilityKind,
+    /// The nodes that can be contacted to fetch the replica.
+    pub nodes: Vec<TicketNodeInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A description of a node listed in a replica ticket.
+pub struct TicketNodeInfo {
+    /// The ID of the node.
+    pub node_id: NodeId,
+    /// The URL of the node's home relay, if it has one.
+    pub relay_url: Option<RelayUrl>,
+    /// The addresses at which the node might be reached directly.
+    pub direct_addresses: Vec<SocketAddr>,
+}
+
+impl From<&DocTicket> for TicketInfo {
+    fn from(ticket: &DocTicket) -> Self {
+        TicketInfo {
+            namespace_id: ticket.capability.id(),
+            capability: ticket.capability.kind(),
+            nodes: ticket
+                .nodes
+                .iter()
+                .map(|node| TicketNodeInfo {
+                    node_id: node.node_id,
+                    relay_url: node.info.relay_url.clone(),
+                    direct_addresses: node.info.direct_addresses.iter().copied().collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Describes what a replica ticket grants without importing the replica.
+///
+/// # Arguments
+///
+/// * `ticket` - The ticket, in its textual form.
+///
+/// # Returns
+///
+/// A description of the replica, the access granted to it, and the nodes it can be fetched from.
+pub fn inspect_ticket(ticket: &str) -> Result<TicketInfo, Box<dyn Error + Send + Sync>> {
+    let ticket = DocTicket::from_str(ticket.trim())?;
+    Ok(TicketInfo::from(&ticket))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The conditions a replica ticket must meet to be accepted.
+///
+/// By default, tickets are accepted read-only, regardless of the replica's size.
+pub struct AcceptPolicy {
+    /// Whether write access granted by a ticket is kept. If not, the replica is imported read-only.
+    pub(crate) allow_write: bool,
+    /// The largest size, in bytes, the replica may have.
+    pub(crate) max_size: Option<u64>,
+    /// How long to spend learning the replica's size from peers.
+    pub(crate) probe_timeout: Duration,
+}
+
+impl Default for AcceptPolicy {
+    fn default() -> Self {
+        AcceptPolicy {
+            allow_write: false,
+            max_size: None,
+            probe_timeout: DEFAULT_PROBE_TIMEOUT,
+        }
+    }
+}
+
+impl AcceptPolicy {
+    /// Sets whether write access granted by a ticket is kept.
+    pub fn allow_write(mut self, allow_write: bool) -> Self {
+        self.allow_write = allow_write;
+        self
+    }
+
+    /// Sets the largest size, in bytes, the replica may have.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Sets how long to spend learning the replica's size from peers.
+    pub fn probe_timeout(mut self, probe_timeout: Duration) -> Self {
+        self.probe_timeout = probe_timeout;
+        self
+    }
+}
+
+impl OkuFs {
+    /// Imports a replica from a ticket, subject to an acceptance policy.
+    /// Unless the policy allows writing, a ticket granting write access is downgraded to read-only access.
+    /// If the policy limits the replica's size, the replica's entries are fetched without their content until its size is known; a replica that is too large is then removed.
+    /// If the ticket lists several nodes, the replica's content is downloaded from all of them at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket, in its textual form.
+    ///
+    /// * `policy` - The conditions the ticket must meet to be accepted.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the imported replica.
+    pub async fn accept_ticket(
+        &self,
+        ticket: &str,
+        policy: AcceptPolicy,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        self.accept_ticket_with_cancellation(ticket, policy, CancellationToken::new())
+            .await
+    }
+
+    /// Imports a replica from a ticket, subject to an acceptance policy, stopping early if cancelled.
+    /// Waiting for a free sync slot is abandoned after the configured network timeout.
+    /// Until the replica's content is held in full, the import is recorded on disk, so that it is resumed by [`OkuFs::resume_pending_syncs`] if interrupted.
+    /// A replica not already held is removed if its import is cancelled while its size is being learned.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket, in its textual form.
+    ///
+    /// * `policy` - The conditions the ticket must meet to be accepted.
+    ///
+    /// * `cancellation` - A token which, once cancelled, stops the import.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the imported replica.
+    pub async fn accept_ticket_with_cancellation(
+        &self,
+        ticket: &str,
+        policy: AcceptPolicy,
+        cancellation: CancellationToken,
+    ) -> Result<NamespaceId, Box<dyn Error + Send + Sync>> {
+        let mut ticket = DocTicket::from_str(ticket.trim())?;
+        let namespace_id = ticket.capability.id();
+        if matches!(ticket.capability, Capability::Write(_)) && !policy.allow_write {
+            ticket.capability = Capability::Read(namespace_id);
+        }
+        let already_held = self.list_replicas().await?.contains(&namespace_id);
+        // A replica listing several nodes has its content downloaded from all of them at once, rather than from whichever is synced with first.
+        ticket.nodes = self.order_peers(ticket.nodes)?;
+        let peers = ticket.nodes.clone();
+        let parallel = !already_held && peers.len() > 1;
+        let operation = format!("Importing replica {}", namespace_id);
+        let accepted_ticket = ticket.clone();
+        let document = self
+            .with_network_timeout(&operation, &cancellation, self.import_ticket(ticket))
+            .await?;
+        if !already_held {
+            self.record_pending_sync(&accepted_ticket, &policy)?;
+        }
+        if !already_held && (policy.max_size.is_some() || parallel) {
+            document
+                .set_download_policy(DownloadPolicy::NothingExcept(Vec::new()))
+                .await?;
+        }
+        let mut synced = false;
+        if let Some(max_size) = policy.max_size {
+            let events = document.subscribe().await?;
+            let probe = tokio::time::timeout(policy.probe_timeout, async {
+                tokio::pin!(events);
+                while let Some(Ok(event)) = events.next().await {
+                    if let LiveEvent::SyncFinished(sync_event) = event {
+                        if sync_event.result.is_ok() {
+                            break;
+                        }
+                    }
+                }
+            });
+            let cancelled = tokio::select! {
+                _ = cancellation.cancelled() => true,
+                probed = probe => {
+                    synced = probed.is_ok();
+                    false
+                }
+            };
+            let size = self.get_size(namespace_id).await?;
+            if cancelled || size > max_size {
+                if !already_held {
+                    document.leave().await?;
+                    drop(document);
+                    self.node.docs.drop_doc(namespace_id).await?;
+                    self.remove_pending_sync(namespace_id)?;
+                }
+                if cancelled {
+                    return Err(OkuFsError::Cancelled(operation).into());
+                }
+                return Err(OkuFsError::TicketRejected(format!(
+                    "replica {} holds {} bytes, exceeding the limit of {} bytes",
+                    namespace_id, size, max_size
+                ))
+                .into());
+            }
+            if !already_held && !parallel {
+                self.restore_download_policy(&document).await?;
+            }
+        }
+        if !already_held {
+            self.emit(OkuFsEvent::ReplicaImported(namespace_id));
+            self.forward_remote_events(document).await?;
+            self.accept_pending_sync(namespace_id, peers.clone(), cancellation.clone())?;
+        }
+        if parallel {
+            self.spawn_parallel_download(namespace_id, peers, synced, cancellation)
+                .await?;
+        }
+        Ok(namespace_id)
+    }
+
+    /// Creates a ticket granting access to a replica, pointing to this node.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `mode` - Whether the ticket grants read-only or writable access to the replica.
+    ///
+    /// # Returns
+    ///
+    /// A ticket granting access to the replica.
+    pub async fn create_replica_ticket(
+        &self,
+        namespace_id: NamespaceId,
+        mode: ShareMode,
+    ) -> Result<DocTicket, Box<dyn Error + Send + Sync>> {
+        Ok(self.open_document(namespace_id).await?.share(mode).await?)
+    }
+
+    /// Creates a ticket for a single file, so that it can be shared without sharing the replica holding it.
+    /// The ticket points to the file's content as stored, so the content of files in encrypted replicas cannot be read by recipients.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// A ticket pointing to the file's content and this node.
+    pub async fn create_file_ticket(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<BlobTicket, Box<dyn Error + Send + Sync>> {
+        let entry = self.get_latest_entry(namespace_id, path).await?;
+        Ok(self
+            .node
+            .blobs
+            .share(
+                entry.content_hash(),
+                BlobFormat::Raw,
+                ShareTicketOptions::RelayAndAddresses,
+            )
+            .await?)
+    }
+
+    /// Fetches the content of a single file from a file ticket, without importing the replica holding it.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The file ticket, in its textual form.
+    ///
+    /// # Returns
+    ///
+    /// The file's content.
+    pub async fn fetch_file_with_file_ticket(
+        &self,
+        ticket: &str,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let ticket = BlobTicket::from_str(ticket.trim())?;
+        let hash = ticket.hash();
+        let already_held = self.read_blob(hash).await.is_ok();
+        let content = self
+            .fetch_blob(hash, vec![ticket.node_addr().clone()])
+            .await?;
+        // Content fetched only to be returned is left for garbage collection to remove.
+        if !already_held {
+            self.release_blob(hash).await?;
+        }
+        Ok(content)
+    }
+
+    /// Fetches a single file from a file ticket and saves it into a local replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The file ticket, in its textual form.
+    ///
+    /// * `namespace_id` - The ID of the replica to save the file into.
+    ///
+    /// * `path` - The path to save the file at.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the file.
+    pub async fn save_file_with_file_ticket(
+        &self,
+        ticket: &str,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        let content = self.fetch_file_with_file_ticket(ticket).await?;
+        self.create_or_modify_file(namespace_id, path, content)
+            .await
+    }
+}
@@ -0,0 +1,43 @@
+use crate::error::OkuFsError;
+use crate::fs::OkuFs;
+use std::{error::Error, future::Future, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// The default time a network operation may take before it is abandoned.
+pub const DEFAULT_NETWORK_TIMEOUT: Duration = Duration::from_secs(60);
+
+impl OkuFs {
+    /// Runs a network operation, abandoning it if it takes longer than the configured timeout or is cancelled.
+    /// The operation is rejected if the file system is offline.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - A description of the operation, for reporting why it was abandoned.
+    ///
+    /// * `cancellation` - A token which, once cancelled, abandons the operation.
+    ///
+    /// * `future` - The operation.
+    ///
+    /// # Returns
+    ///
+    /// The outcome of the operation, or an error if it was abandoned.
+    pub(crate) async fn with_network_timeout<T>(
+        &self,
+        operation: &str,
+        cancellation: &CancellationToken,
+        future: impl Future<Output = Result<T, Box<dyn Error + Send + Sync>>>,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        self.ensure_online(operation)?;
+        let timeout = self
+            .config
+            .network_timeout
+            .unwrap_or(DEFAULT_NETWORK_TIMEOUT);
+        tokio::select! {
+            _ = cancellation.cancelled() => Err(OkuFsError::Cancelled(operation.to_string()).into()),
+            outcome = tokio::time::timeout(timeout, future) => match outcome {
+                Ok(outcome) => outcome,
+                Err(_) => Err(OkuFsError::TimedOut(operation.to_string(), timeout).into()),
+            },
+        }
+    }
+}
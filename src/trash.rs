@@ -0,0 +1,310 @@
+use crate::fs::{is_directory_marker, normalise_path, OkuFs, METADATA_DIRECTORY};
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    bytes::Hash,
+    client::Entry,
+    sync::{store::Query, NamespaceId},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The directory holding deleted files, within the directory reserved for file system metadata.
+/// Being within the metadata directory, files in the trash are hidden from listings of a replica.
+pub const TRASH_DIRECTORY_NAME: &str = "trash";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A file held in the trash of a replica.
+pub struct TrashItem {
+    /// The path the file had before it was deleted.
+    pub path: PathBuf,
+    /// The time the file was deleted, in microseconds since the Unix epoch.
+    pub deleted_at: u64,
+    /// The hash of the file's content.
+    pub hash: Hash,
+    /// The size, in bytes, of the file's content as stored.
+    pub size: u64,
+}
+
+/// Gets the path at which a deleted file is held in the trash.
+/// Files are held under a directory named after the time of their deletion, mirroring the layout of the replica.
+///
+/// # Arguments
+///
+/// * `path` - The path the file had before it was deleted.
+///
+/// * `deleted_at` - The time the file was deleted, in microseconds since the Unix epoch.
+///
+/// # Returns
+///
+/// The path of the file within the trash.
+pub fn trash_path(path: PathBuf, deleted_at: u64) -> PathBuf {
+    let path = normalise_path(path);
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(TRASH_DIRECTORY_NAME)
+        .join(deleted_at.to_string())
+        .join(path.strip_prefix("/").unwrap_or(&path))
+}
+
+/// Gets the time of a deletion, as recorded in the trash.
+///
+/// # Returns
+///
+/// The current time, in microseconds since the Unix epoch.
+fn deletion_time() -> Result<u64, Box<dyn Error + Send + Sync>> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_micros()
+        .try_into()?)
+}
+
+/// Recovers the original path and deletion time of a file from its path within the trash.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file within the trash.
+///
+/// # Returns
+///
+/// The path the file had before it was deleted, and the time it was deleted, if the path lies within the trash.
+fn parse_trash_path(path: &Path) -> Option<(PathBuf, u64)> {
+    let relative_path = path
+        .strip_prefix(PathBuf::from(METADATA_DIRECTORY).join(TRASH_DIRECTORY_NAME))
+        .ok()?;
+    let mut components = relative_path.components();
+    let deleted_at = match components.next()? {
+        Component::Normal(name) => name.to_str()?.parse().ok()?,
+        _ => return None,
+    };
+    Some((PathBuf::from("/").join(components.as_path()), deleted_at))
+}
+
+impl OkuFs {
+    /// Moves a file to the trash before it is deleted, removing files kept in the trash for longer than the configured retention.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    pub(crate) async fn move_to_trash(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.flush_write(namespace_id, path.clone()).await?;
+        // Deleting a file which does not exist leaves nothing to restore.
+        let Ok(entry) = self.get_latest_entry(namespace_id, path.clone()).await else {
+            return Ok(());
+        };
+        self.trash_entry(namespace_id, path, &entry, deletion_time()?)
+            .await?;
+        self.remove_expired_trash(namespace_id).await
+    }
+
+    /// Moves the files within a directory to the trash before the directory is deleted, removing files kept in the trash for longer than the configured retention.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `path` - The path of the directory.
+    pub(crate) async fn move_directory_to_trash(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.flush_writes(namespace_id).await?;
+        let entries = self
+            .list_directory_entries(namespace_id, path.clone())
+            .await?;
+        // Every file in the directory is deleted at once, so they are restored as a group.
+        let deleted_at = deletion_time()?;
+        for entry in entries {
+            if is_directory_marker(entry.key()) {
+                continue;
+            }
+            let entry_path = self.entry_path(entry.key());
+            self.trash_entry(namespace_id, entry_path, &entry, deleted_at)
+                .await?;
+        }
+        self.remove_expired_trash(namespace_id).await
+    }
+
+    /// Keeps a copy of a file's latest version in the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the file.
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// * `entry` - The latest entry of the file.
+    ///
+    /// * `deleted_at` - The time the file was deleted, in microseconds since the Unix epoch.
+    async fn trash_entry(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        entry: &Entry,
+        deleted_at: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let trashed_path = trash_path(path.clone(), deleted_at);
+        self.set_entry_content(namespace_id, trashed_path.clone(), entry)
+            .await?;
+        self.copy_metadata(namespace_id, path, namespace_id, trashed_path)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes files kept in the trash for longer than the configured retention.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    async fn remove_expired_trash(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(trash_retention) = self.config.trash_retention {
+            self.empty_trash(namespace_id, Some(trash_retention))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Lists the files held in the trash of a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The deleted files, most recently deleted first.
+    pub async fn list_trash(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<TrashItem>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let prefix =
+            self.entry_prefix(PathBuf::from(METADATA_DIRECTORY).join(TRASH_DIRECTORY_NAME));
+        let entries = document
+            .get_many(Query::single_latest_per_key().key_prefix(prefix).build())
+            .await?;
+        pin_mut!(entries);
+        let mut items = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if is_directory_marker(entry.key()) {
+                continue;
+            }
+            if let Some((path, deleted_at)) = parse_trash_path(&self.entry_path(entry.key())) {
+                items.push(TrashItem {
+                    path,
+                    deleted_at,
+                    hash: entry.content_hash(),
+                    size: entry.content_len(),
+                });
+            }
+        }
+        items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+        Ok(items)
+    }
+
+    /// Restores a file from the trash to the path it had before it was deleted, replacing any file since written at that path.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `item` - The file in the trash to restore.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the restored file.
+    pub async fn restore_from_trash(
+        &self,
+        namespace_id: NamespaceId,
+        item: &TrashItem,
+    ) -> Result<Hash, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        self.authorize_write(namespace_id, item.path.clone(), self.author_id)
+            .await?;
+        let trashed_path = trash_path(item.path.clone(), item.deleted_at);
+        let entry = self
+            .get_latest_entry(namespace_id, trashed_path.clone())
+            .await?;
+        self.flush_write(namespace_id, item.path.clone()).await?;
+        let hash = self
+            .set_entry_content(namespace_id, item.path.clone(), &entry)
+            .await?;
+        self.copy_metadata(
+            namespace_id,
+            trashed_path.clone(),
+            namespace_id,
+            item.path.clone(),
+        )
+        .await?;
+        self.remove_from_trash(namespace_id, trashed_path).await?;
+        Ok(hash)
+    }
+
+    /// Deletes files from the trash of a replica outright.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `older_than` - If given, only files deleted longer ago than this are removed; otherwise, the trash is emptied.
+    ///
+    /// # Returns
+    ///
+    /// The number of files removed from the trash.
+    pub async fn empty_trash(
+        &self,
+        namespace_id: NamespaceId,
+        older_than: Option<Duration>,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        let now: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_micros()
+            .try_into()?;
+        let cutoff = older_than.map(|older_than| {
+            now.saturating_sub(older_than.as_micros().try_into().unwrap_or(u64::MAX))
+        });
+        let mut removed = 0;
+        for item in self.list_trash(namespace_id).await? {
+            if cutoff.is_none_or(|cutoff| item.deleted_at < cutoff) {
+                self.remove_from_trash(namespace_id, trash_path(item.path, item.deleted_at))
+                    .await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Deletes a file held in the trash, along with its metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `trashed_path` - The path of the file within the trash.
+    async fn remove_from_trash(
+        &self,
+        namespace_id: NamespaceId,
+        trashed_path: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        document
+            .del(self.author_id, self.entry_key(trashed_path.clone()))
+            .await?;
+        self.delete_metadata(namespace_id, trashed_path).await?;
+        Ok(())
+    }
+}
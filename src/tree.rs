@@ -0,0 +1,125 @@
+use crate::error::OkuFsError;
+use crate::fs::{is_directory_marker, OkuFs};
+use iroh::{client::Entry, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Component, Path, PathBuf},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The contents of a directory, with each directory within it holding its own contents in turn.
+pub struct DirTree {
+    /// The directories immediately within the directory, by name.
+    /// These include both explicitly created directories and those implied by the paths of files.
+    pub directories: BTreeMap<String, DirTree>,
+    /// The latest entries of the files immediately within the directory, by name.
+    pub files: BTreeMap<String, Entry>,
+}
+
+impl DirTree {
+    /// Places an entry in the tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the entry, relative to the directory at the root of the tree.
+    ///
+    /// * `entry` - The entry.
+    fn insert(&mut self, path: &Path, entry: Entry) {
+        let names: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let Some((file_name, directory_names)) = names.split_last() else {
+            return;
+        };
+        let mut directory = self;
+        for name in directory_names {
+            directory = directory.directories.entry(name.clone()).or_default();
+        }
+        match is_directory_marker(entry.key()) {
+            true => {
+                directory.directories.entry(file_name.clone()).or_default();
+            }
+            false => {
+                directory.files.insert(file_name.clone(), entry);
+            }
+        }
+    }
+
+    /// Checks whether the directory holds neither files nor directories.
+    pub fn is_empty(&self) -> bool {
+        self.directories.is_empty() && self.files.is_empty()
+    }
+
+    /// Counts the files within the directory, including those within the directories it holds.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+            + self
+                .directories
+                .values()
+                .map(|directory| directory.file_count())
+                .sum::<usize>()
+    }
+
+    /// Lists the files within the directory, including those within the directories it holds.
+    ///
+    /// # Returns
+    ///
+    /// The path of each file relative to the directory, with its latest entry.
+    pub fn walk(&self) -> Vec<(PathBuf, &Entry)> {
+        let mut files: Vec<(PathBuf, &Entry)> = self
+            .files
+            .iter()
+            .map(|(name, entry)| (PathBuf::from(name), entry))
+            .collect();
+        for (name, directory) in self.directories.iter() {
+            files.extend(
+                directory
+                    .walk()
+                    .into_iter()
+                    .map(|(path, entry)| (Path::new(name).join(path), entry)),
+            );
+        }
+        files
+    }
+}
+
+impl OkuFs {
+    /// Gets the contents of a directory as a tree, rather than a flat list of entries.
+    /// Directories are derived from the paths of the files within them, alongside those created explicitly.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica containing the directory.
+    ///
+    /// * `path` - The path of the directory.
+    ///
+    /// # Returns
+    ///
+    /// The directories and files within the directory, excluding file system metadata.
+    pub async fn get_tree(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+    ) -> Result<DirTree, Box<dyn Error + Send + Sync>> {
+        let entries = self
+            .list_directory_entries(namespace_id, path.clone())
+            .await?;
+        if entries.is_empty() && path != Path::new("/") {
+            return Err(OkuFsError::FsEntryNotFound.into());
+        }
+        let mut tree = DirTree::default();
+        for entry in entries {
+            let entry_path = self.entry_path(entry.key());
+            if let Ok(relative_path) = entry_path.strip_prefix(&path) {
+                tree.insert(relative_path, entry);
+            }
+        }
+        Ok(tree)
+    }
+}
@@ -0,0 +1,1076 @@
+use crate::error::OkuFsError;
+use crate::fs::{normalise_path, OkuFs, METADATA_DIRECTORY};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use futures::{pin_mut, StreamExt};
+use iroh::{
+    rpc_protocol::ShareMode,
+    sync::{AuthorId, NamespaceId, NamespaceSecret},
+};
+use multibase::Base;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{error::Error, fmt, path::PathBuf, str::FromStr};
+
+/// The directory holding capability tokens, within the directory reserved for file system metadata.
+pub const CAPABILITIES_DIRECTORY_NAME: &str = "capabilities";
+
+/// The directory holding revocations of capability tokens, within the directory reserved for file system metadata.
+pub const REVOCATIONS_DIRECTORY_NAME: &str = "revocations";
+
+/// The ability to create, modify, and delete files.
+pub const WRITE_ABILITY: &str = "fs/write";
+
+/// The version of the UCAN specification that tokens are written in.
+pub const UCAN_VERSION: &str = "0.9.0";
+
+/// The multicodec prefix of an Ed25519 public key.
+const ED25519_PUBLIC_KEY_CODEC: [u8; 2] = [0xed, 0x01];
+
+/// The prefix of a version 1 CID addressing raw bytes by their SHA-256 hash.
+const RAW_SHA256_CID_PREFIX: [u8; 4] = [0x01, 0x55, 0x12, 0x20];
+
+/// Gets the path at which a capability token is held.
+///
+/// # Arguments
+///
+/// * `cid` - The content identifier of the token.
+///
+/// # Returns
+///
+/// The path of the token within a replica.
+pub fn capability_path(cid: &str) -> PathBuf {
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(CAPABILITIES_DIRECTORY_NAME)
+        .join(format!("{}.jwt", cid))
+}
+
+/// Gets the path at which the revocation of a capability token is held.
+///
+/// # Arguments
+///
+/// * `cid` - The content identifier of the revoked token.
+///
+/// # Returns
+///
+/// The path of the revocation within a replica.
+pub fn revocation_path(cid: &str) -> PathBuf {
+    PathBuf::from(METADATA_DIRECTORY)
+        .join(REVOCATIONS_DIRECTORY_NAME)
+        .join(format!("{}.json", cid))
+}
+
+/// Gets the decentralised identifier of an Ed25519 public key, such as that of an author or a replica.
+///
+/// # Arguments
+///
+/// * `public_key` - The public key.
+///
+/// # Returns
+///
+/// A `did:key` identifier.
+pub fn did_key(public_key: &[u8; 32]) -> String {
+    format!(
+        "did:key:{}",
+        multibase::encode(
+            Base::Base58Btc,
+            [ED25519_PUBLIC_KEY_CODEC.as_slice(), public_key].concat()
+        )
+    )
+}
+
+/// Gets the Ed25519 public key named by a decentralised identifier.
+///
+/// # Arguments
+///
+/// * `did` - A `did:key` identifier.
+///
+/// # Returns
+///
+/// The public key, if the identifier names an Ed25519 public key.
+pub fn did_key_public_key(did: &str) -> Option<[u8; 32]> {
+    let (_, bytes) = multibase::decode(did.strip_prefix("did:key:")?).ok()?;
+    bytes
+        .strip_prefix(ED25519_PUBLIC_KEY_CODEC.as_slice())?
+        .try_into()
+        .ok()
+}
+
+/// Gets the resource a capability must be granted over to act on a path within a replica.
+///
+/// # Arguments
+///
+/// * `namespace_id` - The ID of the replica.
+///
+/// * `path` - The path within the replica.
+///
+/// # Returns
+///
+/// A URI naming the path within the replica.
+pub fn resource_uri(namespace_id: NamespaceId, path: PathBuf) -> String {
+    format!(
+        "oku://{}{}",
+        namespace_id,
+        normalise_path(path).to_string_lossy()
+    )
+}
+
+/// Gets the replica a resource is within.
+///
+/// # Arguments
+///
+/// * `resource` - A URI naming a path within a replica.
+///
+/// # Returns
+///
+/// The ID of the replica, if the URI names a path within one.
+pub fn resource_namespace(resource: &str) -> Option<NamespaceId> {
+    resource
+        .strip_prefix("oku://")?
+        .split('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// An ability granted over a resource.
+pub struct Capability {
+    /// The resource the ability is granted over. Granting an ability over a directory grants it over everything within.
+    pub with: String,
+    /// The ability granted.
+    pub can: String,
+}
+
+impl Capability {
+    /// Creates a capability to write to a path within a replica, and to everything within it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `path` - The path within the replica.
+    ///
+    /// # Returns
+    ///
+    /// A capability granting the ability to create, modify, and delete files at or below the path.
+    pub fn write(namespace_id: NamespaceId, path: PathBuf) -> Self {
+        Capability {
+            with: resource_uri(namespace_id, path),
+            can: WRITE_ABILITY.to_string(),
+        }
+    }
+
+    /// Checks whether this capability grants an ability over a resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The resource to act on.
+    ///
+    /// * `ability` - The ability to exercise.
+    ///
+    /// # Returns
+    ///
+    /// Whether the ability is granted over the resource.
+    pub fn covers(&self, resource: &str, ability: &str) -> bool {
+        let ability_granted = self.can == ability || self.can == "*";
+        let with = self.with.trim_end_matches('/');
+        let resource_granted = resource == self.with
+            || resource == with
+            || resource
+                .strip_prefix(with)
+                .is_some_and(|rest| rest.starts_with('/'));
+        ability_granted && resource_granted
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// The header of a capability token.
+pub struct UcanHeader {
+    /// The algorithm the token is signed with.
+    pub alg: String,
+    /// The type of the token.
+    pub typ: String,
+    /// The version of the UCAN specification the token is written in.
+    pub ucv: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// The claims made by a capability token.
+pub struct UcanPayload {
+    /// The identifier of the party granting the capabilities.
+    pub iss: String,
+    /// The identifier of the party the capabilities are granted to.
+    pub aud: String,
+    /// The time, in seconds since the Unix epoch, after which the token is no longer valid.
+    pub exp: Option<i64>,
+    /// The time, in seconds since the Unix epoch, before which the token is not yet valid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<i64>,
+    /// A nonce distinguishing otherwise identical tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nnc: Option<String>,
+    /// The capabilities granted.
+    pub att: Vec<Capability>,
+    /// The tokens proving that the issuer holds the capabilities it grants.
+    #[serde(default)]
+    pub prf: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A signed token granting capabilities over replicas, following the UCAN specification.
+pub struct Ucan {
+    /// The header of the token.
+    header: UcanHeader,
+    /// The claims made by the token.
+    payload: UcanPayload,
+    /// The issuer's signature of the header and claims.
+    signature: Vec<u8>,
+    /// The encoded token.
+    token: String,
+}
+
+impl FromStr for Ucan {
+    type Err = OkuFsError;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| OkuFsError::InvalidCapability(reason.to_string());
+        let mut parts = token.trim().split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("a token must have three parts"));
+        };
+        let decode = |part: &str| {
+            URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|_| invalid("a part is not valid base64"))
+        };
+        Ok(Ucan {
+            header: serde_json::from_slice(&decode(header)?)
+                .map_err(|e| invalid(&e.to_string()))?,
+            payload: serde_json::from_slice(&decode(payload)?)
+                .map_err(|e| invalid(&e.to_string()))?,
+            signature: decode(signature)?,
+            token: token.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Ucan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.token)
+    }
+}
+
+impl Serialize for Ucan {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.token)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ucan {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let token = String::deserialize(deserializer)?;
+        token.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Ucan {
+    /// Creates a token, signing it with the issuer's key.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The claims made by the token.
+    ///
+    /// * `sign` - Signs a message with the key of the token's issuer.
+    ///
+    /// # Returns
+    ///
+    /// The signed token.
+    pub(crate) fn sign(
+        payload: UcanPayload,
+        sign: impl FnOnce(&[u8]) -> Signature,
+    ) -> Result<Ucan, Box<dyn Error + Send + Sync>> {
+        let header = UcanHeader {
+            alg: "EdDSA".to_string(),
+            typ: "JWT".to_string(),
+            ucv: UCAN_VERSION.to_string(),
+        };
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&payload)?)
+        );
+        let signature = sign(signed.as_bytes()).to_bytes().to_vec();
+        let token = format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(&signature));
+        Ok(Ucan {
+            header,
+            payload,
+            signature,
+            token,
+        })
+    }
+
+    /// Gets the header of the token.
+    pub fn header(&self) -> &UcanHeader {
+        &self.header
+    }
+
+    /// Gets the claims made by the token.
+    pub fn payload(&self) -> &UcanPayload {
+        &self.payload
+    }
+
+    /// Gets the content identifier of the token, by which it is referred to as a proof.
+    ///
+    /// # Returns
+    ///
+    /// A version 1 CID of the encoded token.
+    pub fn cid(&self) -> String {
+        let digest = Sha256::digest(self.token.as_bytes());
+        multibase::encode(
+            Base::Base32Lower,
+            [RAW_SHA256_CID_PREFIX.as_slice(), digest.as_slice()].concat(),
+        )
+    }
+
+    /// Checks that the token was signed by its issuer.
+    pub fn verify_signature(&self) -> Result<(), OkuFsError> {
+        let invalid = |reason: &str| OkuFsError::InvalidCapability(reason.to_string());
+        if self.header.alg != "EdDSA" {
+            return Err(invalid("only EdDSA signatures are supported"));
+        }
+        let issuer_key = did_key_public_key(&self.payload.iss)
+            .and_then(|public_key| VerifyingKey::from_bytes(&public_key).ok())
+            .ok_or(invalid("the issuer is not an Ed25519 key"))?;
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|_| invalid("the signature is malformed"))?;
+        let (signed, _) = self
+            .token
+            .rsplit_once('.')
+            .ok_or(invalid("a token must have three parts"))?;
+        issuer_key
+            .verify(signed.as_bytes(), &signature)
+            .map_err(|_| invalid("the signature does not match the issuer"))
+    }
+
+    /// Checks whether the token is valid at a given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time, in seconds since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// Whether the time falls within the token's validity period.
+    pub fn is_active(&self, now: i64) -> bool {
+        self.payload.exp.is_none_or(|exp| now < exp)
+            && self.payload.nbf.is_none_or(|nbf| now >= nbf)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A record withdrawing a previously issued capability token.
+/// A token may be revoked by its own issuer, or by the replica it grants capabilities over.
+pub struct Revocation {
+    /// The identifier of the party revoking the token.
+    pub iss: String,
+    /// The content identifier of the revoked token.
+    pub revoke: String,
+    /// The revoking party's signature of `REVOKE:` followed by the content identifier of the revoked token.
+    pub challenge: String,
+}
+
+impl Revocation {
+    /// Creates a revocation, signing it with the revoking party's key.
+    ///
+    /// # Arguments
+    ///
+    /// * `iss` - The identifier of the party revoking the token.
+    ///
+    /// * `cid` - The content identifier of the token to revoke.
+    ///
+    /// * `sign` - Signs a message with the key of the revoking party.
+    ///
+    /// # Returns
+    ///
+    /// The signed revocation.
+    pub(crate) fn sign(iss: String, cid: &str, sign: impl FnOnce(&[u8]) -> Signature) -> Self {
+        let signature = sign(format!("REVOKE:{}", cid).as_bytes());
+        Revocation {
+            iss,
+            revoke: cid.to_string(),
+            challenge: URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+        }
+    }
+
+    /// Checks that the revocation was signed by the party revoking the token.
+    pub fn verify_signature(&self) -> Result<(), OkuFsError> {
+        let invalid = |reason: &str| OkuFsError::InvalidCapability(reason.to_string());
+        let issuer_key = did_key_public_key(&self.iss)
+            .and_then(|public_key| VerifyingKey::from_bytes(&public_key).ok())
+            .ok_or(invalid("the revoking party is not an Ed25519 key"))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(&self.challenge)
+            .ok()
+            .and_then(|signature| Signature::from_slice(&signature).ok())
+            .ok_or(invalid("the revocation's signature is malformed"))?;
+        issuer_key
+            .verify(format!("REVOKE:{}", self.revoke).as_bytes(), &signature)
+            .map_err(|_| invalid("the revocation's signature does not match the revoking party"))
+    }
+
+    /// Checks whether this revocation withdraws a token.
+    ///
+    /// # Arguments
+    ///
+    /// * `ucan` - The token.
+    ///
+    /// * `root` - The identifier of the replica the token grants capabilities over.
+    ///
+    /// # Returns
+    ///
+    /// Whether the token is revoked by a party entitled to revoke it.
+    pub fn revokes(&self, ucan: &Ucan, root: &str) -> bool {
+        self.revoke == ucan.cid() && (self.iss == root || self.iss == ucan.payload.iss)
+    }
+}
+
+/// Checks that a token grants an ability over a resource, tracing its proofs back to a root issuer.
+///
+/// # Arguments
+///
+/// * `ucan` - The token to check.
+///
+/// * `action` - The ability to exercise and the resource to exercise it over.
+///
+/// * `root` - The identifier of the issuer that holds every capability without proof.
+///
+/// * `proofs` - The tokens that the token's proofs may refer to.
+///
+/// * `revocations` - The revocations of tokens that are no longer honoured.
+///
+/// * `now` - The time, in seconds since the Unix epoch, at which the token is being used.
+fn verify_delegation(
+    ucan: &Ucan,
+    action: &Capability,
+    root: &str,
+    proofs: &[Ucan],
+    revocations: &[Revocation],
+    now: i64,
+) -> Result<(), OkuFsError> {
+    ucan.verify_signature()?;
+    if revocations
+        .iter()
+        .any(|revocation| revocation.revokes(ucan, root))
+    {
+        return Err(OkuFsError::InvalidCapability(
+            "the token has been revoked".to_string(),
+        ));
+    }
+    if !ucan.is_active(now) {
+        return Err(OkuFsError::InvalidCapability(
+            "the token has expired or is not yet valid".to_string(),
+        ));
+    }
+    if !ucan
+        .payload
+        .att
+        .iter()
+        .any(|capability| capability.covers(&action.with, &action.can))
+    {
+        return Err(OkuFsError::InvalidCapability(format!(
+            "the token does not grant {} over {}",
+            action.can, action.with
+        )));
+    }
+    if ucan.payload.iss == root {
+        return Ok(());
+    }
+    // Proofs are referred to by the hash of their content, so a chain of proofs cannot loop back on itself.
+    let proven = ucan.payload.prf.iter().any(|cid| {
+        proofs.iter().any(|proof| {
+            proof.cid() == *cid
+                && proof.payload.aud == ucan.payload.iss
+                && verify_delegation(proof, action, root, proofs, revocations, now).is_ok()
+        })
+    });
+    if !proven {
+        return Err(OkuFsError::InvalidCapability(
+            "the issuer's authority cannot be traced back to the replica".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl OkuFs {
+    /// Grants capabilities over a replica to another party, such as the ability to write only under a certain directory.
+    /// The token is issued by the replica itself, so only replicas this node can write to can be delegated from.
+    /// The token is recorded in the replica, so that peers holding the replica honour it.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to grant capabilities over.
+    ///
+    /// * `audience` - The decentralised identifier of the party to grant the capabilities to.
+    ///
+    /// * `capabilities` - The capabilities to grant, each over a resource within the replica.
+    ///
+    /// * `expiry` - An optional time after which the capabilities are no longer granted.
+    ///
+    /// # Returns
+    ///
+    /// A token granting the capabilities, to be sent to the audience.
+    pub async fn delegate_capability(
+        &self,
+        namespace_id: NamespaceId,
+        audience: &str,
+        capabilities: Vec<Capability>,
+        expiry: Option<DateTime<Utc>>,
+    ) -> Result<Ucan, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        if did_key_public_key(audience).is_none() {
+            return Err(OkuFsError::InvalidCapability(
+                "the audience is not an Ed25519 key".to_string(),
+            )
+            .into());
+        }
+        if let Some(capability) = capabilities
+            .iter()
+            .find(|capability| resource_namespace(&capability.with) != Some(namespace_id))
+        {
+            return Err(OkuFsError::InvalidCapability(format!(
+                "{} is not within the replica",
+                capability.with
+            ))
+            .into());
+        }
+        let namespace_secret = self.namespace_secret(namespace_id).await?;
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let payload = UcanPayload {
+            iss: did_key(namespace_id.as_bytes()),
+            aud: audience.to_string(),
+            exp: expiry.map(|expiry| expiry.timestamp()),
+            nbf: None,
+            nnc: Some(URL_SAFE_NO_PAD.encode(nonce)),
+            att: capabilities,
+            prf: Vec::new(),
+        };
+        let ucan = Ucan::sign(payload, |message| namespace_secret.sign(message))?;
+        self.add_capability(namespace_id, &ucan).await?;
+        Ok(ucan)
+    }
+
+    /// Checks that a token grants an ability over a resource within a replica.
+    /// Unless issued by the replica itself, the token's authority must be proven by tokens recorded in the replica.
+    /// Tokens revoked in the replica, or proven only by revoked tokens, are not honoured.
+    ///
+    /// # Arguments
+    ///
+    /// * `ucan` - The token to check, such as one received from a peer.
+    ///
+    /// * `action` - The ability to exercise and the resource to exercise it over.
+    pub async fn verify_capability(
+        &self,
+        ucan: &Ucan,
+        action: &Capability,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let namespace_id = resource_namespace(&action.with).ok_or(
+            OkuFsError::InvalidCapability(format!("{} is not within a replica", action.with)),
+        )?;
+        let proofs = self.list_capabilities(namespace_id).await?;
+        let revocations = self.list_revocations(namespace_id).await?;
+        Ok(verify_delegation(
+            ucan,
+            action,
+            &did_key(namespace_id.as_bytes()),
+            &proofs,
+            &revocations,
+            chrono::Utc::now().timestamp(),
+        )?)
+    }
+
+    /// Records a capability token in a replica, so that it is honoured when checking writes to the replica.
+    /// The token is synchronised to peers along with the rest of the replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to record the token in.
+    ///
+    /// * `ucan` - The token to record.
+    ///
+    /// # Returns
+    ///
+    /// The content identifier of the token.
+    pub async fn add_capability(
+        &self,
+        namespace_id: NamespaceId,
+        ucan: &Ucan,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        ucan.verify_signature()?;
+        let cid = ucan.cid();
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_bytes(
+                self.author_id,
+                self.entry_key(capability_path(&cid)),
+                ucan.to_string(),
+            )
+            .await?;
+        Ok(cid)
+    }
+
+    /// Lists the capability tokens recorded in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The tokens recorded in the replica.
+    pub async fn list_capabilities(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<Ucan>, Box<dyn Error + Send + Sync>> {
+        // Tokens that cannot be read grant nothing, so they are passed over rather than failing the listing.
+        Ok(self
+            .read_metadata_directory(namespace_id, CAPABILITIES_DIRECTORY_NAME)
+            .await?
+            .into_iter()
+            .filter_map(|content| String::from_utf8_lossy(&content).parse().ok())
+            .collect())
+    }
+
+    /// Revokes a capability token granting capabilities over a replica, signing the revocation with the replica's key.
+    /// The revocation is recorded in the replica, so that peers holding the replica stop honouring the token, and any tokens delegated on its authority.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica the token grants capabilities over.
+    ///
+    /// * `cid` - The content identifier of the token to revoke.
+    pub async fn revoke_capability(
+        &self,
+        namespace_id: NamespaceId,
+        cid: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        let namespace_secret = self.namespace_secret(namespace_id).await?;
+        let revocation = Revocation::sign(did_key(namespace_id.as_bytes()), cid, |message| {
+            namespace_secret.sign(message)
+        });
+        self.add_revocation(namespace_id, &revocation).await
+    }
+
+    /// Records the revocation of a capability token in a replica, such as one signed by a peer that delegated the token.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica to record the revocation in.
+    ///
+    /// * `revocation` - The revocation to record.
+    pub async fn add_revocation(
+        &self,
+        namespace_id: NamespaceId,
+        revocation: &Revocation,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.ensure_not_frozen(namespace_id)?;
+        revocation.verify_signature()?;
+        let document = self.open_document(namespace_id).await?;
+        document
+            .set_bytes(
+                self.author_id,
+                self.entry_key(revocation_path(&revocation.revoke)),
+                serde_json::to_vec(revocation)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Lists the revocations of capability tokens recorded in a replica.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The revocations recorded in the replica that bear a valid signature.
+    pub async fn list_revocations(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<Vec<Revocation>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .read_metadata_directory(namespace_id, REVOCATIONS_DIRECTORY_NAME)
+            .await?
+            .into_iter()
+            .filter_map(|content| serde_json::from_slice::<Revocation>(&content).ok())
+            .filter(|revocation| revocation.verify_signature().is_ok())
+            .collect())
+    }
+
+    /// Reads the content of every entry within a directory reserved for file system metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// * `directory_name` - The name of the directory, within the directory reserved for file system metadata.
+    ///
+    /// # Returns
+    ///
+    /// The content of each entry in the directory.
+    async fn read_metadata_directory(
+        &self,
+        namespace_id: NamespaceId,
+        directory_name: &str,
+    ) -> Result<Vec<bytes::Bytes>, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        let query = iroh::sync::store::Query::single_latest_per_key()
+            .key_prefix(self.entry_prefix(PathBuf::from(METADATA_DIRECTORY).join(directory_name)))
+            .build();
+        let entries = document.get_many(query).await?;
+        pin_mut!(entries);
+        let mut contents = Vec::new();
+        while let Some(entry) = entries.next().await {
+            contents.push(self.read_entry_content(&entry?).await?);
+        }
+        Ok(contents)
+    }
+
+    /// Gets the secret key of a replica, with which the replica issues and revokes capability tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica.
+    ///
+    /// # Returns
+    ///
+    /// The replica's secret key, if this node can write to the replica.
+    async fn namespace_secret(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<NamespaceSecret, Box<dyn Error + Send + Sync>> {
+        let document = self.open_document(namespace_id).await?;
+        match document
+            .share(ShareMode::Write)
+            .await
+            .map(|ticket| ticket.capability)
+        {
+            Ok(iroh::sync::Capability::Write(namespace_secret)) => Ok(namespace_secret),
+            _ => Err(OkuFsError::InvalidCapability(
+                "this node cannot write to the replica".to_string(),
+            )
+            .into()),
+        }
+    }
+
+    /// Rejects a local write to a file unless the author has been granted the ability to write to it.
+    /// Writes are only checked if capabilities are enforced in the configuration.
+    /// Capabilities must be recorded in the replica, and issued either by the replica itself or by a party it delegated to.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of the replica being written to.
+    ///
+    /// * `path` - The path of the file or directory being written to.
+    ///
+    /// * `author_id` - The author writing to the replica.
+    pub(crate) async fn authorize_write(
+        &self,
+        namespace_id: NamespaceId,
+        path: PathBuf,
+        author_id: AuthorId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.config.enforce_capabilities {
+            return Ok(());
+        }
+        let root = did_key(namespace_id.as_bytes());
+        let audience = did_key(author_id.as_bytes());
+        let action = Capability::write(namespace_id, path.clone());
+        let now = chrono::Utc::now().timestamp();
+        let capabilities = self.list_capabilities(namespace_id).await?;
+        let revocations = self.list_revocations(namespace_id).await?;
+        let authorized = capabilities.iter().any(|ucan| {
+            ucan.payload.aud == audience
+                && verify_delegation(ucan, &action, &root, &capabilities, &revocations, now).is_ok()
+        });
+        if !authorized {
+            return Err(OkuFsError::NotAuthorized(
+                author_id.to_string(),
+                normalise_path(path).to_string_lossy().to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    /// The resource the tokens in these tests grant capabilities over.
+    const RESOURCE: &str = "oku://replica/docs";
+
+    /// Gets the decentralised identifier of a signing key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The signing key.
+    ///
+    /// # Returns
+    ///
+    /// A `did:key` identifier.
+    fn did(key: &SigningKey) -> String {
+        did_key(&key.verifying_key().to_bytes())
+    }
+
+    /// Issues a token granting the ability to write to a resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `issuer` - The key of the party granting the capability.
+    ///
+    /// * `audience` - The key of the party the capability is granted to.
+    ///
+    /// * `with` - The resource the capability is granted over.
+    ///
+    /// * `proofs` - The tokens proving that the issuer holds the capability.
+    ///
+    /// # Returns
+    ///
+    /// The signed token.
+    fn issue(issuer: &SigningKey, audience: &SigningKey, with: &str, proofs: &[&Ucan]) -> Ucan {
+        let payload = UcanPayload {
+            iss: did(issuer),
+            aud: did(audience),
+            exp: None,
+            nbf: None,
+            nnc: None,
+            att: vec![Capability {
+                with: with.to_string(),
+                can: WRITE_ABILITY.to_string(),
+            }],
+            prf: proofs.iter().map(|proof| proof.cid()).collect(),
+        };
+        Ucan::sign(payload, |message| issuer.sign(message)).unwrap()
+    }
+
+    /// Gets the capability to write to a path within the resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path, relative to the resource.
+    ///
+    /// # Returns
+    ///
+    /// The capability.
+    fn write_action(path: &str) -> Capability {
+        Capability {
+            with: format!("{}{}", RESOURCE, path),
+            can: WRITE_ABILITY.to_string(),
+        }
+    }
+
+    #[test]
+    fn capability_covers_paths_within_resource() {
+        let capability = Capability {
+            with: RESOURCE.to_string(),
+            can: WRITE_ABILITY.to_string(),
+        };
+        assert!(capability.covers(RESOURCE, WRITE_ABILITY));
+        assert!(capability.covers("oku://replica/docs/a/b.txt", WRITE_ABILITY));
+        assert!(!capability.covers("oku://replica/docs2/a.txt", WRITE_ABILITY));
+        assert!(!capability.covers("oku://replica", WRITE_ABILITY));
+        assert!(!capability.covers(RESOURCE, "fs/read"));
+        let capability = Capability {
+            with: format!("{}/", RESOURCE),
+            can: "*".to_string(),
+        };
+        assert!(capability.covers(RESOURCE, "fs/read"));
+        assert!(capability.covers("oku://replica/docs/a.txt", WRITE_ABILITY));
+    }
+
+    #[test]
+    fn did_key_round_trip() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        assert_eq!(
+            did_key_public_key(&did(&key)),
+            Some(key.verifying_key().to_bytes())
+        );
+        assert_eq!(did_key_public_key("did:web:example.com"), None);
+    }
+
+    #[test]
+    fn parse_signed_token() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let author = SigningKey::from_bytes(&[2; 32]);
+        let ucan = issue(&root, &author, RESOURCE, &[]);
+        let parsed: Ucan = ucan.to_string().parse().unwrap();
+        assert_eq!(parsed, ucan);
+        assert!(parsed.verify_signature().is_ok());
+        // Claims cannot be changed without invalidating the signature.
+        let mut parts: Vec<&str> = ucan.token.split('.').collect();
+        let forged_payload = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&UcanPayload {
+                aud: did(&root),
+                ..ucan.payload.clone()
+            })
+            .unwrap(),
+        );
+        parts[1] = &forged_payload;
+        let forged: Ucan = parts.join(".").parse().unwrap();
+        assert!(forged.verify_signature().is_err());
+        assert!("a.b".parse::<Ucan>().is_err());
+    }
+
+    #[test]
+    fn token_validity_period() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let author = SigningKey::from_bytes(&[2; 32]);
+        let mut ucan = issue(&root, &author, RESOURCE, &[]);
+        ucan.payload.nbf = Some(100);
+        ucan.payload.exp = Some(200);
+        assert!(!ucan.is_active(99));
+        assert!(ucan.is_active(100));
+        assert!(ucan.is_active(199));
+        assert!(!ucan.is_active(200));
+    }
+
+    #[test]
+    fn verify_delegation_chain() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let alice = SigningKey::from_bytes(&[2; 32]);
+        let bob = SigningKey::from_bytes(&[3; 32]);
+        let to_alice = issue(&root, &alice, RESOURCE, &[]);
+        let to_bob = issue(&alice, &bob, &format!("{}/shared", RESOURCE), &[&to_alice]);
+        let proofs = [to_alice.clone(), to_bob.clone()];
+        let action = write_action("/shared/a.txt");
+        assert!(verify_delegation(&to_alice, &action, &did(&root), &proofs, &[], 0).is_ok());
+        assert!(verify_delegation(&to_bob, &action, &did(&root), &proofs, &[], 0).is_ok());
+        // A delegated token only grants what it names, even if its proof grants more.
+        assert!(verify_delegation(
+            &to_bob,
+            &write_action("/a.txt"),
+            &did(&root),
+            &proofs,
+            &[],
+            0
+        )
+        .is_err());
+        // The chain must lead back to the root.
+        assert!(verify_delegation(&to_bob, &action, &did(&bob), &proofs, &[], 0).is_err());
+        // Proofs that are not held cannot be traced.
+        assert!(
+            verify_delegation(&to_bob, &action, &did(&root), &[to_bob.clone()], &[], 0).is_err()
+        );
+    }
+
+    #[test]
+    fn reject_broadened_delegation() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let alice = SigningKey::from_bytes(&[2; 32]);
+        let bob = SigningKey::from_bytes(&[3; 32]);
+        let to_alice = issue(&root, &alice, &format!("{}/shared", RESOURCE), &[]);
+        let to_bob = issue(&alice, &bob, RESOURCE, &[&to_alice]);
+        let proofs = [to_alice, to_bob.clone()];
+        assert!(verify_delegation(
+            &to_bob,
+            &write_action("/private.txt"),
+            &did(&root),
+            &proofs,
+            &[],
+            0
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_proof_for_another_audience() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let alice = SigningKey::from_bytes(&[2; 32]);
+        let bob = SigningKey::from_bytes(&[3; 32]);
+        let mallory = SigningKey::from_bytes(&[4; 32]);
+        let to_alice = issue(&root, &alice, RESOURCE, &[]);
+        // Mallory cites a token granted to Alice as proof of their own authority.
+        let to_bob = issue(&mallory, &bob, RESOURCE, &[&to_alice]);
+        let proofs = [to_alice, to_bob.clone()];
+        assert!(verify_delegation(
+            &to_bob,
+            &write_action("/a.txt"),
+            &did(&root),
+            &proofs,
+            &[],
+            0
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_expired_proof() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let alice = SigningKey::from_bytes(&[2; 32]);
+        let bob = SigningKey::from_bytes(&[3; 32]);
+        let mut payload = issue(&root, &alice, RESOURCE, &[]).payload;
+        payload.exp = Some(100);
+        let to_alice = Ucan::sign(payload, |message| root.sign(message)).unwrap();
+        let to_bob = issue(&alice, &bob, RESOURCE, &[&to_alice]);
+        let proofs = [to_alice, to_bob.clone()];
+        let action = write_action("/a.txt");
+        assert!(verify_delegation(&to_bob, &action, &did(&root), &proofs, &[], 99).is_ok());
+        assert!(verify_delegation(&to_bob, &action, &did(&root), &proofs, &[], 100).is_err());
+    }
+
+    #[test]
+    fn revocation_withdraws_chain() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let alice = SigningKey::from_bytes(&[2; 32]);
+        let bob = SigningKey::from_bytes(&[3; 32]);
+        let to_alice = issue(&root, &alice, RESOURCE, &[]);
+        let to_bob = issue(&alice, &bob, RESOURCE, &[&to_alice]);
+        let proofs = [to_alice.clone(), to_bob.clone()];
+        let action = write_action("/a.txt");
+        let revoke = |key: &SigningKey, ucan: &Ucan| {
+            Revocation::sign(did(key), &ucan.cid(), |message| key.sign(message))
+        };
+
+        // Revoking a proof withdraws every token relying on it.
+        let by_root = revoke(&root, &to_alice);
+        assert!(by_root.verify_signature().is_ok());
+        assert!(verify_delegation(&to_bob, &action, &did(&root), &proofs, &[by_root], 0).is_err());
+
+        // A token's issuer may revoke it.
+        let by_issuer = revoke(&alice, &to_bob);
+        assert!(by_issuer.revokes(&to_bob, &did(&root)));
+        assert!(
+            verify_delegation(&to_bob, &action, &did(&root), &proofs, &[by_issuer], 0).is_err()
+        );
+
+        // Other parties, including the token's audience, may not.
+        let by_audience = revoke(&bob, &to_bob);
+        assert!(!by_audience.revokes(&to_bob, &did(&root)));
+        assert!(
+            verify_delegation(&to_bob, &action, &did(&root), &proofs, &[by_audience], 0).is_ok()
+        );
+    }
+
+    #[test]
+    fn reject_forged_revocation() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let alice = SigningKey::from_bytes(&[2; 32]);
+        let ucan = issue(&root, &alice, RESOURCE, &[]);
+        let mut revocation =
+            Revocation::sign(did(&root), &ucan.cid(), |message| alice.sign(message));
+        assert!(revocation.verify_signature().is_err());
+        revocation.challenge = "not a signature".to_string();
+        assert!(revocation.verify_signature().is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use crate::event::OkuFsEvent;
+use crate::fs::OkuFs;
+use futures::{pin_mut, StreamExt};
+use iroh::{bytes::Hash, sync::NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+/// How much of a limit on storage has been used.
+pub enum UsageLevel {
+    /// Usage is below the warning threshold.
+    #[default]
+    Normal,
+    /// Usage has reached the warning threshold, but not the limit.
+    Warning,
+    /// Usage has reached the limit.
+    Exceeded,
+}
+
+#[derive(Debug, Default)]
+/// The most recently reported usage levels of the replicas and the local content store.
+pub(crate) struct UsageLevels {
+    /// The usage level of each replica's quota.
+    replicas: HashMap<NamespaceId, UsageLevel>,
+    /// Whether the local content store has reached its watermark.
+    store_watermark_reached: bool,
+    /// The replicas whose usage is being checked, each with whether it has been written to since its check began.
+    pending_checks: HashMap<NamespaceId, bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// How much storage one replica uses.
+pub struct ReplicaStorage {
+    /// The ID of the replica.
+    pub namespace_id: NamespaceId,
+    /// The size, in bytes, of the latest version of each entry as it is read, once decrypted and decompressed where held locally.
+    pub logical_size: u64,
+    /// The size, in bytes, of the distinct content held locally that any version of an entry in the replica refers to.
+    pub physical_size: u64,
+    /// The part of the physical size, in bytes, belonging to content that other replicas also refer to.
+    pub shared_size: u64,
+    /// The number of bytes saved by versions within the replica referring to the same content.
+    pub deduplicated_size: u64,
+    /// The number of versions held, being the latest version written by each author of each entry, including deletions.
+    pub versions: usize,
+    /// The number of versions that have been superseded by a later version of the same entry.
+    pub historical_versions: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// A breakdown of what is held in the local content store.
+pub struct StorageReport {
+    /// How much storage each replica uses.
+    pub replicas: Vec<ReplicaStorage>,
+    /// The total size, in bytes, of all content held locally.
+    pub store_size: u64,
+    /// The size, in bytes, of content held locally that no replica refers to.
+    pub unreferenced_size: u64,
+}
+
+impl OkuFs {
+    /// Reports how much storage each replica uses, and how much of it is shared between versions and replicas.
+    /// Unlike [`OkuFs::get_size`], this accounts for every version held, and for content actually on disk.
+    ///
+    /// # Returns
+    ///
+    /// The storage used by each replica, along with the size of the local content store.
+    pub async fn storage_report(&self) -> Result<StorageReport, Box<dyn Error + Send + Sync>> {
+        let blob_sizes = self.blob_sizes().await?;
+        let mut replica_hashes: Vec<(NamespaceId, HashSet<Hash>)> = Vec::new();
+        let mut replicas = Vec::new();
+        for namespace_id in self.list_replicas().await? {
+            let document = self.open_document(namespace_id).await?;
+            let query = iroh::sync::store::Query::all().include_empty().build();
+            let entries = document.get_many(query).await?;
+            pin_mut!(entries);
+            let mut keys = HashSet::new();
+            let mut hashes = HashSet::new();
+            let mut versions = 0;
+            let mut referenced_size = 0;
+            while let Some(entry) = entries.next().await {
+                let entry = entry?;
+                versions += 1;
+                keys.insert(entry.key().to_vec());
+                if entry.content_len() == 0 {
+                    continue;
+                }
+                if let Some(size) = blob_sizes.get(&entry.content_hash()) {
+                    referenced_size += size;
+                    hashes.insert(entry.content_hash());
+                }
+            }
+
+            let query = iroh::sync::store::Query::single_latest_per_key().build();
+            let entries = document.get_many(query).await?;
+            pin_mut!(entries);
+            let mut logical_size = 0;
+            while let Some(entry) = entries.next().await {
+                let entry = entry?;
+                logical_size += match blob_sizes.contains_key(&entry.content_hash()) {
+                    true => self.content_size(&entry).await?,
+                    false => entry.content_len(),
+                };
+            }
+
+            let physical_size = hashes.iter().map(|hash| blob_sizes[hash]).sum::<u64>();
+            replicas.push(ReplicaStorage {
+                namespace_id,
+                logical_size,
+                physical_size,
+                shared_size: 0,
+                deduplicated_size: referenced_size - physical_size,
+                versions,
+                historical_versions: versions - keys.len(),
+            });
+            replica_hashes.push((namespace_id, hashes));
+        }
+
+        for (replica, (_, hashes)) in replicas.iter_mut().zip(replica_hashes.iter()) {
+            replica.shared_size = hashes
+                .iter()
+                .filter(|hash| {
+                    replica_hashes
+                        .iter()
+                        .any(|(other_namespace_id, other_hashes)| {
+                            *other_namespace_id != replica.namespace_id
+                                && other_hashes.contains(*hash)
+                        })
+                })
+                .map(|hash| blob_sizes[hash])
+                .sum();
+        }
+        let referenced_hashes: HashSet<&Hash> = replica_hashes
+            .iter()
+            .flat_map(|(_, hashes)| hashes.iter())
+            .collect();
+        Ok(StorageReport {
+            replicas,
+            store_size: blob_sizes.values().sum(),
+            unreferenced_size: blob_sizes
+                .iter()
+                .filter(|(hash, _)| !referenced_hashes.contains(hash))
+                .map(|(_, size)| size)
+                .sum(),
+        })
+    }
+
+    /// Lists the content held in full in the local content store.
+    ///
+    /// # Returns
+    ///
+    /// The size, in bytes, of each piece of content held locally, by its hash.
+    pub(crate) async fn blob_sizes(
+        &self,
+    ) -> Result<HashMap<Hash, u64>, Box<dyn Error + Send + Sync>> {
+        let blobs = self.node.blobs.list().await?;
+        pin_mut!(blobs);
+        let mut blob_sizes = HashMap::new();
+        while let Some(blob) = blobs.next().await {
+            let blob = blob?;
+            blob_sizes.insert(blob.hash, blob.size);
+        }
+        Ok(blob_sizes)
+    }
+
+    /// Gets the size of the local content store.
+    ///
+    /// # Returns
+    ///
+    /// The total size, in bytes, of all content held locally.
+    pub async fn get_store_size(&self) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let blobs = self.node.blobs.list().await?;
+        pin_mut!(blobs);
+        let blob_sizes: Vec<u64> = blobs.map(|blob| blob.unwrap().size).collect().await;
+        Ok(blob_sizes.iter().sum())
+    }
+
+    /// Checks storage usage once a replica has been written to, with at most one check of each replica underway at once.
+    /// Writes made while a check is underway are covered by a single further check, so that writing many entries at once does not measure the replica again for each of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of a replica that has been written to.
+    pub(crate) fn schedule_usage_check(&self, namespace_id: NamespaceId) {
+        {
+            let mut usage_levels = self.usage_levels.lock().unwrap();
+            if let Some(written_since) = usage_levels.pending_checks.get_mut(&namespace_id) {
+                *written_since = true;
+                return;
+            }
+            usage_levels.pending_checks.insert(namespace_id, false);
+        }
+        let self_clone = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self_clone.check_usage(namespace_id).await {
+                    tracing::warn!("{}", e);
+                }
+                let check_again = {
+                    let mut usage_levels = self_clone.usage_levels.lock().unwrap();
+                    match usage_levels.pending_checks.get_mut(&namespace_id) {
+                        Some(written_since) if *written_since => {
+                            *written_since = false;
+                            true
+                        }
+                        _ => {
+                            usage_levels.pending_checks.remove(&namespace_id);
+                            false
+                        }
+                    }
+                };
+                if !check_again {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Compares storage usage against the configured limits, reporting any newly crossed thresholds as events.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace_id` - The ID of a replica that has been written to.
+    pub(crate) async fn check_usage(
+        &self,
+        namespace_id: NamespaceId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(quota) = self.config.replica_quota {
+            let usage = self.get_size(namespace_id).await?;
+            let level = if usage >= quota {
+                UsageLevel::Exceeded
+            } else if usage as f64 >= quota as f64 * self.config.quota_warning_ratio {
+                UsageLevel::Warning
+            } else {
+                UsageLevel::Normal
+            };
+            let previous_level = self
+                .usage_levels
+                .lock()
+                .unwrap()
+                .replicas
+                .insert(namespace_id, level)
+                .unwrap_or_default();
+            if level > previous_level {
+                self.emit(OkuFsEvent::QuotaThresholdCrossed {
+                    namespace_id,
+                    level,
+                    usage,
+                    quota,
+                });
+            }
+        }
+        if let Some(watermark) = self.config.store_watermark {
+            let usage = self.get_store_size().await?;
+            let reached = usage >= watermark;
+            let previously_reached = std::mem::replace(
+                &mut self.usage_levels.lock().unwrap().store_watermark_reached,
+                reached,
+            );
+            if reached && !previously_reached {
+                self.emit(OkuFsEvent::StoreWatermarkReached { usage, watermark });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,598 @@
+use crate::error::OkuFsError;
+use crate::fs::{normalise_path, OkuFs, METADATA_DIRECTORY};
+use crate::http_util::{collect_limited_body, percent_decode, token_matches};
+use base64::Engine;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    body::Incoming, header, server::conn::http1, service::service_fn, Method, Request, Response,
+    StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use iroh::{client::Entry, sync::NamespaceId};
+use std::{
+    collections::BTreeMap, error::Error, fmt::Write, net::SocketAddr, path::PathBuf, str::FromStr,
+};
+use tokio::net::TcpListener;
+
+/// The methods supported by the WebDAV server.
+const ALLOWED_METHODS: &str = "OPTIONS, PROPFIND, GET, HEAD, PUT, DELETE, MKCOL";
+
+/// An item listed in response to a `PROPFIND` request.
+enum DavResource {
+    /// A directory, being either a replica or a path prefix shared by files within a replica.
+    Collection,
+    /// A file, described by its latest entry and the size of its content.
+    File(Entry, u64),
+}
+
+impl OkuFs {
+    /// Serves the replicas in the file system over WebDAV, so they can be mounted by operating systems and file managers.
+    /// Each replica appears as a top-level directory named after its ID; replicas may also be reached through their aliases.
+    /// Requests must carry the token, either as a bearer token or as the password of basic authentication with any user name. Connections are not encrypted, so the server should only be reached on trusted networks or through a tunnel.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen for WebDAV clients on.
+    ///
+    /// * `token` - The token requests must carry.
+    pub async fn serve_webdav(
+        &self,
+        address: SocketAddr,
+        token: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let listener = TcpListener::bind(address).await?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let self_clone = self.clone();
+            let token = token.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let self_clone = self_clone.clone();
+                    let token = token.clone();
+                    async move { self_clone.respond_to_webdav_request(request, &token).await }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    tracing::warn!("{}", e);
+                }
+            });
+        }
+    }
+
+    /// Responds to a request from a WebDAV client.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request from the client.
+    ///
+    /// * `token` - The token requests must carry.
+    ///
+    /// # Returns
+    ///
+    /// The response to send to the client.
+    async fn respond_to_webdav_request(
+        &self,
+        request: Request<Incoming>,
+        token: &str,
+    ) -> Result<Response<Full<Bytes>>, hyper::Error> {
+        if !webdav_request_authorised(&request, token) {
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, "Basic realm=\"oku\"")
+                .body(Full::default())
+                .unwrap());
+        }
+        let result = match *request.method() {
+            Method::OPTIONS => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("DAV", "1")
+                .header(header::ALLOW, ALLOWED_METHODS)
+                .body(Full::default())
+                .unwrap()),
+            Method::GET | Method::HEAD => self.webdav_get(&request).await,
+            Method::PUT => self.webdav_put(request).await,
+            Method::DELETE => self.webdav_delete(&request).await,
+            _ if request.method().as_str() == "PROPFIND" => self.webdav_propfind(&request).await,
+            _ if request.method().as_str() == "MKCOL" => self.webdav_mkcol(&request).await,
+            _ => Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, ALLOWED_METHODS)
+                .body(Full::default())
+                .unwrap()),
+        };
+        Ok(result.unwrap_or_else(|e| {
+            let status = match e.downcast_ref::<OkuFsError>() {
+                Some(OkuFsError::FsEntryNotFound) => StatusCode::NOT_FOUND,
+                Some(OkuFsError::ReplicaFrozen(_)) => StatusCode::FORBIDDEN,
+                Some(OkuFsError::NotAuthorized(_, _)) => StatusCode::FORBIDDEN,
+                Some(OkuFsError::WriteLimitExceeded(_, _)) => StatusCode::PAYLOAD_TOO_LARGE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            Response::builder()
+                .status(status)
+                .body(Full::new(Bytes::from(e.to_string())))
+                .unwrap()
+        }))
+    }
+
+    /// Finds the replica and path a WebDAV request refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request from the client.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the replica and the path within it, or `None` if the request refers to the root of the server.
+    /// File system metadata cannot be reached, so it is neither read nor written by clients.
+    fn resolve_webdav_path<B>(
+        &self,
+        request: &Request<B>,
+    ) -> Result<Option<(NamespaceId, PathBuf)>, Box<dyn Error + Send + Sync>> {
+        let path = percent_decode(request.uri().path());
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        let Some(replica) = segments.next() else {
+            return Ok(None);
+        };
+        let namespace_id = match NamespaceId::from_str(replica) {
+            Ok(namespace_id) => namespace_id,
+            Err(_) => self
+                .resolve_alias(replica)?
+                .ok_or(OkuFsError::FsEntryNotFound)?,
+        };
+        let path = normalise_path(segments.collect::<PathBuf>());
+        if path.starts_with(METADATA_DIRECTORY) {
+            return Err(OkuFsError::FsEntryNotFound.into());
+        }
+        Ok(Some((namespace_id, path)))
+    }
+
+    /// Responds to a `GET` or `HEAD` request by sending the content of a file.
+    async fn webdav_get<B>(
+        &self,
+        request: &Request<B>,
+    ) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+        let (namespace_id, path) = self
+            .resolve_webdav_path(request)?
+            .ok_or(OkuFsError::FsEntryNotFound)?;
+        let entry = self.get_latest_entry(namespace_id, path).await?;
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, self.content_size(&entry).await?)
+            .header(header::ETAG, format!("\"{}\"", entry.content_hash()))
+            .header(header::LAST_MODIFIED, http_date(entry.timestamp()));
+        if request.method() == Method::HEAD {
+            return Ok(response.body(Full::default())?);
+        }
+        let content = self.read_entry_content(&entry).await?;
+        Ok(response.body(Full::new(content))?)
+    }
+
+    /// Responds to a `PUT` request by creating or modifying a file.
+    async fn webdav_put(
+        &self,
+        request: Request<Incoming>,
+    ) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+        let Some((namespace_id, path)) = self.resolve_webdav_path(&request)? else {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Full::default())?);
+        };
+        let content = collect_limited_body(
+            request.into_body(),
+            self.config.limits.received_size_limit(),
+        )
+        .await?;
+        // Entries with no content mark deletions, so empty files cannot be stored.
+        if content.is_empty() {
+            return Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Full::new(Bytes::from("Empty files cannot be stored.")))?);
+        }
+        let existed = self
+            .get_latest_entry(namespace_id, path.clone())
+            .await
+            .is_ok();
+        self.create_or_modify_file(namespace_id, path, content)
+            .await?;
+        let status = if existed {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::CREATED
+        };
+        Ok(Response::builder().status(status).body(Full::default())?)
+    }
+
+    /// Responds to a `DELETE` request by deleting a file or directory.
+    /// Whether the file or directory exists is judged by the latest versions written by any author, as when reading it, so that files last written by peers can also be deleted.
+    async fn webdav_delete<B>(
+        &self,
+        request: &Request<B>,
+    ) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+        let Some((namespace_id, path)) = self.resolve_webdav_path(request)? else {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Full::default())?);
+        };
+        if self
+            .get_latest_entry(namespace_id, path.clone())
+            .await
+            .is_ok()
+        {
+            self.delete_file(namespace_id, path).await?;
+        } else if !self
+            .list_directory_entries(namespace_id, path.clone())
+            .await?
+            .is_empty()
+        {
+            self.delete_directory(namespace_id, path).await?;
+        } else {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Full::default())?);
+        }
+        Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Full::default())?)
+    }
+
+    /// Responds to a `MKCOL` request by creating an empty directory.
+    async fn webdav_mkcol<B>(
+        &self,
+        request: &Request<B>,
+    ) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+        let Some((namespace_id, path)) = self.resolve_webdav_path(request)? else {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Full::default())?);
+        };
+        if self
+            .get_latest_entry(namespace_id, path.clone())
+            .await
+            .is_ok()
+        {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Full::default())?);
+        }
+        self.create_directory(namespace_id, path).await?;
+        Ok(Response::builder()
+            .status(StatusCode::CREATED)
+            .body(Full::default())?)
+    }
+
+    /// Responds to a `PROPFIND` request by describing a file or the contents of a directory.
+    async fn webdav_propfind<B>(
+        &self,
+        request: &Request<B>,
+    ) -> Result<Response<Full<Bytes>>, Box<dyn Error + Send + Sync>> {
+        let include_children = request
+            .headers()
+            .get("Depth")
+            .is_none_or(|depth| depth.as_bytes() != b"0");
+        let request_path = request.uri().path().trim_end_matches('/').to_string();
+        let mut resources = Vec::new();
+        match self.resolve_webdav_path(request)? {
+            None => {
+                resources.push((String::from("/"), DavResource::Collection));
+                if include_children {
+                    for namespace_id in self.list_replicas().await? {
+                        resources.push((format!("/{}/", namespace_id), DavResource::Collection));
+                    }
+                }
+            }
+            Some((namespace_id, path)) => {
+                if let Ok(entry) = self.get_latest_entry(namespace_id, path.clone()).await {
+                    let size = self.content_size(&entry).await?;
+                    resources.push((request_path, DavResource::File(entry, size)));
+                } else {
+                    let tree = self.get_tree(namespace_id, path.clone()).await?;
+                    resources.push((format!("{}/", request_path), DavResource::Collection));
+                    if include_children {
+                        let mut children = BTreeMap::new();
+                        for name in tree.directories.into_keys() {
+                            children.insert(name, DavResource::Collection);
+                        }
+                        for (name, entry) in tree.files {
+                            let size = self.content_size(&entry).await?;
+                            children.insert(name, DavResource::File(entry, size));
+                        }
+                        for (name, resource) in children {
+                            let href = match resource {
+                                DavResource::Collection => {
+                                    format!("{}/{}/", request_path, percent_encode(&name))
+                                }
+                                DavResource::File(..) => {
+                                    format!("{}/{}", request_path, percent_encode(&name))
+                                }
+                            };
+                            resources.push((href, resource));
+                        }
+                    }
+                }
+            }
+        }
+        let mut body = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+        );
+        for (href, resource) in resources {
+            write_propfind_response(&mut body, &href, &resource)?;
+        }
+        body.push_str("</D:multistatus>\n");
+        Ok(Response::builder()
+            .status(StatusCode::MULTI_STATUS)
+            .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(Full::new(Bytes::from(body)))?)
+    }
+}
+
+/// Checks whether a WebDAV request carries the expected token, either as a bearer token or as the password of basic authentication.
+///
+/// # Arguments
+///
+/// * `request` - The request from the client.
+///
+/// * `token` - The token requests must carry.
+///
+/// # Returns
+///
+/// Whether the request is authorised.
+fn webdav_request_authorised<B>(request: &Request<B>, token: &str) -> bool {
+    let Some(authorization) = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    if let Some(presented) = authorization.strip_prefix("Bearer ") {
+        return token_matches(presented, token);
+    }
+    authorization
+        .strip_prefix("Basic ")
+        .and_then(|credentials| {
+            base64::engine::general_purpose::STANDARD
+                .decode(credentials)
+                .ok()
+        })
+        .and_then(|credentials| String::from_utf8(credentials).ok())
+        .and_then(|credentials| {
+            credentials
+                .split_once(':')
+                .map(|(_, password)| password.to_string())
+        })
+        .is_some_and(|presented| token_matches(&presented, token))
+}
+
+/// Writes the description of one resource into the body of a `PROPFIND` response.
+///
+/// # Arguments
+///
+/// * `body` - The body of the response.
+///
+/// * `href` - The encoded path of the resource.
+///
+/// * `resource` - The resource to describe.
+fn write_propfind_response(
+    body: &mut String,
+    href: &str,
+    resource: &DavResource,
+) -> Result<(), std::fmt::Error> {
+    let display_name = percent_decode(href.trim_end_matches('/'))
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    writeln!(body, "<D:response><D:href>{}</D:href>", xml_escape(href))?;
+    writeln!(body, "<D:propstat><D:prop>")?;
+    writeln!(
+        body,
+        "<D:displayname>{}</D:displayname>",
+        xml_escape(&display_name)
+    )?;
+    match resource {
+        DavResource::Collection => {
+            writeln!(body, "<D:resourcetype><D:collection/></D:resourcetype>")?;
+        }
+        DavResource::File(entry, size) => {
+            writeln!(body, "<D:resourcetype/>")?;
+            writeln!(body, "<D:getcontentlength>{}</D:getcontentlength>", size)?;
+            writeln!(
+                body,
+                "<D:getlastmodified>{}</D:getlastmodified>",
+                http_date(entry.timestamp())
+            )?;
+            writeln!(body, "<D:getetag>\"{}\"</D:getetag>", entry.content_hash())?;
+        }
+    }
+    writeln!(
+        body,
+        "</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>"
+    )?;
+    writeln!(body, "</D:response>")?;
+    Ok(())
+}
+
+/// Formats a time as used in HTTP headers.
+///
+/// # Arguments
+///
+/// * `timestamp` - The time, in microseconds since the Unix epoch.
+///
+/// # Returns
+///
+/// The time in the format of RFC 7231.
+fn http_date(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp_micros(timestamp as i64)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Escapes text for inclusion in an XML document.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Encodes a path segment for inclusion in a URL.
+fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::new();
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_metadata::FileMetadata;
+    use crate::fs::tests::start_test_fs;
+    use http_body_util::BodyExt;
+
+    /// Builds a request to the WebDAV server.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The method of the request.
+    ///
+    /// * `path` - The path requested.
+    ///
+    /// * `authorization` - The value of the `Authorization` header, if any.
+    ///
+    /// # Returns
+    ///
+    /// The request.
+    fn webdav_request(method: &str, path: &str, authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        request.body(()).unwrap()
+    }
+
+    /// Reads the body of a response as text.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The response.
+    ///
+    /// # Returns
+    ///
+    /// The body of the response.
+    async fn response_text(response: Response<Full<Bytes>>) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn authorise_requests_carrying_token() {
+        let basic = |credentials: &str| {
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            )
+        };
+        let authorised = |authorization: Option<&str>| {
+            webdav_request_authorised(&webdav_request("GET", "/", authorization), "token")
+        };
+        assert!(authorised(Some("Bearer token")));
+        assert!(authorised(Some(&basic("anyone:token"))));
+        assert!(!authorised(None));
+        assert!(!authorised(Some("Bearer wrong")));
+        assert!(!authorised(Some("token")));
+        assert!(!authorised(Some(&basic("token:wrong"))));
+        assert!(!authorised(Some(&basic("token"))));
+        assert!(!authorised(Some("Basic not base64")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn propfind_lists_directory_without_metadata() {
+        let oku_fs = start_test_fs("webdav-propfind").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a b.txt"), "a")
+            .await
+            .unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/d/b.txt"), "b")
+            .await
+            .unwrap();
+        let metadata = FileMetadata::from([(String::from("k"), String::from("v"))]);
+        oku_fs
+            .set_metadata(namespace_id, PathBuf::from("/a b.txt"), &metadata)
+            .await
+            .unwrap();
+        let response = oku_fs
+            .webdav_propfind(&webdav_request(
+                "PROPFIND",
+                &format!("/{}/", namespace_id),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+        let body = response_text(response).await;
+        assert!(body.contains(&format!("<D:href>/{}/a%20b.txt</D:href>", namespace_id)));
+        assert!(body.contains("<D:getcontentlength>1</D:getcontentlength>"));
+        assert!(body.contains(&format!("<D:href>/{}/d/</D:href>", namespace_id)));
+        assert!(!body.contains(".oku"));
+        // Without depth, only the directory itself is described.
+        let mut request = webdav_request("PROPFIND", &format!("/{}/d", namespace_id), None);
+        request
+            .headers_mut()
+            .insert("Depth", header::HeaderValue::from_static("0"));
+        let body = response_text(oku_fs.webdav_propfind(&request).await.unwrap()).await;
+        assert!(body.contains(&format!("<D:href>/{}/d/</D:href>", namespace_id)));
+        assert!(!body.contains("b.txt"));
+        oku_fs.shutdown();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hide_metadata_from_clients() {
+        let oku_fs = start_test_fs("webdav-metadata").await;
+        let namespace_id = oku_fs.create_replica().await.unwrap();
+        oku_fs
+            .create_or_modify_file(namespace_id, PathBuf::from("/a.txt"), "a")
+            .await
+            .unwrap();
+        let metadata = FileMetadata::from([(String::from("k"), String::from("v"))]);
+        oku_fs
+            .set_metadata(namespace_id, PathBuf::from("/a.txt"), &metadata)
+            .await
+            .unwrap();
+        let response = oku_fs
+            .webdav_get(&webdav_request(
+                "GET",
+                &format!("/{}/a.txt", namespace_id),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response_text(response).await, "a");
+        let error = oku_fs
+            .webdav_get(&webdav_request(
+                "GET",
+                &format!("/{}/.oku/file_metadata/a.txt", namespace_id),
+                None,
+            ))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OkuFsError>(),
+            Some(OkuFsError::FsEntryNotFound)
+        ));
+        oku_fs.shutdown();
+    }
+}